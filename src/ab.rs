@@ -0,0 +1,126 @@
+//! A/B runs, clients alternate between two servers under one load shape. The
+//! sides are compared per client with the Mann-Whitney U test, which needs no
+//! normal distribution and so suits skewed latencies
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Labels of the clients of each side, odd client ids go to A
+pub const LABEL_A: &str = "A";
+pub const LABEL_B: &str = "B";
+/// Differences with a lower p-value are reported as significant
+const SIGNIFICANCE: f64 = 0.05;
+
+/// One metric of both sides
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AbMetric {
+    pub metric: String,
+    pub a_median: f64,
+    pub b_median: f64,
+    /// Two sided, None with fewer than two clients on a side
+    pub p_value: Option<f64>,
+    pub significant: bool,
+}
+
+impl AbMetric {
+    pub fn new(metric: &str, a: &[f64], b: &[f64]) -> Self {
+        let p_value = mann_whitney(a, b);
+        Self {
+            metric: metric.to_string(),
+            a_median: median(a),
+            b_median: median(b),
+            p_value,
+            significant: p_value.map_or(false, |p| p < SIGNIFICANCE),
+        }
+    }
+}
+
+/// Table of the compared metrics, printable with `{}`
+pub struct AbTable<'a>(pub &'a [AbMetric]);
+
+impl fmt::Display for AbTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12}{:>12}{:>12}{:>10}",
+            "a/b metric", "A p50", "B p50", "p-value"
+        )?;
+        for m in self.0 {
+            let p_value = m.p_value.map_or("-".to_string(), |p| format!("{:.4}", p));
+            writeln!(
+                f,
+                "{:<12}{:>12.3}{:>12.3}{:>10}{}",
+                m.metric,
+                m.a_median,
+                m.b_median,
+                p_value,
+                if m.significant { "  SIGNIFICANT" } else { "" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn median(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut values = values.to_vec();
+    values.sort_by(|a, b| a.total_cmp(b));
+    values[(values.len() - 1) / 2]
+}
+
+/// Two sided p-value of the Mann-Whitney U test with the normal approximation
+/// and tie correction
+fn mann_whitney(a: &[f64], b: &[f64]) -> Option<f64> {
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let mut all: Vec<(f64, bool)> = a
+        .iter()
+        .map(|v| (*v, true))
+        .chain(b.iter().map(|v| (*v, false)))
+        .collect();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+    // ranks from 1, ties share their average rank
+    let mut rank_sum_a = 0.0;
+    let mut tie_term = 0.0;
+    let mut start = 0;
+    while start < all.len() {
+        let mut end = start + 1;
+        while end < all.len() && all[end].0 == all[start].0 {
+            end += 1;
+        }
+        let rank = (start + end + 1) as f64 / 2.0;
+        rank_sum_a += rank * all[start..end].iter().filter(|(_, is_a)| *is_a).count() as f64;
+        let ties = (end - start) as f64;
+        tie_term += ties * ties * ties - ties;
+        start = end;
+    }
+    let u = rank_sum_a - n1 * (n1 + 1.0) / 2.0;
+    let n = n1 + n2;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_term / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        // every value equal
+        return Some(1.0);
+    }
+    let z = (u - n1 * n2 / 2.0).abs() / variance.sqrt();
+    Some((2.0 * (1.0 - normal_cdf(z))).clamp(0.0, 1.0))
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun 7.1.26, absolute error below 1.5e-7
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    sign * (1.0 - poly * (-x * x).exp())
+}
@@ -0,0 +1,255 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_std::channel::Sender;
+use parking_lot::Mutex;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use tide::{Body, Request, Response, StatusCode};
+
+use crate::{
+    bench::{Arrival, BenchControl, BenchEvent, BenchPlan, BenchRunner},
+    collector::Collector,
+    whep::ClientConfig,
+};
+
+#[derive(Debug, Deserialize)]
+struct StartRequest {
+    url: String,
+    token: String,
+    #[serde(default = "default_count")]
+    count: usize,
+    #[serde(default = "default_interval_ms")]
+    interval_ms: u64,
+    #[serde(default = "default_live_ms")]
+    live_ms: u64,
+    #[serde(default = "default_signal_timeout_ms")]
+    signal_timeout_ms: u64,
+    #[serde(default)]
+    arrival: Arrival,
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    duration_ms: Option<u64>,
+    #[serde(default)]
+    shared_sockets: usize,
+    #[serde(default)]
+    threads: usize,
+    #[serde(default)]
+    sustain: bool,
+    #[serde(default)]
+    loops: Option<u32>,
+}
+
+/// Body of `PUT /target-count`
+#[derive(Debug, Serialize, Deserialize)]
+struct TargetRequest {
+    count: usize,
+}
+
+fn default_count() -> usize {
+    1
+}
+
+fn default_interval_ms() -> u64 {
+    1000
+}
+
+fn default_live_ms() -> u64 {
+    100000
+}
+
+fn default_signal_timeout_ms() -> u64 {
+    10000
+}
+
+#[derive(Default)]
+struct AgentInner {
+    stop_handle: Option<Arc<AtomicBool>>,
+    control_tx: Option<Sender<BenchControl>>,
+    collector: Collector,
+    subscribers: Vec<Sender<Arc<str>>>,
+}
+
+#[derive(Clone, Default)]
+struct Agent {
+    inner: Arc<Mutex<AgentInner>>,
+}
+
+impl Agent {
+    fn is_running(&self) -> bool {
+        self.inner.lock().stop_handle.is_some()
+    }
+
+    fn start(&self, req: StartRequest) {
+        let plan = BenchPlan {
+            count: req.count,
+            interval: Duration::from_millis(req.interval_ms),
+            live: Duration::from_millis(req.live_ms),
+            client: ClientConfig {
+                signal_timeout: Duration::from_millis(req.signal_timeout_ms),
+                ..Default::default()
+            },
+            arrival: req.arrival,
+            burst: 1,
+            seed: req.seed,
+            duration: req.duration_ms.map(Duration::from_millis),
+            shared_sockets: req.shared_sockets,
+            threads: req.threads,
+            sustain: req.sustain,
+            loops: req.loops,
+            fault: None,
+            chaos: None,
+            starve: None,
+            labels: vec![],
+            mix: vec![],
+            record_http: None,
+            audio_dump: None,
+            clients: vec![],
+            delete_limits: None,
+            decimation: None,
+            ramp_delay: None,
+            max_concurrent_connects: None,
+            worker: None,
+            script: None,
+        };
+        log::info!("[Agent] starting plan {:?}", plan);
+
+        let (event_tx, event_rx) = async_std::channel::unbounded::<BenchEvent>();
+        let mut runner = BenchRunner::new(&req.url, &req.token, plan, event_tx);
+        let (control_tx, control_rx) = async_std::channel::unbounded();
+        runner.set_control(control_rx);
+        {
+            let mut inner = self.inner.lock();
+            inner.stop_handle = Some(runner.stop_handle());
+            inner.control_tx = Some(control_tx);
+            inner.collector = Collector::default();
+        }
+
+        async_std::task::spawn(async move {
+            runner.run().await;
+        });
+
+        let agent = self.clone();
+        async_std::task::spawn(async move {
+            while let Ok(event) = event_rx.recv().await {
+                let json: Arc<str> = serde_json::to_string(&event)
+                    .expect("should serialize event")
+                    .into();
+                let mut inner = agent.inner.lock();
+                inner
+                    .subscribers
+                    .retain(|tx| tx.try_send(json.clone()).is_ok());
+                inner.collector.on_event(event);
+            }
+            log::info!("[Agent] plan finished");
+            let mut inner = agent.inner.lock();
+            inner.stop_handle = None;
+            inner.control_tx = None;
+        });
+    }
+
+    fn stop(&self) -> bool {
+        match self.inner.lock().stop_handle.as_ref() {
+            Some(stopped) => {
+                log::info!("[Agent] stopping plan");
+                stopped.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_target(&self, count: usize) -> bool {
+        match self.inner.lock().control_tx.as_ref() {
+            Some(control_tx) => {
+                log::info!("[Agent] target count {}", count);
+                control_tx.try_send(BenchControl::SetTarget(count)).is_ok()
+            }
+            None => false,
+        }
+    }
+
+    fn subscribe(&self) -> async_std::channel::Receiver<Arc<str>> {
+        let (tx, rx) = async_std::channel::unbounded();
+        self.inner.lock().subscribers.push(tx);
+        rx
+    }
+}
+
+async fn start_plan(mut req: Request<Agent>) -> tide::Result {
+    let body: StartRequest = req.body_json().await?;
+    let agent = req.state();
+    if agent.is_running() {
+        return Ok(Response::builder(StatusCode::Conflict)
+            .body("plan already running")
+            .build());
+    }
+    agent.start(body);
+    Ok(Response::new(StatusCode::Created))
+}
+
+async fn stop_plan(req: Request<Agent>) -> tide::Result {
+    if req.state().stop() {
+        Ok(Response::new(StatusCode::Accepted))
+    } else {
+        Ok(Response::new(StatusCode::NotFound))
+    }
+}
+
+async fn put_target_count(mut req: Request<Agent>) -> tide::Result {
+    let body: TargetRequest = req.body_json().await?;
+    if req.state().set_target(body.count) {
+        Ok(Response::new(StatusCode::Accepted))
+    } else {
+        Ok(Response::new(StatusCode::NotFound))
+    }
+}
+
+async fn get_stats(req: Request<Agent>) -> tide::Result {
+    let inner = req.state().inner.lock();
+    let body = Body::from_json(&inner.collector.snapshot())?;
+    Ok(Response::builder(StatusCode::Ok).body(body).build())
+}
+
+async fn stream_events(req: Request<Agent>, sender: tide::sse::Sender) -> tide::Result<()> {
+    let rx = req.state().subscribe();
+    while let Ok(json) = rx.recv().await {
+        sender.send("bench", json, None).await?;
+    }
+    Ok(())
+}
+
+pub async fn run_agent(listen: &str) -> std::io::Result<()> {
+    let mut app = tide::with_state(Agent::default());
+    app.at("/plans").post(start_plan).delete(stop_plan);
+    app.at("/target-count").put(put_target_count);
+    app.at("/stats").get(get_stats);
+    app.at("/events").get(tide::sse::endpoint(stream_events));
+    log::info!("[Agent] listening on {}", listen);
+    app.listen(listen.to_string()).await
+}
+
+/// Ask the agent at `agent`, e.g. `http://10.0.0.5:9000`, to scale its running
+/// plan to `count` clients
+pub async fn request_target_count(agent: &str, count: usize) -> Result<(), String> {
+    let url = format!("{}/target-count", agent.trim_end_matches('/'));
+    let body = serde_json::to_string(&TargetRequest { count }).expect("should serialize target");
+    let res = reqwest::Client::new()
+        .put(&url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| format!("cannot reach agent: {}", e))?;
+    match res.status() {
+        status if status.is_success() => Ok(()),
+        reqwest::StatusCode::NOT_FOUND => Err("agent has no running plan".to_string()),
+        status => Err(format!("agent answered {}", status)),
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+/// Levels at or below count as silence, comfort noise and DTX sit far under speech
+const SILENCE_DBOV: f32 = -70.0;
+
+/// Levels of the client-to-mixer audio level extension, RFC 6464, cumulative
+/// over the session. 0 dBov is the loudest, -127 digital silence
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct AudioLevelStats {
+    /// Audio packets which carried a level
+    pub packets: u64,
+    pub avg_dbov: f32,
+    pub peak_dbov: f32,
+    /// Share of the packets at or below the silence threshold
+    pub silence_ratio: f32,
+}
+
+#[derive(Debug, Default)]
+pub struct AudioLevelTracker {
+    packets: u64,
+    sum_dbov: f64,
+    peak_dbov: Option<f32>,
+    silent: u64,
+}
+
+impl AudioLevelTracker {
+    /// `level` as parsed by str0m, the sign carries no meaning
+    pub fn on_level(&mut self, level: i8) {
+        let dbov = -(level as f32).abs();
+        self.packets += 1;
+        self.sum_dbov += dbov as f64;
+        self.peak_dbov = Some(self.peak_dbov.map_or(dbov, |peak| peak.max(dbov)));
+        if dbov <= SILENCE_DBOV {
+            self.silent += 1;
+        }
+    }
+
+    /// None until an audio packet carried the extension
+    pub fn stats(&self) -> Option<AudioLevelStats> {
+        let peak_dbov = self.peak_dbov?;
+        Some(AudioLevelStats {
+            packets: self.packets,
+            avg_dbov: (self.sum_dbov / self.packets as f64) as f32,
+            peak_dbov,
+            silence_ratio: self.silent as f32 / self.packets as f32,
+        })
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use parking_lot::RwLock;
+use serde::Deserialize;
+
+use crate::rt;
+
+/// Refresh this long before the token expires
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// Delay before retrying a failed token request
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+/// How often a token file is checked for changes
+const FILE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bearer token shared by all clients, each signaling request reads the current one
+#[derive(Debug, Clone)]
+pub struct TokenSource(Arc<RwLock<Arc<str>>>);
+
+impl TokenSource {
+    pub fn fixed(token: &str) -> Self {
+        Self(Arc::new(RwLock::new(token.into())))
+    }
+
+    pub fn get(&self) -> Arc<str> {
+        self.0.read().clone()
+    }
+
+    pub fn set(&self, token: &str) {
+        *self.0.write() = token.into();
+    }
+}
+
+/// OAuth2 client credentials grant settings
+#[derive(Debug, Clone)]
+pub struct OAuthConfig {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+/// Request a token, returns it with its lifetime if the server told it
+pub async fn fetch_token(config: &OAuthConfig) -> Result<(String, Option<Duration>), String> {
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", config.client_id.as_str()),
+        ("client_secret", config.client_secret.as_str()),
+    ];
+    if let Some(scope) = &config.scope {
+        form.push(("scope", scope.as_str()));
+    }
+    let res = reqwest::Client::new()
+        .post(&config.token_url)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| format!("token request failed: {}", e))?;
+    let status = res.status();
+    let body = res
+        .text()
+        .await
+        .map_err(|e| format!("token response failed: {}", e))?;
+    if !status.is_success() {
+        return Err(format!("token request failed: {} {}", status, body));
+    }
+    let token: TokenResponse =
+        serde_json::from_str(&body).map_err(|e| format!("invalid token response: {}", e))?;
+    Ok((
+        token.access_token,
+        token.expires_in.map(Duration::from_secs),
+    ))
+}
+
+/// Short lived tokens are refreshed halfway through their lifetime
+fn refresh_delay(lifetime: Duration) -> Duration {
+    lifetime.saturating_sub(REFRESH_MARGIN).max(lifetime / 2)
+}
+
+/// Fetch the first token, then keep refreshing it in the background before it expires
+pub async fn oauth_token_source(config: OAuthConfig) -> Result<TokenSource, String> {
+    let (token, lifetime) = fetch_token(&config).await?;
+    let source = TokenSource::fixed(&token);
+    let Some(lifetime) = lifetime else {
+        log::info!("[Auth] token without expiry, not refreshing");
+        return Ok(source);
+    };
+    let refreshed = source.clone();
+    rt::spawn(async move {
+        let mut lifetime = lifetime;
+        let mut delay = refresh_delay(lifetime);
+        loop {
+            rt::sleep(delay).await;
+            match fetch_token(&config).await {
+                Ok((token, next)) => {
+                    log::info!("[Auth] token refreshed");
+                    refreshed.set(&token);
+                    lifetime = next.unwrap_or(lifetime);
+                    delay = refresh_delay(lifetime);
+                }
+                Err(e) => {
+                    log::warn!("[Auth] {}, retrying", e);
+                    delay = RETRY_DELAY;
+                }
+            }
+        }
+    });
+    Ok(source)
+}
+
+fn read_token_file(path: &PathBuf) -> io::Result<(String, Option<SystemTime>)> {
+    let modified = fs::metadata(path)?.modified().ok();
+    let token = fs::read_to_string(path)?.trim().to_string();
+    Ok((token, modified))
+}
+
+/// Read the token from a file and re-read it when the file changes or on SIGHUP,
+/// so an external refresher can keep long runs authenticated
+pub fn file_token_source(path: PathBuf) -> io::Result<TokenSource> {
+    let (token, mut modified) = read_token_file(&path)?;
+    let source = TokenSource::fixed(&token);
+    let hangup = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    if let Err(e) = signal_hook::flag::register(signal_hook::consts::SIGHUP, hangup.clone()) {
+        log::warn!("[Auth] cannot handle SIGHUP {:?}", e);
+    }
+    let reloaded = source.clone();
+    rt::spawn(async move {
+        loop {
+            rt::sleep(FILE_POLL_INTERVAL).await;
+            let changed = fs::metadata(&path).ok().and_then(|m| m.modified().ok()) != modified;
+            if !changed && !hangup.swap(false, Ordering::Relaxed) {
+                continue;
+            }
+            match read_token_file(&path) {
+                // a refresher truncating before writing must not blank the token
+                Ok((token, _)) if token.is_empty() => {}
+                Ok((token, at)) => {
+                    log::info!("[Auth] token reloaded from {}", path.display());
+                    reloaded.set(&token);
+                    modified = at;
+                }
+                Err(e) => log::warn!("[Auth] cannot read {} {:?}", path.display(), e),
+            }
+        }
+    });
+    Ok(source)
+}
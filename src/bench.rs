@@ -1,100 +1,1620 @@
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    panic::AssertUnwindSafe,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use async_std::channel::Sender;
+use async_std::channel::{Receiver, Sender};
+use futures_util::FutureExt;
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
-use crate::whep::{Stats, WhepClient, WhepEvent};
+use crate::{
+    ab,
+    auth::TokenSource,
+    decimate::{Decimation, StatsRollup},
+    delay::DelayRange,
+    growth,
+    handshake::HandshakeLimiter,
+    media::StreamKind,
+    mux::SocketPool,
+    roster::ClientEntry,
+    rt,
+    script::Script,
+    sdp::AnswerSummary,
+    shard::{Shard, ShardSummary},
+    simulcast::LayerSwitch,
+    stream::StreamChange,
+    teardown::{self, DeleteLimits, ShutdownReport, Teardown},
+    urltemplate,
+    watchdog::parse_duration,
+    whep::{ClientConfig, HttpTiming, Stats, WhepClient, WhepError, WhepEvent},
+};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum FailureCategory {
+    Url,
+    /// Token rejected with 401 or 403
+    Auth,
+    /// Offer answered with 401 and a WWW-Authenticate challenge
+    AuthChallenge,
+    /// Other unexpected signaling responses
+    Server,
+    /// Signaling answered with 5xx
+    Server5xx,
+    Sdp,
+    Webrtc,
+    Network,
+    /// Signaling request timed out
+    Timeout,
+    /// Offer answered but the connection did not come up in time
+    IceTimeout,
+    /// Ice connected but the DTLS handshake did not finish
+    Dtls,
+    /// Media stopped arriving
+    MediaStall,
+    /// Answer rejected a required media kind
+    MediaRejected,
+    Panic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureReason {
+    pub category: FailureCategory,
+    pub message: String,
+}
+
+impl From<&WhepError> for FailureReason {
+    fn from(err: &WhepError) -> Self {
+        let category = match err {
+            WhepError::UrlError => FailureCategory::Url,
+            WhepError::ServerError(_) | WhepError::Throttled(_) => FailureCategory::Server,
+            WhepError::SdpError => FailureCategory::Sdp,
+            WhepError::WebrtcError(_) => FailureCategory::Webrtc,
+            WhepError::AuthRejected(_) => FailureCategory::Auth,
+            WhepError::AuthChallenge(_) => FailureCategory::AuthChallenge,
+            WhepError::HttpStatus(status) if *status >= 500 => FailureCategory::Server5xx,
+            WhepError::HttpStatus(_) => FailureCategory::Server,
+            WhepError::DtlsTimeout => FailureCategory::Dtls,
+            WhepError::MediaStall => FailureCategory::MediaStall,
+            WhepError::MediaRejected(_) => FailureCategory::MediaRejected,
+            WhepError::NetworkError(_) => FailureCategory::Network,
+            WhepError::Timeout => FailureCategory::Timeout,
+            WhepError::IceTimeout => FailureCategory::IceTimeout,
+        };
+        let message = match err {
+            WhepError::AuthChallenge(challenge) => challenge.clone(),
+            err => format!("{:?}", err),
+        };
+        Self { category, message }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BenchEvent {
     Connecting(usize),
+    /// The client got a handshake slot after waiting this many ms, sent before it
+    /// offers whether or not it connects
+    Queued(usize, u32),
     Connected(usize),
     Stats(usize, Stats),
+    Failed(usize, FailureReason),
+    StreamChanged(usize, StreamChange),
+    /// The server forwards another simulcast layer
+    LayerSwitched(usize, LayerSwitch),
+    /// Server shed load, the client retries signaling later
+    Throttled(usize),
+    /// Lifetime of the client ended and it joins again, iteration counts from 1
+    Reconnecting(usize, u32),
+    /// A fault was injected into the client, it joins again right away
+    Faulted(usize),
+    /// The replacement join after a fault connected, with ms since the fault
+    Recovered(usize, u64),
+    /// Received bitrate of a media kind stayed below the expected one
+    Starved(usize, StreamKind),
+    /// A signaling request completed
+    Http(usize, HttpTiming),
+    /// The server answered, what it negotiated
+    Answer(usize, AnswerSummary),
+    /// Labels of the client's cohorts, sent after Connecting when it has any
+    Labeled(usize, Vec<String>),
+    /// A session of the client did not end with a plain DELETE
+    Teardown(usize, Teardown),
+    /// A keepalive of the session failed, the client stays connected
+    KeepaliveFailed(usize, FailureReason),
+    /// The keyframe answering a requested one arrived, with ms since the request
+    KeyframeReceived(usize, u64),
     Disconnected(usize),
 }
 
+impl BenchEvent {
+    /// Id of the client the event belongs to
+    pub fn client_id(&self) -> usize {
+        match self {
+            BenchEvent::Connecting(id)
+            | BenchEvent::Queued(id, _)
+            | BenchEvent::Connected(id)
+            | BenchEvent::Stats(id, _)
+            | BenchEvent::Failed(id, _)
+            | BenchEvent::StreamChanged(id, _)
+            | BenchEvent::LayerSwitched(id, _)
+            | BenchEvent::Throttled(id)
+            | BenchEvent::Reconnecting(id, _)
+            | BenchEvent::Faulted(id)
+            | BenchEvent::Recovered(id, _)
+            | BenchEvent::Starved(id, _)
+            | BenchEvent::Http(id, _)
+            | BenchEvent::Answer(id, _)
+            | BenchEvent::Labeled(id, _)
+            | BenchEvent::Teardown(id, _)
+            | BenchEvent::KeepaliveFailed(id, _)
+            | BenchEvent::KeyframeReceived(id, _)
+            | BenchEvent::Disconnected(id) => *id,
+        }
+    }
+
+    /// Rewrite client id, used when merging events from multiple runners
+    pub fn map_client_id<F: Fn(usize) -> usize>(self, f: F) -> Self {
+        match self {
+            BenchEvent::Connecting(id) => BenchEvent::Connecting(f(id)),
+            BenchEvent::Queued(id, ms) => BenchEvent::Queued(f(id), ms),
+            BenchEvent::Connected(id) => BenchEvent::Connected(f(id)),
+            BenchEvent::Stats(id, stats) => BenchEvent::Stats(f(id), stats),
+            BenchEvent::Failed(id, reason) => BenchEvent::Failed(f(id), reason),
+            BenchEvent::StreamChanged(id, change) => BenchEvent::StreamChanged(f(id), change),
+            BenchEvent::LayerSwitched(id, switch) => BenchEvent::LayerSwitched(f(id), switch),
+            BenchEvent::Throttled(id) => BenchEvent::Throttled(f(id)),
+            BenchEvent::Reconnecting(id, iteration) => BenchEvent::Reconnecting(f(id), iteration),
+            BenchEvent::Faulted(id) => BenchEvent::Faulted(f(id)),
+            BenchEvent::Recovered(id, ms) => BenchEvent::Recovered(f(id), ms),
+            BenchEvent::Starved(id, kind) => BenchEvent::Starved(f(id), kind),
+            BenchEvent::Http(id, timing) => BenchEvent::Http(f(id), timing),
+            BenchEvent::Answer(id, answer) => BenchEvent::Answer(f(id), answer),
+            BenchEvent::Labeled(id, labels) => BenchEvent::Labeled(f(id), labels),
+            BenchEvent::Teardown(id, outcome) => BenchEvent::Teardown(f(id), outcome),
+            BenchEvent::KeepaliveFailed(id, reason) => BenchEvent::KeepaliveFailed(f(id), reason),
+            BenchEvent::KeyframeReceived(id, ms) => BenchEvent::KeyframeReceived(f(id), ms),
+            BenchEvent::Disconnected(id) => BenchEvent::Disconnected(f(id)),
+        }
+    }
+}
+
+/// Forward each event to all consumers, closed consumers are dropped
+pub async fn fanout(
+    event_rx: Receiver<BenchEvent>,
+    mut consumers: Vec<(&'static str, Sender<BenchEvent>)>,
+) {
+    let mut gauged_at = Instant::now();
+    while let Ok(event) = event_rx.recv().await {
+        let mut alive = Vec::with_capacity(consumers.len());
+        for (name, tx) in consumers.drain(..) {
+            if tx.send(event.clone()).await.is_ok() {
+                alive.push((name, tx));
+            }
+        }
+        consumers = alive;
+        // queued events of a consumer which cannot keep up grow without bound
+        if gauged_at.elapsed() >= Duration::from_secs(1) {
+            gauged_at = Instant::now();
+            growth::gauge("events.queued", event_rx.len() as u64);
+            for (name, tx) in &consumers {
+                growth::gauge(&format!("events.queued.{}", name), tx.len() as u64);
+            }
+        }
+    }
+}
+
+/// Commands for changing a running benchmark
+#[derive(Debug, Clone, Copy)]
+pub enum BenchControl {
+    /// Spawn more clients
+    Spawn(usize),
+    /// Disconnect the most recently spawned clients
+    Kill(usize),
+    /// Stop spawning and disconnect all clients
+    Stop,
+    /// Stop spawning until resumed, with `hold` running clients also stay past
+    /// their lifetime. Paused time does not count towards the run duration
+    Pause {
+        hold: bool,
+    },
+    Resume,
+    /// Ask this many connected clients at once for a keyframe, each sends a PLI
+    RequestKeyframe(usize),
+    /// Change the client count of the plan. Missing clients are started at the
+    /// arrival rate of the plan, the newest clients above it disconnect
+    SetTarget(usize),
+    /// Change how clients roll up their stats, running clients included
+    SetDecimation(Option<Decimation>),
+}
+
+/// How client start times are scheduled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Arrival {
+    /// One client every plan interval
+    #[default]
+    Fixed,
+    /// Exponential inter-arrival times with `rate` clients per second on average
+    Poisson { rate: f64 },
+}
+
+impl FromStr for Arrival {
+    type Err = String;
+
+    /// Parse `fixed` or `poisson:<rate>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "fixed" => Ok(Arrival::Fixed),
+            Some(("poisson", rate)) => match rate.parse::<f64>() {
+                Ok(rate) if rate > 0.0 => Ok(Arrival::Poisson { rate }),
+                _ => Err(format!("invalid poisson rate {}", rate)),
+            },
+            _ => Err(format!(
+                "unknown arrival {}, expected fixed or poisson:<rate>",
+                s
+            )),
+        }
+    }
+}
+
+/// Clients per second as an alternative to an interval, e.g. `25/s`, `0.5/s`,
+/// `90/m` or `1000/h`. With `,burst=<n>` groups of n clients start together
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rate {
+    pub per_sec: f64,
+    pub burst: usize,
+}
+
+impl Rate {
+    /// Interval between bursts which gives the rate
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.burst as f64 / self.per_sec)
+    }
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid rate {}, expected e.g. `25/s` or `2/s,burst=10`", s);
+        let mut parts = s.split(',');
+        let (count, unit) = parts
+            .next()
+            .and_then(|rate| rate.trim().split_once('/'))
+            .ok_or_else(invalid)?;
+        let count: f64 = count.parse().map_err(|_| invalid())?;
+        let secs = match unit {
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(invalid()),
+        };
+        let mut rate = Rate {
+            per_sec: count / secs,
+            burst: 1,
+        };
+        for part in parts {
+            match part.trim().split_once('=') {
+                Some(("burst", burst)) => rate.burst = burst.parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+        if !(rate.per_sec > 0.0 && rate.per_sec.is_finite()) || rate.burst == 0 {
+            return Err(invalid());
+        }
+        Ok(rate)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FaultMode {
+    /// Stop answering STUN and media, the socket stays open
+    Silence,
+    /// Close the socket
+    Close,
+}
+
+impl FromStr for FaultMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "silence" => Ok(FaultMode::Silence),
+            "close" => Ok(FaultMode::Close),
+            _ => Err(format!(
+                "unknown fault mode {}, expected silence or close",
+                s
+            )),
+        }
+    }
+}
+
+/// Kill a share of the clients mid-stream without DELETE and join them again, to
+/// measure how long the server takes to reap the dead session
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FaultPlan {
+    /// Share of clients faulted, in percent
+    pub percent: f64,
+    /// Time after connecting when the fault hits
+    pub after: Duration,
+    pub mode: FaultMode,
+}
+
+/// Every interval kill a random share of the connected clients without DELETE and
+/// join them again, to test the session garbage collection of the server under load
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ChaosPlan {
+    /// Share of the connected clients killed each round, in percent
+    pub percent: f64,
+    pub interval: Duration,
+    pub mode: FaultMode,
+}
+
+/// Label of a client id range, `<label>=<from>-<to>` like `mobile=1-100`, or
+/// open ended like `desktop=101-`. Ranges may overlap, a client gets every label
+/// whose range it is in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelRule {
+    pub label: String,
+    pub from: usize,
+    pub to: Option<usize>,
+}
+
+impl LabelRule {
+    pub fn matches(&self, client_id: usize) -> bool {
+        client_id >= self.from && self.to.map_or(true, |to| client_id <= to)
+    }
+}
+
+/// Labels of every rule matching the client
+pub fn labels_of(rules: &[LabelRule], client_id: usize) -> Vec<String> {
+    rules
+        .iter()
+        .filter(|rule| rule.matches(client_id))
+        .map(|rule| rule.label.clone())
+        .collect()
+}
+
+impl FromStr for LabelRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("invalid label {}, expected e.g. `mobile=1-100`", s);
+        let (label, range) = s.split_once('=').ok_or_else(usage)?;
+        let (from, to) = range.split_once('-').ok_or_else(usage)?;
+        let from = from.trim().parse().map_err(|_| usage())?;
+        let to = match to.trim() {
+            "" => None,
+            to => Some(to.parse().map_err(|_| usage())?),
+        };
+        if label.trim().is_empty() || to.map_or(false, |to| to < from) {
+            return Err(usage());
+        }
+        Ok(LabelRule {
+            label: label.trim().to_string(),
+            from,
+            to,
+        })
+    }
+}
+
+/// Kind of client making up a weighted share of a mixed run, e.g.
+/// `mobile:weight=30,live=2m,downlink-kbps=800` or `audio:weight=10,media=audio`.
+/// Clients are labeled with the profile name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientProfile {
+    pub name: String,
+    pub weight: f64,
+    /// Lifetime instead of the one of the plan
+    pub live: Option<Duration>,
+    /// Only this media kind is offered
+    pub media: Option<StreamKind>,
+    pub downlink_kbps: Option<u64>,
+}
+
+impl FromStr for ClientProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        let invalid = |part: &str| format!("invalid parameter {} of client profile {}", part, name);
+        let mut profile = ClientProfile {
+            name: name.trim().to_string(),
+            weight: 1.0,
+            live: None,
+            media: None,
+            downlink_kbps: None,
+        };
+        for part in params.split(',').filter(|p| !p.trim().is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+            let value = value.trim();
+            match key.trim().replace('_', "-").as_str() {
+                "weight" => profile.weight = value.parse().map_err(|_| invalid(part))?,
+                "live" => profile.live = Some(parse_duration(value).ok_or_else(|| invalid(part))?),
+                "media" => profile.media = Some(value.parse().map_err(|_| invalid(part))?),
+                "downlink-kbps" => {
+                    profile.downlink_kbps = Some(value.parse().map_err(|_| invalid(part))?)
+                }
+                _ => return Err(invalid(part)),
+            }
+        }
+        if profile.name.is_empty() || !(profile.weight >= 0.0 && profile.weight.is_finite()) {
+            return Err(format!(
+                "invalid client profile {}, expected e.g. `mobile:weight=30,live=2m`",
+                s
+            ));
+        }
+        Ok(profile)
+    }
+}
+
+impl ClientProfile {
+    /// Client config of the profile, None when it keeps the shared one
+    fn config(&self, shared: &ClientConfig) -> Option<ClientConfig> {
+        if self.media.is_none() && self.downlink_kbps.is_none() {
+            return None;
+        }
+        Some(ClientConfig {
+            media: self
+                .media
+                .map_or_else(|| shared.media.clone(), |kind| vec![kind]),
+            downlink_kbps: self.downlink_kbps.or(shared.downlink_kbps),
+            ..shared.clone()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BenchPlan {
     pub count: usize,
     pub interval: Duration,
+    pub arrival: Arrival,
+    /// Clients starting together at each arrival
+    pub burst: usize,
+    /// Seed of the schedule rng, same seed gives the same schedule
+    pub seed: Option<u64>,
     pub live: Duration,
+    /// Duration of the whole run, clients are stopped after it elapsed
+    pub duration: Option<Duration>,
+    pub client: ClientConfig,
+    /// Number of udp sockets shared by all clients, 0 gives each client its own socket
+    pub shared_sockets: usize,
+    /// Number of dedicated worker threads running the clients, 0 uses the async-std scheduler
+    pub threads: usize,
+    /// Keep `count` clients running, replacing ended ones at the arrival rate
+    pub sustain: bool,
+    /// Join again this many times after the lifetime expired, 0 repeats forever
+    pub loops: Option<u32>,
+    pub fault: Option<FaultPlan>,
+    pub chaos: Option<ChaosPlan>,
+    pub starve: Option<StarvePlan>,
+    /// Cohorts for slicing the results, by client id
+    pub labels: Vec<LabelRule>,
+    /// Weighted client profiles, every client uses the plan as is when empty
+    pub mix: Vec<ClientProfile>,
+    /// Share of clients recording headers and bodies of their signaling, in percent
+    pub record_http: Option<f64>,
+    /// Share of clients decoding their received audio to WAV files, needs the `wav` feature
+    pub audio_dump: Option<AudioDumpPlan>,
+    /// Source of a script with client hooks, see [`crate::script`]
+    pub script: Option<String>,
+    /// Url, token and label per client id, see [`crate::roster`]
+    #[serde(default)]
+    pub clients: Vec<ClientEntry>,
+    /// Bounds of the DELETEs at shutdown, unbounded when None
+    #[serde(default)]
+    pub delete_limits: Option<DeleteLimits>,
+    /// Roll the stats of each client up before emitting them, see [`crate::decimate`]
+    #[serde(default)]
+    pub decimation: Option<Decimation>,
+    /// Random delay added to each arrival, slowing the ramp per client
+    #[serde(default)]
+    pub ramp_delay: Option<DelayRange>,
+    /// Clients between offer and connected at once, unbounded when None
+    #[serde(default)]
+    pub max_concurrent_connects: Option<usize>,
+    /// Index and count of the workers of a cluster run, client ids of the runner are
+    /// local to its worker. Url placeholders use the global ids
+    #[serde(default)]
+    pub worker: Option<(usize, usize)>,
+}
+
+/// WAV dumps of the received audio, written as `client-<id>.wav` into `dir`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioDumpPlan {
+    pub dir: PathBuf,
+    /// Share of clients dumping their audio, in percent
+    pub percent: f64,
+}
+
+/// Expected bitrates, clients receiving less for too long are flagged as starved
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StarvePlan {
+    pub audio_kbps: Option<u64>,
+    pub video_kbps: Option<u64>,
+    /// Share of the expected bitrate a client must at least receive
+    pub ratio: f64,
+    /// How long the bitrate may stay below before the client is flagged
+    pub after: Duration,
+}
+
+impl StarvePlan {
+    fn expected_kbps(&self, kind: StreamKind) -> Option<u64> {
+        match kind {
+            StreamKind::Audio => self.audio_kbps,
+            StreamKind::Video => self.video_kbps,
+        }
+    }
+}
+
+/// Starvation state of one session, per media kind
+#[derive(Debug, Default)]
+struct Starvation {
+    below_since: [Option<Instant>; 2],
+    flagged: [bool; 2],
+}
+
+impl Starvation {
+    /// Kinds which became starved with these stats, flagged again only after recovering
+    fn on_stats(&mut self, plan: &StarvePlan, stats: &Stats, now: Instant) -> Vec<StreamKind> {
+        let mut starved = vec![];
+        for kind in [StreamKind::Audio, StreamKind::Video] {
+            let Some(expected) = plan.expected_kbps(kind) else {
+                continue;
+            };
+            let recv_kbps: u64 = stats
+                .streams
+                .iter()
+                .filter(|s| s.codec.kind() == Some(kind))
+                .map(|s| s.recv_kbps)
+                .sum();
+            let index = kind as usize;
+            if recv_kbps as f64 >= expected as f64 * plan.ratio {
+                self.below_since[index] = None;
+                self.flagged[index] = false;
+                continue;
+            }
+            let since = *self.below_since[index].get_or_insert(now);
+            if !self.flagged[index] && now - since >= plan.after {
+                self.flagged[index] = true;
+                starved.push(kind);
+            }
+        }
+        starved
+    }
+}
+
+/// Time given to clients to disconnect when the runner stops
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+/// Time the sessions still open after the shutdown grace get for their DELETE
+const TEARDOWN_GRACE: Duration = Duration::from_secs(5);
+/// How often ended clients are looked for in sustain mode
+const SUSTAIN_POLL: Duration = Duration::from_millis(100);
+/// Check interval of a paused bootstrap
+const PAUSE_POLL: Duration = Duration::from_millis(200);
+/// Period of the shard summary logs
+const SHARD_LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// How long a faulted client keeps trying to join again, and the delay between tries
+const RECOVER_TIMEOUT: Duration = Duration::from_secs(60);
+const RECOVER_RETRY: Duration = Duration::from_secs(1);
+/// First retry delay of a throttled client without Retry-After, doubled per attempt
+const THROTTLE_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Largest stretch of the arrival delays after throttling
+const MAX_SLOWDOWN: f64 = 16.0;
+
+#[derive(Debug)]
+struct ThrottleState {
+    slowdown: f64,
+    resume_at: Option<Instant>,
+}
+
+/// Load shedding seen by the clients, slows down the arrival of new ones
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    state: Mutex<ThrottleState>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(ThrottleState {
+                slowdown: 1.0,
+                resume_at: None,
+            }),
+        }
+    }
+}
+
+impl Throttle {
+    fn on_throttled(&self, retry_after: Duration) {
+        let mut state = self.state.lock();
+        state.slowdown = (state.slowdown * 2.0).min(MAX_SLOWDOWN);
+        let resume_at = Instant::now() + retry_after;
+        state.resume_at = Some(state.resume_at.map_or(resume_at, |t| t.max(resume_at)));
+    }
+
+    /// Stretch an arrival delay by the current slowdown, which decays with every arrival.
+    /// No client starts before the latest Retry-After passed
+    fn arrival_delay(&self, delay: Duration) -> Duration {
+        let mut state = self.state.lock();
+        let mut delay = delay.mul_f64(state.slowdown);
+        if let Some(resume_at) = state.resume_at.take() {
+            delay = delay.max(resume_at.saturating_duration_since(Instant::now()));
+        }
+        state.slowdown = (state.slowdown * 0.9).max(1.0);
+        delay
+    }
 }
 
 pub struct BenchRunner {
     plan: BenchPlan,
-    url: String,
-    token: String,
+    url: Arc<str>,
+    /// Server of the even client ids in an A/B run
+    ab_url: Option<Arc<str>>,
+    token: TokenSource,
+    client_config: Arc<ClientConfig>,
     count: usize,
     event_tx: Sender<BenchEvent>,
+    stopped: Arc<AtomicBool>,
+    control_rx: Option<Receiver<BenchControl>>,
+    /// Flags of each spawned client, the client task drops its references when it ends
+    clients: BTreeMap<usize, ClientHandle>,
+    socket_pool: Option<SocketPool>,
+    shards: Vec<Shard>,
+    rng: StdRng,
+    started_at: Option<Instant>,
+    /// Set while paused, with the time spent in earlier pauses
+    paused_at: Option<Instant>,
+    paused_for: Duration,
+    /// Clients skip their lifetime while set
+    hold: Arc<AtomicBool>,
+    throttle: Arc<Throttle>,
+    handshakes: Option<HandshakeLimiter>,
+    next_chaos: Option<Instant>,
+    script: Option<Arc<Script>>,
+    shutdown_report: Option<ShutdownReport>,
+    /// Set once the plan's clients were started
+    ramped: bool,
+    /// Clients still to start for a raised target after the ramp
+    pending: usize,
+    /// Decimation of the plan, shared with the clients so it can change during the run
+    decimation: Arc<Mutex<Option<Decimation>>>,
+}
+
+/// Runner side of a spawned client
+struct ClientHandle {
+    killed: Arc<AtomicBool>,
+    /// Set by the chaos schedule, the client drops its session without DELETE
+    chaos: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
+    /// Set by a keyframe request burst, the client sends a PLI
+    keyframe: Arc<AtomicBool>,
 }
 
 impl BenchRunner {
     pub fn new(url: &str, token: &str, plan: BenchPlan, event_tx: Sender<BenchEvent>) -> Self {
+        let client_config = plan.client.clone();
+        let seed = plan.seed.unwrap_or_else(rand::random);
+        log::info!("[BenchRunner] rng seed {}", seed);
+        let script = plan
+            .script
+            .as_deref()
+            .and_then(|source| match Script::compile(source) {
+                Ok(script) => Some(Arc::new(script)),
+                Err(e) => {
+                    log::error!("[BenchRunner] invalid script, running without it {}", e);
+                    None
+                }
+            });
+        let decimation = Arc::new(Mutex::new(plan.decimation));
+        let handshakes = plan.max_concurrent_connects.map(HandshakeLimiter::new);
         BenchRunner {
             plan,
-            url: url.to_string(),
-            token: token.to_string(),
+            url: url.into(),
+            ab_url: None,
+            token: TokenSource::fixed(token),
+            client_config: Arc::new(client_config),
             count: 0,
             event_tx,
+            stopped: Arc::new(AtomicBool::new(false)),
+            control_rx: None,
+            clients: BTreeMap::new(),
+            socket_pool: None,
+            shards: vec![],
+            rng: StdRng::seed_from_u64(seed),
+            started_at: None,
+            paused_at: None,
+            paused_for: Duration::ZERO,
+            hold: Arc::new(AtomicBool::new(false)),
+            throttle: Arc::new(Throttle::default()),
+            handshakes,
+            next_chaos: None,
+            script,
+            shutdown_report: None,
+            ramped: false,
+            pending: 0,
+            decimation,
         }
     }
 
+    /// Handle for stopping the runner from outside, all clients will disconnect after it set
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stopped.clone()
+    }
+
+    /// Read the token from a source which may replace it during the run
+    pub fn set_token_source(&mut self, token: TokenSource) {
+        self.token = token;
+    }
+
+    /// Send every second client to `url`, labeled by side, for comparing two servers
+    /// under the same load
+    pub fn set_ab_url(&mut self, url: &str) {
+        self.ab_url = Some(url.into());
+    }
+
+    /// Accept runtime commands from the given channel
+    pub fn set_control(&mut self, control_rx: Receiver<BenchControl>) {
+        self.control_rx = Some(control_rx);
+    }
+
+    /// Bootstrap the plan, serve control commands until all clients are done or the
+    /// run duration elapsed, then disconnect the remaining clients
+    pub async fn run(&mut self) {
+        self.bootstrap().await;
+        self.serve_control().await;
+        self.shutdown(SHUTDOWN_GRACE).await;
+    }
+
     pub async fn bootstrap(&mut self) {
-        while self.count < self.plan.count {
-            self.count += 1;
-            let client_id = self.count;
-            let event_tx = self.event_tx.clone();
-            event_tx
-                .send(BenchEvent::Connecting(client_id))
-                .await
-                .expect("should send connecting event");
-            let url = self.url.clone();
-            let token = self.token.clone();
-            let live_time = self.plan.live;
-            async_std::task::spawn(async move {
-                let mut client = WhepClient::new(&url, &token).expect("should create whep client");
-                client.prepare().await.expect("should connect");
-                let started = std::time::Instant::now();
-                loop {
-                    if started.elapsed() > live_time {
-                        log::info!("[WhepClient] disconnecting after life time expired");
-                        client.disconnect().await.expect("should disconnect");
-                        break;
+        self.started_at.get_or_insert_with(Instant::now);
+        let mut spawned = 0;
+        let mut next_at = None;
+        while spawned < self.plan.count && !self.stopped.load(Ordering::Relaxed) {
+            if self.paused_at.is_some() {
+                // the schedule restarts on resume instead of catching up
+                next_at = None;
+                self.wait(PAUSE_POLL).await;
+                continue;
+            }
+            if self.expired() {
+                break;
+            }
+            let due = *next_at.get_or_insert_with(Instant::now);
+            for _ in 0..self.plan.burst.max(1).min(self.plan.count - spawned) {
+                spawned += 1;
+                self.spawn_client().await;
+            }
+            // scheduled from the previous arrival, the time spent spawning does not add up
+            let arrival = self.next_arrival();
+            let next = due + self.throttle.arrival_delay(arrival);
+            next_at = Some(next);
+            let delay = next.saturating_duration_since(Instant::now());
+            self.wait(delay.min(self.remaining())).await;
+        }
+        self.ramped = true;
+
+        log::info!("[BenchRunner] done");
+    }
+
+    /// Time left of the run duration, paused time excluded
+    fn remaining(&self) -> Duration {
+        let paused = self.paused_for + self.paused_at.map_or(Duration::ZERO, |at| at.elapsed());
+        match (self.plan.duration, self.started_at) {
+            (Some(duration), Some(started_at)) => {
+                duration.saturating_sub(started_at.elapsed().saturating_sub(paused))
+            }
+            _ => Duration::MAX,
+        }
+    }
+
+    fn expired(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    /// Number of clients still running
+    pub fn active_clients(&mut self) -> usize {
+        // clients which already ended only hold the reference kept here
+        self.clients
+            .retain(|_, handle| Arc::strong_count(&handle.killed) > 1);
+        self.clients.len()
+    }
+
+    /// Stop all clients and wait up to `grace` for them to disconnect
+    pub async fn shutdown(&mut self, grace: Duration) {
+        let started = Instant::now();
+        self.stopped.store(true, Ordering::Relaxed);
+        teardown::limit_deletes(self.plan.delete_limits);
+        // a rate limit spreads the DELETEs out, the grace has to cover them
+        let spread = self.plan.delete_limits.map_or(Duration::ZERO, |limits| {
+            limits.min_duration(self.active_clients())
+        });
+        let deadline = started + grace + spread;
+        while self.active_clients() > 0 && Instant::now() < deadline {
+            rt::sleep(Duration::from_millis(100)).await;
+        }
+        if self.active_clients() > 0 {
+            log::warn!(
+                "[BenchRunner] {} clients still running after shutdown grace",
+                self.active_clients()
+            );
+        }
+        let outcomes = teardown::settle(TEARDOWN_GRACE).await;
+        self.report_teardowns(outcomes).await;
+        let report = ShutdownReport {
+            teardown_ms: started.elapsed().as_millis() as u64,
+            deletes: teardown::deletes_sent(),
+            limits: self.plan.delete_limits,
+        };
+        log::info!("[BenchRunner] {}", report);
+        self.shutdown_report = Some(report);
+        teardown::limit_deletes(None);
+    }
+
+    /// Teardown of the last shutdown, None before it
+    pub fn shutdown_report(&self) -> Option<ShutdownReport> {
+        self.shutdown_report
+    }
+
+    async fn report_teardowns(&self, outcomes: Vec<(usize, Teardown)>) {
+        for (client_id, outcome) in outcomes {
+            emit(&self.event_tx, BenchEvent::Teardown(client_id, outcome)).await;
+        }
+    }
+
+    /// Delay until the next client start
+    fn next_arrival(&mut self) -> Duration {
+        let arrival = match self.plan.arrival {
+            Arrival::Fixed => self.plan.interval,
+            Arrival::Poisson { rate } => {
+                // inverse transform sampling of the exponential distribution, bursts
+                // arrive as often as clients would divided by their size
+                let uniform: f64 = self.rng.gen();
+                let rate = rate / self.plan.burst.max(1) as f64;
+                Duration::from_secs_f64(-(1.0 - uniform).ln() / rate)
+            }
+        };
+        match self.plan.ramp_delay {
+            Some(range) => arrival + range.sample(&mut self.rng),
+            None => arrival,
+        }
+    }
+
+    /// Handle control commands until the runner is stopped, the run duration
+    /// elapsed or all clients are done. In sustain mode ended clients are replaced
+    /// and only stopping or the run duration end it
+    pub async fn serve_control(&mut self) {
+        let mut next_log = Instant::now() + SHARD_LOG_INTERVAL;
+        let mut next_backfill = Instant::now();
+        while !self.stopped.load(Ordering::Relaxed) {
+            if self.expired() {
+                log::info!("[BenchRunner] run duration elapsed");
+                break;
+            }
+            let tick = if self.plan.sustain {
+                if self.paused_at.is_none()
+                    && self.active_clients() < self.plan.count
+                    && Instant::now() >= next_backfill
+                {
+                    log::debug!(
+                        "[BenchRunner] backfill, {} clients active",
+                        self.active_clients()
+                    );
+                    let missing = self.plan.count - self.active_clients();
+                    for _ in 0..self.plan.burst.max(1).min(missing) {
+                        self.spawn_client().await;
+                    }
+                    let arrival = self.next_arrival();
+                    next_backfill = Instant::now() + self.throttle.arrival_delay(arrival);
+                }
+                SUSTAIN_POLL
+            } else if self.pending > 0 {
+                if self.paused_at.is_none() && Instant::now() >= next_backfill {
+                    let burst = self.plan.burst.max(1).min(self.pending);
+                    self.pending -= burst;
+                    for _ in 0..burst {
+                        self.spawn_client().await;
                     }
+                    let arrival = self.next_arrival();
+                    next_backfill = Instant::now() + self.throttle.arrival_delay(arrival);
+                }
+                SUSTAIN_POLL
+            } else if self.active_clients() == 0 {
+                log::info!("[BenchRunner] all clients done");
+                break;
+            } else {
+                Duration::from_secs(1)
+            };
+            self.wait(tick.min(self.remaining())).await;
+            self.report_teardowns(teardown::take_outcomes()).await;
+            if Instant::now() >= next_log {
+                next_log = Instant::now() + SHARD_LOG_INTERVAL;
+                for (index, summary) in self.shard_summaries().iter().enumerate() {
+                    log::info!("[BenchRunner] shard {} {:?}", index, summary);
+                }
+            }
+        }
+    }
 
-                    match client.recv().await {
-                        Ok(event) => match event {
-                            WhepEvent::Connected => {
-                                event_tx
-                                    .send(BenchEvent::Connected(client_id))
-                                    .await
-                                    .expect("should send connected event");
-                                log::info!("[WhepClient] connected");
-                            }
-                            WhepEvent::Disconnected => {
-                                log::info!("[WhepClient] disconnected");
-                                break;
-                            }
-                            WhepEvent::Stats(stats) => {
-                                log::info!("[WhepClient] stats: {:?}", stats);
-                                event_tx
-                                    .send(BenchEvent::Stats(client_id, stats))
-                                    .await
-                                    .expect("should send stats event");
-                            }
-                            WhepEvent::Continue => {}
-                        },
-                        Err(err) => {
-                            log::error!("[WhepClient] error: {:?}", err);
-                            break;
-                        }
+    /// Kill the chosen share of the connected clients when the chaos interval passed,
+    /// returns the time of the next round
+    fn chaos_round(&mut self) -> Option<Instant> {
+        let plan = self.plan.chaos?;
+        let now = Instant::now();
+        let next = *self.next_chaos.get_or_insert(now + plan.interval);
+        if now < next {
+            return Some(next);
+        }
+        self.next_chaos = Some(now + plan.interval);
+        if self.paused_at.is_none() && !self.stopped.load(Ordering::Relaxed) {
+            let mut killed = 0;
+            for handle in self.clients.values() {
+                if handle.connected.load(Ordering::Relaxed)
+                    && self.rng.gen::<f64>() * 100.0 < plan.percent
+                {
+                    handle.chaos.store(true, Ordering::Relaxed);
+                    killed += 1;
+                }
+            }
+            log::info!("[BenchRunner] chaos killed {} clients", killed);
+        }
+        self.next_chaos
+    }
+
+    /// Sleep for the given duration while handling control commands and chaos rounds
+    async fn wait(&mut self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+        loop {
+            let remain = deadline.saturating_duration_since(Instant::now());
+            if remain.is_zero() {
+                break;
+            }
+            let remain = match self.chaos_round() {
+                Some(next) => remain.min(next.saturating_duration_since(Instant::now())),
+                None => remain,
+            };
+            // woken early for a chaos round, the deadline is checked again above
+            let Some(control_rx) = &self.control_rx else {
+                rt::sleep(remain).await;
+                continue;
+            };
+            match rt::timeout(remain, control_rx.recv()).await {
+                Ok(Ok(control)) => self.on_control(control).await,
+                Ok(Err(_)) => {
+                    self.control_rx = None;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    async fn on_control(&mut self, control: BenchControl) {
+        log::info!("[BenchRunner] control {:?}", control);
+        match control {
+            BenchControl::Spawn(count) => {
+                for _ in 0..count {
+                    self.spawn_client().await;
+                }
+            }
+            BenchControl::Kill(count) => {
+                self.active_clients();
+                for handle in self.clients.values().rev().take(count) {
+                    handle.killed.store(true, Ordering::Relaxed);
+                }
+            }
+            BenchControl::Stop => {
+                self.stopped.store(true, Ordering::Relaxed);
+            }
+            BenchControl::Pause { hold } => {
+                self.paused_at.get_or_insert_with(Instant::now);
+                self.hold.store(hold, Ordering::Relaxed);
+            }
+            BenchControl::Resume => {
+                if let Some(at) = self.paused_at.take() {
+                    self.paused_for += at.elapsed();
+                }
+                self.hold.store(false, Ordering::Relaxed);
+            }
+            BenchControl::RequestKeyframe(count) => {
+                self.active_clients();
+                let connected = self
+                    .clients
+                    .values()
+                    .filter(|handle| handle.connected.load(Ordering::Relaxed));
+                let picked = connected.choose_multiple(&mut self.rng, count);
+                for handle in &picked {
+                    handle.keyframe.store(true, Ordering::Relaxed);
+                }
+                log::info!(
+                    "[BenchRunner] requesting keyframes from {} clients",
+                    picked.len()
+                );
+            }
+            BenchControl::SetTarget(count) => {
+                self.plan.count = count;
+                let active = self.active_clients();
+                for handle in self
+                    .clients
+                    .values()
+                    .rev()
+                    .take(active.saturating_sub(count))
+                {
+                    handle.killed.store(true, Ordering::Relaxed);
+                }
+                // the ramp and the sustain backfill start the missing clients themselves
+                if self.ramped && !self.plan.sustain {
+                    self.pending = count.saturating_sub(active);
+                }
+            }
+            BenchControl::SetDecimation(decimation) => {
+                self.plan.decimation = decimation;
+                *self.decimation.lock() = decimation;
+            }
+        }
+    }
+
+    /// Shared sockets are bound on first use, falling back to a socket per client on error
+    fn socket_pool(&mut self) -> Option<SocketPool> {
+        if self.plan.shared_sockets > 0 && self.socket_pool.is_none() {
+            match SocketPool::new(self.plan.shared_sockets, self.client_config.socket) {
+                Ok(pool) => self.socket_pool = Some(pool),
+                Err(e) => {
+                    log::error!("[BenchRunner] cannot create socket pool {:?}", e);
+                    self.plan.shared_sockets = 0;
+                }
+            }
+        }
+        self.socket_pool.clone()
+    }
+
+    /// Worker threads are started on first use
+    fn shards(&mut self) -> Option<&[Shard]> {
+        if self.plan.threads > 0 && self.shards.is_empty() {
+            self.shards = (0..self.plan.threads)
+                .map(|index| Shard::start(index, self.event_tx.clone()))
+                .collect();
+        }
+        (!self.shards.is_empty()).then_some(self.shards.as_slice())
+    }
+
+    /// Per worker thread aggregates, empty when clients run on the shared scheduler
+    pub fn shard_summaries(&self) -> Vec<ShardSummary> {
+        self.shards.iter().map(|s| s.summary()).collect()
+    }
+
+    /// Profile of the next client, drawn by weight
+    fn pick_profile(&mut self) -> Option<ClientProfile> {
+        let total: f64 = self.plan.mix.iter().map(|p| p.weight).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut pick = self.rng.gen::<f64>() * total;
+        for profile in &self.plan.mix {
+            if pick < profile.weight {
+                return Some(profile.clone());
+            }
+            pick -= profile.weight;
+        }
+        self.plan.mix.last().cloned()
+    }
+
+    async fn spawn_client(&mut self) {
+        self.count += 1;
+        let client_id = self.count;
+        emit(&self.event_tx, BenchEvent::Connecting(client_id)).await;
+        let mut labels = labels_of(&self.plan.labels, client_id);
+        let profile = self.pick_profile();
+        if let Some(profile) = &profile {
+            labels.push(profile.name.clone());
+        }
+        let url = match &self.ab_url {
+            Some(url) if client_id % 2 == 0 => {
+                labels.push(ab::LABEL_B.to_string());
+                url.clone()
+            }
+            Some(_) => {
+                labels.push(ab::LABEL_A.to_string());
+                self.url.clone()
+            }
+            None => self.url.clone(),
+        };
+        let entry = self.plan.clients.get(client_id - 1);
+        let url = entry
+            .and_then(|entry| entry.url.as_deref())
+            .map_or(url, Arc::from);
+        let url = if urltemplate::is_template(&url) {
+            let global_id = match self.plan.worker {
+                Some((index, total)) => (client_id - 1) * total + index + 1,
+                None => client_id,
+            };
+            match urltemplate::render(&url, global_id) {
+                Ok(rendered) => Arc::from(rendered),
+                Err(e) => {
+                    log::warn!("[BenchRunner] {}, using the url as is", e);
+                    url
+                }
+            }
+        } else {
+            url
+        };
+        let token = entry
+            .and_then(|entry| entry.token.as_deref())
+            .map_or_else(|| self.token.clone(), TokenSource::fixed);
+        if let Some(label) = entry.and_then(|entry| entry.label.clone()) {
+            labels.push(label);
+        }
+        if !labels.is_empty() {
+            emit(&self.event_tx, BenchEvent::Labeled(client_id, labels)).await;
+        }
+        let handle = ClientHandle {
+            killed: Arc::new(AtomicBool::new(false)),
+            chaos: Arc::new(AtomicBool::new(false)),
+            connected: Arc::new(AtomicBool::new(false)),
+            keyframe: Arc::new(AtomicBool::new(false)),
+        };
+        let fault = self
+            .plan
+            .fault
+            .filter(|fault| self.rng.gen::<f64>() * 100.0 < fault.percent);
+        let record_http = self
+            .plan
+            .record_http
+            .map_or(false, |percent| self.rng.gen::<f64>() * 100.0 < percent);
+        let audio_dump = self
+            .plan
+            .audio_dump
+            .as_ref()
+            .filter(|dump| self.rng.gen::<f64>() * 100.0 < dump.percent);
+        #[cfg(feature = "wav")]
+        let audio_dump = audio_dump.and_then(|dump| {
+            let path = dump.dir.join(format!("client-{}.wav", client_id));
+            match crate::wav::AudioDump::create(&path) {
+                Ok(dump) => Some(Arc::new(Mutex::new(dump))),
+                Err(e) => {
+                    log::error!("[BenchRunner] cannot create {} {:?}", path.display(), e);
+                    None
+                }
+            }
+        });
+        #[cfg(not(feature = "wav"))]
+        if audio_dump.is_some() {
+            log::warn!("[BenchRunner] audio dumps need the wav feature");
+        }
+        let task = ClientTask {
+            client_id,
+            record_http,
+            #[cfg(feature = "wav")]
+            audio_dump,
+            url,
+            token,
+            live_time: profile
+                .as_ref()
+                .and_then(|p| p.live)
+                .unwrap_or(self.plan.live),
+            loops: self.plan.loops,
+            fault,
+            starve: self.plan.starve,
+            decimation: self.decimation.clone(),
+            config: profile
+                .as_ref()
+                .and_then(|p| p.config(&self.client_config))
+                .map_or_else(|| self.client_config.clone(), Arc::new),
+            socket_pool: self.socket_pool(),
+            stopped: self.stopped.clone(),
+            killed: handle.killed.clone(),
+            chaos: self.plan.chaos,
+            chaos_picked: handle.chaos.clone(),
+            keyframe_picked: handle.keyframe.clone(),
+            connected: handle.connected.clone(),
+            hold: self.hold.clone(),
+            throttle: self.throttle.clone(),
+            handshakes: self.handshakes.clone(),
+            script: self.script.clone(),
+            event_tx: self.event_tx.clone(),
+        };
+        self.clients.insert(client_id, handle);
+        match self.shards() {
+            Some(shards) => shards[client_id % shards.len()].spawn(task),
+            None => {
+                rt::spawn(task.supervise());
+            }
+        }
+    }
+}
+
+pub(crate) async fn emit(event_tx: &Sender<BenchEvent>, event: BenchEvent) {
+    if let Err(e) = event_tx.send(event).await {
+        log::debug!("[BenchRunner] no consumer for event {:?}", e.0);
+    }
+}
+
+enum SessionEnd {
+    /// Stopped, disconnected by the server or not started
+    Ended,
+    /// Lifetime expired
+    Expired,
+    Faulted(WhepClient, FaultMode),
+}
+
+/// Faulted client waiting for its replacement to connect
+struct Recovery {
+    faulted_at: Instant,
+    /// Kept unpolled in silence mode, so its socket stays open but mute
+    _abandoned: Option<WhepClient>,
+}
+
+pub(crate) struct ClientTask {
+    pub(crate) client_id: usize,
+    record_http: bool,
+    /// Shared by the sessions of the client, so reconnects continue the same file
+    #[cfg(feature = "wav")]
+    audio_dump: Option<Arc<Mutex<crate::wav::AudioDump>>>,
+    url: Arc<str>,
+    token: TokenSource,
+    live_time: Duration,
+    loops: Option<u32>,
+    fault: Option<FaultPlan>,
+    starve: Option<StarvePlan>,
+    decimation: Arc<Mutex<Option<Decimation>>>,
+    config: Arc<ClientConfig>,
+    socket_pool: Option<SocketPool>,
+    stopped: Arc<AtomicBool>,
+    killed: Arc<AtomicBool>,
+    chaos: Option<ChaosPlan>,
+    /// Set when a chaos round picked the client
+    chaos_picked: Arc<AtomicBool>,
+    /// Set when a keyframe request burst picked the client
+    keyframe_picked: Arc<AtomicBool>,
+    /// Set while a session of the client is connected, for picking chaos victims
+    connected: Arc<AtomicBool>,
+    /// Runner is paused with hold, the lifetime does not expire
+    hold: Arc<AtomicBool>,
+    throttle: Arc<Throttle>,
+    handshakes: Option<HandshakeLimiter>,
+    script: Option<Arc<Script>>,
+    pub(crate) event_tx: Sender<BenchEvent>,
+}
+
+impl ClientTask {
+    /// Run the client, a failing or panicking client must not take the whole run down
+    pub(crate) async fn supervise(self) {
+        let client_id = self.client_id;
+        match AssertUnwindSafe(self.run()).catch_unwind().await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                log::error!("[WhepClient] error: {:?}", err);
+                let reason = FailureReason::from(&err);
+                emit(&self.event_tx, BenchEvent::Failed(client_id, reason)).await;
+            }
+            Err(_) => {
+                log::error!("[WhepClient] client task panicked");
+                let reason = FailureReason {
+                    category: FailureCategory::Panic,
+                    message: "client task panicked".to_string(),
+                };
+                emit(&self.event_tx, BenchEvent::Failed(client_id, reason)).await;
+            }
+        }
+        emit(&self.event_tx, BenchEvent::Disconnected(client_id)).await;
+    }
+
+    fn create_client(&self) -> Result<WhepClient, WhepError> {
+        let token = self.token.get();
+        let mut client = match &self.socket_pool {
+            Some(pool) => {
+                let socket = pool.acquire().into();
+                WhepClient::with_socket(&self.url, &token, &self.config, socket)?
+            }
+            None => WhepClient::new(&self.url, &token, &self.config)?,
+        };
+        client.set_client_id(self.client_id);
+        client.set_record_http(self.record_http);
+        #[cfg(feature = "wav")]
+        client.set_audio_dump(self.audio_dump.clone());
+        client.set_script(self.script.clone());
+        Ok(client)
+    }
+
+    fn should_stop(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed) || self.killed.load(Ordering::Relaxed)
+    }
+
+    /// Signal with a fresh client per attempt while the server sheds load,
+    /// None when stopped while waiting for a retry
+    async fn connect(&self) -> Result<Option<WhepClient>, WhepError> {
+        let mut attempt = 0;
+        loop {
+            let mut client = self.create_client()?;
+            let prepared = client.prepare().await;
+            self.emit_http_timings(&mut client).await;
+            match prepared {
+                Ok(()) => {
+                    if let Some(answer) = client.answer_summary() {
+                        let event = BenchEvent::Answer(self.client_id, answer.clone());
+                        emit(&self.event_tx, event).await;
                     }
+                    return Ok(Some(client));
                 }
-                event_tx
-                    .send(BenchEvent::Disconnected(client_id))
-                    .await
-                    .expect("should send disconnected event");
-            });
-            async_std::task::sleep(self.plan.interval).await;
+                Err(WhepError::Throttled(retry_after))
+                    if attempt < self.config.throttle_retries =>
+                {
+                    let backoff = THROTTLE_BACKOFF * 2u32.pow(attempt.min(5));
+                    let delay = retry_after.unwrap_or(backoff).min(MAX_RETRY_DELAY);
+                    attempt += 1;
+                    log::debug!(
+                        "[WhepClient] throttled, retry {} after {:?}",
+                        attempt,
+                        delay
+                    );
+                    self.throttle.on_throttled(delay);
+                    emit(&self.event_tx, BenchEvent::Throttled(self.client_id)).await;
+                    rt::sleep(delay).await;
+                    if self.should_stop() {
+                        return Ok(None);
+                    }
+                }
+                Err(err @ WhepError::MediaRejected(_)) => {
+                    // the server created a session for the answer, release it
+                    if let Err(e) = self.disconnect(&mut client).await {
+                        log::debug!("[WhepClient] delete after rejected media failed {:?}", e);
+                    }
+                    return Err(err);
+                }
+                Err(err) => return Err(err),
+            }
         }
+    }
 
-        log::info!("[BenchRunner] done");
+    async fn emit_http_timings(&self, client: &mut WhepClient) {
+        for timing in client.take_http_timings() {
+            emit(&self.event_tx, BenchEvent::Http(self.client_id, timing)).await;
+        }
+    }
+
+    async fn disconnect(&self, client: &mut WhepClient) -> Result<(), WhepError> {
+        let res = client.disconnect().await;
+        self.emit_http_timings(client).await;
+        res
+    }
+
+    /// Join, rejoining with a fresh offer after each lifetime in loop mode and
+    /// after an injected fault
+    async fn run(&self) -> Result<(), WhepError> {
+        let mut iteration = 0;
+        let mut fault = self.fault;
+        let mut recovery: Option<Recovery> = None;
+        let mut rollup = self.decimation.lock().map(StatsRollup::new);
+        loop {
+            let end = self.session(fault.take(), &mut recovery, &mut rollup).await;
+            // the partial rollup of the session, it does not carry over into the next one
+            if let Some(stats) = rollup.as_mut().and_then(|rollup| rollup.flush()) {
+                emit(&self.event_tx, BenchEvent::Stats(self.client_id, stats)).await;
+            }
+            self.connected.store(false, Ordering::Relaxed);
+            self.chaos_picked.store(false, Ordering::Relaxed);
+            let end = match end {
+                Ok(end) => end,
+                // the server may refuse the replacement until it reaped the dead session
+                Err(WhepError::ServerError(e))
+                    if recovery
+                        .as_ref()
+                        .map_or(false, |r| r.faulted_at.elapsed() < RECOVER_TIMEOUT) =>
+                {
+                    log::debug!("[WhepClient] replacement rejected {:?}, retrying", e);
+                    rt::sleep(RECOVER_RETRY).await;
+                    if self.should_stop() {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            match end {
+                SessionEnd::Ended => return Ok(()),
+                SessionEnd::Faulted(client, mode) => {
+                    log::debug!("[WhepClient] injected fault {:?}", mode);
+                    emit(&self.event_tx, BenchEvent::Faulted(self.client_id)).await;
+                    recovery = Some(Recovery {
+                        faulted_at: Instant::now(),
+                        // dropping the client closes its socket without a DELETE
+                        _abandoned: (mode == FaultMode::Silence).then_some(client),
+                    });
+                }
+                SessionEnd::Expired => {
+                    iteration += 1;
+                    let again = match self.loops {
+                        Some(0) => true,
+                        Some(loops) => iteration <= loops,
+                        None => false,
+                    };
+                    if !again || self.should_stop() {
+                        return Ok(());
+                    }
+                    log::debug!("[WhepClient] reconnecting, iteration {}", iteration);
+                    emit(
+                        &self.event_tx,
+                        BenchEvent::Reconnecting(self.client_id, iteration),
+                    )
+                    .await;
+                }
+            }
+        }
+    }
+
+    /// One join until disconnected or faulted by `fault`. A pending recovery ends
+    /// once this join connected
+    async fn session(
+        &self,
+        fault: Option<FaultPlan>,
+        recovery: &mut Option<Recovery>,
+        rollup: &mut Option<StatsRollup>,
+    ) -> Result<SessionEnd, WhepError> {
+        let mut handshake = match &self.handshakes {
+            Some(limiter) => {
+                let (permit, queue_ms) = limiter.acquire().await;
+                emit(&self.event_tx, BenchEvent::Queued(self.client_id, queue_ms)).await;
+                Some(permit)
+            }
+            None => None,
+        };
+        if self.should_stop() {
+            return Ok(SessionEnd::Ended);
+        }
+        let Some(mut client) = self.connect().await? else {
+            return Ok(SessionEnd::Ended);
+        };
+        let started = Instant::now();
+        let mut connected_at = None;
+        let mut starvation = Starvation::default();
+        loop {
+            if started.elapsed() > self.live_time && !self.hold.load(Ordering::Relaxed) {
+                log::debug!("[WhepClient] disconnecting after life time expired");
+                return self
+                    .disconnect(&mut client)
+                    .await
+                    .map(|_| SessionEnd::Expired);
+            }
+            if self.should_stop() {
+                log::debug!("[WhepClient] disconnecting after runner stopped");
+                return self
+                    .disconnect(&mut client)
+                    .await
+                    .map(|_| SessionEnd::Ended);
+            }
+            if let (Some(fault), Some(at)) = (fault, connected_at) {
+                if at.elapsed() > fault.after {
+                    client.abandon();
+                    return Ok(SessionEnd::Faulted(client, fault.mode));
+                }
+            }
+            if let (Some(chaos), Some(_)) = (self.chaos, connected_at) {
+                if self.chaos_picked.swap(false, Ordering::Relaxed) {
+                    client.abandon();
+                    return Ok(SessionEnd::Faulted(client, chaos.mode));
+                }
+            }
+            if connected_at.is_some()
+                && self.keyframe_picked.swap(false, Ordering::Relaxed)
+                && !client.request_keyframe()
+            {
+                log::debug!("[WhepClient] no video stream to request a keyframe on");
+            }
+
+            if client.keepalive_due() {
+                let res = client.keepalive().await;
+                self.emit_http_timings(&mut client).await;
+                if let Err(err) = res {
+                    log::debug!("[WhepClient] keepalive failed {:?}", err);
+                    let reason = FailureReason::from(&err);
+                    emit(
+                        &self.event_tx,
+                        BenchEvent::KeepaliveFailed(self.client_id, reason),
+                    )
+                    .await;
+                }
+            }
+
+            let event = match client.recv().await {
+                Ok(event) => event,
+                Err(err) => {
+                    if let Err(e) = self.disconnect(&mut client).await {
+                        log::warn!("[WhepClient] disconnect after error failed {:?}", e);
+                    }
+                    return Err(err);
+                }
+            };
+            if let Some(ms) = client.take_keyframe_latency() {
+                log::debug!("[WhepClient] requested keyframe arrived after {} ms", ms);
+                emit(
+                    &self.event_tx,
+                    BenchEvent::KeyframeReceived(self.client_id, ms),
+                )
+                .await;
+            }
+            match event {
+                WhepEvent::Connected => {
+                    log::debug!("[WhepClient] connected");
+                    connected_at = Some(Instant::now());
+                    // the slot goes to the next client once connected
+                    drop(handshake.take());
+                    self.connected.store(true, Ordering::Relaxed);
+                    emit(&self.event_tx, BenchEvent::Connected(self.client_id)).await;
+                    if let Some(script) = &self.script {
+                        script.on_connected(self.client_id);
+                    }
+                    if let Some(recovery) = recovery.take() {
+                        let recover_ms = recovery.faulted_at.elapsed().as_millis() as u64;
+                        log::debug!("[WhepClient] recovered after {} ms", recover_ms);
+                        emit(
+                            &self.event_tx,
+                            BenchEvent::Recovered(self.client_id, recover_ms),
+                        )
+                        .await;
+                    }
+                }
+                WhepEvent::Disconnected => {
+                    log::debug!("[WhepClient] disconnected");
+                    return Ok(SessionEnd::Ended);
+                }
+                WhepEvent::Stats(stats) => {
+                    log::trace!("[WhepClient] stats: {:?}", stats);
+                    if let Some(plan) = &self.starve {
+                        for kind in starvation.on_stats(plan, &stats, Instant::now()) {
+                            log::debug!("[WhepClient] {:?} starved", kind);
+                            emit(&self.event_tx, BenchEvent::Starved(self.client_id, kind)).await;
+                        }
+                    }
+                    let hang_up = self
+                        .script
+                        .as_ref()
+                        .map_or(false, |script| script.on_stats(self.client_id, &stats));
+                    let decimation = *self.decimation.lock();
+                    if rollup.as_ref().map(StatsRollup::decimation) != decimation {
+                        // changed during the run, the samples so far go out as they were rolled up
+                        if let Some(stats) = rollup.as_mut().and_then(StatsRollup::flush) {
+                            emit(&self.event_tx, BenchEvent::Stats(self.client_id, stats)).await;
+                        }
+                        *rollup = decimation.map(StatsRollup::new);
+                    }
+                    let stats = match rollup.as_mut() {
+                        Some(rollup) => rollup.on_stats(stats, Instant::now()),
+                        None => Some(stats),
+                    };
+                    if let Some(stats) = stats {
+                        emit(&self.event_tx, BenchEvent::Stats(self.client_id, stats)).await;
+                    }
+                    if hang_up {
+                        log::debug!("[WhepClient] disconnecting as the script asked");
+                        return self
+                            .disconnect(&mut client)
+                            .await
+                            .map(|_| SessionEnd::Ended);
+                    }
+                }
+                WhepEvent::StreamChanged(change) => {
+                    emit(
+                        &self.event_tx,
+                        BenchEvent::StreamChanged(self.client_id, change),
+                    )
+                    .await;
+                }
+                WhepEvent::LayerSwitched(switch) => {
+                    emit(
+                        &self.event_tx,
+                        BenchEvent::LayerSwitched(self.client_id, switch),
+                    )
+                    .await;
+                }
+                WhepEvent::Continue => {}
+            }
+        }
     }
 }
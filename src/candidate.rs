@@ -0,0 +1,297 @@
+//! Candidate pair the ICE agent selected. str0m does not expose it, it is the pair
+//! the first non-STUN datagram after ICE connected is sent on, typed by the
+//! candidate lines of both descriptions. The candidate policy limits which
+//! candidates a client uses, the interface filter which local addresses it offers
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::stun::IceRole;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CandidatePair {
+    /// `host`, `srflx`, `prflx` or `relay`
+    pub local_type: String,
+    pub local: SocketAddr,
+    pub remote_type: String,
+    pub remote: SocketAddr,
+    pub protocol: String,
+    /// Answer received until ICE connected on the pair
+    pub nominated_ms: Option<u32>,
+}
+
+impl CandidatePair {
+    /// Types and protocol, the key of the distribution across clients
+    pub fn kind(&self) -> String {
+        format!("{}-{}/{}", self.local_type, self.remote_type, self.protocol)
+    }
+}
+
+impl fmt::Display for CandidatePair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} => {} {} {}",
+            self.local_type, self.local, self.remote_type, self.remote, self.protocol
+        )
+    }
+}
+
+/// Clients by ICE role and by each part of their selected pair, for spotting a
+/// share of the clients which silently fell back to relay or tcp
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IceBreakdown {
+    pub roles: BTreeMap<IceRole, usize>,
+    pub local_types: BTreeMap<String, usize>,
+    pub remote_types: BTreeMap<String, usize>,
+    pub protocols: BTreeMap<String, usize>,
+    /// Clients with a relay candidate on either side of the pair
+    pub relayed: usize,
+}
+
+impl IceBreakdown {
+    pub fn add(&mut self, role: Option<IceRole>, pair: Option<&CandidatePair>) {
+        if let Some(role) = role {
+            *self.roles.entry(role).or_default() += 1;
+        }
+        if let Some(pair) = pair {
+            *self.local_types.entry(pair.local_type.clone()).or_default() += 1;
+            *self
+                .remote_types
+                .entry(pair.remote_type.clone())
+                .or_default() += 1;
+            *self.protocols.entry(pair.protocol.clone()).or_default() += 1;
+            if pair.local_type == "relay" || pair.remote_type == "relay" {
+                self.relayed += 1;
+            }
+        }
+    }
+
+    /// Clients whose pair runs over tcp
+    pub fn tcp(&self) -> usize {
+        self.protocols.get("tcp").copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roles.is_empty() && self.protocols.is_empty()
+    }
+}
+
+fn shares<K: fmt::Display>(counts: &BTreeMap<K, usize>) -> String {
+    let total = counts.values().sum::<usize>().max(1) as f64;
+    let shares: Vec<String> = counts
+        .iter()
+        .map(|(key, count)| format!("{} {:.1}%", key, *count as f64 * 100.0 / total))
+        .collect();
+    shares.join(", ")
+}
+
+impl fmt::Display for IceBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ice roles: {}\nlocal candidates: {}\nremote candidates: {}\ntransports: {}",
+            shares(&self.roles),
+            shares(&self.local_types),
+            shares(&self.remote_types),
+            shares(&self.protocols)
+        )?;
+        if self.relayed > 0 || self.tcp() > 0 {
+            write!(
+                f,
+                "\nfallbacks: {} clients relayed, {} over tcp",
+                self.relayed,
+                self.tcp()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Candidates a client may use, like the ice transport policy of browsers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CandidatePolicy {
+    #[default]
+    All,
+    /// Only the relay candidates of the answer, the client has no TURN allocation
+    /// of its own and pairs its host candidates with the relay of the server
+    Relay,
+    /// Only host candidates on both sides, no STUN servers are asked
+    Host,
+}
+
+impl FromStr for CandidatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(CandidatePolicy::All),
+            "relay" => Ok(CandidatePolicy::Relay),
+            "host" => Ok(CandidatePolicy::Host),
+            _ => Err(format!(
+                "unknown candidate policy {}, expected all, relay or host",
+                s
+            )),
+        }
+    }
+}
+
+impl CandidatePolicy {
+    fn allows(self, kind: &str) -> bool {
+        match self {
+            CandidatePolicy::All => true,
+            CandidatePolicy::Relay => kind == "relay",
+            CandidatePolicy::Host => kind == "host",
+        }
+    }
+
+    /// Drop the candidate lines of `sdp` the policy does not allow, None when no
+    /// candidate is left
+    pub fn filter(self, sdp: &str) -> Option<String> {
+        if self == CandidatePolicy::All {
+            return Some(sdp.to_string());
+        }
+        let mut kept = 0;
+        let mut lines = vec![];
+        for line in sdp.lines() {
+            if let Some(value) = line.trim().strip_prefix("a=candidate:") {
+                let mut fields = value.split_whitespace().skip_while(|f| *f != "typ");
+                if !fields.nth(1).map_or(false, |kind| self.allows(kind)) {
+                    continue;
+                }
+                kept += 1;
+            }
+            lines.push(line);
+        }
+        if kept == 0 {
+            return None;
+        }
+        // sdp lines end with crlf, including the last one
+        let mut out = lines.join("\r\n");
+        out.push_str("\r\n");
+        Some(out)
+    }
+}
+
+/// Regex matched against interface names, anchored to the whole name
+#[derive(Debug, Clone)]
+pub struct IfacePattern(Regex);
+
+impl FromStr for IfacePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Regex::new(&format!("^(?:{})$", s))
+            .map(IfacePattern)
+            .map_err(|e| format!("invalid interface pattern {}: {}", s, e))
+    }
+}
+
+impl IfacePattern {
+    fn as_str(&self) -> &str {
+        let pattern = self.0.as_str();
+        &pattern[4..pattern.len() - 2]
+    }
+}
+
+impl Serialize for IfacePattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for IfacePattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Local interfaces whose addresses become host candidates. Loopback and
+/// link-local addresses are skipped unless an include pattern names their
+/// interface, or no other address is left
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InterfaceFilter {
+    /// Only interfaces matching one of these, all when empty
+    pub include: Vec<IfacePattern>,
+    pub exclude: Vec<IfacePattern>,
+}
+
+fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_link_local(),
+        IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+impl InterfaceFilter {
+    fn included(&self, name: &str) -> bool {
+        self.include.iter().any(|p| p.0.is_match(name))
+    }
+
+    /// Addresses of the interfaces the filter allows, in the given order
+    pub fn apply(&self, interfaces: Vec<(String, IpAddr)>) -> Vec<IpAddr> {
+        let (allowed, fallback): (Vec<_>, Vec<_>) = interfaces
+            .into_iter()
+            .filter(|(name, _)| self.include.is_empty() || self.included(name))
+            .filter(|(name, _)| !self.exclude.iter().any(|p| p.0.is_match(name)))
+            .partition(|(name, ip)| {
+                !(ip.is_loopback() || is_link_local(ip)) || self.included(name)
+            });
+        let skipped: Vec<String> = fallback
+            .iter()
+            .map(|(name, ip)| format!("{} {}", name, ip))
+            .collect();
+        if allowed.is_empty() {
+            return fallback.into_iter().map(|(_, ip)| ip).collect();
+        }
+        if !skipped.is_empty() {
+            log::debug!(
+                "[Candidate] skip loopback and link-local {}",
+                skipped.join(", ")
+            );
+        }
+        allowed.into_iter().map(|(_, ip)| ip).collect()
+    }
+}
+
+/// Type and transport of each candidate address of an sdp
+pub fn parse_candidates(sdp: &str) -> HashMap<SocketAddr, (String, String)> {
+    let mut candidates = HashMap::new();
+    for line in sdp.lines().map(|l| l.trim()) {
+        let Some(value) = line.strip_prefix("a=candidate:") else {
+            continue;
+        };
+        // foundation component transport priority address port typ <type>
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        let (Some(transport), Some(ip), Some(port), Some("typ"), Some(kind)) = (
+            fields.get(2),
+            fields.get(4),
+            fields.get(5),
+            fields.get(6).copied(),
+            fields.get(7),
+        ) else {
+            continue;
+        };
+        let (Ok(ip), Ok(port)) = (ip.parse(), port.parse()) else {
+            continue;
+        };
+        candidates.insert(
+            SocketAddr::new(ip, port),
+            (kind.to_string(), transport.to_ascii_lowercase()),
+        );
+    }
+    candidates
+}
+
+/// STUN shares the socket with DTLS and SRTP, its first byte is 0 to 3 (RFC 7983)
+pub fn is_stun(datagram: &[u8]) -> bool {
+    matches!(datagram.first(), Some(0..=3))
+}
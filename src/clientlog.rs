@@ -0,0 +1,105 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_std::channel::Receiver;
+
+use crate::{bench::BenchEvent, whepext};
+
+fn client_path(dir: &Path, id: usize) -> PathBuf {
+    dir.join(format!("client-{}.log", id))
+}
+
+fn describe(event: &BenchEvent) -> (usize, String) {
+    match event {
+        BenchEvent::Connecting(id) => (*id, "connecting".to_string()),
+        BenchEvent::Connected(id) => (*id, "connected".to_string()),
+        BenchEvent::Stats(id, stats) => (
+            *id,
+            format!(
+                "stats recv {} kbps, send {} kbps, rtt {} ms, lost {:.3}",
+                stats.recv_kbps, stats.send_kbps, stats.rtt_ms, stats.lost
+            ),
+        ),
+        BenchEvent::Failed(id, reason) => (
+            *id,
+            format!("failed {:?}: {}", reason.category, reason.message),
+        ),
+        BenchEvent::StreamChanged(id, change) => (
+            *id,
+            format!(
+                "stream changed mid {} ssrc {} => {}",
+                change.mid, change.old_ssrc, change.new_ssrc
+            ),
+        ),
+        BenchEvent::LayerSwitched(id, switch) => (
+            *id,
+            format!(
+                "layer switched mid {} {} => {}",
+                switch.mid, switch.from, switch.to
+            ),
+        ),
+        BenchEvent::Queued(id, ms) => (*id, format!("got a handshake slot after {} ms", ms)),
+        BenchEvent::Throttled(id) => (*id, "throttled, retrying".to_string()),
+        BenchEvent::Reconnecting(id, iteration) => {
+            (*id, format!("reconnecting, iteration {}", iteration))
+        }
+        BenchEvent::Faulted(id) => (*id, "fault injected, rejoining".to_string()),
+        BenchEvent::Recovered(id, ms) => (*id, format!("recovered after {} ms", ms)),
+        BenchEvent::Starved(id, kind) => (*id, format!("{:?} starved", kind)),
+        BenchEvent::Http(id, timing) => (
+            *id,
+            format!(
+                "{:?} {} ttfb {} ms, total {} ms",
+                timing.method, timing.status, timing.ttfb_ms, timing.total_ms
+            ),
+        ),
+        BenchEvent::Answer(id, answer) => (
+            *id,
+            format!(
+                "answer {}, whep extensions {}",
+                answer.profile(),
+                whepext::describe(&answer.whep_extensions)
+            ),
+        ),
+        BenchEvent::Labeled(id, labels) => (*id, format!("labels {}", labels.join(", "))),
+        BenchEvent::Teardown(id, outcome) => (*id, format!("session teardown {:?}", outcome)),
+        BenchEvent::KeepaliveFailed(id, reason) => {
+            (*id, format!("keepalive failed {}", reason.message))
+        }
+        BenchEvent::KeyframeReceived(id, ms) => {
+            (*id, format!("requested keyframe arrived after {} ms", ms))
+        }
+        BenchEvent::Disconnected(id) => (*id, "disconnected".to_string()),
+    }
+}
+
+fn append(dir: &Path, event: &BenchEvent) -> io::Result<()> {
+    let (id, line) = describe(event);
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    // opened per event, thousands of clients would exhaust file descriptors otherwise
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(client_path(dir, id))?;
+    writeln!(file, "{} {}", ts, line)
+}
+
+/// Write the history of each client to `<dir>/client-<id>.log`, blocks the calling thread
+pub fn run_client_logs(dir: PathBuf, event_rx: Receiver<BenchEvent>) {
+    if let Err(e) = fs::create_dir_all(&dir) {
+        log::error!("[ClientLog] cannot create {} {:?}", dir.display(), e);
+        return;
+    }
+    while let Ok(event) = event_rx.recv_blocking() {
+        if let Err(e) = append(&dir, &event) {
+            log::warn!("[ClientLog] write failed {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use async_std::{
+    channel::Sender,
+    io::{prelude::BufReadExt, BufReader, WriteExt},
+    net::{TcpListener, TcpStream},
+    stream::StreamExt,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bench::{labels_of, Arrival, BenchEvent, BenchPlan, BenchRunner},
+    roster,
+};
+
+/// Messages exchanged between coordinator and workers, one json object per line
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClusterMessage {
+    Start {
+        url: String,
+        token: String,
+        plan: BenchPlan,
+    },
+    Event(BenchEvent),
+}
+
+async fn send_message(stream: &mut TcpStream, msg: &ClusterMessage) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(msg)?;
+    line.push(b'\n');
+    stream.write_all(&line).await
+}
+
+/// Split a plan between workers, keeping the overall arrival rate of the original plan
+fn split_plan(plan: &BenchPlan, index: usize, workers: usize) -> BenchPlan {
+    let extra = if index < plan.count % workers { 1 } else { 0 };
+    BenchPlan {
+        count: plan.count / workers + extra,
+        interval: plan.interval * workers as u32,
+        arrival: match plan.arrival {
+            Arrival::Fixed => Arrival::Fixed,
+            Arrival::Poisson { rate } => Arrival::Poisson {
+                rate: rate / workers as f64,
+            },
+        },
+        // distinct but reproducible schedule per worker
+        seed: plan.seed.map(|seed| seed.wrapping_add(index as u64)),
+        // the bound holds across the cluster, validation keeps it at one slot per
+        // worker or more
+        max_concurrent_connects: plan
+            .max_concurrent_connects
+            .map(|max| (max + workers - 1 - index) / workers),
+        ..plan.clone()
+    }
+}
+
+/// Run the plan on all workers, returns after every worker finished
+pub async fn run_coordinator(
+    workers: &[String],
+    url: &str,
+    token: &str,
+    plan: BenchPlan,
+    event_tx: Sender<BenchEvent>,
+) {
+    let total = workers.len();
+    let mut handles = vec![];
+    for (index, addr) in workers.iter().enumerate() {
+        let worker_plan = split_plan(&plan, index, total);
+        let addr = addr.clone();
+        let url = url.to_string();
+        let token = token.to_string();
+        let event_tx = event_tx.clone();
+        handles.push(async_std::task::spawn(async move {
+            if let Err(e) =
+                drive_worker(index, total, &addr, url, token, worker_plan, event_tx).await
+            {
+                log::error!("[Coordinator] worker {} error {:?}", addr, e);
+            }
+        }));
+        // stagger workers so clients from all workers interleave
+        async_std::task::sleep(plan.interval).await;
+    }
+    for handle in handles {
+        handle.await;
+    }
+}
+
+async fn drive_worker(
+    index: usize,
+    total: usize,
+    addr: &str,
+    url: String,
+    token: String,
+    mut plan: BenchPlan,
+    event_tx: Sender<BenchEvent>,
+) -> std::io::Result<()> {
+    // label ranges refer to global ids, workers only know their own
+    let labels = std::mem::take(&mut plan.labels);
+    plan.clients = roster::shard(&plan.clients, index, total);
+    plan.worker = Some((index, total));
+    let mut stream = TcpStream::connect(addr).await?;
+    log::info!("[Coordinator] connected to worker {}", addr);
+    send_message(&mut stream, &ClusterMessage::Start { url, token, plan }).await?;
+
+    let mut lines = BufReader::new(stream).lines();
+    while let Some(line) = lines.next().await {
+        match serde_json::from_str(&line?)? {
+            ClusterMessage::Event(event) => {
+                // interleave worker-local ids into one global id space
+                let event = event.map_client_id(|id| (id - 1) * total + index + 1);
+                let labeled = match &event {
+                    BenchEvent::Connecting(id) => Some((*id, labels_of(&labels, *id))),
+                    _ => None,
+                };
+                if event_tx.send(event).await.is_err() {
+                    break;
+                }
+                if let Some((id, labels)) = labeled.filter(|(_, labels)| !labels.is_empty()) {
+                    if event_tx
+                        .send(BenchEvent::Labeled(id, labels))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+            msg => {
+                log::warn!("[Coordinator] unexpected message from worker {:?}", msg);
+            }
+        }
+    }
+    log::info!("[Coordinator] worker {} finished", addr);
+    Ok(())
+}
+
+pub async fn run_worker(listen: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    log::info!("[Worker] listening on {}", listen);
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        log::info!("[Worker] coordinator connected from {}", remote);
+        async_std::task::spawn(async move {
+            if let Err(e) = serve_coordinator(stream).await {
+                log::error!("[Worker] coordinator {} error {:?}", remote, e);
+            }
+        });
+    }
+}
+
+async fn serve_coordinator(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut lines = BufReader::new(stream.clone()).lines();
+    let (url, token, plan) = match lines.next().await {
+        Some(line) => match serde_json::from_str(&line?)? {
+            ClusterMessage::Start { url, token, plan } => (url, token, plan),
+            msg => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("expected start message, got {:?}", msg),
+                ))
+            }
+        },
+        None => return Ok(()),
+    };
+
+    log::info!("[Worker] starting plan {:?}", plan);
+    let (event_tx, event_rx) = async_std::channel::unbounded::<BenchEvent>();
+    let mut runner = BenchRunner::new(&url, &token, plan, event_tx);
+    let stopped = runner.stop_handle();
+    async_std::task::spawn(async move {
+        runner.run().await;
+    });
+
+    // channel closes after runner and all of its clients are done
+    while let Ok(event) = event_rx.recv().await {
+        if let Err(e) = send_message(&mut stream, &ClusterMessage::Event(event)).await {
+            // the coordinator is gone or aborted the run, disconnect the clients
+            log::warn!("[Worker] coordinator lost, stopping plan");
+            stopped.store(true, std::sync::atomic::Ordering::Relaxed);
+            return Err(e);
+        }
+    }
+    log::info!("[Worker] plan finished");
+    Ok(())
+}
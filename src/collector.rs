@@ -0,0 +1,522 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bench::{BenchEvent, FailureCategory, FailureReason},
+    media::StreamKind,
+    sdp::AnswerSummary,
+    whep::Stats,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ClientStatus {
+    Connecting,
+    Connected,
+    /// Connected but not receiving any media
+    Stalled,
+    /// Failed or ended without ever connecting
+    Errored,
+}
+
+impl ClientStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ClientStatus::Connecting => "Connecting",
+            ClientStatus::Connected => "Running",
+            ClientStatus::Stalled => "Stalled",
+            ClientStatus::Errored => "Errored",
+        }
+    }
+}
+
+/// Stats samples kept per client unless set with [`Collector::with_history`]
+pub const HISTORY_SIZE: usize = 10;
+const ERRORS_SIZE: usize = 5;
+/// Number of aggregate samples kept, one per sample call
+const AGGREGATE_WINDOW: usize = 600;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Client {
+    pub id: usize,
+    pub connected: bool,
+    pub failed: bool,
+    pub stats: Option<Stats>,
+    /// Time from connecting to connected
+    pub connect_ms: Option<u64>,
+    pub errors: VecDeque<String>,
+    /// Number of SSRC changes seen over the session
+    pub stream_changes: u32,
+    /// Number of simulcast layer switches seen over the session
+    pub layer_switches: u32,
+    /// Video resolution changes after the first keyframe
+    pub resolution_changes: u32,
+    /// Signaling attempts rejected with 429 or 503
+    pub throttled: u32,
+    /// Current join in loop mode, 0 for the first one
+    pub iteration: u32,
+    /// Injected faults and the time until the latest replacement connected
+    pub faults: u32,
+    pub recover_ms: Option<u64>,
+    /// Time until the keyframe answering the latest requested one arrived
+    pub keyframe_ms: Option<u64>,
+    /// Wait for a handshake slot of the latest join
+    pub queue_ms: Option<u32>,
+    /// Times a media kind was flagged as starved
+    pub starved: u32,
+    /// Negotiated answer of the latest join
+    pub answer: Option<AnswerSummary>,
+    /// Cohorts the client belongs to
+    pub labels: Vec<String>,
+    #[serde(skip)]
+    pub created_at: Option<Instant>,
+    /// Recent stats samples, newest last
+    #[serde(skip)]
+    pub history: VecDeque<Stats>,
+}
+
+impl Client {
+    fn rejoin(&mut self) {
+        self.connected = false;
+        self.connect_ms = None;
+        self.created_at = Some(Instant::now());
+    }
+
+    pub fn status(&self) -> ClientStatus {
+        if self.failed {
+            ClientStatus::Errored
+        } else if !self.connected {
+            ClientStatus::Connecting
+        } else if self
+            .stats
+            .as_ref()
+            .map(|s| s.recv_kbps == 0)
+            .unwrap_or(false)
+        {
+            ClientStatus::Stalled
+        } else {
+            ClientStatus::Connected
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateSample {
+    pub elapsed_ms: u64,
+    pub clients: usize,
+    pub connected: usize,
+    #[serde(default)]
+    pub failed: usize,
+    pub send_kbps: u64,
+    pub recv_kbps: u64,
+    pub avg_rtt_ms: u32,
+    pub avg_mos: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Health {
+    Good,
+    Warn,
+    Bad,
+}
+
+/// Limits for classifying client health, a client is as bad as its worst metric
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthThresholds {
+    pub warn_loss: f32,
+    pub bad_loss: f32,
+    pub warn_rtt_ms: u32,
+    pub bad_rtt_ms: u32,
+    pub warn_kbps: u64,
+    pub bad_kbps: u64,
+}
+
+impl HealthThresholds {
+    pub fn classify(&self, client: &Client) -> Option<Health> {
+        if client.failed {
+            return Some(Health::Bad);
+        }
+        let stats = client.stats.as_ref()?;
+        let loss = if stats.lost >= self.bad_loss {
+            Health::Bad
+        } else if stats.lost >= self.warn_loss {
+            Health::Warn
+        } else {
+            Health::Good
+        };
+        let rtt = if stats.rtt_ms >= self.bad_rtt_ms {
+            Health::Bad
+        } else if stats.rtt_ms >= self.warn_rtt_ms {
+            Health::Warn
+        } else {
+            Health::Good
+        };
+        let bitrate = if stats.recv_kbps < self.bad_kbps {
+            Health::Bad
+        } else if stats.recv_kbps < self.warn_kbps {
+            Health::Warn
+        } else {
+            Health::Good
+        };
+        Some(loss.max(rtt).max(bitrate))
+    }
+}
+
+/// Aggregated view over BenchEvents, shared by the UI and the agent API
+#[derive(Debug)]
+pub struct Collector {
+    clients: BTreeMap<usize, Client>,
+    aggregates: VecDeque<AggregateSample>,
+    first_sample_at: Option<Instant>,
+    failures: BTreeMap<FailureCategory, usize>,
+    history_depth: usize,
+    /// Received on the wire by all clients since the start, ended sessions included
+    recv_bytes: u64,
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Self::with_history(HISTORY_SIZE)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Snapshot<'a> {
+    pub clients_sum: usize,
+    pub clients_connected: usize,
+    pub clients_failed: usize,
+    pub failures: &'a BTreeMap<FailureCategory, usize>,
+    pub sum_send_kbps: u64,
+    pub sum_recv_kbps: u64,
+    pub sum_audio_pps: u64,
+    pub sum_video_pps: u64,
+    pub clients: Vec<&'a Client>,
+}
+
+impl Collector {
+    /// Keep the last `depth` stats samples of each client in its ring buffer,
+    /// 0 keeps none for consumers which only need the latest
+    pub fn with_history(depth: usize) -> Self {
+        Self {
+            clients: BTreeMap::new(),
+            aggregates: VecDeque::new(),
+            first_sample_at: None,
+            failures: BTreeMap::new(),
+            history_depth: depth,
+            recv_bytes: 0,
+        }
+    }
+
+    pub fn on_event(&mut self, event: BenchEvent) {
+        match event {
+            BenchEvent::Connecting(id) => self.add_client(id),
+            BenchEvent::Connected(id) => self.set_client_connected(id),
+            BenchEvent::Stats(id, stats) => self.set_client_stats(id, stats),
+            BenchEvent::Failed(id, reason) => self.set_client_failed(id, reason),
+            BenchEvent::StreamChanged(id, _) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.stream_changes += 1;
+                }
+            }
+            BenchEvent::LayerSwitched(id, _) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.layer_switches += 1;
+                }
+            }
+            BenchEvent::Queued(id, ms) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.queue_ms = Some(ms);
+                }
+            }
+            BenchEvent::Throttled(id) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.throttled += 1;
+                }
+            }
+            BenchEvent::Reconnecting(id, iteration) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.iteration = iteration;
+                    client.rejoin();
+                }
+            }
+            BenchEvent::Faulted(id) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.faults += 1;
+                    client.rejoin();
+                }
+            }
+            BenchEvent::Http(..) | BenchEvent::Teardown(..) | BenchEvent::KeepaliveFailed(..) => {}
+            BenchEvent::KeyframeReceived(id, ms) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.keyframe_ms = Some(ms);
+                }
+            }
+            BenchEvent::Answer(id, answer) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.answer = Some(answer);
+                }
+            }
+            BenchEvent::Labeled(id, labels) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.labels = labels;
+                }
+            }
+            BenchEvent::Starved(id, _) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.starved += 1;
+                }
+            }
+            BenchEvent::Recovered(id, ms) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.recover_ms = Some(ms);
+                }
+            }
+            BenchEvent::Disconnected(id) => self.remove_client(id),
+        }
+    }
+
+    pub fn add_client(&mut self, id: usize) {
+        self.clients.insert(
+            id,
+            Client {
+                id,
+                created_at: Some(Instant::now()),
+                ..Default::default()
+            },
+        );
+    }
+
+    pub fn set_client_connected(&mut self, id: usize) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.connected = true;
+            client.connect_ms = client.created_at.map(|t| t.elapsed().as_millis() as u64);
+        }
+    }
+
+    pub fn set_client_stats(&mut self, id: usize, stats: Stats) {
+        let depth = self.history_depth;
+        if let Some(client) = self.clients.get_mut(&id) {
+            let previous = client.stats.as_ref().map_or(0, |s| s.traffic.wire_bytes);
+            // counters start over with each session of a rejoining client
+            self.recv_bytes += match stats.traffic.wire_bytes.checked_sub(previous) {
+                Some(delta) => delta,
+                None => stats.traffic.wire_bytes,
+            };
+            client.resolution_changes += stats
+                .resolution_changes
+                .iter()
+                .filter(|change| change.from.is_some())
+                .count() as u32;
+            if depth > 0 {
+                while client.history.len() >= depth {
+                    client.history.pop_front();
+                }
+                client.history.push_back(stats.clone());
+            }
+            client.stats = Some(stats);
+        }
+    }
+
+    pub fn add_client_error(&mut self, id: usize, err: String) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            if client.errors.len() >= ERRORS_SIZE {
+                client.errors.pop_front();
+            }
+            client.errors.push_back(err);
+        }
+    }
+
+    pub fn set_client_failed(&mut self, id: usize, reason: FailureReason) {
+        *self.failures.entry(reason.category).or_default() += 1;
+        self.add_client_error(id, reason.message);
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.failed = true;
+        }
+    }
+
+    /// Clients which failed or never connected are kept as failed for inspection
+    pub fn remove_client(&mut self, id: usize) {
+        match self.clients.get_mut(&id) {
+            Some(client) if client.failed || !client.connected => client.failed = true,
+            _ => {
+                self.clients.remove(&id);
+            }
+        }
+    }
+
+    pub fn get_client(&self, id: usize) -> Option<&Client> {
+        self.clients.get(&id)
+    }
+
+    pub fn get_clients(&self) -> &BTreeMap<usize, Client> {
+        &self.clients
+    }
+
+    pub fn clients_sum(&self) -> usize {
+        self.clients.values().filter(|v| !v.failed).count()
+    }
+
+    pub fn clients_connected(&self) -> usize {
+        self.clients.values().filter(|v| v.connected).count()
+    }
+
+    pub fn clients_failed(&self) -> usize {
+        self.clients.values().filter(|v| v.failed).count()
+    }
+
+    /// Number of failures per category since start
+    pub fn failure_counts(&self) -> &BTreeMap<FailureCategory, usize> {
+        &self.failures
+    }
+
+    /// Failure counts formatted as `Category n, ...`, empty when nothing failed
+    pub fn failure_summary(&self) -> String {
+        self.failures
+            .iter()
+            .map(|(category, count)| format!("{:?} {}", category, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn sum_send_kbps(&self) -> u64 {
+        self.clients
+            .values()
+            .filter_map(|v| v.stats.as_ref())
+            .map(|v| v.send_kbps)
+            .sum::<u64>()
+    }
+
+    pub fn total_recv_bytes(&self) -> u64 {
+        self.recv_bytes
+    }
+
+    /// Rtp packets per second of all clients by media kind
+    pub fn sum_pps(&self, kind: StreamKind) -> u64 {
+        self.clients
+            .values()
+            .filter_map(|v| v.stats.as_ref())
+            .map(|v| match kind {
+                StreamKind::Audio => v.audio_pps,
+                StreamKind::Video => v.video_pps,
+            })
+            .sum()
+    }
+
+    pub fn sum_recv_kbps(&self) -> u64 {
+        self.clients
+            .values()
+            .filter_map(|v| v.stats.as_ref())
+            .map(|v| v.recv_kbps)
+            .sum::<u64>()
+    }
+
+    /// Number of clients in (good, warn, bad) health
+    pub fn health_counts(&self, thresholds: &HealthThresholds) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for client in self.clients.values() {
+            match thresholds.classify(client) {
+                Some(Health::Good) => counts.0 += 1,
+                Some(Health::Warn) => counts.1 += 1,
+                Some(Health::Bad) => counts.2 += 1,
+                None => {}
+            }
+        }
+        counts
+    }
+
+    pub fn avg_rtt_ms(&self) -> u32 {
+        let rtts: Vec<u32> = self
+            .clients
+            .values()
+            .filter_map(|v| v.stats.as_ref())
+            .map(|v| v.rtt_ms)
+            .collect();
+        if rtts.is_empty() {
+            0
+        } else {
+            rtts.iter().sum::<u32>() / rtts.len() as u32
+        }
+    }
+
+    pub fn max_rtt_ms(&self) -> u32 {
+        self.clients
+            .values()
+            .filter_map(|v| v.stats.as_ref())
+            .map(|v| v.rtt_ms)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Average loss fraction of the clients with stats
+    pub fn avg_loss(&self) -> f32 {
+        let losses: Vec<f32> = self
+            .clients
+            .values()
+            .filter_map(|v| v.stats.as_ref())
+            .map(|v| v.lost)
+            .collect();
+        if losses.is_empty() {
+            0.0
+        } else {
+            losses.iter().sum::<f32>() / losses.len() as f32
+        }
+    }
+
+    /// Connected clients not receiving any media
+    pub fn clients_stalled(&self) -> usize {
+        self.clients
+            .values()
+            .filter(|v| v.status() == ClientStatus::Stalled)
+            .count()
+    }
+
+    /// Average audio MOS of clients which receive audio
+    pub fn avg_mos(&self) -> Option<f32> {
+        let scores: Vec<f32> = self
+            .clients
+            .values()
+            .filter_map(|v| v.stats.as_ref()?.mos)
+            .collect();
+        (!scores.is_empty()).then(|| scores.iter().sum::<f32>() / scores.len() as f32)
+    }
+
+    /// Append current aggregates to the rolling window
+    pub fn sample(&mut self, now: Instant) {
+        let first = *self.first_sample_at.get_or_insert(now);
+        let sample = AggregateSample {
+            elapsed_ms: (now - first).as_millis() as u64,
+            clients: self.clients_sum(),
+            connected: self.clients_connected(),
+            failed: self.clients_failed(),
+            send_kbps: self.sum_send_kbps(),
+            recv_kbps: self.sum_recv_kbps(),
+            avg_rtt_ms: self.avg_rtt_ms(),
+            avg_mos: self.avg_mos(),
+        };
+        if self.aggregates.len() >= AGGREGATE_WINDOW {
+            self.aggregates.pop_front();
+        }
+        self.aggregates.push_back(sample);
+    }
+
+    pub fn aggregates(&self) -> &VecDeque<AggregateSample> {
+        &self.aggregates
+    }
+
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot {
+            clients_sum: self.clients_sum(),
+            clients_connected: self.clients_connected(),
+            clients_failed: self.clients_failed(),
+            failures: self.failure_counts(),
+            sum_send_kbps: self.sum_send_kbps(),
+            sum_recv_kbps: self.sum_recv_kbps(),
+            sum_audio_pps: self.sum_pps(StreamKind::Audio),
+            sum_video_pps: self.sum_pps(StreamKind::Video),
+            clients: self.clients.values().collect(),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Parquet export of stats samples for analytics tools like DuckDB or Spark.
+//! Each run writes `stats-<run id>.parquet` with one row per sample:
+//!
+//! ```text
+//! message stats {
+//!   required int64 ts (TIMESTAMP(MILLIS,true));
+//!   required int64 client_id;
+//!   required int64 send_kbps;
+//!   required int64 recv_kbps;
+//!   required int32 rtt_ms;
+//!   required float loss;
+//!   optional float mos;
+//!   required int64 keyframes;
+//!   required int64 wire_bytes;
+//!   required int64 payload_bytes;
+//! }
+//! ```
+
+use std::{
+    fs::{self, File},
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_std::channel::Receiver;
+use parquet::{
+    basic::Compression,
+    data_type::{FloatType, Int32Type, Int64Type},
+    errors::ParquetError,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+
+use crate::{bench::BenchEvent, whep::Stats};
+
+const SCHEMA: &str = "message stats {
+  required int64 ts (TIMESTAMP(MILLIS,true));
+  required int64 client_id;
+  required int64 send_kbps;
+  required int64 recv_kbps;
+  required int32 rtt_ms;
+  required float loss;
+  optional float mos;
+  required int64 keyframes;
+  required int64 wire_bytes;
+  required int64 payload_bytes;
+}";
+
+/// Samples buffered before a row group is written
+const ROW_GROUP: usize = 100_000;
+
+#[derive(Default)]
+struct Rows {
+    ts: Vec<i64>,
+    client_id: Vec<i64>,
+    send_kbps: Vec<i64>,
+    recv_kbps: Vec<i64>,
+    rtt_ms: Vec<i32>,
+    loss: Vec<f32>,
+    /// Present values only, `mos_def` marks which rows have one
+    mos: Vec<f32>,
+    mos_def: Vec<i16>,
+    keyframes: Vec<i64>,
+    wire_bytes: Vec<i64>,
+    payload_bytes: Vec<i64>,
+}
+
+impl Rows {
+    fn push(&mut self, ts: i64, client_id: usize, stats: &Stats) {
+        self.ts.push(ts);
+        self.client_id.push(client_id as i64);
+        self.send_kbps.push(stats.send_kbps as i64);
+        self.recv_kbps.push(stats.recv_kbps as i64);
+        self.rtt_ms.push(stats.rtt_ms as i32);
+        self.loss.push(stats.lost);
+        self.mos.extend(stats.mos);
+        self.mos_def.push(stats.mos.is_some() as i16);
+        self.keyframes.push(stats.keyframes as i64);
+        self.wire_bytes.push(stats.traffic.wire_bytes as i64);
+        self.payload_bytes.push(stats.traffic.payload_bytes as i64);
+    }
+
+    fn len(&self) -> usize {
+        self.ts.len()
+    }
+
+    /// Write the rows as one row group, columns in schema order
+    fn write(&self, writer: &mut SerializedFileWriter<File>) -> Result<(), ParquetError> {
+        let mut group = writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = group.next_column()? {
+            match index {
+                0 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&self.ts, None, None)?,
+                1 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&self.client_id, None, None)?,
+                2 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&self.send_kbps, None, None)?,
+                3 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&self.recv_kbps, None, None)?,
+                4 => column
+                    .typed::<Int32Type>()
+                    .write_batch(&self.rtt_ms, None, None)?,
+                5 => column
+                    .typed::<FloatType>()
+                    .write_batch(&self.loss, None, None)?,
+                6 => {
+                    column
+                        .typed::<FloatType>()
+                        .write_batch(&self.mos, Some(&self.mos_def), None)?
+                }
+                7 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&self.keyframes, None, None)?,
+                8 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&self.wire_bytes, None, None)?,
+                9 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&self.payload_bytes, None, None)?,
+                _ => unreachable!("schema has 10 columns"),
+            };
+            column.close()?;
+            index += 1;
+        }
+        group.close()?;
+        Ok(())
+    }
+}
+
+fn open(dir: &Path, run_id: &str) -> Result<SerializedFileWriter<File>, ParquetError> {
+    fs::create_dir_all(dir)?;
+    let file = File::create(dir.join(format!("stats-{}.parquet", run_id)))?;
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    SerializedFileWriter::new(file, schema, Arc::new(props))
+}
+
+fn export(dir: &Path, run_id: &str, event_rx: Receiver<BenchEvent>) -> Result<(), ParquetError> {
+    let mut writer = open(dir, run_id)?;
+    let mut rows = Rows::default();
+    while let Ok(event) = event_rx.recv_blocking() {
+        let BenchEvent::Stats(id, stats) = event else {
+            continue;
+        };
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        rows.push(ts, id, &stats);
+        if rows.len() >= ROW_GROUP {
+            rows.write(&mut writer)?;
+            rows = Rows::default();
+        }
+    }
+    if !rows.ts.is_empty() {
+        rows.write(&mut writer)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// Write stats samples into `dir` until the event channel closes, blocks the
+/// calling thread. The file is only readable once its footer was written at the end
+pub fn run_parquet(dir: &Path, run_id: &str, event_rx: Receiver<BenchEvent>) {
+    match export(dir, run_id, event_rx) {
+        Ok(()) => log::info!("[Parquet] wrote stats to {}", dir.display()),
+        Err(e) => log::error!("[Parquet] export failed {:?}", e),
+    }
+}
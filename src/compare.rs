@@ -0,0 +1,123 @@
+use std::{fmt, path::Path};
+
+use serde::Serialize;
+
+use crate::summary::{Percentiles, RunSummary};
+
+/// Change of one percentile between two runs
+#[derive(Debug, Clone, Serialize)]
+pub struct Delta {
+    pub metric: &'static str,
+    pub percentile: &'static str,
+    pub old: f64,
+    pub new: f64,
+    /// Relative change in percent, positive is worse
+    pub worse_pct: f64,
+    pub regressed: bool,
+}
+
+struct Metric {
+    name: &'static str,
+    higher_is_better: bool,
+    /// Values below this are treated as this, so tiny baselines do not explode percentages
+    floor: f64,
+    get: fn(&RunSummary) -> &Percentiles,
+}
+
+const METRICS: [Metric; 4] = [
+    Metric {
+        name: "connect ms",
+        higher_is_better: false,
+        floor: 1.0,
+        get: |s| &s.connect_ms,
+    },
+    Metric {
+        name: "recv kbps",
+        higher_is_better: true,
+        floor: 1.0,
+        get: |s| &s.recv_kbps,
+    },
+    Metric {
+        name: "rtt ms",
+        higher_is_better: false,
+        floor: 1.0,
+        get: |s| &s.rtt_ms,
+    },
+    Metric {
+        name: "loss",
+        higher_is_better: false,
+        floor: 0.001,
+        get: |s| &s.loss,
+    },
+];
+
+/// Parse a tolerance like `10%` or `10` into percent
+pub fn parse_tolerance(value: &str) -> Result<f64, String> {
+    value
+        .trim()
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .map_err(|_| format!("invalid tolerance {}", value))
+}
+
+pub fn load_summary(path: &Path) -> Result<RunSummary, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    serde_json::from_str(&content).map_err(|e| format!("cannot parse {}: {}", path.display(), e))
+}
+
+/// Compare percentiles of two runs, `tolerance` is in percent
+pub fn compare(old: &RunSummary, new: &RunSummary, tolerance: f64) -> Vec<Delta> {
+    let mut deltas = vec![];
+    for metric in METRICS.iter() {
+        let (old_p, new_p) = ((metric.get)(old), (metric.get)(new));
+        for (percentile, old_value, new_value) in [
+            ("p50", old_p.p50, new_p.p50),
+            ("p90", old_p.p90, new_p.p90),
+            ("p99", old_p.p99, new_p.p99),
+        ] {
+            let base = old_value.max(metric.floor);
+            let change = (new_value.max(metric.floor) - base) / base * 100.0;
+            let worse_pct = if metric.higher_is_better {
+                -change
+            } else {
+                change
+            };
+            deltas.push(Delta {
+                metric: metric.name,
+                percentile,
+                old: old_value,
+                new: new_value,
+                worse_pct,
+                regressed: worse_pct > tolerance,
+            });
+        }
+    }
+    deltas
+}
+
+/// Table of deltas, printable with `{}`
+pub struct DeltaTable<'a>(pub &'a [Delta]);
+
+impl fmt::Display for DeltaTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12}{:>6}{:>12}{:>12}{:>10}",
+            "metric", "pct", "old", "new", "worse %"
+        )?;
+        for d in self.0 {
+            writeln!(
+                f,
+                "{:<12}{:>6}{:>12.3}{:>12.3}{:>10.1}{}",
+                d.metric,
+                d.percentile,
+                d.old,
+                d.new,
+                d.worse_pct,
+                if d.regressed { "  REGRESSION" } else { "" }
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+//! Benchmark options from a TOML file. Keys are the long flag names, written with
+//! `-` or `_`, and may be grouped in tables such as `[plan]` or `[thresholds]`,
+//! table names only serve readability. Tables under `[profiles.<name>]` hold named
+//! benchmark definitions which override the shared options when selected. Tables
+//! under `[mix.<name>]` are client profiles, see `--mix`
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+const PROFILES: &str = "profiles";
+const MIX: &str = "mix";
+
+/// Option name with its values, a single one unless the file had an array
+pub type ConfigOption = (String, Vec<String>);
+
+fn value_string(value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(v) => Ok(v.to_string()),
+        toml::Value::Float(v) => Ok(v.to_string()),
+        toml::Value::Boolean(v) => Ok(v.to_string()),
+        toml::Value::Datetime(v) => Ok(v.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => Err("nested value".to_string()),
+    }
+}
+
+fn flatten(table: &toml::Table, out: &mut Vec<ConfigOption>) -> Result<(), String> {
+    for (key, value) in table {
+        let values = match value {
+            toml::Value::Table(table) => {
+                flatten(table, out)?;
+                continue;
+            }
+            toml::Value::Array(items) => items
+                .iter()
+                .map(value_string)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("{}: {}", key, e))?,
+            value => vec![value_string(value)?],
+        };
+        out.push((key.replace('-', "_"), values));
+    }
+    Ok(())
+}
+
+/// Client profiles of the `mix` table as `--mix` values, `<name>:<key>=<value>,..`
+fn take_mix(table: &mut toml::Table, out: &mut Vec<ConfigOption>) -> Result<(), String> {
+    let mix = match table.remove(MIX) {
+        Some(toml::Value::Table(mix)) => mix,
+        Some(_) => return Err(format!("{} must be a table", MIX)),
+        None => return Ok(()),
+    };
+    let mut profiles = vec![];
+    for (name, profile) in &mix {
+        let toml::Value::Table(profile) = profile else {
+            return Err(format!("{}.{} must be a table", MIX, name));
+        };
+        let params = profile
+            .iter()
+            .map(|(key, value)| Ok(format!("{}={}", key, value_string(value)?)))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|e| format!("{}.{}: {}", MIX, name, e))?;
+        profiles.push(format!("{}:{}", name, params.join(",")));
+    }
+    out.push((MIX.to_string(), profiles));
+    Ok(())
+}
+
+/// Read the shared options of the file and those of `profile`, keys normalized
+/// to snake case
+pub fn load(path: &Path, profile: Option<&str>) -> Result<Vec<ConfigOption>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read config {}: {}", path.display(), e))?;
+    let mut table: toml::Table = text
+        .parse()
+        .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+    let profiles = match table.remove(PROFILES) {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => return Err(format!("{} must be a table", PROFILES)),
+        None => toml::Table::new(),
+    };
+    let mut options = vec![];
+    take_mix(&mut table, &mut options)?;
+    flatten(&table, &mut options)?;
+    if let Some(name) = profile {
+        let Some(toml::Value::Table(mut selected)) = profiles.get(name).cloned() else {
+            let names: Vec<&str> = profiles.keys().map(|k| k.as_str()).collect();
+            return Err(format!(
+                "unknown profile {}, config has: {}",
+                name,
+                names.join(", ")
+            ));
+        };
+        take_mix(&mut selected, &mut options)?;
+        flatten(&selected, &mut options)?;
+    }
+    // later options, i.e. those of the profile, win
+    let merged: BTreeMap<String, Vec<String>> = options.into_iter().collect();
+    Ok(merged.into_iter().collect())
+}
@@ -0,0 +1,223 @@
+//! Received media validated against what the published stream should be, e.g.
+//! `video=2500kbps,audio=64kbps,fps=30`. Every stats sample of a client counts
+//! as conforming per dimension when it is within the tolerance of the expected
+//! value, the summary reports the share of conforming samples per client
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{media::StreamKind, whep::Stats};
+
+const DEFAULT_TOLERANCE: f64 = 0.1;
+
+/// Expected bitrates and frame rate, parsed from `video=2500kbps,audio=64kbps,fps=30`
+/// with an optional `tolerance=10%`, 10% by default. Bitrates take `kbps` or `mbps`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Expectation {
+    pub video_kbps: Option<u64>,
+    pub audio_kbps: Option<u64>,
+    pub fps: Option<f32>,
+    /// Allowed deviation in both directions as a share of the expected value
+    pub tolerance: f64,
+}
+
+fn parse_kbps(value: &str) -> Option<u64> {
+    let value = value.to_ascii_lowercase();
+    if let Some(mbps) = value.strip_suffix("mbps") {
+        return mbps.trim().parse::<f64>().ok().map(|m| (m * 1000.0) as u64);
+    }
+    value
+        .strip_suffix("kbps")
+        .unwrap_or(&value)
+        .trim()
+        .parse()
+        .ok()
+}
+
+impl FromStr for Expectation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |part: &str| {
+            format!(
+                "invalid expectation {}, expected e.g. `video=2500kbps,audio=64kbps,fps=30`",
+                part
+            )
+        };
+        let mut expectation = Self {
+            video_kbps: None,
+            audio_kbps: None,
+            fps: None,
+            tolerance: DEFAULT_TOLERANCE,
+        };
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (name, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+            let value = value.trim();
+            match name.trim() {
+                "video" => {
+                    expectation.video_kbps = Some(parse_kbps(value).ok_or_else(|| invalid(part))?)
+                }
+                "audio" => {
+                    expectation.audio_kbps = Some(parse_kbps(value).ok_or_else(|| invalid(part))?)
+                }
+                "fps" => expectation.fps = Some(value.parse().map_err(|_| invalid(part))?),
+                "tolerance" => {
+                    let percent: f64 = value
+                        .trim_end_matches('%')
+                        .parse()
+                        .map_err(|_| invalid(part))?;
+                    if !(0.0..100.0).contains(&percent) {
+                        return Err(invalid(part));
+                    }
+                    expectation.tolerance = percent / 100.0;
+                }
+                _ => return Err(invalid(part)),
+            }
+        }
+        if expectation.video_kbps.is_none()
+            && expectation.audio_kbps.is_none()
+            && expectation.fps.is_none()
+        {
+            return Err(invalid(s));
+        }
+        Ok(expectation)
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(kbps) = self.video_kbps {
+            parts.push(format!("video={}kbps", kbps));
+        }
+        if let Some(kbps) = self.audio_kbps {
+            parts.push(format!("audio={}kbps", kbps));
+        }
+        if let Some(fps) = self.fps {
+            parts.push(format!("fps={}", fps));
+        }
+        parts.push(format!("tolerance={}%", self.tolerance * 100.0));
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+impl Expectation {
+    fn conforms(&self, expected: f64, actual: f64) -> bool {
+        (actual - expected).abs() <= expected * self.tolerance
+    }
+}
+
+/// Conforming samples of one client
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tracker {
+    samples: u64,
+    video: u64,
+    audio: u64,
+    fps: u64,
+    all: u64,
+}
+
+impl Tracker {
+    pub fn on_stats(&mut self, expectation: &Expectation, stats: &Stats) {
+        let kbps = |kind: StreamKind| -> u64 {
+            stats
+                .streams
+                .iter()
+                .filter(|s| s.kind == Some(kind))
+                .map(|s| s.recv_kbps)
+                .sum()
+        };
+        let video = expectation
+            .video_kbps
+            .map(|expected| expectation.conforms(expected as f64, kbps(StreamKind::Video) as f64));
+        let audio = expectation
+            .audio_kbps
+            .map(|expected| expectation.conforms(expected as f64, kbps(StreamKind::Audio) as f64));
+        let fps = expectation
+            .fps
+            .map(|expected| expectation.conforms(expected as f64, stats.video_fps as f64));
+        self.samples += 1;
+        self.video += video.unwrap_or(false) as u64;
+        self.audio += audio.unwrap_or(false) as u64;
+        self.fps += fps.unwrap_or(false) as u64;
+        self.all += [video, audio, fps].iter().all(|ok| ok.unwrap_or(true)) as u64;
+    }
+
+    pub fn conformance(&self, expectation: &Expectation) -> Conformance {
+        let percent = |count: u64| {
+            if self.samples == 0 {
+                0.0
+            } else {
+                count as f64 * 100.0 / self.samples as f64
+            }
+        };
+        Conformance {
+            samples: self.samples,
+            video: expectation.video_kbps.map(|_| percent(self.video)),
+            audio: expectation.audio_kbps.map(|_| percent(self.audio)),
+            fps: expectation.fps.map(|_| percent(self.fps)),
+            overall: percent(self.all),
+        }
+    }
+}
+
+/// Percent of the samples of a client which conformed, None for dimensions
+/// without expectation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Conformance {
+    pub samples: u64,
+    pub video: Option<f64>,
+    pub audio: Option<f64>,
+    pub fps: Option<f64>,
+    /// Samples which conformed in every dimension
+    pub overall: f64,
+}
+
+/// Conformance of every client which sent stats
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConformanceSummary {
+    pub expected: String,
+    pub clients: BTreeMap<usize, Conformance>,
+}
+
+impl ConformanceSummary {
+    /// Average overall conformance across the clients
+    pub fn average(&self) -> f64 {
+        if self.clients.is_empty() {
+            return 0.0;
+        }
+        self.clients.values().map(|c| c.overall).sum::<f64>() / self.clients.len() as f64
+    }
+
+    /// Clients with the lowest overall conformance first
+    pub fn worst(&self, count: usize) -> Vec<(usize, &Conformance)> {
+        let mut clients: Vec<(usize, &Conformance)> =
+            self.clients.iter().map(|(id, c)| (*id, c)).collect();
+        clients.sort_by(|a, b| a.1.overall.total_cmp(&b.1.overall));
+        clients.truncate(count);
+        clients
+    }
+}
+
+impl fmt::Display for ConformanceSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "conformance to {}: {:.1}% of samples on average over {} clients",
+            self.expected,
+            self.average(),
+            self.clients.len()
+        )?;
+        let worst: Vec<String> = self
+            .worst(5)
+            .into_iter()
+            .filter(|(_, c)| c.overall < 100.0)
+            .map(|(id, c)| format!("{} ({:.1}%)", id, c.overall))
+            .collect();
+        if !worst.is_empty() {
+            write!(f, ", worst clients: {}", worst.join(", "))?;
+        }
+        Ok(())
+    }
+}
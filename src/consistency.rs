@@ -0,0 +1,90 @@
+//! Negotiated parameters of the answers compared across the clients of a run.
+//! A consistent server answers every client alike, divergence under load points
+//! at nondeterminism like a fallback codec or a dropped extension
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::sdp::AnswerSummary;
+
+/// Parameters of an answer which should be the same for every client
+pub fn parameters(answer: &AnswerSummary) -> BTreeMap<&'static str, String> {
+    BTreeMap::from([
+        ("codecs", answer.codecs.join(", ")),
+        ("extensions", answer.extensions.join(", ")),
+        ("directions", answer.directions.join(", ")),
+        (
+            "ice",
+            if answer.ice_lite { "lite" } else { "full" }.to_string(),
+        ),
+    ])
+}
+
+/// Parameter which differed between clients
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Divergence {
+    pub parameter: String,
+    /// Clients per value
+    pub variants: BTreeMap<String, usize>,
+    /// Ids of the clients whose value differs from the most common one
+    pub outliers: Vec<usize>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  {} differ:", self.parameter)?;
+        for (value, count) in &self.variants {
+            let value = if value.is_empty() { "none" } else { value };
+            writeln!(f, "    {} clients: {}", count, value)?;
+        }
+        let ids: Vec<String> = self
+            .outliers
+            .iter()
+            .take(20)
+            .map(|id| id.to_string())
+            .collect();
+        let more = self.outliers.len().saturating_sub(ids.len());
+        write!(f, "    inconsistent clients: {}", ids.join(", "))?;
+        if more > 0 {
+            write!(f, " and {} more", more)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parameters with more than one value across the clients, by parameter name
+pub fn divergences<'a>(
+    answers: impl Iterator<Item = (usize, &'a BTreeMap<&'static str, String>)>,
+) -> Vec<Divergence> {
+    let mut values: BTreeMap<&str, Vec<(usize, &str)>> = BTreeMap::new();
+    for (id, parameters) in answers {
+        for (parameter, value) in parameters {
+            values.entry(parameter).or_default().push((id, value));
+        }
+    }
+    values
+        .into_iter()
+        .filter_map(|(parameter, clients)| {
+            let mut variants: BTreeMap<String, usize> = BTreeMap::new();
+            for (_, value) in &clients {
+                *variants.entry(value.to_string()).or_default() += 1;
+            }
+            if variants.len() < 2 {
+                return None;
+            }
+            let (common, _) = variants.iter().max_by_key(|(_, count)| **count)?;
+            let mut outliers: Vec<usize> = clients
+                .iter()
+                .filter(|(_, value)| *value != common.as_str())
+                .map(|(id, _)| *id)
+                .collect();
+            outliers.sort();
+            Some(Divergence {
+                parameter: parameter.to_string(),
+                variants,
+                outliers,
+            })
+        })
+        .collect()
+}
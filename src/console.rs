@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use async_std::{
+    channel::{Receiver, Sender},
+    io::{prelude::BufReadExt, BufReader},
+    stream::StreamExt,
+};
+use parking_lot::Mutex;
+
+use crate::{
+    bench::{BenchControl, BenchEvent},
+    collector::Collector,
+};
+
+const HELP: &str =
+    "commands: add <n>, kill <n>, pause [hold], resume, keyframe <n>, stats, stop, help";
+
+enum Command {
+    Control(BenchControl),
+    Stats,
+    Help,
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let mut parts = line.split_whitespace();
+    let cmd = parts.next().unwrap_or_default();
+    let mut count = || -> Result<usize, String> {
+        parts
+            .next()
+            .ok_or(format!("{} requires a number", cmd))?
+            .parse::<usize>()
+            .map_err(|e| format!("invalid number: {}", e))
+    };
+    match cmd {
+        "add" => Ok(Command::Control(BenchControl::Spawn(count()?))),
+        "kill" => Ok(Command::Control(BenchControl::Kill(count()?))),
+        "stop" => Ok(Command::Control(BenchControl::Stop)),
+        "pause" => match parts.next() {
+            None => Ok(Command::Control(BenchControl::Pause { hold: false })),
+            Some("hold") => Ok(Command::Control(BenchControl::Pause { hold: true })),
+            Some(arg) => Err(format!("unknown pause argument '{}'", arg)),
+        },
+        "resume" => Ok(Command::Control(BenchControl::Resume)),
+        "keyframe" => Ok(Command::Control(BenchControl::RequestKeyframe(count()?))),
+        "stats" => Ok(Command::Stats),
+        "help" => Ok(Command::Help),
+        _ => Err(format!("unknown command '{}'", cmd)),
+    }
+}
+
+/// Read line commands from stdin and forward them to the runner
+pub async fn run_console(control_tx: Sender<BenchControl>, event_rx: Receiver<BenchEvent>) {
+    let collector = Arc::new(Mutex::new(Collector::default()));
+    let pump = collector.clone();
+    async_std::task::spawn(async move {
+        while let Ok(event) = event_rx.recv().await {
+            pump.lock().on_event(event);
+        }
+    });
+
+    println!("{}", HELP);
+    let mut lines = BufReader::new(async_std::io::stdin()).lines();
+    while let Some(Ok(line)) = lines.next().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_command(&line) {
+            Ok(Command::Control(control)) => {
+                if control_tx.send(control).await.is_err() {
+                    println!("runner is not running");
+                    break;
+                }
+            }
+            Ok(Command::Stats) => {
+                let collector = collector.lock();
+                println!(
+                    "clients {}, connected {}, failed {}, send {} kbps, recv {} kbps, avg rtt {} ms",
+                    collector.clients_sum(),
+                    collector.clients_connected(),
+                    collector.clients_failed(),
+                    collector.sum_send_kbps(),
+                    collector.sum_recv_kbps(),
+                    collector.avg_rtt_ms()
+                );
+                let failures = collector.failure_summary();
+                if !failures.is_empty() {
+                    println!("failures: {}", failures);
+                }
+            }
+            Ok(Command::Help) => println!("{}", HELP),
+            Err(e) => println!("{}, {}", e, HELP),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Downsampling of the per-client stats for very large runs. Thousands of clients
+//! reporting every 2 seconds drown the event channel, the UI and the exports, so
+//! each client rolls its samples up before emitting them. Rates are averaged
+//! over the rollup, counters and gauges are the latest ones
+
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{watchdog::parse_duration, whep::Stats};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Decimation {
+    /// One rollup of every this many samples
+    Every(u32),
+    /// One rollup per window
+    Window(Duration),
+}
+
+impl FromStr for Decimation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || {
+            format!(
+                "invalid stats decimation {}, expected e.g. `every=5` or `window=10s`",
+                s
+            )
+        };
+        let (kind, value) = s.split_once('=').ok_or_else(usage)?;
+        match kind.trim() {
+            "every" => match value.trim().parse() {
+                Ok(n) if n > 0 => Ok(Decimation::Every(n)),
+                _ => Err(usage()),
+            },
+            "window" => parse_duration(value.trim())
+                .filter(|window| !window.is_zero())
+                .map(Decimation::Window)
+                .ok_or_else(usage),
+            _ => Err(usage()),
+        }
+    }
+}
+
+impl fmt::Display for Decimation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decimation::Every(n) => write!(f, "every {} samples", n),
+            Decimation::Window(window) => write!(f, "every {}s", window.as_secs_f64()),
+        }
+    }
+}
+
+/// Sums of the averaged rates
+#[derive(Debug, Default)]
+struct Sums {
+    send_kbps: u64,
+    recv_kbps: u64,
+    rtt_ms: u64,
+    lost: f64,
+    audio_pps: u64,
+    video_pps: u64,
+    video_fps: f64,
+    mos: f64,
+    mos_samples: u32,
+}
+
+/// Rollup of the stats of one client, kept across its sessions
+#[derive(Debug)]
+pub struct StatsRollup {
+    decimation: Decimation,
+    /// The first sample of a client is emitted as is, so it shows up right away
+    emitted: bool,
+    started_at: Option<Instant>,
+    samples: u32,
+    sums: Sums,
+    /// Latest sample, the changes of the earlier ones are moved into it
+    latest: Option<Stats>,
+}
+
+impl StatsRollup {
+    pub fn new(decimation: Decimation) -> Self {
+        Self {
+            decimation,
+            emitted: false,
+            started_at: None,
+            samples: 0,
+            sums: Sums::default(),
+            latest: None,
+        }
+    }
+
+    pub fn decimation(&self) -> Decimation {
+        self.decimation
+    }
+
+    /// Add a sample, returns the rollup when it is due
+    pub fn on_stats(&mut self, stats: Stats, now: Instant) -> Option<Stats> {
+        if !self.emitted {
+            self.emitted = true;
+            return Some(stats);
+        }
+        let started_at = *self.started_at.get_or_insert(now);
+        self.add(stats);
+        let due = match self.decimation {
+            Decimation::Every(n) => self.samples >= n,
+            Decimation::Window(window) => now.saturating_duration_since(started_at) >= window,
+        };
+        if due {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    fn add(&mut self, mut stats: Stats) {
+        self.samples += 1;
+        let sums = &mut self.sums;
+        sums.send_kbps += stats.send_kbps;
+        sums.recv_kbps += stats.recv_kbps;
+        sums.rtt_ms += stats.rtt_ms as u64;
+        sums.lost += stats.lost as f64;
+        sums.audio_pps += stats.audio_pps;
+        sums.video_pps += stats.video_pps;
+        sums.video_fps += stats.video_fps as f64;
+        if let Some(mos) = stats.mos {
+            sums.mos += mos as f64;
+            sums.mos_samples += 1;
+        }
+        // changes are reported since the previous sample, none may get lost
+        if let Some(latest) = self.latest.take() {
+            let mut resolution_changes = latest.resolution_changes;
+            resolution_changes.append(&mut stats.resolution_changes);
+            stats.resolution_changes = resolution_changes;
+            let mut playout_delay_changes = latest.playout_delay_changes;
+            playout_delay_changes.append(&mut stats.playout_delay_changes);
+            stats.playout_delay_changes = playout_delay_changes;
+            let mut timeline = latest.timeline;
+            timeline.append(&mut stats.timeline);
+            stats.timeline = timeline;
+        }
+        self.latest = Some(stats);
+    }
+
+    /// The rollup of the samples so far, None without any
+    pub fn flush(&mut self) -> Option<Stats> {
+        let mut stats = self.latest.take()?;
+        let sums = std::mem::take(&mut self.sums);
+        let n = self.samples.max(1) as u64;
+        stats.send_kbps = sums.send_kbps / n;
+        stats.recv_kbps = sums.recv_kbps / n;
+        stats.rtt_ms = (sums.rtt_ms / n) as u32;
+        stats.lost = (sums.lost / n as f64) as f32;
+        stats.audio_pps = sums.audio_pps / n;
+        stats.video_pps = sums.video_pps / n;
+        stats.video_fps = (sums.video_fps / n as f64) as f32;
+        stats.mos = (sums.mos_samples > 0).then(|| (sums.mos / sums.mos_samples as f64) as f32);
+        self.samples = 0;
+        self.started_at = None;
+        Some(stats)
+    }
+}
@@ -0,0 +1,57 @@
+//! Artificial latency for emulating distant viewers, so the session setup timers
+//! of the server see realistic gaps between the requests of a client
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::watchdog::parse_duration;
+
+/// Delay drawn uniformly from a range, parsed from e.g. `0-2000ms`, `1s-3s` or a
+/// fixed `500ms`. A unit after the end applies to a bare start
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DelayRange {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl DelayRange {
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        if self.max <= self.min {
+            return self.min;
+        }
+        rng.gen_range(self.min..=self.max)
+    }
+}
+
+impl FromStr for DelayRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("invalid delay {}, expected e.g. `0-2000ms` or `500ms`", s);
+        let (min, max) = s.split_once('-').unwrap_or((s, s));
+        let (min, max) = (min.trim(), max.trim());
+        let max = parse_duration(max).ok_or_else(usage)?;
+        let min = if !min.is_empty() && min.chars().all(|c| c.is_ascii_digit()) {
+            let unit = s.trim_end_matches(|c: char| !c.is_ascii_digit());
+            parse_duration(&format!("{}{}", min, &s[unit.len()..])).ok_or_else(usage)?
+        } else {
+            parse_duration(min).ok_or_else(usage)?
+        };
+        if min > max {
+            return Err(usage());
+        }
+        Ok(Self { min, max })
+    }
+}
+
+impl fmt::Display for DelayRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}ms", self.min.as_millis())
+        } else {
+            write!(f, "{}-{}ms", self.min.as_millis(), self.max.as_millis())
+        }
+    }
+}
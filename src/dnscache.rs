@@ -0,0 +1,85 @@
+//! Lookups of signaling hosts shared by all clients of the process. At high join
+//! rates every client would otherwise resolve the same host again, concurrent
+//! lookups of a host wait for the same one and its addresses are kept for a ttl.
+//! Failed lookups are not cached
+
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use parking_lot::Mutex;
+
+type Resolving = Shared<BoxFuture<'static, Result<Arc<Vec<IpAddr>>, String>>>;
+
+enum Entry {
+    Resolved {
+        addrs: Arc<Vec<IpAddr>>,
+        expires_at: Instant,
+    },
+    Resolving(Resolving),
+}
+
+static CACHE: Mutex<BTreeMap<String, Entry>> = parking_lot::const_mutex(BTreeMap::new());
+
+/// Addresses of a host, `cached` when they came from the cache or a lookup
+/// another client started
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    pub addrs: Vec<IpAddr>,
+    pub cached: bool,
+}
+
+async fn resolve(host: String) -> Result<Arc<Vec<IpAddr>>, String> {
+    let addrs = async_std::net::ToSocketAddrs::to_socket_addrs(&(host.as_str(), 0))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Arc::new(addrs.map(|addr| addr.ip()).collect()))
+}
+
+/// Addresses of `host`, looked up when there are none younger than `ttl`
+pub async fn lookup(host: &str, ttl: Duration) -> Result<Lookup, String> {
+    let (resolving, cached) = {
+        let mut cache = CACHE.lock();
+        match cache.get(host) {
+            Some(Entry::Resolved { addrs, expires_at }) if *expires_at > Instant::now() => {
+                return Ok(Lookup {
+                    addrs: addrs.to_vec(),
+                    cached: true,
+                });
+            }
+            Some(Entry::Resolving(resolving)) => (resolving.clone(), true),
+            _ => {
+                let resolving = resolve(host.to_string()).boxed().shared();
+                cache.insert(host.to_string(), Entry::Resolving(resolving.clone()));
+                (resolving, false)
+            }
+        }
+    };
+    let result = resolving.clone().await;
+    // whoever finishes first stores the result, the starter may have been dropped
+    let mut cache = CACHE.lock();
+    if matches!(cache.get(host), Some(Entry::Resolving(current)) if current.ptr_eq(&resolving)) {
+        match &result {
+            Ok(addrs) => {
+                cache.insert(
+                    host.to_string(),
+                    Entry::Resolved {
+                        addrs: addrs.clone(),
+                        expires_at: Instant::now() + ttl,
+                    },
+                );
+            }
+            Err(_) => {
+                cache.remove(host);
+            }
+        }
+    }
+    result.map(|addrs| Lookup {
+        addrs: addrs.to_vec(),
+        cached,
+    })
+}
@@ -0,0 +1,52 @@
+//! Downlink cap of a throttled client. Datagrams above the rate are dropped before
+//! they reach the WebRTC stack, like on a congested last mile. STUN always passes
+//! so the session stays up
+
+use std::time::{Duration, Instant};
+
+/// Traffic above the rate passes for this long before drops start
+const BURST: Duration = Duration::from_millis(100);
+/// At least one full datagram fits into the bucket
+const MIN_CAPACITY: f64 = 1500.0;
+
+/// Token bucket in bytes
+#[derive(Debug)]
+pub struct DownlinkLimiter {
+    bytes_per_sec: f64,
+    capacity: f64,
+    tokens: f64,
+    at: Instant,
+    dropped: u64,
+}
+
+impl DownlinkLimiter {
+    pub fn new(kbps: u64, now: Instant) -> Self {
+        let bytes_per_sec = kbps as f64 * 1000.0 / 8.0;
+        let capacity = (bytes_per_sec * BURST.as_secs_f64()).max(MIN_CAPACITY);
+        Self {
+            bytes_per_sec,
+            capacity,
+            tokens: capacity,
+            at: now,
+            dropped: 0,
+        }
+    }
+
+    /// Whether a datagram of `len` bytes passes
+    pub fn admit(&mut self, len: usize, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.at).as_secs_f64();
+        self.at = now;
+        self.tokens = (self.tokens + elapsed * self.bytes_per_sec).min(self.capacity);
+        if self.tokens < len as f64 {
+            self.dropped += 1;
+            return false;
+        }
+        self.tokens -= len as f64;
+        true
+    }
+
+    /// Datagrams dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
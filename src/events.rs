@@ -0,0 +1,49 @@
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_std::channel::Receiver;
+use serde::Serialize;
+
+use crate::bench::BenchEvent;
+
+/// One line of the event stream
+#[derive(Debug, Serialize)]
+pub struct EventLine<'a> {
+    /// Unix time in miliseconds
+    pub ts: u64,
+    pub client_id: usize,
+    pub event: &'a BenchEvent,
+}
+
+fn write_line<W: Write>(out: &mut W, event: &BenchEvent) -> io::Result<()> {
+    let line = EventLine {
+        ts: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        client_id: event.client_id(),
+        event,
+    };
+    serde_json::to_writer(&mut *out, &line)?;
+    out.write_all(b"\n")
+}
+
+/// Write every event as a JSON object per line, blocks the calling thread.
+/// Output is flushed whenever the channel runs empty so pipelines see events live
+pub fn run_ndjson<W: Write>(out: W, event_rx: Receiver<BenchEvent>) {
+    let mut out = io::BufWriter::new(out);
+    while let Ok(event) = event_rx.recv_blocking() {
+        let mut res = write_line(&mut out, &event);
+        if res.is_ok() && event_rx.is_empty() {
+            res = out.flush();
+        }
+        if let Err(e) = res {
+            // a closed pipe, e.g. `| head`, ends the stream
+            log::warn!("[Events] write failed {:?}", e);
+            return;
+        }
+    }
+    let _ = out.flush();
+}
@@ -0,0 +1,268 @@
+//! Pass/fail gates over the run metrics, e.g. `p95(connect_ms) < 2000`, checked
+//! against the summary collector at the end of the run or each second during it
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{sketch::QuantileSketch, summary::SummaryCollector};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GateMetric {
+    /// One sample per join, from the timing sketches
+    ConnectMs,
+    RecoverMs,
+    DnsMs,
+    PostTtfbMs,
+    PostMs,
+    DeleteMs,
+    QueueMs,
+    /// One sample per client
+    SignalingMs,
+    IceMs,
+    DtlsMs,
+    RecvKbps,
+    PayloadKbps,
+    RttMs,
+    Loss,
+    Mos,
+    /// 1 for a failed or connected client, 0 otherwise, `avg` gives the share
+    Failed,
+    Connected,
+}
+
+impl GateMetric {
+    pub const NAMES: [(&'static str, GateMetric); 17] = [
+        ("connect_ms", GateMetric::ConnectMs),
+        ("recover_ms", GateMetric::RecoverMs),
+        ("dns_ms", GateMetric::DnsMs),
+        ("post_ttfb_ms", GateMetric::PostTtfbMs),
+        ("post_ms", GateMetric::PostMs),
+        ("delete_ms", GateMetric::DeleteMs),
+        ("queue_ms", GateMetric::QueueMs),
+        ("signaling_ms", GateMetric::SignalingMs),
+        ("ice_ms", GateMetric::IceMs),
+        ("dtls_ms", GateMetric::DtlsMs),
+        ("recv_kbps", GateMetric::RecvKbps),
+        ("payload_kbps", GateMetric::PayloadKbps),
+        ("rtt_ms", GateMetric::RttMs),
+        ("loss", GateMetric::Loss),
+        ("mos", GateMetric::Mos),
+        ("failed", GateMetric::Failed),
+        ("connected", GateMetric::Connected),
+    ];
+
+    fn name(&self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, m)| m == self)
+            .map(|(name, _)| *name)
+            .expect("should have a name")
+    }
+}
+
+/// Samples of a metric, kept exactly or as a sketch
+pub enum Distribution {
+    Values(Vec<f64>),
+    Sketch(QuantileSketch),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Aggregate {
+    Avg,
+    Min,
+    Max,
+    Count,
+    /// Percentile from 0 to 100
+    Percentile(f64),
+}
+
+impl Distribution {
+    /// None without samples, except for `count`
+    fn aggregate(&self, aggregate: Aggregate) -> Option<f64> {
+        match (self, aggregate) {
+            (Distribution::Values(values), Aggregate::Count) => Some(values.len() as f64),
+            (Distribution::Sketch(sketch), Aggregate::Count) => Some(sketch.count() as f64),
+            (Distribution::Values(values), _) if values.is_empty() => None,
+            (Distribution::Sketch(sketch), _) if sketch.count() == 0 => None,
+            (Distribution::Values(values), Aggregate::Avg) => {
+                Some(values.iter().sum::<f64>() / values.len() as f64)
+            }
+            (Distribution::Values(values), Aggregate::Min) => {
+                values.iter().copied().reduce(f64::min)
+            }
+            (Distribution::Values(values), Aggregate::Max) => {
+                values.iter().copied().reduce(f64::max)
+            }
+            (Distribution::Values(values), Aggregate::Percentile(p)) => {
+                let mut values = values.clone();
+                values.sort_by(|a, b| a.total_cmp(b));
+                let rank = ((values.len() - 1) as f64 * p / 100.0).round() as usize;
+                Some(values[rank])
+            }
+            (Distribution::Sketch(sketch), Aggregate::Avg) => Some(sketch.mean()),
+            (Distribution::Sketch(sketch), Aggregate::Min) => Some(sketch.quantile(0.0)),
+            (Distribution::Sketch(sketch), Aggregate::Max) => Some(sketch.quantile(1.0)),
+            (Distribution::Sketch(sketch), Aggregate::Percentile(p)) => {
+                Some(sketch.quantile(p / 100.0))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregate::Avg => write!(f, "avg"),
+            Aggregate::Min => write!(f, "min"),
+            Aggregate::Max => write!(f, "max"),
+            Aggregate::Count => write!(f, "count"),
+            Aggregate::Percentile(p) => write!(f, "p{}", p),
+        }
+    }
+}
+
+/// `<aggregate>(<metric>) <op> <threshold>`, e.g. `p95(connect_ms) < 2000` or
+/// `avg(failed) <= 0.01`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Gate {
+    pub aggregate: Aggregate,
+    pub metric: GateMetric,
+    pub op: Op,
+    pub threshold: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op {
+    Lt,
+    Le,
+    Eq,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    pub(crate) const SYMBOLS: [(&'static str, Op); 5] = [
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("==", Op::Eq),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ];
+
+    pub(crate) fn symbol(&self) -> &'static str {
+        Self::SYMBOLS
+            .iter()
+            .find(|(_, op)| op == self)
+            .map(|(symbol, _)| *symbol)
+            .expect("should have a symbol")
+    }
+
+    pub fn holds(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Op::Lt => value < threshold,
+            Op::Le => value <= threshold,
+            Op::Eq => value == threshold,
+            Op::Gt => value > threshold,
+            Op::Ge => value >= threshold,
+        }
+    }
+}
+
+impl FromStr for Gate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("invalid gate {}, expected e.g. `p95(connect_ms) < 2000`", s);
+        let (aggregate, rest) = s.trim().split_once('(').ok_or_else(usage)?;
+        let (metric, condition) = rest.split_once(')').ok_or_else(usage)?;
+        let aggregate = match aggregate.trim() {
+            "avg" => Aggregate::Avg,
+            "min" => Aggregate::Min,
+            "max" => Aggregate::Max,
+            "count" => Aggregate::Count,
+            other => match other.strip_prefix('p').map(str::parse::<f64>) {
+                Some(Ok(p)) if (0.0..=100.0).contains(&p) => Aggregate::Percentile(p),
+                _ => {
+                    return Err(format!(
+                        "unknown aggregate {}, expected avg, min, max, count or p<0-100>",
+                        other
+                    ))
+                }
+            },
+        };
+        let metric = GateMetric::NAMES
+            .iter()
+            .find(|(name, _)| *name == metric.trim())
+            .map(|(_, m)| *m)
+            .ok_or_else(|| {
+                let names: Vec<&str> = GateMetric::NAMES.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "unknown metric {}, expected one of {}",
+                    metric.trim(),
+                    names.join(", ")
+                )
+            })?;
+        let condition = condition.trim();
+        let (op, threshold) = Op::SYMBOLS
+            .iter()
+            .find_map(|(symbol, op)| Some((*op, condition.strip_prefix(symbol)?)))
+            .ok_or_else(usage)?;
+        Ok(Gate {
+            aggregate,
+            metric,
+            op,
+            threshold: threshold.trim().parse().map_err(|_| usage())?,
+        })
+    }
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}({}) {} {}",
+            self.aggregate,
+            self.metric.name(),
+            self.op.symbol(),
+            self.threshold
+        )
+    }
+}
+
+/// Outcome of one gate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GateResult {
+    pub gate: String,
+    /// None when the metric had no samples, which fails the gate
+    pub value: Option<f64>,
+    pub passed: bool,
+}
+
+impl fmt::Display for GateResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self.value {
+            Some(value) => format!("{:.3}", value),
+            None => "no samples".to_string(),
+        };
+        write!(
+            f,
+            "gate `{}`: {} {}",
+            self.gate,
+            value,
+            if self.passed { "passed" } else { "FAILED" }
+        )
+    }
+}
+
+impl Gate {
+    pub fn evaluate(&self, summary: &SummaryCollector) -> GateResult {
+        let value = summary.distribution(self.metric).aggregate(self.aggregate);
+        let passed = value.map_or(false, |v| self.op.holds(v, self.threshold));
+        GateResult {
+            gate: self.to_string(),
+            value,
+            passed,
+        }
+    }
+}
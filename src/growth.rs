@@ -0,0 +1,144 @@
+//! Memory growth of the benchmark process. Subsystems report gauges of what they
+//! hold, clients, buffered samples and queued events, next to the memory of the
+//! process. A gauge which keeps growing over the whole window without leveling
+//! off is reported, so a long run dying by OOM leaves a clue which part grew
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::selfmon;
+
+/// Gauges are recorded once per interval
+pub const INTERVAL: Duration = Duration::from_secs(60);
+/// Samples a gauge has to keep growing over before it is reported
+pub const WINDOW: usize = 30;
+/// Growth over the window below this ratio is not reported
+const MIN_RATIO: f64 = 1.2;
+
+static GAUGES: Mutex<BTreeMap<String, u64>> = parking_lot::const_mutex(BTreeMap::new());
+
+/// Report the current value of a gauge, read at the next interval
+pub fn gauge(name: &str, value: u64) {
+    let mut gauges = GAUGES.lock();
+    match gauges.get_mut(name) {
+        Some(current) => *current = value,
+        None => {
+            gauges.insert(name.to_string(), value);
+        }
+    }
+}
+
+/// A gauge which grew over a whole window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrowthWarning {
+    pub gauge: String,
+    pub from: u64,
+    pub to: u64,
+    pub window_secs: u64,
+    /// Since the start of the run
+    pub at_ms: u64,
+}
+
+impl fmt::Display for GrowthWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} grew from {} to {} over {} min without leveling off",
+            self.gauge,
+            self.from,
+            self.to,
+            self.window_secs / 60
+        )
+    }
+}
+
+/// Growth without a plateau: every sample of the later half of the window is
+/// above every sample of the earlier half, and the window ends well above its start
+fn unbounded(samples: &VecDeque<u64>) -> bool {
+    if samples.len() < WINDOW {
+        return false;
+    }
+    let (first, last) = (samples[0], samples[samples.len() - 1]);
+    let half = samples.len() / 2;
+    let earlier_max = samples.iter().take(half).max().copied().unwrap_or(0);
+    let later_min = samples.iter().skip(half).min().copied().unwrap_or(0);
+    later_min > earlier_max && last as f64 >= first.max(1) as f64 * MIN_RATIO
+}
+
+/// History of the gauges over the last window, call `sample` about once per second
+#[derive(Debug)]
+pub struct GrowthMonitor {
+    started_at: Instant,
+    next_at: Instant,
+    history: BTreeMap<String, VecDeque<u64>>,
+    /// Gauges reported and still growing, warned again once they leveled off
+    growing: BTreeMap<String, bool>,
+    warnings: Vec<GrowthWarning>,
+}
+
+impl Default for GrowthMonitor {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            started_at: now,
+            next_at: now,
+            history: BTreeMap::new(),
+            growing: BTreeMap::new(),
+            warnings: vec![],
+        }
+    }
+}
+
+impl GrowthMonitor {
+    pub fn sample(&mut self, now: Instant) {
+        if now < self.next_at {
+            return;
+        }
+        self.next_at = now + INTERVAL;
+        let mut gauges = GAUGES.lock().clone();
+        if let Some(rss_kb) = selfmon::status_kb("VmRSS:") {
+            gauges.insert("process.rss_kb".to_string(), rss_kb);
+        }
+        // heap and anonymous mappings, the allocator is not instrumented
+        if let Some(data_kb) = selfmon::status_kb("VmData:") {
+            gauges.insert("process.data_kb".to_string(), data_kb);
+        }
+        log::debug!("[Growth] {:?}", gauges);
+        for (name, value) in gauges {
+            let samples = self.history.entry(name.clone()).or_default();
+            samples.push_back(value);
+            if samples.len() > WINDOW {
+                samples.pop_front();
+            }
+            let growing = unbounded(samples);
+            let warned = self.growing.insert(name.clone(), growing).unwrap_or(false);
+            if !growing || warned {
+                continue;
+            }
+            let warning = GrowthWarning {
+                gauge: name,
+                from: samples[0],
+                to: value,
+                window_secs: INTERVAL.as_secs() * (WINDOW as u64 - 1),
+                at_ms: (now - self.started_at).as_millis() as u64,
+            };
+            log::warn!("[Growth] {}", warning);
+            self.warnings.push(warning);
+        }
+    }
+
+    pub fn warnings(&self) -> &[GrowthWarning] {
+        &self.warnings
+    }
+
+    /// Forget the warnings reported so far, the history stays
+    pub fn take_warnings(&mut self) -> Vec<GrowthWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+}
@@ -0,0 +1,219 @@
+//! gRPC service for orchestration platforms, the counterpart of the REST agent
+
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_std::channel::Sender;
+use futures_util::{Stream, StreamExt};
+use parking_lot::Mutex;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{
+    bench::{BenchControl, BenchEvent, BenchPlan, BenchRunner},
+    collector::Collector,
+    whep::ClientConfig,
+};
+
+pub mod proto {
+    tonic::include_proto!("whep_benchmark");
+}
+
+use proto::{
+    benchmark_server::{Benchmark, BenchmarkServer},
+    control_request::Action,
+    Aggregate, ControlReply, ControlRequest, Failure, StartPlan, StreamStatsRequest,
+};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct ServiceInner {
+    stop_handle: Option<Arc<AtomicBool>>,
+    control_tx: Option<Sender<BenchControl>>,
+    collector: Collector,
+    subscribers: Vec<Sender<Aggregate>>,
+}
+
+#[derive(Clone, Default)]
+struct BenchService {
+    inner: Arc<Mutex<ServiceInner>>,
+}
+
+impl BenchService {
+    fn is_running(&self) -> bool {
+        self.inner.lock().stop_handle.is_some()
+    }
+
+    fn start(&self, req: StartPlan) -> Result<(), Status> {
+        if self.is_running() {
+            return Err(Status::already_exists("plan already running"));
+        }
+        let plan = BenchPlan {
+            count: req.count as usize,
+            interval: Duration::from_millis(req.interval_ms),
+            live: Duration::from_millis(req.live_ms),
+            client: ClientConfig {
+                signal_timeout: Duration::from_millis(req.signal_timeout_ms),
+                ..Default::default()
+            },
+            arrival: Default::default(),
+            burst: 1,
+            seed: req.seed,
+            duration: req.duration_ms.map(Duration::from_millis),
+            shared_sockets: 0,
+            threads: 0,
+            sustain: req.sustain,
+            loops: req.loops,
+            fault: None,
+            chaos: None,
+            starve: None,
+            labels: vec![],
+            mix: vec![],
+            record_http: None,
+            audio_dump: None,
+            clients: vec![],
+            delete_limits: None,
+            decimation: None,
+            ramp_delay: None,
+            max_concurrent_connects: None,
+            worker: None,
+            script: None,
+        };
+        log::info!("[Grpc] starting plan {:?}", plan);
+
+        let (event_tx, event_rx) = async_std::channel::unbounded::<BenchEvent>();
+        let (control_tx, control_rx) = async_std::channel::unbounded();
+        let mut runner = BenchRunner::new(&req.url, &req.token, plan, event_tx);
+        runner.set_control(control_rx);
+        {
+            let mut inner = self.inner.lock();
+            inner.stop_handle = Some(runner.stop_handle());
+            inner.control_tx = Some(control_tx);
+            inner.collector = Collector::default();
+        }
+
+        async_std::task::spawn(async move {
+            runner.run().await;
+        });
+
+        let service = self.clone();
+        async_std::task::spawn(async move {
+            while let Ok(event) = event_rx.recv().await {
+                service.inner.lock().collector.on_event(event);
+            }
+            log::info!("[Grpc] plan finished");
+            let mut inner = service.inner.lock();
+            inner.stop_handle = None;
+            inner.control_tx = None;
+        });
+        Ok(())
+    }
+
+    fn control(&self, control: BenchControl) -> Result<(), Status> {
+        let inner = self.inner.lock();
+        let Some(control_tx) = inner.control_tx.as_ref() else {
+            return Err(Status::failed_precondition("no plan running"));
+        };
+        if let BenchControl::Stop = control {
+            if let Some(stopped) = inner.stop_handle.as_ref() {
+                log::info!("[Grpc] stopping plan");
+                stopped.store(true, Ordering::Relaxed);
+            }
+        }
+        control_tx
+            .try_send(control)
+            .map_err(|_| Status::failed_precondition("plan already finished"))
+    }
+
+    /// Sample the collector and push the aggregate to all subscribers
+    fn sample(&self, now: Instant) {
+        let mut inner = self.inner.lock();
+        inner.collector.sample(now);
+        let Some(sample) = inner.collector.aggregates().back() else {
+            return;
+        };
+        let aggregate = Aggregate {
+            elapsed_ms: sample.elapsed_ms,
+            clients: sample.clients as u64,
+            connected: sample.connected as u64,
+            failed: sample.failed as u64,
+            send_kbps: sample.send_kbps,
+            recv_kbps: sample.recv_kbps,
+            avg_rtt_ms: sample.avg_rtt_ms,
+            avg_mos: sample.avg_mos,
+            failures: inner
+                .collector
+                .failure_counts()
+                .iter()
+                .map(|(category, count)| Failure {
+                    category: format!("{:?}", category),
+                    count: *count as u64,
+                })
+                .collect(),
+        };
+        inner
+            .subscribers
+            .retain(|tx| tx.try_send(aggregate.clone()).is_ok());
+    }
+}
+
+#[tonic::async_trait]
+impl Benchmark for BenchService {
+    type StreamStatsStream = Pin<Box<dyn Stream<Item = Result<Aggregate, Status>> + Send>>;
+
+    async fn control_plan(
+        &self,
+        request: Request<ControlRequest>,
+    ) -> Result<Response<ControlReply>, Status> {
+        match request.into_inner().action {
+            Some(Action::Start(plan)) => self.start(plan)?,
+            Some(Action::Stop(_)) => self.control(BenchControl::Stop)?,
+            Some(Action::Spawn(count)) => self.control(BenchControl::Spawn(count as usize))?,
+            Some(Action::Kill(count)) => self.control(BenchControl::Kill(count as usize))?,
+            Some(Action::Pause(pause)) => self.control(BenchControl::Pause { hold: pause.hold })?,
+            Some(Action::Resume(_)) => self.control(BenchControl::Resume)?,
+            Some(Action::Keyframe(count)) => {
+                self.control(BenchControl::RequestKeyframe(count as usize))?
+            }
+            None => return Err(Status::invalid_argument("missing action")),
+        }
+        Ok(Response::new(ControlReply {
+            running: self.is_running(),
+        }))
+    }
+
+    async fn stream_stats(
+        &self,
+        _request: Request<StreamStatsRequest>,
+    ) -> Result<Response<Self::StreamStatsStream>, Status> {
+        let (tx, rx) = async_std::channel::unbounded();
+        self.inner.lock().subscribers.push(tx);
+        Ok(Response::new(Box::pin(rx.map(Ok))))
+    }
+}
+
+/// Serve the gRPC api, plans are started with `ControlPlan`
+pub async fn run_grpc(listen: SocketAddr) -> Result<(), tonic::transport::Error> {
+    let service = BenchService::default();
+
+    let sampled = service.clone();
+    async_std::task::spawn(async move {
+        loop {
+            async_std::task::sleep(SAMPLE_INTERVAL).await;
+            sampled.sample(Instant::now());
+        }
+    });
+
+    log::info!("[Grpc] listening on {}", listen);
+    Server::builder()
+        .add_service(BenchmarkServer::new(service))
+        .serve(listen)
+        .await
+}
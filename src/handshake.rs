@@ -0,0 +1,48 @@
+//! Bound on the clients setting up a connection at once, from the offer until ice
+//! and dtls are done. A spawn burst then connects in waves instead of flooding the
+//! server and the local cpu with handshakes. The wait for a slot is reported as
+//! `queue_ms`, of failed joins too, apart from the time the server took
+
+use std::time::Instant;
+
+use async_std::channel::{self, Receiver, Sender};
+
+/// Slots shared by all clients of a runner
+#[derive(Debug, Clone)]
+pub struct HandshakeLimiter {
+    // a bounded channel as semaphore, a slot is taken by sending and freed by receiving
+    acquire: Sender<()>,
+    release: Receiver<()>,
+}
+
+/// Held from the offer until the client connected or gave up
+#[derive(Debug)]
+pub struct HandshakePermit {
+    release: Receiver<()>,
+}
+
+impl Drop for HandshakePermit {
+    fn drop(&mut self) {
+        let _ = self.release.try_recv();
+    }
+}
+
+impl HandshakeLimiter {
+    pub fn new(limit: usize) -> Self {
+        let (acquire, release) = channel::bounded(limit.max(1));
+        Self { acquire, release }
+    }
+
+    /// Wait for a free slot, returns it with the time waited in ms
+    pub async fn acquire(&self) -> (HandshakePermit, u32) {
+        let started = Instant::now();
+        self.acquire
+            .send(())
+            .await
+            .expect("should keep the receiver open");
+        let permit = HandshakePermit {
+            release: self.release.clone(),
+        };
+        (permit, started.elapsed().as_millis() as u32)
+    }
+}
@@ -0,0 +1,137 @@
+//! HAR 1.2 export of the signaling of clients recording it, readable by browser
+//! dev tools and HAR viewers. The file is written once the event stream ended
+
+use std::path::Path;
+
+use async_std::channel::Receiver;
+use serde_json::{json, Value};
+
+use crate::{
+    bench::BenchEvent,
+    whep::{HttpExchange, HttpMethod, HttpTiming},
+};
+
+/// ISO 8601 UTC time of unix milliseconds
+fn iso_time(unix_ms: u64) -> String {
+    let secs = unix_ms / 1000;
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    // civil from days, Howard Hinnant
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        unix_ms % 1000
+    )
+}
+
+fn headers(pairs: &[(String, String)]) -> Value {
+    pairs
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect()
+}
+
+fn content_type(pairs: &[(String, String)]) -> &str {
+    pairs
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .map_or("", |(_, value)| value.as_str())
+}
+
+fn entry(client_id: usize, timing: &HttpTiming, exchange: &HttpExchange) -> Value {
+    let method = match timing.method {
+        HttpMethod::Post => "POST",
+        HttpMethod::Delete => "DELETE",
+        HttpMethod::Patch => "PATCH",
+        HttpMethod::Head => "HEAD",
+    };
+    let version = timing
+        .version
+        .map_or("HTTP/1.1".to_string(), |v| v.to_string());
+    let mut request = json!({
+        "method": method,
+        "url": exchange.url,
+        "httpVersion": version,
+        "cookies": [],
+        "headers": headers(&exchange.request_headers),
+        "queryString": [],
+        "headersSize": -1,
+        "bodySize": exchange.request_body.as_ref().map_or(0, |b| b.len()),
+    });
+    if let Some(body) = &exchange.request_body {
+        request["postData"] = json!({
+            "mimeType": content_type(&exchange.request_headers),
+            "text": body,
+        });
+    }
+    let body = exchange.response_body.as_deref().unwrap_or("");
+    json!({
+        "startedDateTime": iso_time(exchange.started_ms),
+        "time": timing.total_ms + timing.dns_ms.unwrap_or(0),
+        "request": request,
+        "response": {
+            "status": timing.status,
+            "statusText": "",
+            "httpVersion": version,
+            "cookies": [],
+            "headers": headers(&exchange.response_headers),
+            "content": {
+                "size": body.len(),
+                "mimeType": content_type(&exchange.response_headers),
+                "text": body,
+            },
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": body.len(),
+        },
+        "cache": {},
+        "timings": {
+            "dns": timing.dns_ms.map_or(-1, |ms| ms as i64),
+            "send": 0,
+            "wait": timing.ttfb_ms,
+            "receive": timing.total_ms.saturating_sub(timing.ttfb_ms),
+        },
+        "comment": format!("client {}", client_id),
+    })
+}
+
+/// Collect the recorded exchanges until the event channel closes, then write them to `path`
+pub fn run_har(path: &Path, event_rx: Receiver<BenchEvent>) {
+    let mut entries = vec![];
+    while let Ok(event) = event_rx.recv_blocking() {
+        if let BenchEvent::Http(id, timing) = &event {
+            if let Some(exchange) = &timing.exchange {
+                entries.push(entry(*id, timing, exchange));
+            }
+        }
+    }
+    let har = json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": "whep-benchmark", "version": env!("CARGO_PKG_VERSION") },
+            "entries": entries,
+        }
+    });
+    let json = serde_json::to_string_pretty(&har).expect("should serialize har");
+    match std::fs::write(path, json) {
+        Ok(()) => log::info!(
+            "[Har] wrote {} requests to {}",
+            entries.len(),
+            path.display()
+        ),
+        Err(e) => log::error!("[Har] cannot write {} {:?}", path.display(), e),
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::whep::{ClientConfig, WhepClient, WhepEvent};
+
+/// One line verdict printed by the health check
+#[derive(Debug, Serialize)]
+pub struct Verdict {
+    pub healthy: bool,
+    pub reason: String,
+    pub connect_ms: Option<u64>,
+    /// Time media stayed above the bitrate floor
+    pub media_ms: u64,
+    pub recv_kbps: u64,
+}
+
+/// Connect one client and require `required` time of media at or above `min_kbps`
+pub async fn run_healthcheck(
+    url: &str,
+    token: &str,
+    config: &ClientConfig,
+    required: Duration,
+    min_kbps: u64,
+) -> Verdict {
+    let mut verdict = Verdict {
+        healthy: false,
+        reason: String::new(),
+        connect_ms: None,
+        media_ms: 0,
+        recv_kbps: 0,
+    };
+    let started = Instant::now();
+    let deadline = config.signal_timeout + config.ice_timeout + required;
+
+    let mut client = match WhepClient::new(url, token, config) {
+        Ok(client) => client,
+        Err(e) => {
+            verdict.reason = format!("{:?}", e);
+            return verdict;
+        }
+    };
+    if let Err(e) = client.prepare().await {
+        verdict.reason = format!("{:?}", e);
+        return verdict;
+    }
+
+    let mut good_since: Option<Instant> = None;
+    loop {
+        if started.elapsed() > deadline {
+            verdict.reason = "deadline exceeded".to_string();
+            break;
+        }
+        match client.recv().await {
+            Ok(WhepEvent::Connected) => {
+                verdict.connect_ms = Some(started.elapsed().as_millis() as u64);
+            }
+            Ok(WhepEvent::Stats(stats)) => {
+                verdict.recv_kbps = stats.recv_kbps;
+                if stats.recv_kbps >= min_kbps {
+                    let since = *good_since.get_or_insert_with(Instant::now);
+                    verdict.media_ms = since.elapsed().as_millis() as u64;
+                } else {
+                    good_since = None;
+                    verdict.media_ms = 0;
+                }
+                if verdict.media_ms >= required.as_millis() as u64 {
+                    verdict.healthy = true;
+                    verdict.reason = "ok".to_string();
+                    break;
+                }
+            }
+            Ok(WhepEvent::Disconnected) => {
+                verdict.reason = "disconnected".to_string();
+                break;
+            }
+            Ok(WhepEvent::StreamChanged(_))
+            | Ok(WhepEvent::LayerSwitched(_))
+            | Ok(WhepEvent::Continue) => {}
+            Err(e) => {
+                verdict.reason = format!("{:?}", e);
+                break;
+            }
+        }
+    }
+
+    if let Err(e) = client.disconnect().await {
+        log::warn!("[HealthCheck] disconnect failed {:?}", e);
+    }
+    verdict
+}
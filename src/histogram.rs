@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+/// Upper bounds of the interarrival buckets in miliseconds, a last bucket takes
+/// everything above. Roughly doubling, so pacing bursts and stalls both show up
+pub const INTERARRIVAL_BOUNDS_MS: [f64; 11] = [
+    1.0, 2.0, 5.0, 10.0, 20.0, 40.0, 80.0, 160.0, 320.0, 640.0, 1280.0,
+];
+const BUCKETS: usize = INTERARRIVAL_BOUNDS_MS.len() + 1;
+
+/// Fixed size histogram of packet interarrival times
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub counts: [u64; BUCKETS],
+}
+
+impl Histogram {
+    pub fn record(&mut self, value_ms: f64) {
+        let bucket = INTERARRIVAL_BOUNDS_MS
+            .iter()
+            .position(|bound| value_ms <= *bound)
+            .unwrap_or(BUCKETS - 1);
+        self.counts[bucket] += 1;
+    }
+
+    pub fn add(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+/// Interarrival histograms of all clients by media kind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterarrivalSummary {
+    pub bounds_ms: Vec<f64>,
+    pub audio: Histogram,
+    pub video: Histogram,
+}
+
+impl Default for InterarrivalSummary {
+    fn default() -> Self {
+        Self {
+            bounds_ms: INTERARRIVAL_BOUNDS_MS.to_vec(),
+            audio: Histogram::default(),
+            video: Histogram::default(),
+        }
+    }
+}
+
+impl InterarrivalSummary {
+    pub fn add(&mut self, other: &InterarrivalSummary) {
+        self.audio.add(&other.audio);
+        self.video.add(&other.video);
+    }
+}
@@ -0,0 +1,91 @@
+//! Whep benchmarking library
+//!
+//! Embed the benchmark by creating a [`BenchRunner`] with a channel and consuming
+//! [`BenchEvent`]s from the other end. Enable the `tokio` feature to run the
+//! clients on tokio instead of async-std, and the `grpc` feature for the gRPC
+//! control and stats service, `parquet` for the Parquet export, `plugins` for
+//! WebAssembly plugins and `wav` for WAV dumps of received audio.
+
+pub mod ab;
+pub mod agent;
+pub mod audiolevel;
+pub mod auth;
+pub mod bench;
+pub mod candidate;
+pub mod clientlog;
+pub mod cluster;
+pub mod collector;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+pub mod compare;
+pub mod config;
+pub mod conformance;
+pub mod consistency;
+pub mod console;
+pub mod decimate;
+pub mod delay;
+pub mod dnscache;
+pub mod downlink;
+pub mod events;
+pub mod gate;
+pub mod growth;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod handshake;
+pub mod har;
+pub mod healthcheck;
+pub mod histogram;
+pub mod logbuf;
+pub mod logfile;
+pub mod mdns;
+pub mod media;
+pub mod mockserver;
+pub mod mos;
+pub mod munge;
+pub mod mux;
+pub mod playout;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod pool;
+pub mod probe;
+pub mod procs;
+pub mod progress;
+pub mod ramp;
+pub mod record;
+pub mod reload;
+pub mod replay;
+pub mod report;
+pub mod resilience;
+pub mod resolution;
+pub mod roster;
+pub mod rt;
+pub mod rtpext;
+pub mod script;
+pub mod sdp;
+pub mod selfmon;
+pub mod shard;
+pub mod silence;
+pub mod simulcast;
+pub mod sketch;
+pub mod skew;
+pub mod soak;
+pub mod statsd;
+pub mod stream;
+pub mod stun;
+pub mod summary;
+pub mod sweep;
+pub mod teardown;
+pub mod timeline;
+pub mod traffic;
+pub mod tui;
+pub mod urltemplate;
+pub mod watchdog;
+#[cfg(feature = "wav")]
+pub mod wav;
+pub mod web;
+pub mod webhook;
+pub mod whep;
+pub mod whepext;
+
+pub use bench::{BenchControl, BenchEvent, BenchPlan, BenchRunner};
+pub use whep::{Stats, WhepClient, WhepError, WhepEvent};
@@ -0,0 +1,92 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Log file which rotates to `<path>.1`, `<path>.2`, ... by size and optionally by age
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    opened_at: Instant,
+    max_bytes: u64,
+    max_age: Option<Duration>,
+    keep: usize,
+}
+
+impl RotatingFile {
+    pub fn open(
+        path: &Path,
+        max_bytes: u64,
+        max_age: Option<Duration>,
+        keep: usize,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            written,
+            opened_at: Instant::now(),
+            max_bytes,
+            max_age,
+            keep,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        name.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            // shift older files up, the oldest one falls off
+            let _ = fs::remove_file(self.rotated_path(self.keep));
+            for index in (1..self.keep).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = 0;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+
+    fn should_rotate(&self, incoming: usize) -> bool {
+        let too_big = self.written > 0 && self.written + incoming as u64 > self.max_bytes;
+        let too_old = self
+            .max_age
+            .map(|age| self.opened_at.elapsed() >= age)
+            .unwrap_or(false);
+        too_big || too_old
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len()) {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -1,75 +1,2096 @@
-use std::sync::Arc;
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use clap::Parser;
-use dioxus_tui::Config;
+use clap::{error::ErrorKind, ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
+use parking_lot::{Mutex, RwLock};
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
-mod bench;
-mod tui;
-mod whep;
+use whep_benchmark::{
+    agent, auth, bench, candidate, clientlog, cluster, collector, compare, config, conformance,
+    console, decimate, delay, events, gate, healthcheck, logbuf, logfile, media, mockserver, munge,
+    mux, probe, procs, progress, ramp, record, reload, replay, report, roster, script, soak,
+    statsd, stun, summary, sweep, teardown, tui, urltemplate, watchdog, web, webhook, whep,
+};
 
-use tui::dioxus_app;
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Role {
+    /// Run all clients locally
+    Standalone,
+    /// Distribute the plan to workers and collect their events
+    Coordinator,
+    /// Wait for a coordinator and run the received plan
+    Worker,
+}
 
-/// Whep benchmarking tool
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Whep server url
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    /// Summary table and log lines
+    #[default]
+    Text,
+    /// Exactly one JSON document with the results on stdout, logs go to stderr
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum EventFormat {
+    /// One JSON object per line
+    Ndjson,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a benchmark plan against a whep server
+    Bench(BenchArgs),
+    /// Do a single signaling exchange, print what the server negotiated and exit
+    Probe(ProbeArgs),
+    /// Print an exported run summary
+    Report {
+        /// Summary written by `bench --output`
+        summary: PathBuf,
+
+        /// Write a standalone HTML report with charts to this file instead of printing
+        #[arg(long)]
+        html: Option<PathBuf>,
+    },
+    /// Compare two exported run summaries, exits 1 when a metric regressed
+    Compare {
+        old: PathBuf,
+        new: PathBuf,
+
+        /// Allowed worsening of each percentile, e.g. `10%`
+        #[arg(long, default_value = "10%", value_parser = compare::parse_tolerance)]
+        tolerance: f64,
+    },
+    /// Serve an api, plans are started and controlled remotely
+    Agent(AgentArgs),
+    /// Change the client count of the plan running on an agent, it ramps up at the
+    /// plan's arrival rate or disconnects its newest clients
+    Scale {
+        /// Clients the plan should run
+        count: usize,
+
+        /// Base url of the agent api
+        #[arg(env, long, default_value = "http://127.0.0.1:9000")]
+        agent: String,
+    },
+    /// Serve a whep endpoint streaming synthetic media, for testing the benchmark itself
+    MockServer(MockServerArgs),
+    /// Feed the events of a run recorded with `bench --record-events` through the
+    /// UI and the summary again, without load
+    Replay(ReplayArgs),
+}
+
+/// Whep server and its credentials
+#[derive(clap::Args, Debug)]
+struct TargetArgs {
+    /// Whep server url. Placeholders spread clients across streams, e.g.
+    /// `https://edge/whep/room-{id % 10}`, with `+ - * / %` on the client id
+    #[arg(env, long)]
+    url: Option<String>,
+
+    /// Whep server token. Without --token, --token-path and --oauth-token-url the
+    /// clients signal anonymously, without Authorization header
+    #[arg(env, long, conflicts_with_all = ["oauth_token_url", "token_path"])]
+    token: Option<String>,
+
+    /// Read the token from a file, re-read when it changes or on SIGHUP
+    #[arg(env, long, conflicts_with = "oauth_token_url")]
+    token_path: Option<PathBuf>,
+
+    /// OAuth2 token endpoint, the bearer token is fetched with client credentials
+    /// and refreshed before it expires
+    #[arg(env, long, requires_all = ["client_id", "client_secret"])]
+    oauth_token_url: Option<String>,
+
+    /// OAuth2 client id
+    #[arg(env, long)]
+    client_id: Option<String>,
+
+    /// OAuth2 client secret
+    #[arg(env, long)]
+    client_secret: Option<String>,
+
+    /// OAuth2 scope
+    #[arg(env, long)]
+    oauth_scope: Option<String>,
+}
+
+/// Settings of each client
+#[derive(clap::Args, Debug)]
+struct ClientArgs {
+    /// Timeout of each signaling http request (POST/DELETE/PATCH) in miliseconds
+    #[arg(env, long, default_value = "10000")]
+    signal_timeout_ms: u64,
+
+    /// Time allowed for ice and dtls to connect after sending the offer in miliseconds,
+    /// the client then fails with IceTimeout and its session is deleted
+    #[arg(
+        env,
+        long,
+        visible_alias = "connect-timeout-ms",
+        default_value = "30000"
+    )]
+    ice_timeout_ms: u64,
+
+    /// Verify remote DTLS fingerprint
+    #[arg(env, long, default_value = "true", action = ArgAction::Set)]
+    dtls_fingerprint_verification: bool,
+
+    /// Fail connected clients which received no media for this many miliseconds
+    #[arg(env, long)]
+    stall_timeout_ms: Option<u64>,
+
+    /// Audio reordering buffer size in packets
+    #[arg(env, long)]
+    reorder_audio: Option<usize>,
+
+    /// Video reordering buffer size in packets
+    #[arg(env, long)]
+    reorder_video: Option<usize>,
+
+    /// Receive raw rtp packets, keyframe and per stream stats need this enabled
+    #[arg(env, long, default_value = "true", action = ArgAction::Set)]
+    rtp_mode: bool,
+
+    /// Resolve `.local` mDNS candidates of the answer, drop them when disabled
+    #[arg(env, long, default_value = "true", action = ArgAction::Set)]
+    resolve_mdns: bool,
+
+    /// Signaling retries of a client after the server answered 429 or 503
+    #[arg(env, long, default_value = "3")]
+    throttle_retries: u32,
+
+    /// Keep the Authorization header when the offer is redirected to another origin
+    #[arg(env, long, default_value = "true", action = ArgAction::Set)]
+    redirect_auth: bool,
+
+    /// Signaling over this http version only, `1.1`, `2` or `3`. Negotiated when
+    /// unset, HTTP/3 needs the http3 feature
     #[arg(env, long)]
-    url: String,
+    http_version: Option<whep::HttpVersion>,
 
-    /// Whep server token
+    /// Random delay before each offer, keepalive and DELETE, e.g. `0-2000ms`, to
+    /// emulate distant viewers. Signaling timings do not include it
     #[arg(env, long)]
-    token: String,
+    signal_delay: Option<delay::DelayRange>,
+
+    /// Share dns lookups of the signaling host between all clients for this many
+    /// seconds, instead of resolving it for every new connection. Cached lookups are
+    /// reported as dns cached ms, apart from the dns ms of real lookups
+    #[arg(env, long)]
+    dns_cache_ttl: Option<u64>,
+
+    /// Rewrite the offer before posting it, `<regex>=><replacement>` matched per
+    /// sdp line, e.g. `^a=extmap:.*$=>` drops the header extensions. `\n` in the
+    /// replacement starts a new line. Repeat for several, applied in order before
+    /// the `on_offer` hook of the script
+    #[arg(env, long)]
+    offer_rewrite: Vec<munge::OfferRewrite>,
+
+    /// User-Agent of signaling requests
+    #[arg(env, long, default_value = "Whep Benchmark in Rust")]
+    user_agent: String,
+
+    /// Media kinds the answer has to accept, e.g. `video,audio`. A client whose answer
+    /// rejects one fails with MediaRejected
+    #[arg(env, long, value_delimiter = ',')]
+    require: Vec<media::StreamKind>,
+
+    /// Repair malformed answers, e.g. a missing `a=setup` or bare LF line endings,
+    /// instead of failing the client with SdpError. The fixes are logged
+    #[arg(env, long, default_value = "false")]
+    lenient_sdp: bool,
+
+    /// Pin a signaling host to an address like curl, `host:port:ip`, e.g. to reach one
+    /// edge behind a load balanced name. Repeat or separate with `,`
+    #[arg(env, long, value_delimiter = ',')]
+    resolve: Vec<whep::ResolveOverride>,
+
+    /// Refresh each session resource periodically, `patch:30s` or `head:30s`, for
+    /// gateways expiring sessions which are not refreshed
+    #[arg(env, long)]
+    keepalive: Option<whep::Keepalive>,
+
+    /// STUN server, `host[:port]`, asked for a server reflexive candidate before each
+    /// offer, for load generators behind NAT. Repeat or separate with `,`
+    #[arg(env, long, value_delimiter = ',', value_parser = stun::parse_server)]
+    stun_server: Vec<std::net::SocketAddr>,
+
+    /// Candidates clients may use, `all`, `relay` for only the relay candidates of
+    /// the answer, e.g. to measure the TURN path, or `host` for the direct path
+    #[arg(env, long, default_value = "all")]
+    candidate_policy: candidate::CandidatePolicy,
+
+    /// Header carrying `<run id>/<client id>` on each offer, e.g. `X-Client-Id`
+    #[arg(env, long)]
+    client_id_header: Option<String>,
+
+    /// Local udp ports to bind client sockets in, e.g. `40000-50000`
+    #[arg(env, long)]
+    port_range: Option<mux::PortRange>,
+
+    /// DSCP marking of media packets, a class like `EF`, `AF41`, `CS5` or a number
+    #[arg(env, long)]
+    dscp: Option<mux::Dscp>,
+
+    /// Receive buffer size of udp sockets in bytes, raise it when socket drops show up
+    #[arg(env, long)]
+    so_rcvbuf: Option<usize>,
+
+    /// Send buffer size of udp sockets in bytes
+    #[arg(env, long)]
+    so_sndbuf: Option<usize>,
+
+    /// Largest udp datagram received in full, default 1500. Raise it to e.g. 9000 on
+    /// jumbo frame networks, larger datagrams are dropped and counted as oversized
+    #[arg(env, long)]
+    datagram_size: Option<usize>,
+
+    /// Use plain udp sockets bound to the address of the default route interface
+    /// instead of source address selection, for platforms without it. Chosen
+    /// automatically when binding with source address selection fails
+    #[arg(env, long)]
+    no_sas: bool,
+
+    /// Only offer host candidates of interfaces whose name matches one of these
+    /// regexes, e.g. `eth.*`. Loopback and link-local addresses are skipped unless
+    /// included here or nothing else is left. Repeat or separate with `,`
+    #[arg(env, long, value_delimiter = ',')]
+    iface_include: Vec<candidate::IfacePattern>,
+
+    /// Never offer host candidates of interfaces matching one of these regexes,
+    /// e.g. `docker.*,veth.*,tun.*`
+    #[arg(env, long, value_delimiter = ',')]
+    iface_exclude: Vec<candidate::IfacePattern>,
+
+    /// Ip family of signaling connections and advertised candidates, `v4` or `v6`,
+    /// the other one is only used when the preferred is unavailable
+    #[arg(env, long)]
+    prefer: Option<mux::IpFamily>,
+}
+
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+
+    #[command(flatten)]
+    client: ClientArgs,
 
     /// Number of clients
     #[arg(env, long, default_value = "1")]
     count: usize,
 
+    /// Pause spawning clients while the p95 offer latency of the ramp window is above
+    /// this, resuming once it recovered
+    #[arg(env, long)]
+    ramp_post_p95_ms: Option<u32>,
+
+    /// Pause spawning clients while this share of the offers of the ramp window
+    /// failed, e.g. 0.1
+    #[arg(env, long)]
+    ramp_error_rate: Option<f64>,
+
+    /// Window of offers the ramp thresholds are checked against, in seconds
+    #[arg(env, long, default_value = "30")]
+    ramp_window_secs: u64,
+
+    /// Find the egress capacity, e.g. `start=50,step=50,hold=120s`. Adds `step`
+    /// clients per plateau until the aggregate bitrate stops scaling, optionally up
+    /// to `max=<clients>`, and prints the capacity curve. Replaces --count, clients
+    /// stay until the sweep ends. Standalone role only
+    #[arg(env, long)]
+    sweep: Option<sweep::SweepPlan>,
+
+    /// Multi-day run: the summary is rolled up every --soak-rollup-secs into
+    /// --soak-dir and starts over with the clients still running, keeping memory
+    /// bounded. A crash loses at most the current window
+    #[arg(env, long)]
+    soak: bool,
+
+    /// Length of a soak window in seconds
+    #[arg(env, long, default_value = "3600")]
+    soak_rollup_secs: u64,
+
+    /// Directory of the rollup summaries and the checkpoint of a soak run
+    #[arg(env, long, default_value = "soak")]
+    soak_dir: PathBuf,
+
     /// Interval between clients in miliseconds
     #[arg(env, long, default_value = "1000")]
     interval: u64,
 
+    /// Clients per second instead of --interval, e.g. `25/s`, `0.5/s`, `90/m`, or
+    /// `2/s,burst=10` starting groups of 10 clients every 5 seconds
+    #[arg(env, long, conflicts_with = "interval")]
+    rate: Option<bench::Rate>,
+
+    /// Client arrival process: `fixed` (one per interval) or `poisson:<clients per second>`
+    #[arg(env, long, default_value = "fixed")]
+    arrival: bench::Arrival,
+
+    /// Seed for random schedules, runs with the same seed start clients identically.
+    /// A random seed is logged when not set
+    #[arg(env, long)]
+    seed: Option<u64>,
+
+    /// Duration of the whole run in seconds, the process exits after it or once
+    /// every client is done
+    #[arg(env, long)]
+    duration: Option<u64>,
+
+    /// DELETE requests in flight at once while the clients are torn down at the
+    /// end of the run, unbounded by default
+    #[arg(env, long)]
+    shutdown_concurrency: Option<usize>,
+
+    /// DELETE requests started per second while the clients are torn down at the
+    /// end of the run, the shutdown waits as long as the rate needs
+    #[arg(env, long)]
+    shutdown_rate: Option<f64>,
+
+    /// Roll up the stats of each client before they reach the UI, the exports and
+    /// the summary, for very large runs. `every=5` emits one rollup of every 5
+    /// samples, `window=10s` one per 10 seconds. Rates are averaged over a rollup
+    #[arg(env, long)]
+    stats_decimation: Option<decimate::Decimation>,
+
+    /// Random delay added to each arrival, e.g. `0-2000ms`, slowing the ramp per
+    /// client like viewers trickling in
+    #[arg(env, long)]
+    ramp_delay: Option<delay::DelayRange>,
+
+    /// Clients setting up their connection at once, from the offer until ice and
+    /// dtls are done. Others wait for a slot, the wait is reported as queue ms.
+    /// Split between workers or processes, so it cannot be lower than their number
+    #[arg(env, long)]
+    max_concurrent_connects: Option<usize>,
+
+    /// Time from the start whose stats samples are left out of the summary and
+    /// the baseline comparison, e.g. `30s`, to keep ramp-up noise out of steady
+    /// state numbers. Bare numbers are seconds
+    #[arg(env, long, default_value = "0", value_parser = watchdog::parse_duration_arg)]
+    warmup: std::time::Duration,
+
+    /// Keep `count` clients running for the whole run, replacing each client which
+    /// ends or fails at the arrival rate. Use with --duration or stop it by hand
+    #[arg(env, long)]
+    sustain: bool,
+
+    /// Each client joins again with a fresh offer this many times after its
+    /// lifetime expired, 0 repeats until the run ends
+    #[arg(env = "LOOP", long = "loop")]
+    loops: Option<u32>,
+
+    /// Percent of clients killed mid-stream without DELETE and joined again, to
+    /// measure how long the server takes to accept the replacement
+    #[arg(env, long)]
+    fault_percent: Option<f64>,
+
+    /// Time after connecting when the fault hits in miliseconds
+    #[arg(env, long, default_value = "30000")]
+    fault_after_ms: u64,
+
+    /// Compare two servers in one run, `<url a>,<url b>`. Clients alternate between
+    /// them and the summary adds a side by side table with Mann-Whitney p-values.
+    /// Replaces --url, standalone role only
+    #[arg(env, long, value_delimiter = ',', conflicts_with = "url")]
+    ab: Vec<String>,
+
+    /// Label a range of client ids for slicing the results by cohort, e.g.
+    /// `mobile=1-100` or `desktop=101-`. Labels show in the summary, the events and
+    /// the client log. Repeat or separate with `,`
+    #[arg(env, long, value_delimiter = ',')]
+    label: Vec<bench::LabelRule>,
+
+    /// Weighted client profiles, each client draws one and is labeled with its
+    /// name, e.g. `mobile:weight=30,live=2m,downlink-kbps=800`. A profile may set
+    /// `weight`, `live`, `media` (audio or video only) and `downlink-kbps`. Repeat
+    /// or separate with `;`, in a config file use `[mix.<name>]` tables
+    #[arg(env, long, value_delimiter = ';')]
+    mix: Vec<bench::ClientProfile>,
+
+    /// CSV file with a url, token and label per client, row N is client id N, e.g.
+    /// a `url,token,label` header then `https://a.example/whep/room1,secret1,room1`.
+    /// Empty fields and clients past the last row use --url and --token
+    #[arg(env, long, conflicts_with = "ab")]
+    clients_file: Option<PathBuf>,
+
+    /// Rhai script with client hooks for customizing headers, mutating the offer or
+    /// disconnecting on stats, see the `script` module for the hooks
+    #[arg(env, long)]
+    script: Option<PathBuf>,
+
+    /// Every chaos interval kill this percent of the connected clients without DELETE
+    /// and join them again, faults and recover times include them
+    #[arg(env, long)]
+    chaos_percent: Option<f64>,
+
+    /// Seconds between chaos rounds
+    #[arg(env, long, default_value = "60")]
+    chaos_interval_secs: u64,
+
+    /// How a faulted or chaos killed client dies, `silence` stops answering, `close`
+    /// closes the socket
+    #[arg(env, long, default_value = "silence")]
+    fault_mode: bench::FaultMode,
+
+    /// Also write logs to this file, works together with the UI
+    #[arg(env, long)]
+    log_file: Option<PathBuf>,
+
+    /// Size in megabytes after which the log file is rotated
+    #[arg(env, long, default_value = "100")]
+    log_file_max_mb: u64,
+
+    /// Age in seconds after which the log file is rotated
+    #[arg(env, long)]
+    log_file_rotate_secs: Option<u64>,
+
+    /// Number of rotated log files kept
+    #[arg(env, long, default_value = "5")]
+    log_file_keep: usize,
+
+    /// Identifier of this run attached to exported metrics and the client id header,
+    /// random when not set
+    #[arg(env, long)]
+    run_id: Option<String>,
+
+    /// StatsD/DogStatsD address (host:port) to export metrics to
+    #[arg(env, long)]
+    statsd_addr: Option<String>,
+
+    /// Prefix of exported StatsD metric names
+    #[arg(env, long, default_value = "whep_benchmark")]
+    statsd_prefix: String,
+
+    /// Interval of exported StatsD gauges in miliseconds
+    #[arg(env, long, default_value = "10000")]
+    statsd_interval_ms: u64,
+
+    /// Write lifecycle events and errors of each client to its own file in this directory
+    #[arg(env, long)]
+    client_log_dir: Option<PathBuf>,
+
+    /// Record every event and stats sample into this SQLite file, see `record` for the schema
+    #[arg(env, long)]
+    record: Option<PathBuf>,
+
+    /// Keep a timeline per client in the JSON summary, from its creation over
+    /// signaling, first media and stalls to the disconnect. --record always
+    /// writes the timelines into its `timeline` table
+    #[arg(env, long)]
+    client_timelines: bool,
+
+    /// Record the event stream into this file, `replay` feeds it through the UI and
+    /// the summary again
+    #[arg(env, long)]
+    record_events: Option<PathBuf>,
+
+    /// Write the signaling requests of sampled clients with headers and bodies to this
+    /// HAR file, for replaying and inspecting a misnegotiation. Tokens are redacted
+    #[arg(env, long)]
+    har: Option<PathBuf>,
+
+    /// Percent of clients recorded into the HAR file
+    #[arg(env, long, default_value = "1", requires = "har")]
+    har_percent: f64,
+
+    /// WebAssembly plugins receiving the event stream, their metrics are added to the
+    /// summary. See the `plugin` module for the interface
+    #[cfg(feature = "plugins")]
+    #[arg(env, long, value_delimiter = ',')]
+    plugin: Vec<PathBuf>,
+
+    /// Write stats samples as `stats-<run id>.parquet` into this directory
+    #[cfg(feature = "parquet")]
+    #[arg(env, long)]
+    export_parquet: Option<PathBuf>,
+
+    /// Decode the audio received by sampled clients to `client-<id>.wav` files in
+    /// this directory, for listening to what viewers got
+    #[cfg(feature = "wav")]
+    #[arg(env, long)]
+    wav_dir: Option<PathBuf>,
+
+    /// Percent of clients dumping their audio
+    #[cfg(feature = "wav")]
+    #[arg(env, long, default_value = "1", requires = "wav_dir")]
+    wav_percent: f64,
+
+    /// Stream every client event to stdout in this format, logs and the summary
+    /// then go to stderr
+    #[arg(env, long)]
+    events: Option<EventFormat>,
+
+    /// Write the event stream to this file instead of stdout
+    #[arg(env, long, requires = "events")]
+    events_file: Option<PathBuf>,
+
+    /// Format of the results on stdout, `json` for scripts. With `--quiet` nothing
+    /// but the JSON document is printed
+    #[arg(env, long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Stop the run and exit with code 3 when a rule holds, e.g.
+    /// `connected_ratio < 0.5 for 30s`. Metrics: connected_ratio, failed_ratio,
+    /// recv_kbps, rtt_ms, loss, mos, connected, failed, total_recv_gbytes
+    #[arg(env, long, value_delimiter = ';')]
+    abort_if: Vec<watchdog::AbortRule>,
+
+    /// Stop the run like --abort-if once more than this share of the clients
+    /// failed, e.g. `0.05`. Clients still running disconnect and the summary is
+    /// written
+    #[arg(env, long)]
+    max_failure_ratio: Option<f64>,
+
+    /// Started clients before --max-failure-ratio is checked, a few early failures
+    /// would decide the ratio otherwise
+    #[arg(env, long, default_value = "20", requires = "max_failure_ratio")]
+    failure_ratio_min_clients: usize,
+
+    /// End the run normally once a condition holds, e.g. `total_recv_gbytes > 100` or
+    /// `connected == 0 for 60s`. Operators: <, <=, ==, >=, >. Metrics as for
+    /// --abort-if. Separate with `;`
+    #[arg(env, long, value_delimiter = ';')]
+    stop_when: Vec<watchdog::StopCondition>,
+
+    /// Fail the run with exit code 1 unless this holds at the end, e.g.
+    /// `p95(connect_ms) < 2000` or `avg(loss) < 0.01`. Aggregates: avg, min, max,
+    /// count, p<0-100>. Metrics: connect_ms, recover_ms, dns_ms, post_ttfb_ms,
+    /// post_ms, delete_ms, queue_ms, signaling_ms, ice_ms, dtls_ms, recv_kbps,
+    /// payload_kbps, rtt_ms, loss, mos, failed, connected. Repeat or separate with `;`
+    #[arg(env, long, value_delimiter = ';')]
+    gate: Vec<gate::Gate>,
+
+    /// Check the gates each second after the warmup too, stopping the run once one fails
+    #[arg(env, long, default_value = "false", requires = "gate")]
+    gate_live: bool,
+
+    /// POST JSON notifications here on run start, completion, baseline regression and abort
+    #[arg(env, long)]
+    webhook_url: Option<String>,
+
+    /// Write the run summary as JSON to this file, for `compare` and `report`
+    #[arg(env, long)]
+    output: Option<PathBuf>,
+
+    /// Write a standalone HTML report with bitrate, RTT and connection charts to this file
+    #[arg(env, long)]
+    html_report: Option<PathBuf>,
+
+    /// Write the headline metrics, gate results and top failure reasons as Markdown
+    /// tables to this file, e.g. for a PR comment
+    #[arg(env, long)]
+    summary_md: Option<PathBuf>,
+
+    /// Summary of a previous run, the run fails when it regresses against it
+    #[arg(env, long)]
+    baseline: Option<PathBuf>,
+
+    /// Allowed worsening of each percentile against the baseline, e.g. `10%`
+    #[arg(env, long, default_value = "10%", value_parser = compare::parse_tolerance)]
+    tolerance: f64,
+
     /// Life time of each client in miliseconds
     #[arg(env, long, default_value = "100000")]
     live: u64,
 
+    /// Expected audio bitrate in kbps, clients receiving less are flagged as starved
+    #[arg(env, long)]
+    expected_audio_kbps: Option<u64>,
+
+    /// Expected video bitrate in kbps, clients receiving less are flagged as starved
+    #[arg(env, long)]
+    expected_video_kbps: Option<u64>,
+
+    /// What the published stream should be, e.g. `video=2500kbps,audio=64kbps,fps=30`
+    /// with an optional `tolerance=10%`. The summary reports per client the share of
+    /// stats samples within the tolerance
+    #[arg(env, long)]
+    expect: Option<conformance::Expectation>,
+
+    /// Share of the expected bitrate below which a client is starving
+    #[arg(env, long, default_value = "0.5")]
+    starve_ratio: f64,
+
+    /// Seconds a client may stay below the expected bitrate before it is flagged
+    #[arg(env, long, default_value = "10")]
+    starve_secs: u64,
+
+    /// Number of udp sockets shared by all clients, 0 gives each client its own socket.
+    /// Connectivity checks are routed per client, dtls and media by remote address, so
+    /// each shared socket carries one client per remote media address. Clients of a
+    /// single port server beyond that fail with a network error
+    #[arg(env, long, default_value = "0")]
+    shared_sockets: usize,
+
+    /// Number of dedicated worker threads running the clients, 0 uses the async-std scheduler
+    #[arg(env, long, default_value = "0")]
+    threads: usize,
+
     /// Enable UI
     #[arg(env, long, default_value = "false")]
     ui: bool,
+
+    /// Hide the progress line shown on a terminal stderr without the UI
+    #[arg(env, long)]
+    no_progress: bool,
+
+    /// Number of clients spawned or terminated by `+`/`-` keys in UI
+    #[arg(env, long, default_value = "10")]
+    scale_step: usize,
+
+    /// Number of connected clients the `k` key in UI asks for a keyframe at once,
+    /// the summary reports how long the keyframes took to arrive
+    #[arg(env, long, default_value = "10")]
+    keyframe_burst: usize,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
+    /// Role of this instance
+    #[arg(env, long, value_enum, default_value = "standalone")]
+    role: Role,
+
+    /// Worker addresses, used by coordinator
+    #[arg(env, long, value_delimiter = ',')]
+    workers: Vec<String>,
+
+    /// Listen address of the worker role
+    #[arg(env, long, default_value = "0.0.0.0:9000")]
+    listen: String,
+
+    /// Split the clients between this many local worker processes, streaming their
+    /// events back to this one. Uses all cores and avoids per-process fd limits
+    #[arg(env, long, default_value = "1")]
+    processes: usize,
+
+    /// Serve web dashboard and websocket event stream (/ws/events) on this address
+    #[arg(env, long)]
+    web_ui: Option<String>,
 }
 
-#[async_std::main]
+/// How the UI shows the clients
+#[derive(clap::Args, Debug)]
+struct ViewArgs {
+    /// Recent stats samples kept per client for the UI detail view, memory grows
+    /// with clients times depth
+    #[arg(env, long, default_value = "10")]
+    history_depth: usize,
+
+    /// Above this many clients the UI table collapses into expandable groups of 100
+    /// clients, `g` switches to grouping by health or turns it off
+    #[arg(env, long, default_value = "1000")]
+    group_threshold: usize,
+
+    /// Directory of the table snapshots the `s` key of the UI writes
+    #[arg(env, long, default_value = ".")]
+    snapshot_dir: PathBuf,
+
+    /// Loss fraction at which a client is shown as warning in UI
+    #[arg(env, long, default_value = "0.02")]
+    warn_loss: f32,
+
+    /// Loss fraction at which a client is shown as bad in UI
+    #[arg(env, long, default_value = "0.05")]
+    bad_loss: f32,
+
+    /// RTT in miliseconds at which a client is shown as warning in UI
+    #[arg(env, long, default_value = "150")]
+    warn_rtt: u32,
+
+    /// RTT in miliseconds at which a client is shown as bad in UI
+    #[arg(env, long, default_value = "300")]
+    bad_rtt: u32,
+
+    /// Recv bitrate in kbps below which a client is shown as warning in UI
+    #[arg(env, long, default_value = "300")]
+    warn_kbps: u64,
+
+    /// Recv bitrate in kbps below which a client is shown as bad in UI
+    #[arg(env, long, default_value = "100")]
+    bad_kbps: u64,
+}
+
+impl ViewArgs {
+    fn thresholds(&self) -> collector::HealthThresholds {
+        collector::HealthThresholds {
+            warn_loss: self.warn_loss,
+            bad_loss: self.bad_loss,
+            warn_rtt_ms: self.warn_rtt,
+            bad_rtt_ms: self.bad_rtt,
+            warn_kbps: self.warn_kbps,
+            bad_kbps: self.bad_kbps,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct ProbeArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// Connect one client instead, require media above a bitrate floor, print a JSON verdict
+    /// and exit 0 when healthy, 1 otherwise
+    #[arg(env, long, default_value = "false")]
+    healthcheck: bool,
+
+    /// Seconds of media required by health check
+    #[arg(env, long, default_value = "5")]
+    healthcheck_secs: u64,
+
+    /// Recv bitrate floor in kbps of health check
+    #[arg(env, long, default_value = "100")]
+    healthcheck_kbps: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReplayArgs {
+    /// Events written by `bench --record-events`
+    events: PathBuf,
+
+    /// Playback speed, 2 replays twice as fast and 0 as fast as possible. Times the
+    /// summary measures itself, like the connect times, scale with it
+    #[arg(long, default_value = "1")]
+    speed: f64,
+
+    /// Show the UI while replaying, it stays open until quit
+    #[arg(long)]
+    ui: bool,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
+    /// Write the summary as JSON to this file
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Write a standalone HTML report with charts to this file
+    #[arg(long)]
+    html_report: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct AgentArgs {
+    /// Listen address of the api
+    #[arg(env, long, default_value = "0.0.0.0:9000")]
+    listen: String,
+
+    /// Serve gRPC instead of REST, plans are controlled with `ControlPlan`
+    #[cfg(feature = "grpc")]
+    #[arg(env, long, default_value = "false")]
+    grpc: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct MockServerArgs {
+    /// Listen address of the http server, the whep endpoint is `/whep`
+    #[arg(env, long, default_value = "0.0.0.0:8080")]
+    listen: String,
+
+    /// Address announced in the ice candidate of the answers
+    #[arg(env, long, default_value = "127.0.0.1")]
+    candidate_ip: std::net::IpAddr,
+
+    /// Video bitrate of each session
+    #[arg(env, long, default_value = "1000")]
+    video_kbps: u64,
+
+    /// Interval between video keyframes, keyframe requests force one earlier
+    #[arg(env, long, default_value = "2000")]
+    keyframe_interval_ms: u64,
+
+    /// Refuse sessions beyond this many with 503
+    #[arg(env, long)]
+    max_sessions: Option<usize>,
+}
+
+/// Whep benchmarking tool
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// TOML file with options, keys are the long flag names. Flags and environment
+    /// variables override it. On SIGHUP a bench run reloads `count`, the health
+    /// thresholds and `stats_decimation` from it
+    #[arg(env, long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Named profile of the config file, e.g. `smoke` for `[profiles.smoke]`
+    #[arg(env, long, global = true, requires = "config")]
+    profile: Option<String>,
+
+    /// More logs, `-v` for client lifecycle, `-vv` for sdp and stats. RUST_LOG overrides it
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Only log errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[cfg_attr(not(feature = "tokio"), async_std::main)]
+#[cfg_attr(feature = "tokio", tokio::main)]
 async fn main() {
+    if let Some(path) = early_arg("config") {
+        apply_config(Path::new(&path), early_arg("profile").as_deref());
+    }
     let args: Args = Args::parse();
+    match &args.command {
+        Command::Bench(opts) => run_bench(&args, opts).await,
+        Command::Probe(opts) => run_probe(&args, opts).await,
+        Command::Report {
+            summary: path,
+            html,
+        } => {
+            let summary = compare::load_summary(path).unwrap_or_else(|e| {
+                println!("{}", e);
+                std::process::exit(2);
+            });
+            let Some(html) = html else {
+                println!("{}", summary);
+                return;
+            };
+            let title = format!("whep-benchmark {}", path.display());
+            if let Err(e) = std::fs::write(html, report::render_html(&summary, &title)) {
+                println!("cannot write {}: {}", html.display(), e);
+                std::process::exit(2);
+            }
+        }
+        Command::Compare {
+            old,
+            new,
+            tolerance,
+        } => {
+            let (old, new) = match (compare::load_summary(old), compare::load_summary(new)) {
+                (Ok(old), Ok(new)) => (old, new),
+                (Err(e), _) | (_, Err(e)) => {
+                    println!("{}", e);
+                    std::process::exit(2);
+                }
+            };
+            let deltas = compare::compare(&old, &new, *tolerance);
+            print!("{}", compare::DeltaTable(&deltas));
+            if deltas.iter().any(|d| d.regressed) {
+                std::process::exit(1);
+            }
+        }
+        Command::Agent(opts) => run_agent(&args, opts).await,
+        Command::MockServer(opts) => run_mock_server(&args, opts).await,
+        Command::Replay(opts) => run_replay(&args, opts).await,
+        Command::Scale { count, agent } => {
+            if let Err(e) = agent::request_target_count(agent, *count).await {
+                println!("{}", e);
+                std::process::exit(2);
+            }
+            println!("{} scaling to {} clients", agent, count);
+        }
+    }
+}
+
+async fn run_replay(args: &Args, opts: &ReplayArgs) {
+    if let Err(e) = replay::EventReader::open(&opts.events) {
+        Args::command()
+            .error(
+                ErrorKind::Io,
+                format!("cannot read {}: {}", opts.events.display(), e),
+            )
+            .exit()
+    }
+    let logs = logbuf::LogBuffer::new(200);
+    tracing_subscriber::registry()
+        .with((!opts.ui).then(fmt::layer))
+        .with(env_filter(args))
+        .with(logs.clone())
+        .init();
+
     let (event_tx, event_rx) = async_std::channel::unbounded::<bench::BenchEvent>();
+    let mut consumers = vec![];
+    let summary = Arc::new(Mutex::new(summary::SummaryCollector::default()));
+    let sampler = {
+        let summary = summary.clone();
+        async_std::task::spawn(async move {
+            loop {
+                summary.lock().sample(std::time::Instant::now());
+                async_std::task::sleep(std::time::Duration::from_secs(1)).await;
+            }
+        })
+    };
+    let (summary_tx, summary_rx) = async_std::channel::unbounded();
+    consumers.push(("summary", summary_tx));
+    let summary_task = {
+        let summary = summary.clone();
+        async_std::task::spawn(async move {
+            while let Ok(event) = summary_rx.recv().await {
+                summary.lock().on_event(&event);
+            }
+        })
+    };
 
-    if args.ui {
-        std::thread::spawn(|| {
-            dioxus_tui::launch_cfg_with_props(
-                dioxus_app,
-                tui::AppProps {
-                    rx: Arc::new(event_rx),
-                },
-                Config::default(),
-            );
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let (quit_tx, quit_rx) = async_std::channel::bounded::<()>(1);
+    let mut tui_thread = None;
+    if opts.ui {
+        let (tui_tx, tui_rx) = async_std::channel::unbounded();
+        consumers.push(("tui", tui_tx));
+        let props = tui::AppProps {
+            rx: tui_rx,
+            control: None,
+            scale_step: 0,
+            keyframe_burst: 0,
+            history_depth: opts.view.history_depth,
+            group_threshold: opts.view.group_threshold,
+            thresholds: Arc::new(RwLock::new(opts.view.thresholds())),
+            logs,
+            shutdown: shutdown.clone(),
+            quit: quit_tx,
+            snapshot_dir: opts.view.snapshot_dir.clone(),
+        };
+        tui_thread = Some(std::thread::spawn(move || {
+            if let Err(e) = tui::run_tui(props) {
+                log::error!("[Tui] terminal error {:?}", e);
+            }
+        }));
+    }
+    async_std::task::spawn(bench::fanout(event_rx, consumers));
+
+    let path = opts.events.clone();
+    let speed = opts.speed;
+    let replayed = async_std::task::spawn_blocking(move || replay::replay(&path, speed, event_tx));
+    let replayed = async {
+        match replayed.await {
+            Ok(count) => log::info!("[Replay] replayed {} events", count),
+            Err(e) => log::error!("[Replay] replay stopped {:?}", e),
+        }
+    };
+    if until_aborted(replayed, &quit_rx).await {
+        // the UI stays open for looking around until quit
+        if tui_thread.is_some() {
+            let _ = quit_rx.recv().await;
+        } else {
+            summary_task.await;
+        }
+    }
+    shutdown.store(true, Ordering::Relaxed);
+    if let Some(thread) = tui_thread {
+        let _ = thread.join();
+    }
+    sampler.cancel().await;
+
+    let summary = summary.lock().summary();
+    println!("{}", summary);
+    if let Some(path) = &opts.output {
+        let json = serde_json::to_string_pretty(&summary).expect("should serialize summary");
+        if let Err(e) = std::fs::write(path, json) {
+            log::error!("[Main] cannot write summary to {} {:?}", path.display(), e);
+        }
+    }
+    if let Some(path) = &opts.html_report {
+        let title = format!("whep-benchmark replay of {}", opts.events.display());
+        if let Err(e) = std::fs::write(path, report::render_html(&summary, &title)) {
+            log::error!("[Main] cannot write report to {} {:?}", path.display(), e);
+        }
+    }
+}
+
+async fn run_probe(args: &Args, opts: &ProbeArgs) {
+    validate_target(&opts.target);
+    // a templated url probes the stream of the first client
+    let url = urltemplate::render(url(&opts.target), 1).expect("should be a valid url template");
+    if opts.healthcheck {
+        // stdout is reserved for the verdict
+        tracing_subscriber::registry()
+            .with(fmt::layer().with_writer(std::io::stderr))
+            .with(env_filter(args))
+            .init();
+        let token = token_source(&opts.target).await.get();
+        let verdict = healthcheck::run_healthcheck(
+            &url,
+            &token,
+            &client_config(&opts.client),
+            std::time::Duration::from_secs(opts.healthcheck_secs),
+            opts.healthcheck_kbps,
+        )
+        .await;
+        println!(
+            "{}",
+            serde_json::to_string(&verdict).expect("should serialize verdict")
+        );
+        std::process::exit(if verdict.healthy { 0 } else { 1 });
+    }
+
+    tracing_subscriber::registry()
+        .with(fmt::layer())
+        .with(env_filter(args))
+        .init();
+    let token = token_source(&opts.target).await.get();
+    if let Err(e) = probe::run_probe(&url, &token, &client_config(&opts.client)).await {
+        println!("probe failed: {:?}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run_agent(args: &Args, opts: &AgentArgs) {
+    tracing_subscriber::registry()
+        .with(fmt::layer())
+        .with(env_filter(args))
+        .init();
+
+    #[cfg(feature = "grpc")]
+    if opts.grpc {
+        let listen = opts.listen.parse().unwrap_or_else(|e| {
+            Args::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    format!("invalid listen address: {}", e),
+                )
+                .exit()
         });
+        whep_benchmark::grpc::run_grpc(listen)
+            .await
+            .expect("should run grpc service");
+        return;
     }
 
+    agent::run_agent(&opts.listen)
+        .await
+        .expect("should run agent");
+}
+
+async fn run_mock_server(args: &Args, opts: &MockServerArgs) {
     tracing_subscriber::registry()
         .with(fmt::layer())
-        .with(EnvFilter::from_default_env())
+        .with(env_filter(args))
+        .init();
+
+    let config = mockserver::MockConfig {
+        candidate_ip: opts.candidate_ip,
+        video_kbps: opts.video_kbps,
+        keyframe_interval: std::time::Duration::from_millis(opts.keyframe_interval_ms),
+        max_sessions: opts.max_sessions,
+    };
+    mockserver::run_mock_server(&opts.listen, config)
+        .await
+        .expect("should run mock server");
+}
+
+async fn run_bench(args: &Args, opts: &BenchArgs) {
+    validate_bench(opts);
+    // loaded up front so a bad path fails before the run, not after it
+    let baseline = opts.baseline.as_ref().map(|path| {
+        compare::load_summary(path)
+            .unwrap_or_else(|e| Args::command().error(ErrorKind::InvalidValue, e).exit())
+    });
+
+    let run_id = opts
+        .run_id
+        .clone()
+        .unwrap_or_else(|| format!("{:08x}", rand::random::<u32>()));
+    let logs = logbuf::LogBuffer::new(200);
+    let (event_tx, event_rx) = async_std::channel::unbounded::<bench::BenchEvent>();
+    let mut consumers = vec![];
+    let (control_tx, control_rx) = async_std::channel::unbounded::<bench::BenchControl>();
+    let shutdown = Arc::new(AtomicBool::new(false));
+    // kept open until the end, the runs below race against it
+    let (abort_tx, abort_rx) = async_std::channel::bounded::<()>(1);
+    if opts.role != Role::Worker {
+        watch_interrupt(abort_tx.clone());
+    }
+
+    let summary = Arc::new(Mutex::new(summary::SummaryCollector::with_warmup(
+        opts.warmup,
+    )));
+    summary.lock().set_ab(!opts.ab.is_empty());
+    summary.lock().set_client_timelines(opts.client_timelines);
+    summary.lock().set_expectation(opts.expect);
+    {
+        let summary = summary.clone();
+        async_std::task::spawn(async move {
+            loop {
+                summary.lock().sample(std::time::Instant::now());
+                async_std::task::sleep(std::time::Duration::from_secs(1)).await;
+            }
+        });
+    }
+    let (summary_tx, summary_rx) = async_std::channel::unbounded();
+    consumers.push(("summary", summary_tx));
+    let summary_task = {
+        let summary = summary.clone();
+        async_std::task::spawn(async move {
+            while let Ok(event) = summary_rx.recv().await {
+                summary.lock().on_event(&event);
+            }
+        })
+    };
+
+    let thresholds = Arc::new(RwLock::new(opts.view.thresholds()));
+    if let Some(path) = args.config.clone() {
+        let current = reload::Reloadable {
+            count: opts.count,
+            thresholds: opts.view.thresholds(),
+            decimation: opts.stats_decimation,
+        };
+        async_std::task::spawn(reload::run_reload(
+            path,
+            args.profile.clone(),
+            current,
+            thresholds.clone(),
+            control_tx.clone(),
+        ));
+    }
+
+    let mut tui_thread = None;
+    if opts.ui {
+        let (tui_tx, tui_rx) = async_std::channel::unbounded();
+        consumers.push(("tui", tui_tx));
+        let control = (opts.role == Role::Standalone).then(|| control_tx.clone());
+        let scale_step = opts.scale_step;
+        let keyframe_burst = opts.keyframe_burst;
+        let history_depth = opts.view.history_depth;
+        let group_threshold = opts.view.group_threshold;
+        let snapshot_dir = opts.view.snapshot_dir.clone();
+        let logs = logs.clone();
+        let shutdown = shutdown.clone();
+        let quit = abort_tx.clone();
+        let thresholds = thresholds.clone();
+        tui_thread = Some(std::thread::spawn(move || {
+            let props = tui::AppProps {
+                rx: tui_rx,
+                control,
+                scale_step,
+                keyframe_burst,
+                history_depth,
+                group_threshold,
+                thresholds,
+                logs,
+                shutdown,
+                quit,
+                snapshot_dir,
+            };
+            if let Err(e) = tui::run_tui(props) {
+                log::error!("[Tui] terminal error {:?}", e);
+            }
+        }));
+    }
+
+    if let Some(addr) = opts.statsd_addr.clone() {
+        let (statsd_tx, statsd_rx) = async_std::channel::unbounded();
+        consumers.push(("statsd", statsd_tx));
+        let mut tags = vec![("run_id".to_string(), run_id.clone())];
+        if let Some(host) = opts
+            .target
+            .url
+            .as_deref()
+            .and_then(|u| url::Url::parse(u).ok())
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+        {
+            tags.push(("target".to_string(), host));
+        }
+        let config = statsd::StatsdConfig {
+            addr,
+            prefix: opts.statsd_prefix.clone(),
+            interval: std::time::Duration::from_millis(opts.statsd_interval_ms),
+            tags,
+        };
+        async_std::task::spawn(async move {
+            if let Err(e) = statsd::run_statsd(config, statsd_rx).await {
+                log::error!("[Statsd] exporter error {:?}", e);
+            }
+        });
+    }
+
+    if let Some(dir) = opts.client_log_dir.clone() {
+        let (client_log_tx, client_log_rx) = async_std::channel::unbounded();
+        consumers.push(("client_log", client_log_tx));
+        std::thread::spawn(move || clientlog::run_client_logs(dir, client_log_rx));
+    }
+
+    if let Some(path) = opts.record.clone() {
+        let (record_tx, record_rx) = async_std::channel::unbounded();
+        consumers.push(("record", record_tx));
+        let run_id = run_id.clone();
+        std::thread::spawn(move || record::run_recorder(&path, &run_id, record_rx));
+    }
+    if let Some(path) = opts.record_events.clone() {
+        let (recording_tx, recording_rx) = async_std::channel::unbounded();
+        consumers.push(("record_events", recording_tx));
+        std::thread::spawn(move || replay::run_event_recorder(&path, recording_rx));
+    }
+
+    let har_task = opts.har.clone().map(|path| {
+        let (har_tx, har_rx) = async_std::channel::unbounded();
+        consumers.push(("har", har_tx));
+        async_std::task::spawn_blocking(move || whep_benchmark::har::run_har(&path, har_rx))
+    });
+
+    #[cfg(feature = "wav")]
+    if let Some(dir) = &opts.wav_dir {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            log::error!("[Main] cannot create {} {:?}", dir.display(), e);
+        }
+    }
+
+    #[cfg(feature = "plugins")]
+    let plugin_task = (!opts.plugin.is_empty()).then(|| {
+        let plugins = opts
+            .plugin
+            .iter()
+            .map(|path| {
+                whep_benchmark::plugin::Plugin::load(path).unwrap_or_else(|e| {
+                    Args::command()
+                        .error(
+                            ErrorKind::InvalidValue,
+                            format!("cannot load plugin {}: {}", path.display(), e),
+                        )
+                        .exit()
+                })
+            })
+            .collect();
+        let (plugin_tx, plugin_rx) = async_std::channel::unbounded();
+        consumers.push(("plugin", plugin_tx));
+        async_std::task::spawn_blocking(move || {
+            whep_benchmark::plugin::run_plugins(plugins, plugin_rx)
+        })
+    });
+
+    #[cfg(feature = "parquet")]
+    let parquet_task = opts.export_parquet.clone().map(|dir| {
+        let (parquet_tx, parquet_rx) = async_std::channel::unbounded();
+        consumers.push(("parquet", parquet_tx));
+        let run_id = run_id.clone();
+        async_std::task::spawn_blocking(move || {
+            whep_benchmark::columnar::run_parquet(&dir, &run_id, parquet_rx)
+        })
+    });
+
+    if let Some(EventFormat::Ndjson) = opts.events {
+        let (events_tx, events_rx) = async_std::channel::unbounded();
+        consumers.push(("events", events_tx));
+        match opts.events_file.as_ref() {
+            Some(path) => {
+                let file = std::fs::File::create(path).unwrap_or_else(|e| {
+                    Args::command()
+                        .error(ErrorKind::Io, format!("cannot open events file: {}", e))
+                        .exit()
+                });
+                std::thread::spawn(move || events::run_ndjson(file, events_rx));
+            }
+            None => {
+                std::thread::spawn(move || events::run_ndjson(std::io::stdout(), events_rx));
+            }
+        }
+    }
+    let events_on_stdout = opts.events.is_some() && opts.events_file.is_none();
+    let json = opts.format == OutputFormat::Json;
+
+    // logs share stderr with the event stream, a redrawn line would garble them
+    let progress_task = (!opts.ui
+        && !opts.no_progress
+        && !args.quiet
+        && !events_on_stdout
+        && opts.role != Role::Worker
+        && std::io::stderr().is_terminal())
+    .then(|| {
+        let (progress_tx, progress_rx) = async_std::channel::unbounded();
+        consumers.push(("progress", progress_tx));
+        let duration = opts.duration.map(std::time::Duration::from_secs);
+        async_std::task::spawn(progress::run_progress(opts.count, duration, progress_rx))
+    });
+
+    // the console prompt would interleave with the event stream
+    if !opts.ui && opts.role == Role::Standalone && !events_on_stdout && !json {
+        let (console_tx, console_rx) = async_std::channel::unbounded();
+        consumers.push(("console", console_tx));
+        async_std::task::spawn(console::run_console(control_tx.clone(), console_rx));
+    }
+
+    if let Some(listen) = opts.web_ui.clone() {
+        let (web_tx, web_rx) = async_std::channel::unbounded();
+        consumers.push(("web", web_tx));
+        let logs = logs.clone();
+        async_std::task::spawn(async move {
+            web::run_web_ui(&listen, web_rx, logs)
+                .await
+                .expect("should run web ui");
+        });
+    }
+
+    let aborted = Arc::new(Mutex::new(None::<watchdog::Abort>));
+    let mut abort_rules = opts.abort_if.clone();
+    if let Some(ratio) = opts.max_failure_ratio {
+        // a plan smaller than the minimum is checked once all of its clients started
+        let min_clients = opts.failure_ratio_min_clients.min(opts.count);
+        abort_rules.push(watchdog::AbortRule::max_failure_ratio(ratio, min_clients));
+    }
+    if !abort_rules.is_empty() {
+        let (watchdog_tx, watchdog_rx) = async_std::channel::unbounded();
+        consumers.push(("watchdog", watchdog_tx));
+        let rules = abort_rules;
+        let aborted = aborted.clone();
+        let abort_tx = abort_tx.clone();
+        async_std::task::spawn(async move {
+            if let Some(abort) = watchdog::run_watchdog(rules, watchdog_rx).await {
+                log::error!("[Watchdog] {}, stopping", abort);
+                *aborted.lock() = Some(abort);
+                let _ = abort_tx.send(()).await;
+            }
+        });
+    }
+
+    let ramp_task =
+        (opts.ramp_post_p95_ms.is_some() || opts.ramp_error_rate.is_some()).then(|| {
+            let guard = ramp::RampGuard {
+                post_p95_ms: opts.ramp_post_p95_ms,
+                error_rate: opts.ramp_error_rate,
+                window: std::time::Duration::from_secs(opts.ramp_window_secs),
+            };
+            let (ramp_tx, ramp_rx) = async_std::channel::unbounded();
+            consumers.push(("ramp", ramp_tx));
+            async_std::task::spawn(ramp::run_ramp_guard(
+                guard,
+                opts.count,
+                opts.sustain,
+                control_tx.clone(),
+                ramp_rx,
+            ))
+        });
+
+    let sweep_task = opts.sweep.map(|plan| {
+        let (sweep_tx, sweep_rx) = async_std::channel::unbounded();
+        consumers.push(("sweep", sweep_tx));
+        // the sweep adds clients one by one
+        let interval = arrival_interval(opts) / opts.rate.map_or(1, |rate| rate.burst as u32);
+        async_std::task::spawn(sweep::run_sweep(
+            plan,
+            interval,
+            control_tx.clone(),
+            sweep_rx,
+        ))
+    });
+
+    let soak_rollups = Arc::new(Mutex::new(vec![]));
+    let soak_config = opts.soak.then(|| soak::SoakConfig {
+        dir: opts.soak_dir.clone(),
+        rollup: std::time::Duration::from_secs(opts.soak_rollup_secs),
+    });
+    let soak_task = soak_config.clone().map(|config| {
+        async_std::task::spawn(soak::run_soak(
+            config,
+            summary.clone(),
+            soak_rollups.clone(),
+        ))
+    });
+
+    let stopped = Arc::new(Mutex::new(None::<watchdog::Stop>));
+    if !opts.stop_when.is_empty() {
+        let (stop_tx, stop_rx) = async_std::channel::unbounded();
+        consumers.push(("stop", stop_tx));
+        let conditions = opts.stop_when.clone();
+        let stopped = stopped.clone();
+        let abort_tx = abort_tx.clone();
+        async_std::task::spawn(async move {
+            if let Some(stop) = watchdog::run_stop_conditions(conditions, stop_rx).await {
+                log::info!("[Main] {}, disconnecting clients", stop);
+                *stopped.lock() = Some(stop);
+                let _ = abort_tx.send(()).await;
+            }
+        });
+    }
+
+    if opts.gate_live {
+        let gates = opts.gate.clone();
+        let summary = summary.clone();
+        let aborted = aborted.clone();
+        let abort_tx = abort_tx.clone();
+        let warmup = opts.warmup;
+        async_std::task::spawn(async move {
+            loop {
+                async_std::task::sleep(std::time::Duration::from_secs(1)).await;
+                let summary = summary.lock();
+                if summary.elapsed() < warmup {
+                    continue;
+                }
+                // a metric without samples yet is not a failure while running
+                let failed = gates
+                    .iter()
+                    .map(|gate| gate.evaluate(&summary))
+                    .find(|result| result.value.is_some() && !result.passed);
+                if let Some(result) = failed {
+                    log::error!("[Main] {}, stopping", result);
+                    *aborted.lock() = Some(watchdog::Abort {
+                        rule: format!("gate {}", result.gate),
+                        value: result.value.unwrap_or_default(),
+                        after_ms: summary.elapsed().as_millis() as u64,
+                    });
+                    let _ = abort_tx.try_send(());
+                    break;
+                }
+            }
+        });
+    }
+
+    async_std::task::spawn(bench::fanout(event_rx, consumers));
+
+    // the UI renders captured logs itself, writing to stdout would break its screen
+    let log_file = opts.log_file.as_ref().map(|path| {
+        let max_age = opts
+            .log_file_rotate_secs
+            .map(std::time::Duration::from_secs);
+        let file = logfile::RotatingFile::open(
+            path,
+            opts.log_file_max_mb * 1024 * 1024,
+            max_age,
+            opts.log_file_keep,
+        )
+        .unwrap_or_else(|e| {
+            Args::command()
+                .error(ErrorKind::Io, format!("cannot open log file: {}", e))
+                .exit()
+        });
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(std::sync::Mutex::new(file))
+    });
+    tracing_subscriber::registry()
+        .with((!opts.ui && !events_on_stdout && !json).then(fmt::layer))
+        .with((events_on_stdout || json).then(|| fmt::layer().with_writer(std::io::stderr)))
+        .with(log_file)
+        .with(if json && args.quiet {
+            EnvFilter::new("off")
+        } else {
+            env_filter(args)
+        })
+        .with(logs)
         .init();
 
+    let delete_limits =
+        (opts.shutdown_concurrency.is_some() || opts.shutdown_rate.is_some()).then(|| {
+            teardown::DeleteLimits {
+                concurrency: opts.shutdown_concurrency.unwrap_or(0),
+                per_sec: opts.shutdown_rate,
+            }
+        });
     let plan = bench::BenchPlan {
-        count: args.count,
-        interval: std::time::Duration::from_millis(args.interval),
-        live: std::time::Duration::from_millis(args.live),
+        count: opts.sweep.map_or(opts.count, |sweep| sweep.start),
+        interval: arrival_interval(opts),
+        arrival: opts.arrival,
+        burst: opts.rate.map_or(1, |rate| rate.burst),
+        seed: opts.seed,
+        // sweeping clients stay until the sweep stops the runner
+        live: match opts.sweep {
+            Some(_) => std::time::Duration::from_secs(u32::MAX as u64),
+            None => std::time::Duration::from_millis(opts.live),
+        },
+        duration: opts.duration.map(std::time::Duration::from_secs),
+        client: whep::ClientConfig {
+            run_id: Some(run_id.clone()),
+            ..client_config(&opts.client)
+        },
+        shared_sockets: opts.shared_sockets,
+        threads: opts.threads,
+        sustain: opts.sustain,
+        loops: opts.loops,
+        fault: opts.fault_percent.map(|percent| bench::FaultPlan {
+            percent,
+            after: std::time::Duration::from_millis(opts.fault_after_ms),
+            mode: opts.fault_mode,
+        }),
+        labels: opts.label.clone(),
+        mix: opts.mix.clone(),
+        record_http: opts.har.as_ref().map(|_| opts.har_percent),
+        #[cfg(feature = "wav")]
+        audio_dump: opts.wav_dir.clone().map(|dir| bench::AudioDumpPlan {
+            dir,
+            percent: opts.wav_percent,
+        }),
+        #[cfg(not(feature = "wav"))]
+        audio_dump: None,
+        script: opts.script.as_ref().map(|path| load_script(path)),
+        delete_limits,
+        decimation: opts.stats_decimation,
+        ramp_delay: opts.ramp_delay,
+        max_concurrent_connects: opts.max_concurrent_connects,
+        clients: opts
+            .clients_file
+            .as_ref()
+            .map_or_else(Vec::new, |path| load_clients(path)),
+        chaos: opts.chaos_percent.map(|percent| bench::ChaosPlan {
+            percent,
+            interval: std::time::Duration::from_secs(opts.chaos_interval_secs),
+            mode: opts.fault_mode,
+        }),
+        starve: (opts.expected_audio_kbps.is_some() || opts.expected_video_kbps.is_some()).then(
+            || bench::StarvePlan {
+                audio_kbps: opts.expected_audio_kbps,
+                video_kbps: opts.expected_video_kbps,
+                ratio: opts.starve_ratio,
+                after: std::time::Duration::from_secs(opts.starve_secs),
+            },
+        ),
+    };
+
+    let webhook = opts
+        .webhook_url
+        .as_deref()
+        .filter(|_| opts.role != Role::Worker)
+        .map(webhook::Webhook::new);
+    if let Some(webhook) = &webhook {
+        let started = webhook::Notification::started(&run_id, bench_url(opts), opts.count);
+        webhook.notify(&started).await;
+    }
+
+    let mut shutdown_report = None;
+    match opts.role {
+        Role::Standalone if opts.processes > 1 => {
+            let inherit_stdout = !opts.ui && !events_on_stdout;
+            let workers = procs::WorkerProcesses::spawn(opts.processes, inherit_stdout)
+                .await
+                .unwrap_or_else(|e| {
+                    Args::command()
+                        .error(
+                            ErrorKind::Io,
+                            format!("cannot start worker processes: {}", e),
+                        )
+                        .exit()
+                });
+            // workers get the current token, they do not see refreshes
+            let token = token_source(&opts.target).await.get();
+            let run = cluster::run_coordinator(
+                workers.addrs(),
+                url(&opts.target),
+                &token,
+                plan,
+                event_tx,
+            );
+            if !until_aborted(run, &abort_rx).await {
+                // workers stop their plan once we hang up, give them time to disconnect
+                async_std::task::sleep(bench::SHUTDOWN_GRACE).await;
+            }
+        }
+        Role::Standalone => {
+            let tokens = token_source(&opts.target).await;
+            let mut runner =
+                bench::BenchRunner::new(bench_url(opts), &tokens.get(), plan, event_tx);
+            runner.set_token_source(tokens);
+            if let Some(url) = opts.ab.get(1) {
+                runner.set_ab_url(url);
+            }
+            runner.set_control(control_rx);
+            if !until_aborted(runner.run(), &abort_rx).await {
+                runner.shutdown(bench::SHUTDOWN_GRACE).await;
+            }
+            shutdown_report = runner.shutdown_report();
+        }
+        Role::Coordinator => {
+            // workers get the current token, they do not see refreshes
+            let token = token_source(&opts.target).await.get();
+            let run =
+                cluster::run_coordinator(&opts.workers, url(&opts.target), &token, plan, event_tx);
+            until_aborted(run, &abort_rx).await;
+        }
+        Role::Worker => {
+            cluster::run_worker(&opts.listen)
+                .await
+                .expect("should run worker");
+        }
+    }
+
+    // every client is done, close the UI before printing to the terminal
+    shutdown.store(true, Ordering::Relaxed);
+    if let Some(thread) = tui_thread {
+        let _ = thread.join();
+    }
+    // let the summary consume events still in flight
+    let _ = async_std::future::timeout(std::time::Duration::from_secs(1), summary_task).await;
+    if let Some(task) = progress_task {
+        let _ = async_std::future::timeout(std::time::Duration::from_secs(1), task).await;
+    }
+    // the parquet footer is written once the event stream ended
+    #[cfg(feature = "parquet")]
+    if let Some(task) = parquet_task {
+        let _ = async_std::future::timeout(std::time::Duration::from_secs(10), task).await;
+    }
+    if let Some(task) = har_task {
+        let _ = async_std::future::timeout(std::time::Duration::from_secs(10), task).await;
+    }
+    #[cfg(feature = "plugins")]
+    let plugins = match plugin_task {
+        Some(task) => async_std::future::timeout(std::time::Duration::from_secs(10), task)
+            .await
+            .unwrap_or_default(),
+        None => Default::default(),
+    };
+    #[cfg(not(feature = "plugins"))]
+    let plugins = Default::default();
+    let ramp = match ramp_task {
+        Some(task) => async_std::future::timeout(std::time::Duration::from_secs(1), task)
+            .await
+            .unwrap_or_default(),
+        None => vec![],
+    };
+    let sweep = match sweep_task {
+        Some(task) => async_std::future::timeout(std::time::Duration::from_secs(1), task)
+            .await
+            .unwrap_or_default(),
+        None => vec![],
+    };
+    let gates: Vec<gate::GateResult> = {
+        let summary = summary.lock();
+        opts.gate
+            .iter()
+            .map(|gate| gate.evaluate(&summary))
+            .collect()
+    };
+    let gates_failed = gates.iter().any(|result| !result.passed);
+    // the last window is partial, it is written like the others
+    let last = match (soak_task, &soak_config) {
+        (Some(task), Some(config)) => {
+            task.cancel().await;
+            soak::roll(config, &summary, &soak_rollups)
+        }
+        _ => summary.lock().summary(),
+    };
+    let summary = summary::RunSummary {
+        aborted: aborted.lock().clone(),
+        stopped: stopped.lock().clone(),
+        ramp,
+        sweep,
+        shutdown: shutdown_report,
+        soak: soak_rollups.lock().clone(),
+        plugins,
+        ..last
+    };
+    if events_on_stdout {
+        eprintln!("{}", summary);
+    } else if !json {
+        println!("{}", summary);
+    }
+    if let Some(path) = &opts.output {
+        let json = serde_json::to_string_pretty(&summary).expect("should serialize summary");
+        if let Err(e) = std::fs::write(path, json) {
+            log::error!("[Main] cannot write summary to {} {:?}", path.display(), e);
+        }
+    }
+    if let Some(path) = &opts.html_report {
+        let html = report::render_html(&summary, &format!("whep-benchmark run {}", run_id));
+        if let Err(e) = std::fs::write(path, html) {
+            log::error!("[Main] cannot write report to {} {:?}", path.display(), e);
+        }
+    }
+    if let Some(path) = &opts.summary_md {
+        let title = format!("whep-benchmark run {}", run_id);
+        let markdown = report::render_markdown(&summary, &gates, &title);
+        if let Err(e) = std::fs::write(path, markdown) {
+            log::error!("[Main] cannot write summary to {} {:?}", path.display(), e);
+        }
+    }
+    let deltas = baseline.map(|baseline| compare::compare(&baseline, &summary, opts.tolerance));
+    let regressed = deltas.iter().flatten().any(|d| d.regressed);
+    if let (Some(deltas), false) = (&deltas, json) {
+        print!("{}", compare::DeltaTable(deltas));
+        if regressed {
+            println!("regression against baseline");
+        }
+    }
+    if !json {
+        for result in &gates {
+            println!("{}", result);
+        }
+    }
+    if let Some(webhook) = &webhook {
+        let notification = match &summary.aborted {
+            Some(abort) => webhook::Notification::aborted(&run_id, abort, &summary),
+            None => webhook::Notification::completed(&run_id, &summary),
+        };
+        webhook.notify(&notification).await;
+        if regressed {
+            let violation =
+                webhook::Notification::violation(&run_id, "regression against baseline", &summary);
+            webhook.notify(&violation).await;
+        }
+        for result in gates.iter().filter(|result| !result.passed) {
+            let violation =
+                webhook::Notification::violation(&run_id, &result.to_string(), &summary);
+            webhook.notify(&violation).await;
+        }
+    }
+    let exit_code = if regressed || gates_failed {
+        1
+    } else if summary.aborted.is_some() {
+        3
+    } else {
+        0
+    };
+    if json {
+        let result = serde_json::json!({
+            "run_id": run_id,
+            "exit_code": exit_code,
+            "regressed": regressed,
+            "baseline": deltas,
+            "gates": gates,
+            "summary": summary,
+        });
+        println!(
+            "{}",
+            serde_json::to_string(&result).expect("should serialize result")
+        );
+    }
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+}
+
+/// Ctrl+C and SIGTERM stop the run like the UI quit key, clients delete their
+/// sessions before the summary is printed. A second Ctrl+C exits right away
+fn watch_interrupt(abort_tx: async_std::channel::Sender<()>) {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        let res = signal_hook::flag::register_conditional_shutdown(signal, 1, interrupted.clone())
+            .and_then(|_| signal_hook::flag::register(signal, interrupted.clone()));
+        if let Err(e) = res {
+            log::warn!("[Main] cannot handle signal {} {:?}", signal, e);
+        }
+    }
+    async_std::task::spawn(async move {
+        while !interrupted.load(Ordering::Relaxed) {
+            async_std::task::sleep(std::time::Duration::from_millis(200)).await;
+        }
+        log::warn!("[Main] interrupted, disconnecting clients");
+        let _ = abort_tx.try_send(());
+    });
+}
+
+/// Drive a run until it finished, the watchdog fired or the user quit, true when it finished
+async fn until_aborted(
+    run: impl std::future::Future<Output = ()>,
+    abort_rx: &async_std::channel::Receiver<()>,
+) -> bool {
+    let run = std::pin::pin!(run);
+    let abort = std::pin::pin!(abort_rx.recv());
+    matches!(
+        futures_util::future::select(run, abort).await,
+        futures_util::future::Either::Left(_)
+    )
+}
+
+/// Find `--<name>` or its env var before clap runs, the config file has to be applied first
+fn early_arg(name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix(&flag).and_then(|a| a.strip_prefix('=')) {
+            return Some(value.to_string());
+        }
+    }
+    std::env::var(name.to_ascii_uppercase()).ok()
+}
+
+/// Options of the config file become environment variables where those are not
+/// set, so the environment and flags take precedence over the file
+fn apply_config(path: &Path, profile: Option<&str>) {
+    let fail = |message: String| -> ! {
+        Args::command()
+            .error(ErrorKind::InvalidValue, message)
+            .exit()
     };
+    let command = Args::command();
+    let arguments = all_arguments(&command);
+    for (name, values) in config::load(path, profile).unwrap_or_else(|e| fail(e)) {
+        let matching: Vec<&clap::Arg> = arguments
+            .iter()
+            .copied()
+            .filter(|arg| {
+                arg.get_id() == name.as_str()
+                    || arg.get_long().map(|l| l.replace('-', "_")).as_deref() == Some(name.as_str())
+            })
+            .collect();
+        if matching.is_empty() {
+            fail(format!("unknown option {} in config", name));
+        }
+        let Some((arg, env)) = matching
+            .iter()
+            .find_map(|arg| arg.get_env().map(|env| (arg, env)))
+        else {
+            fail(format!("option {} cannot be set in config", name))
+        };
+        if std::env::var_os(env).is_some() {
+            continue;
+        }
+        let value = match (values.len(), arg.get_value_delimiter()) {
+            (1, _) => values[0].clone(),
+            (_, Some(delimiter)) => values.join(&delimiter.to_string()),
+            (_, None) => fail(format!("option {} takes a single value", name)),
+        };
+        std::env::set_var(env, value);
+    }
+}
+
+/// Arguments of the command and its subcommands, an option shared by several
+/// subcommands reads the same environment variable in each
+fn all_arguments(command: &clap::Command) -> Vec<&clap::Arg> {
+    command
+        .get_arguments()
+        .chain(command.get_subcommands().flat_map(all_arguments))
+        .collect()
+}
+
+fn validate_target(target: &TargetArgs) {
+    if target.url.is_none() {
+        Args::command()
+            .error(ErrorKind::MissingRequiredArgument, "--url is required")
+            .exit();
+    }
+    if let Err(e) = urltemplate::validate(url(target)) {
+        Args::command().error(ErrorKind::InvalidValue, e).exit();
+    }
+}
 
-    let mut runner = bench::BenchRunner::new(&args.url, &args.token, plan, event_tx);
-    runner.bootstrap().await;
-    loop {
-        async_std::task::sleep(std::time::Duration::from_secs(1)).await;
+/// Source of the script, exits when it cannot be read or compiled
+fn load_script(path: &Path) -> String {
+    let source = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        Args::command()
+            .error(ErrorKind::Io, format!("cannot read script: {}", e))
+            .exit()
+    });
+    if let Err(e) = script::Script::compile(&source) {
+        Args::command()
+            .error(ErrorKind::InvalidValue, format!("invalid script: {}", e))
+            .exit()
+    }
+    source
+}
+
+/// Entries of the clients file, exits when it cannot be read or parsed
+fn load_clients(path: &Path) -> Vec<roster::ClientEntry> {
+    let source = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        Args::command()
+            .error(ErrorKind::Io, format!("cannot read clients file: {}", e))
+            .exit()
+    });
+    roster::parse(&source)
+        .unwrap_or_else(|e| Args::command().error(ErrorKind::InvalidValue, e).exit())
+}
+
+fn validate_bench(opts: &BenchArgs) {
+    // workers receive the target with their plan
+    if !opts.ab.is_empty() {
+        if opts.ab.len() != 2 {
+            Args::command()
+                .error(ErrorKind::InvalidValue, "--ab needs two urls")
+                .exit();
+        }
+        if opts.role != Role::Standalone || opts.processes > 1 {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--ab is only supported in standalone role with one process",
+                )
+                .exit();
+        }
+    } else if opts.role != Role::Worker {
+        validate_target(&opts.target);
+    }
+    if opts.ui && opts.events.is_some() && opts.events_file.is_none() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--events needs --events-file when the UI owns the terminal",
+            )
+            .exit();
     }
+    if opts.format == OutputFormat::Json
+        && (opts.ui || (opts.events.is_some() && opts.events_file.is_none()))
+    {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--format json owns stdout, it cannot be combined with --ui or --events without --events-file",
+            )
+            .exit();
+    }
+    if opts.soak && opts.soak_rollup_secs == 0 {
+        Args::command()
+            .error(
+                ErrorKind::InvalidValue,
+                "--soak-rollup-secs must be at least 1",
+            )
+            .exit();
+    }
+    if opts.sweep.is_some() && (opts.role != Role::Standalone || opts.processes > 1) {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--sweep is only supported in standalone role with one process",
+            )
+            .exit();
+    }
+    if (opts.ramp_post_p95_ms.is_some() || opts.ramp_error_rate.is_some())
+        && (opts.role != Role::Standalone || opts.processes > 1)
+    {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--ramp-post-p95-ms and --ramp-error-rate are only supported in standalone role with one process",
+            )
+            .exit();
+    }
+    if opts.processes > 1 && opts.role != Role::Standalone {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--processes is only supported in standalone role",
+            )
+            .exit();
+    }
+    if opts.role == Role::Coordinator && opts.workers.is_empty() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--workers is required for coordinator role",
+            )
+            .exit();
+    }
+    // every worker needs at least one slot of the shared bound
+    let workers = match opts.role {
+        Role::Coordinator => opts.workers.len(),
+        _ => opts.processes,
+    };
+    if opts
+        .max_concurrent_connects
+        .map_or(false, |max| max < workers.max(1))
+    {
+        Args::command()
+            .error(
+                ErrorKind::InvalidValue,
+                "--max-concurrent-connects must be at least the number of workers or processes",
+            )
+            .exit();
+    }
+}
+
+/// RUST_LOG wins when set, otherwise the verbosity flags pick the levels
+fn env_filter(args: &Args) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        let directives = match (args.quiet, args.verbose) {
+            (true, _) => "error",
+            (false, 0) => "warn,whep_benchmark=info",
+            // client lifecycle
+            (false, 1) => "info,whep_benchmark=debug",
+            // sdp bodies, every stats sample and packet level logs
+            (false, _) => "info,whep_benchmark=trace",
+        };
+        EnvFilter::new(directives)
+    })
+}
+
+fn client_config(client: &ClientArgs) -> whep::ClientConfig {
+    whep::ClientConfig {
+        signal_timeout: std::time::Duration::from_millis(client.signal_timeout_ms),
+        ice_timeout: std::time::Duration::from_millis(client.ice_timeout_ms),
+        stall_timeout: client
+            .stall_timeout_ms
+            .map(std::time::Duration::from_millis),
+        dtls_fingerprint_verification: client.dtls_fingerprint_verification,
+        reorder_audio: client.reorder_audio,
+        reorder_video: client.reorder_video,
+        rtp_mode: client.rtp_mode,
+        resolve_mdns: client.resolve_mdns,
+        throttle_retries: client.throttle_retries,
+        redirect_auth: client.redirect_auth,
+        user_agent: client.user_agent.clone(),
+        client_id_header: client.client_id_header.clone(),
+        run_id: None,
+        require: client.require.clone(),
+        resolve: client.resolve.clone(),
+        socket: mux::SocketOptions {
+            port_range: client.port_range,
+            dscp: client.dscp,
+            recv_buffer: client.so_rcvbuf,
+            datagram_size: client.datagram_size,
+            send_buffer: client.so_sndbuf,
+            prefer: client.prefer,
+            no_sas: client.no_sas,
+        },
+        lenient_sdp: client.lenient_sdp,
+        keepalive: client.keepalive,
+        stun_servers: client.stun_server.clone(),
+        candidate_policy: client.candidate_policy,
+        interfaces: candidate::InterfaceFilter {
+            include: client.iface_include.clone(),
+            exclude: client.iface_exclude.clone(),
+        },
+        // set per client profile
+        media: vec![],
+        downlink_kbps: None,
+        http_version: client.http_version,
+        signal_delay: client.signal_delay,
+        offer_rewrites: client.offer_rewrite.clone(),
+        dns_cache_ttl: client.dns_cache_ttl.map(std::time::Duration::from_secs),
+    }
+}
+
+fn url(target: &TargetArgs) -> &str {
+    target.url.as_deref().expect("should have url")
+}
+
+/// Time between arrivals, from --rate or --interval
+fn arrival_interval(opts: &BenchArgs) -> std::time::Duration {
+    opts.rate
+        .map_or(std::time::Duration::from_millis(opts.interval), |rate| {
+            rate.interval()
+        })
+}
+
+/// Target of the run, side A in an A/B run
+fn bench_url(opts: &BenchArgs) -> &str {
+    opts.ab
+        .first()
+        .map_or_else(|| url(&opts.target), String::as_str)
+}
+
+/// Static token, one from a watched file or one fetched with OAuth2 client credentials,
+/// empty for anonymous signaling. Exits when the first token cannot be read
+async fn token_source(target: &TargetArgs) -> auth::TokenSource {
+    if let Some(path) = &target.token_path {
+        return auth::file_token_source(path.clone()).unwrap_or_else(|e| {
+            let message = format!("cannot read {} {}", path.display(), e);
+            Args::command()
+                .error(ErrorKind::InvalidValue, message)
+                .exit()
+        });
+    }
+    let Some(token_url) = &target.oauth_token_url else {
+        if target.token.is_none() {
+            log::info!("[Main] no token given, signaling anonymously");
+        }
+        return auth::TokenSource::fixed(target.token.as_deref().unwrap_or_default());
+    };
+    let config = auth::OAuthConfig {
+        token_url: token_url.clone(),
+        client_id: target.client_id.clone().expect("should have client id"),
+        client_secret: target
+            .client_secret
+            .clone()
+            .expect("should have client secret"),
+        scope: target.oauth_scope.clone(),
+    };
+    auth::oauth_token_source(config)
+        .await
+        .unwrap_or_else(|e| Args::command().error(ErrorKind::InvalidValue, e).exit())
 }
@@ -0,0 +1,151 @@
+//! Minimal one-shot mDNS resolver for `.local` ice candidates, as sent by
+//! browser-like stacks which hide their host addresses
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use async_std::net::UdpSocket;
+
+use crate::rt;
+
+const MDNS_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+/// Ask responders to answer unicast to our ephemeral port
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+pub fn is_mdns_name(host: &str) -> bool {
+    host.ends_with(".local")
+}
+
+fn build_query(name: &str) -> Vec<u8> {
+    let mut buf = vec![0u8; 12];
+    // id 0, flags 0, one question
+    buf[5] = 1;
+    for label in name.trim_end_matches('.').split('.') {
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    buf.extend_from_slice(&TYPE_A.to_be_bytes());
+    buf.extend_from_slice(&(CLASS_IN | UNICAST_RESPONSE).to_be_bytes());
+    buf
+}
+
+/// Read a possibly compressed name at `pos`, returns the name and the position after it
+fn read_name(buf: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = vec![];
+    let mut end = None;
+    // bounded, a malicious pointer loop must not hang us
+    for _ in 0..64 {
+        let len = *buf.get(pos)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        }
+        if len & 0xC0 == 0xC0 {
+            let ptr = ((len & 0x3F) << 8) | *buf.get(pos + 1)? as usize;
+            end.get_or_insert(pos + 2);
+            pos = ptr;
+            continue;
+        }
+        let label = buf.get(pos + 1..pos + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        pos += 1 + len;
+    }
+    None
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(pos)?, *buf.get(pos + 1)?]))
+}
+
+/// Find an A record for `name` in a response
+fn parse_response(buf: &[u8], name: &str) -> Option<IpAddr> {
+    let questions = read_u16(buf, 4)?;
+    let answers = read_u16(buf, 6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(buf, pos)?.1 + 4;
+    }
+    for _ in 0..answers {
+        let (record, next) = read_name(buf, pos)?;
+        let rtype = read_u16(buf, next)?;
+        let rdlen = read_u16(buf, next + 8)? as usize;
+        let rdata = buf.get(next + 10..next + 10 + rdlen)?;
+        if rtype == TYPE_A && rdlen == 4 && record.eq_ignore_ascii_case(name.trim_end_matches('.'))
+        {
+            return Some(IpAddr::V4(Ipv4Addr::new(
+                rdata[0], rdata[1], rdata[2], rdata[3],
+            )));
+        }
+        pos = next + 10 + rdlen;
+    }
+    None
+}
+
+/// Resolve `name` to an ipv4 address, None when nobody answered within `wait`
+pub async fn resolve(name: &str, wait: Duration) -> io::Result<Option<IpAddr>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.send_to(&build_query(name), MDNS_ADDR).await?;
+    let deadline = Instant::now() + wait;
+    let mut buf = [0u8; 1500];
+    loop {
+        let remain = deadline.saturating_duration_since(Instant::now());
+        match rt::timeout(remain, socket.recv_from(&mut buf)).await {
+            Ok(Ok((len, _))) => {
+                if let Some(addr) = parse_response(&buf[..len], name) {
+                    return Ok(Some(addr));
+                }
+            }
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Ok(None),
+        }
+    }
+}
+
+/// Replace `.local` addresses of candidate lines with resolved ones, candidates
+/// which cannot be resolved are dropped so the sdp still parses. With `wait` None
+/// nothing is resolved and all of them are dropped
+pub async fn resolve_candidates(sdp: &str, wait: Option<Duration>) -> String {
+    let mut resolved: HashMap<String, Option<IpAddr>> = HashMap::new();
+    let mut lines = vec![];
+    for line in sdp.lines() {
+        let mut fields: Vec<&str> = line.split(' ').collect();
+        let host = match fields.get(4) {
+            Some(host) if line.starts_with("a=candidate:") && is_mdns_name(host) => {
+                host.to_string()
+            }
+            _ => {
+                lines.push(line.to_string());
+                continue;
+            }
+        };
+        if !resolved.contains_key(&host) {
+            let addr = match wait {
+                Some(wait) => resolve(&host, wait).await.unwrap_or_else(|e| {
+                    log::warn!("[Mdns] resolve {} error {:?}", host, e);
+                    None
+                }),
+                None => None,
+            };
+            log::debug!("[Mdns] resolved {} => {:?}", host, addr);
+            resolved.insert(host.clone(), addr);
+        }
+        match resolved[&host] {
+            Some(addr) => {
+                let addr = addr.to_string();
+                fields[4] = &addr;
+                lines.push(fields.join(" "));
+            }
+            None => log::warn!("[Mdns] drop unresolved candidate {}", line),
+        }
+    }
+    // sdp lines end with crlf, including the last one
+    let mut out = lines.join("\r\n");
+    out.push_str("\r\n");
+    out
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum StreamKind {
+    Audio,
+    Video,
+}
+
+impl std::str::FromStr for StreamKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "audio" => Ok(StreamKind::Audio),
+            "video" => Ok(StreamKind::Video),
+            _ => Err(format!("invalid media kind {}, expected audio or video", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    Opus,
+    Vp8,
+    Vp9,
+    H264,
+    Av1,
+    /// Retransmissions, RFC 4588
+    Rtx,
+    Ulpfec,
+    Flexfec,
+    Other,
+}
+
+impl Codec {
+    pub fn from_name(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "opus" => Codec::Opus,
+            "vp8" => Codec::Vp8,
+            "vp9" => Codec::Vp9,
+            "h264" => Codec::H264,
+            "av1" => Codec::Av1,
+            "rtx" => Codec::Rtx,
+            "ulpfec" => Codec::Ulpfec,
+            "flexfec" | "flexfec-03" => Codec::Flexfec,
+            _ => Codec::Other,
+        }
+    }
+
+    /// RTP clock rate in Hz
+    pub fn clock_rate(&self) -> u32 {
+        match self {
+            Codec::Opus => 48000,
+            _ => 90000,
+        }
+    }
+
+    pub fn is_video(&self) -> bool {
+        matches!(self, Codec::Vp8 | Codec::Vp9 | Codec::H264 | Codec::Av1)
+    }
+
+    /// None for retransmissions, fec and unknown payloads
+    pub fn kind(&self) -> Option<StreamKind> {
+        match self {
+            Codec::Opus => Some(StreamKind::Audio),
+            Codec::Rtx | Codec::Ulpfec | Codec::Flexfec | Codec::Other => None,
+            _ => Some(StreamKind::Video),
+        }
+    }
+}
+
+/// Build payload type => codec map from `a=rtpmap` lines of a sdp
+pub fn parse_rtpmap(sdp: &str) -> HashMap<u8, Codec> {
+    let mut map = HashMap::new();
+    for line in sdp.lines() {
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("a=rtpmap:") {
+            let mut parts = value.split_whitespace();
+            let pt = parts.next().and_then(|pt| pt.parse::<u8>().ok());
+            let name = parts.next().and_then(|enc| enc.split('/').next());
+            if let (Some(pt), Some(name)) = (pt, name) {
+                map.insert(pt, Codec::from_name(name));
+            }
+        }
+    }
+    map
+}
+
+/// Build rtx payload type => retransmitted payload type map from `a=fmtp:<pt> apt=<pt>`
+pub fn parse_rtx_apt(sdp: &str) -> HashMap<u8, u8> {
+    let mut map = HashMap::new();
+    for line in sdp.lines() {
+        let Some(value) = line.trim().strip_prefix("a=fmtp:") else {
+            continue;
+        };
+        let Some((pt, params)) = value.split_once(' ') else {
+            continue;
+        };
+        let apt = params
+            .split(';')
+            .find_map(|param| param.trim().strip_prefix("apt="))
+            .and_then(|apt| apt.parse::<u8>().ok());
+        if let (Ok(pt), Some(apt)) = (pt.parse::<u8>(), apt) {
+            map.insert(pt, apt);
+        }
+    }
+    map
+}
+
+/// Check if a RTP payload carries the beginning of a keyframe
+pub fn is_keyframe(codec: Codec, payload: &[u8]) -> bool {
+    match codec {
+        Codec::Vp8 => is_vp8_keyframe(payload),
+        Codec::Vp9 => is_vp9_keyframe(payload),
+        Codec::H264 => is_h264_keyframe(payload),
+        Codec::Av1 => is_av1_keyframe(payload),
+        _ => false,
+    }
+}
+
+fn is_vp8_keyframe(payload: &[u8]) -> bool {
+    // first bit of vp8 payload header is inverse keyframe flag
+    vp8_header_offset(payload)
+        .and_then(|offset| payload.get(offset))
+        .map(|b| b & 0x01 == 0)
+        .unwrap_or(false)
+}
+
+/// Offset of the VP8 payload header behind the payload descriptor, None unless the
+/// packet starts the first partition of a frame
+pub(crate) fn vp8_header_offset(payload: &[u8]) -> Option<usize> {
+    let first = *payload.first()?;
+    let extended = first & 0x80 != 0;
+    let start = first & 0x10 != 0;
+    let partition = first & 0x07;
+    if !start || partition != 0 {
+        return None;
+    }
+
+    let mut offset = 1;
+    if extended {
+        let ext = *payload.get(offset)?;
+        offset += 1;
+        if ext & 0x80 != 0 {
+            // picture id, 7 or 15 bits
+            let pic_id = *payload.get(offset)?;
+            offset += if pic_id & 0x80 != 0 { 2 } else { 1 };
+        }
+        if ext & 0x40 != 0 {
+            offset += 1;
+        }
+        if ext & 0x30 != 0 {
+            offset += 1;
+        }
+    }
+    Some(offset)
+}
+
+fn is_vp9_keyframe(payload: &[u8]) -> bool {
+    match payload.first() {
+        // P bit not set (not inter-predicted) and B bit set (start of frame)
+        Some(b) => b & 0x40 == 0 && b & 0x08 != 0,
+        None => false,
+    }
+}
+
+fn is_h264_keyframe(payload: &[u8]) -> bool {
+    let Some(&first) = payload.first() else {
+        return false;
+    };
+    match first & 0x1f {
+        5 | 7 => true,
+        // STAP-A
+        24 => {
+            let mut offset = 1;
+            while offset + 2 < payload.len() {
+                let size = u16::from_be_bytes([payload[offset], payload[offset + 1]]) as usize;
+                let nal_type = payload[offset + 2] & 0x1f;
+                if nal_type == 5 || nal_type == 7 {
+                    return true;
+                }
+                offset += 2 + size;
+            }
+            false
+        }
+        // FU-A
+        28 => match payload.get(1) {
+            Some(header) => header & 0x80 != 0 && header & 0x1f == 5,
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_av1_keyframe(payload: &[u8]) -> bool {
+    match payload.first() {
+        // N bit: first packet of a new coded video sequence
+        Some(b) => b & 0x08 != 0,
+        None => false,
+    }
+}
+
+/// Track time between keyframes of a video stream
+#[derive(Debug, Default)]
+pub struct KeyframeTracker {
+    last_rtp_ts: Option<u32>,
+    last_at: Option<Instant>,
+    interval: Option<Duration>,
+    count: u64,
+}
+
+impl KeyframeTracker {
+    pub fn on_keyframe(&mut self, now: Instant, rtp_ts: u32) {
+        // a keyframe is usually split into many packets with same timestamp
+        if self.last_rtp_ts == Some(rtp_ts) {
+            return;
+        }
+        if let Some(last_at) = self.last_at {
+            self.interval = Some(now - last_at);
+        }
+        self.last_rtp_ts = Some(rtp_ts);
+        self.last_at = Some(now);
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn interval_ms(&self) -> u32 {
+        self.interval.map(|d| d.as_millis() as u32).unwrap_or(0)
+    }
+}
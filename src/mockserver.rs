@@ -0,0 +1,357 @@
+//! Minimal WHEP server streaming synthetic media, for end-to-end tests of the
+//! benchmark without a real media server and for calibrating the load generator.
+//!
+//! `POST /whep` with an offer creates a session answering with 201, `Location`
+//! and the answer sdp, `DELETE /whep/<id>` ends it. Sessions send 20 ms opus
+//! frames and VP8 or H264 video frames of random bytes with a periodic keyframe
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_std::net::UdpSocket;
+use parking_lot::Mutex;
+use rand::RngCore;
+use str0m::{
+    change::SdpOffer,
+    format::Codec,
+    media::{Frequency, MediaKind, MediaTime, Mid, Pt},
+    net::{Protocol, Receive},
+    Candidate, Event, IceConnectionState, Input, Output, Rtc, RtcError,
+};
+use tide::{http::mime, Request, Response, StatusCode};
+
+const AUDIO_FRAME: Duration = Duration::from_millis(20);
+const VIDEO_FPS: u32 = 30;
+/// Opus frame of a 32 kbps stream
+const AUDIO_FRAME_BYTES: usize = 80;
+/// Sessions without ice connectivity for this long are dropped
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    /// Address put into the answer candidate, the session sockets bind on all interfaces
+    pub candidate_ip: IpAddr,
+    pub video_kbps: u64,
+    pub keyframe_interval: Duration,
+    /// Sessions beyond this many are refused with 503, for testing backoff
+    pub max_sessions: Option<usize>,
+}
+
+#[derive(Clone)]
+struct MockServer {
+    config: Arc<MockConfig>,
+    /// Stop flag of each running session
+    sessions: Arc<Mutex<HashMap<u64, Arc<AtomicBool>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+/// Media section being sent, with the rtp clock of its frames
+struct Track {
+    mid: Mid,
+    pt: Pt,
+    codec: Codec,
+    clock_rate: Frequency,
+    rtp_time: u64,
+    next_at: Instant,
+}
+
+struct Session {
+    rtc: Rtc,
+    socket: UdpSocket,
+    local_addr: SocketAddr,
+    tracks: Vec<Track>,
+    connected: bool,
+    last_keyframe: Option<Instant>,
+    keyframe_requested: bool,
+}
+
+impl Session {
+    fn on_event(&mut self, event: Event) -> bool {
+        match event {
+            Event::Connected => {
+                log::debug!("[MockServer] session connected");
+                self.connected = true;
+                let now = Instant::now();
+                for track in &mut self.tracks {
+                    track.next_at = now;
+                }
+            }
+            Event::MediaAdded(added) => {
+                if let Some(track) = self.track(added.mid, added.kind) {
+                    self.tracks.push(track);
+                }
+            }
+            Event::KeyframeRequest(_) => self.keyframe_requested = true,
+            Event::IceConnectionStateChange(IceConnectionState::Disconnected) => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Negotiated payload of a media section, VP8 preferred for video
+    fn track(&mut self, mid: Mid, kind: MediaKind) -> Option<Track> {
+        let writer = self.rtc.writer(mid)?;
+        let params: Vec<_> = writer.payload_params().collect();
+        let wanted = match kind {
+            MediaKind::Audio => vec![Codec::Opus],
+            MediaKind::Video => vec![Codec::Vp8, Codec::H264],
+        };
+        let params = wanted
+            .iter()
+            .find_map(|codec| params.iter().find(|p| p.spec().codec == *codec))?;
+        Some(Track {
+            mid,
+            pt: params.pt(),
+            codec: params.spec().codec,
+            clock_rate: params.spec().clock_rate,
+            rtp_time: 0,
+            next_at: Instant::now(),
+        })
+    }
+
+    /// Write every frame which is due
+    fn send_media(&mut self, config: &MockConfig) -> Result<(), RtcError> {
+        let now = Instant::now();
+        let keyframe_due = self.keyframe_requested
+            || self
+                .last_keyframe
+                .map_or(true, |at| at.elapsed() >= config.keyframe_interval);
+        for index in 0..self.tracks.len() {
+            let track = &self.tracks[index];
+            if track.next_at > now {
+                continue;
+            }
+            let (frame, interval) = match track.codec {
+                Codec::Opus => (random_bytes(AUDIO_FRAME_BYTES), AUDIO_FRAME),
+                codec => {
+                    let bytes = (config.video_kbps * 1000 / 8 / VIDEO_FPS as u64) as usize;
+                    // keyframes are larger, like a real encoder
+                    let bytes = if keyframe_due { bytes * 4 } else { bytes };
+                    (
+                        video_frame(codec, keyframe_due, bytes),
+                        Duration::from_secs(1) / VIDEO_FPS,
+                    )
+                }
+            };
+            if track.codec != Codec::Opus && keyframe_due {
+                self.keyframe_requested = false;
+                self.last_keyframe = Some(now);
+            }
+            let (mid, pt) = (track.mid, track.pt);
+            let time = MediaTime::new(track.rtp_time, track.clock_rate);
+            if let Some(writer) = self.rtc.writer(mid) {
+                writer.write(pt, now, time, frame)?;
+            }
+            let track = &mut self.tracks[index];
+            track.rtp_time +=
+                track.clock_rate.get() as u64 * interval.as_micros() as u64 / 1_000_000;
+            track.next_at += interval;
+        }
+        Ok(())
+    }
+
+    /// Drive the session until it ends, the peer disconnects or `stopped` is set
+    async fn run(mut self, config: Arc<MockConfig>, stopped: Arc<AtomicBool>) {
+        let started = Instant::now();
+        let mut buf = vec![0; 2000];
+        while self.rtc.is_alive() && !stopped.load(Ordering::Relaxed) {
+            if !self.connected && started.elapsed() > CONNECT_TIMEOUT {
+                log::warn!("[MockServer] session not connected in time");
+                break;
+            }
+            let timeout = match self.rtc.poll_output() {
+                Ok(Output::Timeout(timeout)) => timeout,
+                Ok(Output::Transmit(send)) => {
+                    if let Err(e) = self.socket.send_to(&send.contents, send.destination).await {
+                        log::debug!("[MockServer] send to {} failed {:?}", send.destination, e);
+                    }
+                    continue;
+                }
+                Ok(Output::Event(event)) => {
+                    if !self.on_event(event) {
+                        break;
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    log::warn!("[MockServer] session error {:?}", e);
+                    break;
+                }
+            };
+            let next_media = self
+                .tracks
+                .iter()
+                .filter(|_| self.connected)
+                .map(|t| t.next_at)
+                .min();
+            let deadline = next_media.map_or(timeout, |at| at.min(timeout));
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let input =
+                match async_std::future::timeout(wait, self.socket.recv_from(&mut buf)).await {
+                    Ok(Ok((n, source))) => match buf[..n].try_into() {
+                        Ok(contents) => Input::Receive(
+                            Instant::now(),
+                            Receive {
+                                proto: Protocol::Udp,
+                                source,
+                                destination: self.local_addr,
+                                contents,
+                            },
+                        ),
+                        Err(_) => continue,
+                    },
+                    Ok(Err(e)) => {
+                        log::warn!("[MockServer] socket error {:?}", e);
+                        break;
+                    }
+                    Err(_) => Input::Timeout(Instant::now()),
+                };
+            if let Err(e) = self.rtc.handle_input(input) {
+                log::warn!("[MockServer] session input error {:?}", e);
+                break;
+            }
+            if self.connected {
+                if let Err(e) = self.send_media(&config) {
+                    log::warn!("[MockServer] write media failed {:?}", e);
+                    break;
+                }
+            }
+        }
+        self.rtc.disconnect();
+    }
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Frame of random bytes behind a valid frame header, so receivers detect keyframes
+fn video_frame(codec: Codec, keyframe: bool, len: usize) -> Vec<u8> {
+    let mut frame = random_bytes(len.max(16));
+    match codec {
+        Codec::Vp8 => {
+            // frame tag: inverse keyframe bit, version 0, show frame
+            frame[0] = if keyframe { 0x10 } else { 0x11 };
+        }
+        Codec::H264 => {
+            // annex b start code and an IDR or non-IDR slice, random bytes could
+            // contain further start codes, so they are cleared
+            for byte in frame.iter_mut() {
+                if *byte < 2 {
+                    *byte = 2;
+                }
+            }
+            frame[..4].copy_from_slice(&[0, 0, 0, 1]);
+            frame[4] = if keyframe { 0x65 } else { 0x41 };
+        }
+        _ => {}
+    }
+    frame
+}
+
+impl MockServer {
+    async fn create(&self, offer: &str) -> Result<(u64, String), String> {
+        let offer = SdpOffer::from_sdp_string(offer).map_err(|e| e.to_string())?;
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .map_err(|e| e.to_string())?;
+        let port = socket.local_addr().map_err(|e| e.to_string())?.port();
+        let local_addr = SocketAddr::new(self.config.candidate_ip, port);
+
+        let mut rtc = Rtc::builder().build();
+        let candidate = Candidate::host(local_addr, Protocol::Udp).map_err(|e| e.to_string())?;
+        rtc.add_local_candidate(candidate);
+        let answer = rtc
+            .sdp_api()
+            .accept_offer(offer)
+            .map_err(|e| e.to_string())?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let stopped = Arc::new(AtomicBool::new(false));
+        self.sessions.lock().insert(id, stopped.clone());
+        let session = Session {
+            rtc,
+            socket,
+            local_addr,
+            tracks: vec![],
+            connected: false,
+            last_keyframe: None,
+            keyframe_requested: false,
+        };
+        let config = self.config.clone();
+        let sessions = self.sessions.clone();
+        async_std::task::spawn(async move {
+            session.run(config, stopped).await;
+            sessions.lock().remove(&id);
+            log::debug!("[MockServer] session {} ended", id);
+        });
+        Ok((id, answer.to_sdp_string()))
+    }
+}
+
+async fn post_offer(mut req: Request<MockServer>) -> tide::Result {
+    let offer = req.body_string().await?;
+    let server = req.state();
+    if let Some(max) = server.config.max_sessions {
+        if server.sessions.lock().len() >= max {
+            return Ok(Response::builder(StatusCode::ServiceUnavailable)
+                .header("Retry-After", "1")
+                .build());
+        }
+    }
+    match server.create(&offer).await {
+        Ok((id, answer)) => {
+            log::debug!("[MockServer] session {} created", id);
+            Ok(Response::builder(StatusCode::Created)
+                .header("Location", format!("/whep/{}", id))
+                .content_type(mime::Mime::from("application/sdp"))
+                .body(answer)
+                .build())
+        }
+        Err(e) => {
+            log::warn!("[MockServer] rejected offer {}", e);
+            Ok(Response::builder(StatusCode::BadRequest).body(e).build())
+        }
+    }
+}
+
+async fn delete_session(req: Request<MockServer>) -> tide::Result {
+    let id: u64 = req.param("id")?.parse().unwrap_or(u64::MAX);
+    match req.state().sessions.lock().remove(&id) {
+        Some(stopped) => {
+            stopped.store(true, Ordering::Relaxed);
+            Ok(Response::new(StatusCode::Ok))
+        }
+        None => Ok(Response::new(StatusCode::NotFound)),
+    }
+}
+
+/// Trickled candidates are not needed, the answer carries the only candidate
+async fn patch_session(_req: Request<MockServer>) -> tide::Result {
+    Ok(Response::new(StatusCode::NoContent))
+}
+
+/// Serve the mock until the process ends, clients use `http://<listen>/whep`
+pub async fn run_mock_server(listen: &str, config: MockConfig) -> std::io::Result<()> {
+    let server = MockServer {
+        config: Arc::new(config),
+        sessions: Default::default(),
+        next_id: Arc::new(AtomicU64::new(1)),
+    };
+    let mut app = tide::with_state(server);
+    app.at("/whep").post(post_offer);
+    app.at("/whep/:id")
+        .delete(delete_session)
+        .patch(patch_session);
+    log::info!("[MockServer] listening on {}, whep endpoint /whep", listen);
+    app.listen(listen.to_string()).await
+}
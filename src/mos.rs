@@ -0,0 +1,21 @@
+//! Simplified ITU-T G.107 E-model, maps network impairments to an estimated
+//! listening quality on the 1 to 4.5 MOS scale
+
+/// Default transmission rating factor without impairments
+const R_BASE: f32 = 93.2;
+/// Codec and playout buffer delay assumed on top of the network
+const CODEC_DELAY_MS: f32 = 10.0;
+/// Rating lost per percent of packet loss
+const LOSS_PENALTY: f32 = 2.5;
+
+/// `loss` is a fraction, jitter counts twice as a playout buffer has to absorb it
+pub fn estimate(rtt_ms: f32, jitter_ms: f32, loss: f32) -> f32 {
+    let latency = rtt_ms / 2.0 + jitter_ms * 2.0 + CODEC_DELAY_MS;
+    let delay_penalty = if latency < 160.0 {
+        latency / 40.0
+    } else {
+        (latency - 120.0) / 10.0
+    };
+    let r = (R_BASE - delay_penalty - loss * 100.0 * LOSS_PENALTY).clamp(0.0, 100.0);
+    1.0 + 0.035 * r + 0.000007 * r * (r - 60.0) * (100.0 - r)
+}
@@ -0,0 +1,81 @@
+//! Rewrites of the offer before it is posted, for reproducing interop quirks of
+//! specific viewer apps like a forced codec order or custom attributes. Rules are
+//! regex replacements applied in order, scripts can rewrite it further with
+//! their `on_offer` hook, see [`crate::script`]
+
+use std::{fmt, str::FromStr};
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const SEPARATOR: &str = "=>";
+
+/// `<regex>=><replacement>`, e.g. `^a=extmap:.*$=>` dropping the header extensions.
+/// The regex matches per line, `$1` in the replacement refers to a group and `\n`
+/// starts a new sdp line
+#[derive(Debug, Clone)]
+pub struct OfferRewrite {
+    spec: String,
+    pattern: Regex,
+    replacement: String,
+}
+
+impl FromStr for OfferRewrite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, replacement) = s.split_once(SEPARATOR).ok_or_else(|| {
+            format!(
+                "invalid offer rewrite {}, expected `<regex>{}<replacement>`",
+                s, SEPARATOR
+            )
+        })?;
+        // sdp lines end with \r\n, R lets `$` match before it
+        let pattern = Regex::new(&format!("(?mR){}", pattern))
+            .map_err(|e| format!("invalid offer rewrite {}: {}", s, e))?;
+        Ok(Self {
+            spec: s.to_string(),
+            pattern,
+            replacement: replacement.replace("\\n", "\r\n"),
+        })
+    }
+}
+
+impl fmt::Display for OfferRewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.spec)
+    }
+}
+
+impl Serialize for OfferRewrite {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.spec)
+    }
+}
+
+impl<'de> Deserialize<'de> for OfferRewrite {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The offer with all rules applied. Lines a rule emptied are removed
+pub fn rewrite(offer: &str, rules: &[OfferRewrite]) -> String {
+    let mut sdp = offer.to_string();
+    if rules.is_empty() {
+        return sdp;
+    }
+    for rule in rules {
+        sdp = rule
+            .pattern
+            .replace_all(&sdp, rule.replacement.as_str())
+            .into_owned();
+    }
+    let mut out = String::with_capacity(sdp.len());
+    for line in sdp.split_terminator("\r\n").filter(|line| !line.is_empty()) {
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    out
+}
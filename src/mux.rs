@@ -0,0 +1,669 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_std::channel::{Receiver, Sender};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pool::{BufferPool, PooledBuf},
+    rt::{self, UdpSocketSas},
+};
+
+/// Datagram buffer size unless configured, enough for a standard ethernet mtu
+const MTU: usize = 1500;
+const STUN_MAGIC_COOKIE: [u8; 4] = [0x21, 0x12, 0xA4, 0x42];
+const STUN_USERNAME: u16 = 0x0006;
+
+/// Inclusive range of local udp ports, parsed from `40000-50000`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("invalid port range {}, expected start-end", s))?;
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<u16>()
+                .map_err(|_| format!("invalid port {}", v))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == 0 || start > end {
+            return Err(format!("invalid port range {}", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// DiffServ code point, parsed from a class name like `EF`, `AF41`, `CS5` or a number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dscp(pub u8);
+
+impl FromStr for Dscp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_uppercase();
+        let value = if name == "EF" {
+            46
+        } else if let Some(class) = name.strip_prefix("CS") {
+            match class.parse::<u8>() {
+                Ok(class) if class <= 7 => class << 3,
+                _ => return Err(format!("invalid dscp {}", s)),
+            }
+        } else if let Some(af) = name.strip_prefix("AF") {
+            // AFxy, class 1-4 and drop precedence 1-3
+            let digits: Vec<u8> = af.bytes().map(|b| b.wrapping_sub(b'0')).collect();
+            match digits[..] {
+                [class @ 1..=4, drop @ 1..=3] => (class << 3) | (drop << 1),
+                _ => return Err(format!("invalid dscp {}", s)),
+            }
+        } else {
+            match name.parse::<u8>() {
+                Ok(value) if value < 64 => value,
+                _ => return Err(format!("invalid dscp {}", s)),
+            }
+        };
+        Ok(Self(value))
+    }
+}
+
+/// Address family preferred for signaling and media, parsed from `v4` or `v6`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    pub fn matches(&self, ip: &IpAddr) -> bool {
+        match self {
+            IpFamily::V4 => ip.is_ipv4(),
+            IpFamily::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+impl FromStr for IpFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "v4" | "ipv4" => Ok(IpFamily::V4),
+            "v6" | "ipv6" => Ok(IpFamily::V6),
+            _ => Err(format!("invalid ip family {}, expected v4 or v6", s)),
+        }
+    }
+}
+
+/// Options applied to every client socket, shared or not
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct SocketOptions {
+    /// Local udp ports to bind, any ephemeral port when None
+    pub port_range: Option<PortRange>,
+    /// Mark outgoing packets, e.g. EF like production WebRTC audio
+    pub dscp: Option<Dscp>,
+    /// SO_RCVBUF and SO_SNDBUF in bytes, the kernel may clamp or double them
+    pub recv_buffer: Option<usize>,
+    pub send_buffer: Option<usize>,
+    /// Sockets bind dual-stack for V6, v4 only otherwise
+    pub prefer: Option<IpFamily>,
+    /// Largest datagram received in full, 1500 when None. Raise it for jumbo
+    /// frames or coalesced packets
+    pub datagram_size: Option<usize>,
+    /// Plain sockets without source address selection, see [`UdpTransport`]
+    #[serde(default)]
+    pub no_sas: bool,
+}
+
+impl SocketOptions {
+    pub fn datagram_size(&self) -> usize {
+        self.datagram_size.unwrap_or(MTU).max(1)
+    }
+}
+
+/// Offset of the next port tried, shared so sockets spread over the range
+static NEXT_PORT: AtomicUsize = AtomicUsize::new(0);
+/// Binding a SAS socket failed once, every later socket is plain
+static SAS_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Udp socket of a client or a socket pool. Source address selection lets one
+/// socket bound to all interfaces answer on the address a packet arrived at.
+/// Where the platform lacks it, a plain socket is bound to the address of the
+/// interface of the default route, the only candidate then
+pub enum UdpTransport {
+    Sas(UdpSocketSas),
+    Plain { socket: rt::UdpSocket, ip: IpAddr },
+}
+
+impl UdpTransport {
+    pub fn local_port(&self) -> u16 {
+        match self {
+            UdpTransport::Sas(socket) => socket.local_addr().port(),
+            UdpTransport::Plain { socket, .. } => {
+                socket.local_addr().map(|addr| addr.port()).unwrap_or(0)
+            }
+        }
+    }
+
+    /// Address every packet is sent from and received at, None with SAS
+    pub fn bound_ip(&self) -> Option<IpAddr> {
+        match self {
+            UdpTransport::Sas(_) => None,
+            UdpTransport::Plain { ip, .. } => Some(*ip),
+        }
+    }
+
+    async fn send(&self, data: &[u8], source: IpAddr, destination: SocketAddr) -> io::Result<()> {
+        match self {
+            UdpTransport::Sas(socket) => socket.send_sas(data, source, destination).await,
+            UdpTransport::Plain { socket, .. } => {
+                socket.send_to(data, destination).await.map(|_| ())
+            }
+        }
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, IpAddr)> {
+        match self {
+            UdpTransport::Sas(socket) => socket.recv_sas(buf).await,
+            UdpTransport::Plain { socket, ip } => {
+                let (n, source) = socket.recv_from(buf).await?;
+                Ok((n, source, *ip))
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn sock_ref(&self) -> socket2::SockRef<'_> {
+        match self {
+            UdpTransport::Sas(socket) => socket2::SockRef::from(socket),
+            UdpTransport::Plain { socket, .. } => socket2::SockRef::from(socket),
+        }
+    }
+}
+
+/// Address of the interface of the default route
+fn plain_ip(prefer: Option<IpFamily>) -> io::Result<IpAddr> {
+    let ip = match prefer {
+        Some(IpFamily::V6) => local_ip_address::local_ipv6(),
+        _ => local_ip_address::local_ip(),
+    };
+    ip.map_err(|e| io::Error::new(io::ErrorKind::NotFound, format!("no local address {}", e)))
+}
+
+fn bind_plain(ip: IpAddr, port: u16) -> io::Result<rt::UdpSocket> {
+    let socket = std::net::UdpSocket::bind(SocketAddr::new(ip, port))?;
+    socket.set_nonblocking(true)?;
+    rt::udp_from_std(socket)
+}
+
+fn bind_transport(options: &SocketOptions) -> io::Result<UdpTransport> {
+    if !options.no_sas && !SAS_UNAVAILABLE.load(Ordering::Relaxed) {
+        let any: IpAddr = match options.prefer {
+            Some(IpFamily::V6) => Ipv6Addr::UNSPECIFIED.into(),
+            _ => Ipv4Addr::UNSPECIFIED.into(),
+        };
+        match bind_in_range(options.port_range, |port| {
+            UdpSocketSas::bind(SocketAddr::new(any, port))
+        }) {
+            Ok(socket) => return Ok(UdpTransport::Sas(socket)),
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => return Err(e),
+            Err(e) => {
+                if !SAS_UNAVAILABLE.swap(true, Ordering::Relaxed) {
+                    log::warn!(
+                        "[Mux] source address selection unavailable {:?}, using plain sockets",
+                        e
+                    );
+                }
+            }
+        }
+    }
+    let ip = plain_ip(options.prefer)?;
+    let socket = bind_in_range(options.port_range, |port| bind_plain(ip, port))?;
+    Ok(UdpTransport::Plain { socket, ip })
+}
+
+/// Bind and apply the options, failing to set an option only logs a warning
+fn bind_udp(options: &SocketOptions) -> io::Result<UdpTransport> {
+    let socket = bind_transport(options)?;
+    #[cfg(unix)]
+    {
+        let sock = socket.sock_ref();
+        if let Some(dscp) = options.dscp {
+            // TOS carries the code point in its upper six bits
+            if let Err(e) = sock.set_tos((dscp.0 as u32) << 2) {
+                log::warn!("[Mux] cannot set dscp {} {:?}", dscp.0, e);
+            }
+        }
+        if let Some(size) = options.recv_buffer {
+            if let Err(e) = sock.set_recv_buffer_size(size) {
+                log::warn!("[Mux] cannot set receive buffer {} {:?}", size, e);
+            }
+            log::debug!("[Mux] receive buffer {:?}", sock.recv_buffer_size());
+        }
+        if let Some(size) = options.send_buffer {
+            if let Err(e) = sock.set_send_buffer_size(size) {
+                log::warn!("[Mux] cannot set send buffer {} {:?}", size, e);
+            }
+            log::debug!("[Mux] send buffer {:?}", sock.send_buffer_size());
+        }
+    }
+    Ok(socket)
+}
+
+/// Drop counters of local udp ports from `/proc/net/udp`, parsed at most once a second
+static UDP_DROPS: Mutex<Option<(Instant, HashMap<u16, u64>)>> = parking_lot::const_mutex(None);
+
+fn read_udp_drops() -> HashMap<u16, u64> {
+    let mut drops = HashMap::new();
+    for path in ["/proc/net/udp", "/proc/net/udp6"] {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        // sl local_address rem_address st tx:rx tr:when retrnsmt uid timeout inode ref pointer drops
+        for line in content.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let port = fields
+                .get(1)
+                .and_then(|addr| addr.rsplit(':').next())
+                .and_then(|port| u16::from_str_radix(port, 16).ok());
+            let count = fields.get(12).and_then(|v| v.parse::<u64>().ok());
+            if let (Some(port), Some(count)) = (port, count) {
+                *drops.entry(port).or_default() += count;
+            }
+        }
+    }
+    drops
+}
+
+/// Packets the kernel dropped on the socket bound to `port`, because its receive
+/// buffer was full. Only available on Linux
+pub fn udp_drops(port: u16) -> Option<u64> {
+    let mut cache = UDP_DROPS.lock();
+    let fresh = matches!(&*cache, Some((at, _)) if at.elapsed() < Duration::from_secs(1));
+    if !fresh {
+        *cache = Some((Instant::now(), read_udp_drops()));
+    }
+    cache
+        .as_ref()
+        .and_then(|(_, drops)| drops.get(&port).copied())
+}
+
+/// Bind a port within `range` when given, ephemeral port otherwise
+fn bind_in_range<T>(
+    range: Option<PortRange>,
+    bind: impl Fn(u16) -> io::Result<T>,
+) -> io::Result<T> {
+    let Some(range) = range else {
+        return bind(0);
+    };
+    let size = (range.end - range.start) as usize + 1;
+    for _ in 0..size {
+        let port = range.start + (NEXT_PORT.fetch_add(1, Ordering::Relaxed) % size) as u16;
+        match bind(port) {
+            Ok(socket) => return Ok(socket),
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AddrInUse,
+        format!("no free port in {}", range),
+    ))
+}
+
+pub struct Packet {
+    pub data: PooledBuf,
+    pub source: SocketAddr,
+    pub destination: IpAddr,
+    /// Datagram filled the whole buffer, so it was probably cut off
+    pub truncated: bool,
+}
+
+/// Count a datagram which filled the whole buffer, warning once per client
+fn on_oversized(count: &mut u64, size: usize) {
+    *count += 1;
+    if *count == 1 {
+        log::warn!(
+            "[Mux] dropped datagram of {} bytes or more, raise the datagram size",
+            size
+        );
+    }
+}
+
+#[derive(Default)]
+struct Routes {
+    clients: HashMap<u64, Sender<Packet>>,
+    by_addr: HashMap<SocketAddr, u64>,
+    by_ufrag: HashMap<String, u64>,
+    /// Stun requests we sent and await a response to, ice-lite servers only answer
+    by_transaction: HashMap<[u8; 12], u64>,
+}
+
+impl Routes {
+    fn remove(&mut self, id: u64) {
+        self.clients.remove(&id);
+        self.by_addr.retain(|_, v| *v != id);
+        self.by_ufrag.retain(|_, v| *v != id);
+        self.by_transaction.retain(|_, v| *v != id);
+    }
+}
+
+struct SharedSocket {
+    socket: UdpTransport,
+    port: u16,
+    routes: Mutex<Routes>,
+    buffers: BufferPool,
+    datagram_size: usize,
+}
+
+impl SharedSocket {
+    /// Pick the owner of a packet. Stun responses go to the client which sent the
+    /// request, stun requests carry our ufrag and teach us the remote address of
+    /// that client unless another client has it. Everything else by remote address
+    fn route(&self, data: &[u8], source: SocketAddr) -> Option<Sender<Packet>> {
+        let mut routes = self.routes.lock();
+        let response = stun_response_transaction(data)
+            .and_then(|transaction| routes.by_transaction.remove(&transaction));
+        let id = match response {
+            Some(id) => id,
+            None => match stun_local_ufrag(data).and_then(|u| routes.by_ufrag.get(u).copied()) {
+                Some(id) => {
+                    routes.by_addr.entry(source).or_insert(id);
+                    id
+                }
+                None => *routes.by_addr.get(&source)?,
+            },
+        };
+        routes.clients.get(&id).cloned()
+    }
+
+    async fn run_recv(self: Arc<Self>) {
+        let mut buf = vec![0; self.datagram_size];
+        loop {
+            let (n, source, destination) = match self.socket.recv(&mut buf).await {
+                Ok(res) => res,
+                Err(e) => {
+                    log::error!("[SocketPool] recv error on port {} {:?}", self.port, e);
+                    continue;
+                }
+            };
+            match self.route(&buf[..n], source) {
+                Some(tx) => {
+                    let packet = Packet {
+                        data: self.buffers.copy_from(&buf[..n]),
+                        source,
+                        destination,
+                        truncated: n == buf.len(),
+                    };
+                    // a full or closed channel only drops this packet, like udp would
+                    let _ = tx.try_send(packet);
+                }
+                None => log::trace!("[SocketPool] drop unroutable packet from {}", source),
+            }
+        }
+    }
+}
+
+/// Small set of udp sockets shared by many clients
+#[derive(Clone)]
+pub struct SocketPool {
+    sockets: Vec<Arc<SharedSocket>>,
+    next: Arc<AtomicUsize>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl SocketPool {
+    pub fn new(size: usize, options: SocketOptions) -> io::Result<Self> {
+        let datagram_size = options.datagram_size();
+        let buffers = BufferPool::new(datagram_size, 4096);
+        let mut sockets = vec![];
+        for _ in 0..size.max(1) {
+            let socket = bind_udp(&options)?;
+            let shared = Arc::new(SharedSocket {
+                port: socket.local_port(),
+                socket,
+                routes: Default::default(),
+                buffers: buffers.clone(),
+                datagram_size,
+            });
+            rt::spawn(shared.clone().run_recv());
+            sockets.push(shared);
+        }
+        log::info!("[SocketPool] created {} shared sockets", sockets.len());
+        Ok(Self {
+            sockets,
+            next: Default::default(),
+            next_id: Default::default(),
+        })
+    }
+
+    /// Assign a client to one of the sockets, round robin
+    pub fn acquire(&self) -> MuxSocket {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.sockets.len();
+        let shared = self.sockets[index].clone();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = async_std::channel::bounded(1024);
+        shared.routes.lock().clients.insert(id, tx);
+        MuxSocket { id, shared, rx }
+    }
+}
+
+/// Slot of a client in a shared socket, unregistered on drop
+pub struct MuxSocket {
+    id: u64,
+    shared: Arc<SharedSocket>,
+    rx: Receiver<Packet>,
+}
+
+impl Drop for MuxSocket {
+    fn drop(&mut self) {
+        self.shared.routes.lock().remove(self.id);
+    }
+}
+
+/// Udp transport of one client, either its own socket or a slot of a shared one
+pub enum ClientSocket {
+    Own {
+        socket: UdpTransport,
+        buf: Box<[u8]>,
+        oversized: u64,
+    },
+    Shared {
+        socket: MuxSocket,
+        /// Last received packet, its buffer returns to the pool on the next recv
+        packet: Option<Packet>,
+        oversized: u64,
+    },
+}
+
+impl ClientSocket {
+    pub fn bind(options: &SocketOptions) -> io::Result<Self> {
+        let socket = bind_udp(options)?;
+        Ok(ClientSocket::Own {
+            socket,
+            buf: vec![0; options.datagram_size()].into_boxed_slice(),
+            oversized: 0,
+        })
+    }
+
+    /// Datagrams dropped because they did not fit the buffer
+    pub fn oversized(&self) -> u64 {
+        match self {
+            ClientSocket::Own { oversized, .. } | ClientSocket::Shared { oversized, .. } => {
+                *oversized
+            }
+        }
+    }
+
+    pub fn local_port(&self) -> u16 {
+        match self {
+            ClientSocket::Own { socket, .. } => socket.local_port(),
+            ClientSocket::Shared { socket, .. } => socket.shared.port,
+        }
+    }
+
+    /// Address of a plain socket, the only local candidate
+    pub fn bound_ip(&self) -> Option<IpAddr> {
+        match self {
+            ClientSocket::Own { socket, .. } => socket.bound_ip(),
+            ClientSocket::Shared { socket, .. } => socket.shared.socket.bound_ip(),
+        }
+    }
+
+    /// Route stun requests carrying this ufrag to this client
+    pub fn set_ufrag(&self, ufrag: &str) {
+        if let ClientSocket::Shared { socket, .. } = self {
+            let mut routes = socket.shared.routes.lock();
+            routes.by_ufrag.insert(ufrag.to_string(), socket.id);
+        }
+    }
+
+    pub async fn send(
+        &self,
+        data: &[u8],
+        source: IpAddr,
+        destination: SocketAddr,
+    ) -> io::Result<()> {
+        match self {
+            ClientSocket::Own { socket, .. } => socket.send(data, source, destination).await,
+            ClientSocket::Shared { socket, .. } => {
+                {
+                    let mut routes = socket.shared.routes.lock();
+                    if let Some(transaction) = stun_request_transaction(data) {
+                        // connectivity checks of several clients may go to one remote
+                        routes.by_transaction.insert(transaction, socket.id);
+                    } else {
+                        // dtls and media of two clients with the same remote cannot be told apart
+                        let owner = *routes.by_addr.entry(destination).or_insert(socket.id);
+                        if owner != socket.id {
+                            return Err(io::Error::new(
+                                io::ErrorKind::AddrInUse,
+                                format!(
+                                    "remote {} is already used by another client on shared port {}, raise --shared-sockets",
+                                    destination, socket.shared.port
+                                ),
+                            ));
+                        }
+                    }
+                }
+                socket.shared.socket.send(data, source, destination).await
+            }
+        }
+    }
+
+    pub async fn recv(&mut self) -> io::Result<(&[u8], SocketAddr, IpAddr)> {
+        match self {
+            ClientSocket::Own {
+                socket,
+                buf,
+                oversized,
+            } => loop {
+                let (n, source, destination) = socket.recv(&mut buf[..]).await?;
+                // a datagram larger than the buffer is cut off, feeding it on corrupts srtp
+                if n == buf.len() {
+                    on_oversized(oversized, n);
+                    continue;
+                }
+                return Ok((&buf[..n], source, destination));
+            },
+            ClientSocket::Shared {
+                socket,
+                packet,
+                oversized,
+            } => loop {
+                let received =
+                    socket.rx.recv().await.map_err(|_| {
+                        io::Error::new(io::ErrorKind::BrokenPipe, "socket pool closed")
+                    })?;
+                if received.truncated {
+                    on_oversized(oversized, received.data.len());
+                    continue;
+                }
+                let packet = packet.insert(received);
+                return Ok((&packet.data[..], packet.source, packet.destination));
+            },
+        }
+    }
+}
+
+impl From<MuxSocket> for ClientSocket {
+    fn from(socket: MuxSocket) -> Self {
+        ClientSocket::Shared {
+            socket,
+            packet: None,
+            oversized: 0,
+        }
+    }
+}
+
+/// Transaction id of a stun message of one of `classes`, 0 for requests and 2
+/// or 3 for success and error responses
+fn stun_transaction(data: &[u8], classes: &[u16]) -> Option<[u8; 12]> {
+    if data.len() < 20 || data[0] & 0xc0 != 0 || data[4..8] != STUN_MAGIC_COOKIE {
+        return None;
+    }
+    let kind = u16::from_be_bytes([data[0], data[1]]);
+    // class bits are spread over bit 4 and 8 of the message type
+    let class = ((kind >> 7) & 0x2) | ((kind >> 4) & 0x1);
+    if !classes.contains(&class) {
+        return None;
+    }
+    data[8..20].try_into().ok()
+}
+
+fn stun_request_transaction(data: &[u8]) -> Option<[u8; 12]> {
+    stun_transaction(data, &[0])
+}
+
+fn stun_response_transaction(data: &[u8]) -> Option<[u8; 12]> {
+    stun_transaction(data, &[2, 3])
+}
+
+/// Local ufrag of a stun binding request, from `USERNAME = local:remote`
+fn stun_local_ufrag(data: &[u8]) -> Option<&str> {
+    if data.len() < 20 || data[0] & 0xc0 != 0 || data[4..8] != STUN_MAGIC_COOKIE {
+        return None;
+    }
+    let mut offset = 20;
+    while offset + 4 <= data.len() {
+        let kind = u16::from_be_bytes([data[offset], data[offset + 1]]);
+        let len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        let value = data.get(offset + 4..offset + 4 + len)?;
+        if kind == STUN_USERNAME {
+            let username = std::str::from_utf8(value).ok()?;
+            return username.split(':').next();
+        }
+        // attributes are padded to 4 bytes
+        offset += 4 + (len + 3) / 4 * 4;
+    }
+    None
+}
+
+/// Ice ufrag of a local sdp
+pub fn sdp_ice_ufrag(sdp: &str) -> Option<&str> {
+    sdp.lines()
+        .find_map(|line| line.trim().strip_prefix("a=ice-ufrag:"))
+}
@@ -0,0 +1,82 @@
+//! Playout delay the server asks the viewer for with the playout-delay header
+//! extension. A server which raises it under load trades viewer latency for
+//! smoothness, the changes over time show when
+
+use std::{fmt, time::Instant};
+
+use serde::{Deserialize, Serialize};
+use str0m::rtp::ExtensionValues;
+
+/// Bounds of the playout delay, the extension has a granularity of 10 ms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PlayoutDelay {
+    pub min_ms: u32,
+    pub max_ms: u32,
+}
+
+impl fmt::Display for PlayoutDelay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{} ms", self.min_ms, self.max_ms)
+    }
+}
+
+/// The requested playout delay changed, `from` is None for the first packet carrying it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayoutDelayChange {
+    /// Since the client started
+    pub at_ms: u64,
+    pub from: Option<PlayoutDelay>,
+    pub to: PlayoutDelay,
+}
+
+#[derive(Debug)]
+pub struct PlayoutDelayTracker {
+    started_at: Instant,
+    current: Option<PlayoutDelay>,
+    /// Changes since the previous stats
+    changes: Vec<PlayoutDelayChange>,
+}
+
+impl Default for PlayoutDelayTracker {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            current: None,
+            changes: vec![],
+        }
+    }
+}
+
+impl PlayoutDelayTracker {
+    pub fn on_packet(&mut self, now: Instant, ext: &ExtensionValues) {
+        let (Some(min), Some(max)) = (ext.play_delay_min, ext.play_delay_max) else {
+            return;
+        };
+        let delay = PlayoutDelay {
+            min_ms: (min.as_seconds() * 1000.0).round() as u32,
+            max_ms: (max.as_seconds() * 1000.0).round() as u32,
+        };
+        if self.current == Some(delay) {
+            return;
+        }
+        log::debug!(
+            "[PlayoutDelay] {} => {}",
+            self.current.map_or("-".to_string(), |d| d.to_string()),
+            delay
+        );
+        self.changes.push(PlayoutDelayChange {
+            at_ms: (now - self.started_at).as_millis() as u64,
+            from: self.current,
+            to: delay,
+        });
+        self.current = Some(delay);
+    }
+
+    pub fn current(&self) -> Option<PlayoutDelay> {
+        self.current
+    }
+
+    pub fn take_changes(&mut self) -> Vec<PlayoutDelayChange> {
+        std::mem::take(&mut self.changes)
+    }
+}
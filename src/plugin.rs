@@ -0,0 +1,158 @@
+//! WebAssembly plugins receiving the event stream, for proprietary scoring which
+//! does not belong upstream. A plugin is a core wasm module without WASI:
+//!
+//! - exports `memory`, `alloc(len: i32) -> i32` and `on_event(ptr: i32, len: i32)`,
+//!   which gets each event as json in a buffer from `alloc`, owned by the plugin
+//! - may export `abi_version() -> i32`, which has to return [`ABI_VERSION`]
+//! - may export `finish() -> i64`, called once the run ended, returning
+//!   `ptr << 32 | len` of a json object of metric names to numbers. They are added
+//!   to the summary as `<plugin>.<metric>`
+//! - may import `env.log(ptr: i32, len: i32)` to log a utf-8 message
+
+use std::{collections::BTreeMap, path::Path};
+
+use async_std::channel::Receiver;
+use wasmtime::{Caller, Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+use crate::bench::BenchEvent;
+
+pub const ABI_VERSION: i32 = 1;
+
+pub struct Plugin {
+    name: String,
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    on_event: TypedFunc<(i32, i32), ()>,
+    finish: Option<TypedFunc<(), i64>>,
+}
+
+impl Plugin {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let name = path.file_stem().map_or("plugin".to_string(), |stem| {
+            stem.to_string_lossy().to_string()
+        });
+        let engine = Engine::default();
+        let module = Module::from_file(&engine, path).map_err(|e| e.to_string())?;
+        let mut linker = Linker::new(&engine);
+        let log_name = name.clone();
+        linker
+            .func_wrap(
+                "env",
+                "log",
+                move |mut caller: Caller<'_, ()>, ptr: i32, len: i32| {
+                    let Some(memory) = caller.get_export("memory").and_then(|e| e.into_memory())
+                    else {
+                        return;
+                    };
+                    let mut buf = vec![0; len.max(0) as usize];
+                    if memory.read(&caller, ptr as usize, &mut buf).is_ok() {
+                        log::info!("[Plugin] {} {}", log_name, String::from_utf8_lossy(&buf));
+                    }
+                },
+            )
+            .map_err(|e| e.to_string())?;
+        let mut store = Store::new(&engine, ());
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .map_err(|e| e.to_string())?;
+        if let Ok(abi_version) = instance.get_typed_func::<(), i32>(&mut store, "abi_version") {
+            let version = abi_version
+                .call(&mut store, ())
+                .map_err(|e| e.to_string())?;
+            if version != ABI_VERSION {
+                return Err(format!(
+                    "abi version {} not supported, expected {}",
+                    version, ABI_VERSION
+                ));
+            }
+        }
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("no memory export")?;
+        Ok(Self {
+            alloc: typed(&instance, &mut store, "alloc")?,
+            on_event: typed(&instance, &mut store, "on_event")?,
+            finish: instance.get_typed_func(&mut store, "finish").ok(),
+            name,
+            store,
+            memory,
+        })
+    }
+
+    fn on_event(&mut self, json: &[u8]) -> Result<(), String> {
+        let len = json.len() as i32;
+        let ptr = self
+            .alloc
+            .call(&mut self.store, len)
+            .map_err(|e| e.to_string())?;
+        self.memory
+            .write(&mut self.store, ptr as usize, json)
+            .map_err(|e| e.to_string())?;
+        self.on_event
+            .call(&mut self.store, (ptr, len))
+            .map_err(|e| e.to_string())
+    }
+
+    fn finish(&mut self) -> Result<BTreeMap<String, f64>, String> {
+        let Some(finish) = self.finish else {
+            return Ok(BTreeMap::new());
+        };
+        let packed = finish
+            .call(&mut self.store, ())
+            .map_err(|e| e.to_string())?;
+        let (ptr, len) = ((packed >> 32) as u32 as usize, packed as u32 as usize);
+        let mut json = vec![0; len];
+        self.memory
+            .read(&self.store, ptr, &mut json)
+            .map_err(|e| e.to_string())?;
+        serde_json::from_slice(&json).map_err(|e| format!("invalid metrics {}", e))
+    }
+}
+
+fn typed<P, R>(
+    instance: &Instance,
+    store: &mut Store<()>,
+    name: &str,
+) -> Result<TypedFunc<P, R>, String>
+where
+    P: wasmtime::WasmParams,
+    R: wasmtime::WasmResults,
+{
+    instance
+        .get_typed_func(store, name)
+        .map_err(|e| format!("export {}: {}", name, e))
+}
+
+/// Feed every event to the plugins until the channel closes, returns their metrics.
+/// A plugin which trapped gets no further events
+pub fn run_plugins(
+    mut plugins: Vec<Plugin>,
+    event_rx: Receiver<BenchEvent>,
+) -> BTreeMap<String, f64> {
+    let mut failed = vec![false; plugins.len()];
+    while let Ok(event) = event_rx.recv_blocking() {
+        let json = serde_json::to_vec(&event).expect("should serialize event");
+        for (plugin, failed) in plugins.iter_mut().zip(failed.iter_mut()) {
+            if *failed {
+                continue;
+            }
+            if let Err(e) = plugin.on_event(&json) {
+                log::error!("[Plugin] {} failed, disabled {}", plugin.name, e);
+                *failed = true;
+            }
+        }
+    }
+    let mut metrics = BTreeMap::new();
+    for (plugin, _) in plugins.iter_mut().zip(failed).filter(|(_, failed)| !failed) {
+        match plugin.finish() {
+            Ok(values) => {
+                for (metric, value) in values {
+                    metrics.insert(format!("{}.{}", plugin.name, metric), value);
+                }
+            }
+            Err(e) => log::error!("[Plugin] {} finish failed {}", plugin.name, e),
+        }
+    }
+    metrics
+}
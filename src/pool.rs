@@ -0,0 +1,73 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+/// Reusable byte buffers, so the receive path does not allocate per packet
+#[derive(Clone)]
+pub struct BufferPool {
+    free: Arc<Mutex<Vec<Vec<u8>>>>,
+    buf_size: usize,
+    max_free: usize,
+}
+
+impl BufferPool {
+    /// Buffers beyond `max_free` idle ones are released instead of kept
+    pub fn new(buf_size: usize, max_free: usize) -> Self {
+        Self {
+            free: Default::default(),
+            buf_size,
+            max_free,
+        }
+    }
+
+    /// Take a buffer holding a copy of `data`
+    pub fn copy_from(&self, data: &[u8]) -> PooledBuf {
+        let mut buf = self
+            .free
+            .lock()
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(self.buf_size));
+        buf.clear();
+        buf.extend_from_slice(data);
+        PooledBuf {
+            buf,
+            pool: self.clone(),
+        }
+    }
+
+    fn release(&self, buf: Vec<u8>) {
+        let mut free = self.free.lock();
+        if free.len() < self.max_free {
+            free.push(buf);
+        }
+    }
+}
+
+/// Buffer which goes back to its pool when dropped
+pub struct PooledBuf {
+    buf: Vec<u8>,
+    pool: BufferPool,
+}
+
+impl Deref for PooledBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        self.pool.release(std::mem::take(&mut self.buf));
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{
+    sdp::AnswerSummary,
+    whep::{ClientConfig, SignalingInfo, WhepClient, WhepError},
+    whepext,
+};
+
+fn print_signaling(info: &SignalingInfo) {
+    println!("status: {}", info.status);
+    println!("latency: {} ms", info.latency.as_millis());
+    println!("answered by: {}", info.url);
+    for link in &info.links {
+        println!("link: {}", link);
+    }
+    let summary = AnswerSummary {
+        whep_extensions: whepext::advertised(&info.links),
+        ..AnswerSummary::parse(&info.answer)
+    };
+    print!("{}", summary);
+}
+
+/// Do one signaling exchange, print what the server negotiated, then release the session
+pub async fn run_probe(url: &str, token: &str, config: &ClientConfig) -> Result<(), WhepError> {
+    let mut client = WhepClient::new(url, token, config)?;
+    let res = client.prepare().await;
+    match client.signaling() {
+        Some(info) => print_signaling(info),
+        None => println!("no response from server"),
+    }
+    if let Err(e) = client.disconnect().await {
+        println!("delete failed: {:?}", e);
+    }
+    res
+}
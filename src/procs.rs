@@ -0,0 +1,113 @@
+//! Local worker processes, so a single load generator uses all of its cores without
+//! running into per-process fd and scheduler limits. Each child runs as a cluster
+//! worker on a loopback port and streams its events back to the parent coordinator
+
+use std::{
+    io,
+    net::TcpListener,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use async_std::net::TcpStream;
+
+/// Time allowed for a child to start listening
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+const START_POLL: Duration = Duration::from_millis(50);
+
+/// Outputs owned by the parent, children must not pick them up from the environment
+const PARENT_ONLY_ENV: &[&str] = &[
+    "UI",
+    "WEB_UI",
+    "EVENTS",
+    "EVENTS_FILE",
+    "CLIENT_LOG_DIR",
+    "STATSD_ADDR",
+    "OUTPUT",
+    "HTML_REPORT",
+    "RECORD",
+    "EXPORT_PARQUET",
+    "BASELINE",
+    "LOG_FILE",
+    "PROCESSES",
+];
+
+/// Worker children, killed when dropped
+pub struct WorkerProcesses {
+    children: Vec<Child>,
+    addrs: Vec<String>,
+}
+
+/// Let the OS pick a free loopback port
+fn free_port() -> io::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+async fn wait_listening(addr: &str, child: &mut Child) -> io::Result<()> {
+    let deadline = Instant::now() + START_TIMEOUT;
+    loop {
+        // the worker treats a connection closed before any message as a no-op
+        match TcpStream::connect(addr).await {
+            Ok(_) => return Ok(()),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => {}
+        }
+        if let Some(status) = child.try_wait()? {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("worker process exited with {}", status),
+            ));
+        }
+        async_std::task::sleep(START_POLL).await;
+    }
+}
+
+impl WorkerProcesses {
+    /// Start `count` copies of this executable in worker role. With `inherit_stdout`
+    /// false their logs are discarded, e.g. while the parent owns the terminal
+    pub async fn spawn(count: usize, inherit_stdout: bool) -> io::Result<Self> {
+        let exe = std::env::current_exe()?;
+        let mut workers = WorkerProcesses {
+            children: vec![],
+            addrs: vec![],
+        };
+        for index in 0..count {
+            let addr = format!("127.0.0.1:{}", free_port()?);
+            let mut command = Command::new(&exe);
+            command
+                .args(["bench", "--role", "worker", "--listen", &addr])
+                .stdin(Stdio::null())
+                .stdout(if inherit_stdout {
+                    Stdio::inherit()
+                } else {
+                    Stdio::null()
+                });
+            for name in PARENT_ONLY_ENV {
+                command.env_remove(name);
+            }
+            // Ctrl+C in the terminal must not kill workers, the parent stops them gracefully
+            #[cfg(unix)]
+            std::os::unix::process::CommandExt::process_group(&mut command, 0);
+            let mut child = command.spawn()?;
+            log::info!("[Procs] worker {} pid {} on {}", index, child.id(), addr);
+            let started = wait_listening(&addr, &mut child).await;
+            workers.children.push(child);
+            started?;
+            workers.addrs.push(addr);
+        }
+        Ok(workers)
+    }
+
+    pub fn addrs(&self) -> &[String] {
+        &self.addrs
+    }
+}
+
+impl Drop for WorkerProcesses {
+    fn drop(&mut self) {
+        for child in self.children.iter_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
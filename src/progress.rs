@@ -0,0 +1,100 @@
+//! One line progress on stderr for runs without the UI, redrawn in place each second
+
+use std::{
+    collections::HashSet,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use async_std::channel::Receiver;
+
+use crate::bench::BenchEvent;
+
+const REDRAW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct Progress {
+    spawned: usize,
+    connected: HashSet<usize>,
+    failed: usize,
+}
+
+impl Progress {
+    fn on_event(&mut self, event: &BenchEvent) {
+        match event {
+            BenchEvent::Connecting(_) => self.spawned += 1,
+            BenchEvent::Connected(id) => {
+                self.connected.insert(*id);
+            }
+            BenchEvent::Failed(id, _) => {
+                self.failed += 1;
+                self.connected.remove(id);
+            }
+            BenchEvent::Disconnected(id) => {
+                self.connected.remove(id);
+            }
+            _ => {}
+        }
+    }
+
+    fn line(&self, target: usize, elapsed: Duration, duration: Option<Duration>) -> String {
+        let width = 20;
+        let done = (self.spawned.min(target) * width)
+            .checked_div(target)
+            .unwrap_or(width);
+        let bar: String = "#".repeat(done) + &"-".repeat(width - done);
+        let secs = elapsed.as_secs();
+        let mut line = format!(
+            "[{}] spawned {}/{} connected {} failed {} elapsed {}:{:02}",
+            bar,
+            self.spawned,
+            target,
+            self.connected.len(),
+            self.failed,
+            secs / 60,
+            secs % 60
+        );
+        let remaining = match duration {
+            Some(duration) => Some(duration.saturating_sub(elapsed)),
+            // ramp eta at the rate clients spawned so far
+            None if self.spawned > 0 && self.spawned < target => Some(Duration::from_secs_f64(
+                elapsed.as_secs_f64() / self.spawned as f64 * (target - self.spawned) as f64,
+            )),
+            None => None,
+        };
+        if let Some(remaining) = remaining {
+            let secs = remaining.as_secs();
+            line.push_str(&format!(" remaining {}:{:02}", secs / 60, secs % 60));
+        }
+        line
+    }
+}
+
+/// Redraw the progress of `target` clients until the event channel closes.
+/// Remaining time is the rest of `duration`, or the ramp estimate without one
+pub async fn run_progress(
+    target: usize,
+    duration: Option<Duration>,
+    event_rx: Receiver<BenchEvent>,
+) {
+    let started_at = Instant::now();
+    let mut progress = Progress::default();
+    let mut next_draw = Instant::now();
+    loop {
+        // drawn between events too, a busy channel must not starve the redraw
+        if Instant::now() >= next_draw {
+            next_draw = Instant::now() + REDRAW;
+            let line = progress.line(target, started_at.elapsed(), duration);
+            // clear the rest of the previous line, it may have been longer
+            eprint!("\r{}\x1b[K", line);
+            let _ = std::io::stderr().flush();
+        }
+        let wait = next_draw.saturating_duration_since(Instant::now());
+        match async_std::future::timeout(wait, event_rx.recv()).await {
+            Ok(Ok(event)) => progress.on_event(&event),
+            Ok(Err(_)) => break,
+            Err(_) => {}
+        }
+    }
+    eprintln!();
+}
@@ -0,0 +1,162 @@
+//! Adaptive ramp-up, pauses spawning clients while the server shows distress in
+//! its signaling and resumes once it recovered. Decisions end up in the summary
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use async_std::channel::{Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bench::{BenchControl, BenchEvent, FailureCategory},
+    whep::HttpMethod,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Offers in the window needed before the server counts as distressed
+const MIN_SAMPLES: usize = 5;
+
+/// Distress thresholds over the offers of the last `window`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RampGuard {
+    pub post_p95_ms: Option<u32>,
+    /// Share of offers answered with an error status or lost to a timeout
+    pub error_rate: Option<f64>,
+    pub window: Duration,
+}
+
+/// The ramp was paused or resumed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RampDecision {
+    pub after_ms: u64,
+    pub paused: bool,
+    pub reason: String,
+}
+
+impl fmt::Display for RampDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ramp {} after {}s, {}",
+            if self.paused { "paused" } else { "resumed" },
+            self.after_ms / 1000,
+            self.reason
+        )
+    }
+}
+
+struct Offer {
+    at: Instant,
+    total_ms: u32,
+    failed: bool,
+}
+
+impl RampGuard {
+    /// Why the server is distressed, None when it is fine
+    fn distress(&self, offers: &VecDeque<Offer>) -> Option<String> {
+        if offers.len() < MIN_SAMPLES {
+            return None;
+        }
+        let failed = offers.iter().filter(|o| o.failed).count();
+        let error_rate = failed as f64 / offers.len() as f64;
+        if let Some(max) = self.error_rate.filter(|max| error_rate > *max) {
+            return Some(format!("error rate {:.3} > {}", error_rate, max));
+        }
+        let mut latencies: Vec<u32> = offers
+            .iter()
+            .filter(|o| !o.failed)
+            .map(|o| o.total_ms)
+            .collect();
+        latencies.sort_unstable();
+        let p95 = *latencies.get((latencies.len().max(1) - 1) * 95 / 100)?;
+        self.post_p95_ms
+            .filter(|max| p95 > *max)
+            .map(|max| format!("post p95 {} ms > {}", p95, max))
+    }
+}
+
+/// Watch the offers while `count` clients are started, or for the whole run with
+/// `sustain`, pausing and resuming the runner. Returns the decisions once the
+/// event channel closed
+pub async fn run_ramp_guard(
+    guard: RampGuard,
+    count: usize,
+    sustain: bool,
+    control_tx: Sender<BenchControl>,
+    event_rx: Receiver<BenchEvent>,
+) -> Vec<RampDecision> {
+    let started = Instant::now();
+    let mut decisions = vec![];
+    let mut offers: VecDeque<Offer> = VecDeque::new();
+    let mut spawned = 0;
+    let mut paused = false;
+    let mut next_check = Instant::now() + CHECK_INTERVAL;
+    loop {
+        let wait = next_check.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            match async_std::future::timeout(wait, event_rx.recv()).await {
+                Ok(Ok(event)) => {
+                    match event {
+                        BenchEvent::Connecting(_) => spawned += 1,
+                        BenchEvent::Http(_, timing) if timing.method == HttpMethod::Post => offers
+                            .push_back(Offer {
+                                at: Instant::now(),
+                                total_ms: timing.total_ms,
+                                failed: timing.status >= 400,
+                            }),
+                        BenchEvent::Failed(_, reason)
+                            if matches!(
+                                reason.category,
+                                FailureCategory::Timeout | FailureCategory::Network
+                            ) =>
+                        {
+                            offers.push_back(Offer {
+                                at: Instant::now(),
+                                total_ms: 0,
+                                failed: true,
+                            })
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                Ok(Err(_)) => return decisions,
+                Err(_) => {}
+            }
+        }
+        let now = Instant::now();
+        next_check = now + CHECK_INTERVAL;
+        while offers.front().map_or(false, |o| now - o.at > guard.window) {
+            offers.pop_front();
+        }
+        // a paused ramp sends no offers, the window empties and it tries again
+        let distress = guard.distress(&offers);
+        let decision = match (&distress, paused) {
+            (Some(reason), false) if sustain || spawned < count => Some((true, reason.clone())),
+            (None, true) => Some((false, "server recovered".to_string())),
+            _ => None,
+        };
+        let Some((pause, reason)) = decision else {
+            continue;
+        };
+        let control = if pause {
+            BenchControl::Pause { hold: false }
+        } else {
+            BenchControl::Resume
+        };
+        if control_tx.send(control).await.is_err() {
+            return decisions;
+        }
+        paused = pause;
+        let decision = RampDecision {
+            after_ms: started.elapsed().as_millis() as u64,
+            paused,
+            reason,
+        };
+        log::warn!("[Ramp] {}", decision);
+        decisions.push(decision);
+    }
+}
@@ -0,0 +1,136 @@
+//! Recording of a run into SQLite, so large runs can be queried with SQL afterwards.
+//!
+//! Schema, `ts` is unix time in miliseconds:
+//!
+//! ```sql
+//! CREATE TABLE run (run_id TEXT, started_at INTEGER);
+//! -- every event except stats samples, `data` is the event as JSON
+//! CREATE TABLE events (ts INTEGER, client_id INTEGER, kind TEXT, data TEXT);
+//! -- one row per stats sample, `streams` is the per stream stats as JSON
+//! CREATE TABLE stats (ts INTEGER, client_id INTEGER, send_kbps INTEGER, recv_kbps INTEGER,
+//!     rtt_ms INTEGER, loss REAL, mos REAL, keyframes INTEGER, wire_bytes INTEGER,
+//!     payload_bytes INTEGER, socket_drops INTEGER, streams TEXT);
+//! -- milestones of each client, see `timeline`, `duration_ms` is set for stalls
+//! CREATE TABLE timeline (ts INTEGER, client_id INTEGER, milestone TEXT, duration_ms INTEGER);
+//! ```
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_std::channel::Receiver;
+use rusqlite::{params, Connection};
+
+use crate::{bench::BenchEvent, timeline};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS run (run_id TEXT, started_at INTEGER);
+CREATE TABLE IF NOT EXISTS events (ts INTEGER, client_id INTEGER, kind TEXT, data TEXT);
+CREATE TABLE IF NOT EXISTS stats (ts INTEGER, client_id INTEGER, send_kbps INTEGER,
+    recv_kbps INTEGER, rtt_ms INTEGER, loss REAL, mos REAL, keyframes INTEGER,
+    wire_bytes INTEGER, payload_bytes INTEGER, socket_drops INTEGER, streams TEXT);
+CREATE TABLE IF NOT EXISTS timeline (ts INTEGER, client_id INTEGER, milestone TEXT,
+    duration_ms INTEGER);
+CREATE INDEX IF NOT EXISTS events_client ON events (client_id, ts);
+CREATE INDEX IF NOT EXISTS stats_client ON stats (client_id, ts);
+CREATE INDEX IF NOT EXISTS timeline_client ON timeline (client_id, ts);
+";
+
+/// Rows written per transaction at most, smaller batches when events are sparse
+const BATCH: usize = 1000;
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Variant name of the event, the key of its JSON form
+fn kind(data: &serde_json::Value) -> String {
+    match data {
+        serde_json::Value::Object(map) => map.keys().next().cloned().unwrap_or_default(),
+        serde_json::Value::String(name) => name.clone(),
+        _ => String::new(),
+    }
+}
+
+fn insert(conn: &Connection, event: &BenchEvent) -> rusqlite::Result<()> {
+    let ts = now_ms();
+    let client_id = event.client_id() as i64;
+    for entry in timeline::entries_of(event, ts as u64) {
+        conn.prepare_cached("INSERT INTO timeline VALUES (?1, ?2, ?3, ?4)")?
+            .execute(params![
+                entry.at_ms as i64,
+                client_id,
+                format!("{:?}", entry.milestone),
+                entry.duration_ms.map(|ms| ms as i64)
+            ])?;
+    }
+    if let BenchEvent::Stats(_, stats) = event {
+        let streams = serde_json::to_string(&stats.streams).expect("should serialize streams");
+        conn.prepare_cached(
+            "INSERT INTO stats VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        )?
+        .execute(params![
+            ts,
+            client_id,
+            stats.send_kbps as i64,
+            stats.recv_kbps as i64,
+            stats.rtt_ms,
+            stats.lost,
+            stats.mos,
+            stats.keyframes as i64,
+            stats.traffic.wire_bytes as i64,
+            stats.traffic.payload_bytes as i64,
+            stats.socket_drops.map(|d| d as i64),
+            streams
+        ])?;
+        return Ok(());
+    }
+    let data = serde_json::to_value(event).expect("should serialize event");
+    conn.prepare_cached("INSERT INTO events VALUES (?1, ?2, ?3, ?4)")?
+        .execute(params![ts, client_id, kind(&data), data.to_string()])?;
+    Ok(())
+}
+
+fn open(path: &Path, run_id: &str) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    // a crash loses at most the last batch, in exchange for far fewer fsyncs
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.execute_batch(SCHEMA)?;
+    conn.execute("INSERT INTO run VALUES (?1, ?2)", params![run_id, now_ms()])?;
+    Ok(conn)
+}
+
+/// Record every event into the database at `path`, blocks the calling thread.
+/// Rows are committed in batches whenever the channel runs empty
+pub fn run_recorder(path: &Path, run_id: &str, event_rx: Receiver<BenchEvent>) {
+    let mut conn = match open(path, run_id) {
+        Ok(conn) => conn,
+        Err(e) => {
+            log::error!("[Record] cannot open {} {:?}", path.display(), e);
+            return;
+        }
+    };
+    while let Ok(event) = event_rx.recv_blocking() {
+        let res = conn.transaction().and_then(|tx| {
+            insert(&tx, &event)?;
+            let mut rows = 1;
+            while rows < BATCH {
+                let Ok(event) = event_rx.try_recv() else {
+                    break;
+                };
+                insert(&tx, &event)?;
+                rows += 1;
+            }
+            tx.commit()
+        });
+        if let Err(e) = res {
+            log::error!("[Record] write failed {:?}", e);
+            return;
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Reload of selected options from the config file on SIGHUP while the run
+//! continues, for adjusting long soaks without restarting and losing their
+//! history. The client count, the health thresholds and the stats decimation are
+//! reloaded, other options of the file and options missing from it stay as they
+//! are. Values of the file replace those given by flags or the environment
+
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_std::channel::Sender;
+use parking_lot::RwLock;
+
+use crate::{
+    bench::BenchControl,
+    collector::HealthThresholds,
+    config::{self, ConfigOption},
+    decimate::Decimation,
+    rt,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Options which can change during a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reloadable {
+    pub count: usize,
+    pub thresholds: HealthThresholds,
+    pub decimation: Option<Decimation>,
+}
+
+fn parse<T: FromStr>(name: &str, values: &[String]) -> Result<T, String> {
+    match values {
+        [value] => value
+            .parse()
+            .map_err(|_| format!("invalid value {} of {}", value, name)),
+        _ => Err(format!("option {} takes a single value", name)),
+    }
+}
+
+impl Reloadable {
+    /// These options with the values of the file applied
+    pub fn reloaded(&self, options: &[ConfigOption]) -> Result<Self, String> {
+        let mut next = *self;
+        let thresholds = &mut next.thresholds;
+        for (name, values) in options {
+            match name.as_str() {
+                "count" => next.count = parse(name, values)?,
+                "stats_decimation" => next.decimation = Some(parse(name, values)?),
+                "warn_loss" => thresholds.warn_loss = parse(name, values)?,
+                "bad_loss" => thresholds.bad_loss = parse(name, values)?,
+                "warn_rtt" => thresholds.warn_rtt_ms = parse(name, values)?,
+                "bad_rtt" => thresholds.bad_rtt_ms = parse(name, values)?,
+                "warn_kbps" => thresholds.warn_kbps = parse(name, values)?,
+                "bad_kbps" => thresholds.bad_kbps = parse(name, values)?,
+                _ => {}
+            }
+        }
+        Ok(next)
+    }
+}
+
+/// Reload the options from the config file on each SIGHUP. Thresholds are
+/// swapped in place, the count and the decimation go to the runner as controls
+pub async fn run_reload(
+    path: PathBuf,
+    profile: Option<String>,
+    mut current: Reloadable,
+    thresholds: Arc<RwLock<HealthThresholds>>,
+    control_tx: Sender<BenchControl>,
+) {
+    let hangup = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    if let Err(e) = signal_hook::flag::register(signal_hook::consts::SIGHUP, hangup.clone()) {
+        log::warn!("[Reload] cannot handle SIGHUP {:?}", e);
+        return;
+    }
+    loop {
+        rt::sleep(POLL_INTERVAL).await;
+        if !hangup.swap(false, Ordering::Relaxed) {
+            continue;
+        }
+        let next = match config::load(&path, profile.as_deref())
+            .and_then(|options| current.reloaded(&options))
+        {
+            Ok(next) => next,
+            Err(e) => {
+                log::warn!("[Reload] keeping the current options, {}", e);
+                continue;
+            }
+        };
+        if next == current {
+            log::info!("[Reload] {} reloaded, nothing changed", path.display());
+            continue;
+        }
+        if next.thresholds != current.thresholds {
+            log::info!("[Reload] health thresholds {:?}", next.thresholds);
+            *thresholds.write() = next.thresholds;
+        }
+        if next.count != current.count {
+            log::info!("[Reload] client count {} -> {}", current.count, next.count);
+            let _ = control_tx.send(BenchControl::SetTarget(next.count)).await;
+        }
+        if next.decimation != current.decimation {
+            log::info!("[Reload] stats decimation {:?}", next.decimation);
+            let _ = control_tx
+                .send(BenchControl::SetDecimation(next.decimation))
+                .await;
+        }
+        current = next;
+    }
+}
@@ -0,0 +1,114 @@
+//! Recording of the event stream of a run into a file, and replaying it later
+//! through the UI and the summary, for looking at a run again without load.
+//!
+//! The file starts with [`MAGIC`], then one frame per event: the milliseconds
+//! since the recording started as u64 and the payload length as u32, both little
+//! endian, then the event as JSON. A recording cut off by a crash replays up to
+//! its last complete frame
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use async_std::channel::{Receiver, Sender};
+
+use crate::bench::BenchEvent;
+
+pub const MAGIC: &[u8; 8] = b"WHEPEVT1";
+
+fn write_frame<W: Write>(out: &mut W, at: Duration, event: &BenchEvent) -> io::Result<()> {
+    let payload = serde_json::to_vec(event)?;
+    out.write_all(&(at.as_millis() as u64).to_le_bytes())?;
+    out.write_all(&(payload.len() as u32).to_le_bytes())?;
+    out.write_all(&payload)
+}
+
+/// Record every event into the file at `path`, blocks the calling thread.
+/// Output is flushed whenever the channel runs empty
+pub fn run_event_recorder(path: &Path, event_rx: Receiver<BenchEvent>) {
+    let started = Instant::now();
+    let res = File::create(path).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        out.write_all(MAGIC)?;
+        while let Ok(event) = event_rx.recv_blocking() {
+            write_frame(&mut out, started.elapsed(), &event)?;
+            if event_rx.is_empty() {
+                out.flush()?;
+            }
+        }
+        out.flush()
+    });
+    if let Err(e) = res {
+        log::error!(
+            "[Replay] cannot record events to {} {:?}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Frames of a recording in order
+pub struct EventReader {
+    reader: BufReader<File>,
+}
+
+impl EventReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an event recording",
+            ));
+        }
+        Ok(Self { reader })
+    }
+
+    /// Next event with its time since the recording started, None at the end
+    pub fn next_event(&mut self) -> io::Result<Option<(Duration, BenchEvent)>> {
+        let mut header = [0; 12];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let at = u64::from_le_bytes(header[..8].try_into().expect("should be 8 bytes"));
+        let len = u32::from_le_bytes(header[8..].try_into().expect("should be 4 bytes"));
+        let mut payload = vec![0; len as usize];
+        match self.reader.read_exact(&mut payload) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                log::warn!("[Replay] recording ends with a truncated frame");
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+        let event = serde_json::from_slice(&payload)?;
+        Ok(Some((Duration::from_millis(at), event)))
+    }
+}
+
+/// Send the recorded events at their recorded pace divided by `speed`, 0 sends
+/// them as fast as the consumers take them. Blocks the calling thread, returns
+/// the number of events sent
+pub fn replay(path: &Path, speed: f64, event_tx: Sender<BenchEvent>) -> io::Result<usize> {
+    let mut reader = EventReader::open(path)?;
+    let started = Instant::now();
+    let mut sent = 0;
+    while let Some((at, event)) = reader.next_event()? {
+        if speed > 0.0 {
+            let due = started + at.div_f64(speed);
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        if event_tx.send_blocking(event).is_err() {
+            break;
+        }
+        sent += 1;
+    }
+    Ok(sent)
+}
@@ -0,0 +1,422 @@
+//! Standalone HTML report of a run summary, charts are inline SVG so the file
+//! opens anywhere without network access. A compact Markdown summary serves CI,
+//! e.g. for posting as a PR comment
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    collector::AggregateSample,
+    gate::GateResult,
+    summary::{Percentiles, RunSummary},
+};
+
+/// Longer timelines are thinned out to keep the file small
+const MAX_POINTS: usize = 600;
+/// Failure categories listed in the Markdown summary, the most frequent first
+const TOP_FAILURES: usize = 5;
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 220.0;
+const MARGIN: f64 = 40.0;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+td,th{border:1px solid #ccc;padding:4px 10px;text-align:right}\
+th:first-child,td:first-child{text-align:left}\
+svg{display:block;margin-bottom:2em}\
+.legend{font-size:12px}";
+
+struct Series<'a> {
+    name: &'a str,
+    color: &'a str,
+    value: fn(&AggregateSample) -> f64,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn chart(out: &mut String, title: &str, samples: &[&AggregateSample], series: &[Series]) {
+    let _ = writeln!(out, "<h2>{}</h2>", escape(title));
+    let Some(last) = samples.last() else {
+        out.push_str("<p>no samples</p>\n");
+        return;
+    };
+    let max_x = (last.elapsed_ms as f64).max(1.0);
+    let max_y = samples
+        .iter()
+        .flat_map(|s| series.iter().map(move |line| (line.value)(s)))
+        .fold(0.0, f64::max)
+        .max(1.0);
+    let x = |ms: u64| MARGIN + ms as f64 / max_x * (WIDTH - 2.0 * MARGIN);
+    let y = |v: f64| HEIGHT - MARGIN - v / max_y * (HEIGHT - 2.0 * MARGIN);
+    let _ = writeln!(
+        out,
+        r#"<svg width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-size="11">"#,
+        w = WIDTH,
+        h = HEIGHT
+    );
+    let _ = writeln!(
+        out,
+        r##"<polyline fill="none" stroke="#888" points="{l},{t} {l},{b} {r},{b}"/>"##,
+        l = MARGIN,
+        t = MARGIN,
+        b = HEIGHT - MARGIN,
+        r = WIDTH - MARGIN
+    );
+    let _ = writeln!(
+        out,
+        r#"<text x="{}" y="{}" text-anchor="end">{:.0}</text><text x="{}" y="{}" text-anchor="end">0</text>"#,
+        MARGIN - 4.0,
+        MARGIN + 4.0,
+        max_y,
+        MARGIN - 4.0,
+        HEIGHT - MARGIN + 4.0
+    );
+    let _ = writeln!(
+        out,
+        r#"<text x="{}" y="{}" text-anchor="end">{}s</text>"#,
+        WIDTH - MARGIN,
+        HEIGHT - MARGIN + 16.0,
+        last.elapsed_ms / 1000
+    );
+    for (i, line) in series.iter().enumerate() {
+        let points: Vec<String> = samples
+            .iter()
+            .map(|s| format!("{:.1},{:.1}", x(s.elapsed_ms), y((line.value)(s))))
+            .collect();
+        let _ = writeln!(
+            out,
+            r#"<polyline fill="none" stroke="{}" stroke-width="1.5" points="{}"/>"#,
+            line.color,
+            points.join(" ")
+        );
+        let _ = writeln!(
+            out,
+            r#"<text class="legend" x="{}" y="{}" fill="{}">{}</text>"#,
+            MARGIN + 10.0 + i as f64 * 140.0,
+            MARGIN - 12.0,
+            line.color,
+            escape(line.name)
+        );
+    }
+    out.push_str("</svg>\n");
+}
+
+/// Render the summary, `title` usually names the run
+pub fn render_html(summary: &RunSummary, title: &str) -> String {
+    let step = summary.timeline.len().div_ceil(MAX_POINTS).max(1);
+    let samples: Vec<&AggregateSample> = summary.timeline.iter().step_by(step).collect();
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{t}</title><style>{s}</style></head><body>\n<h1>{t}</h1>",
+        t = escape(title),
+        s = STYLE
+    );
+    let _ = writeln!(out, "<pre>{}</pre>", escape(&summary.to_string()));
+
+    chart(
+        &mut out,
+        "Bitrate (kbps)",
+        &samples,
+        &[
+            Series {
+                name: "recv",
+                color: "#1f77b4",
+                value: |s| s.recv_kbps as f64,
+            },
+            Series {
+                name: "send",
+                color: "#ff7f0e",
+                value: |s| s.send_kbps as f64,
+            },
+        ],
+    );
+    chart(
+        &mut out,
+        "Average RTT (ms)",
+        &samples,
+        &[Series {
+            name: "rtt",
+            color: "#9467bd",
+            value: |s| s.avg_rtt_ms as f64,
+        }],
+    );
+    chart(
+        &mut out,
+        "Connections",
+        &samples,
+        &[
+            Series {
+                name: "clients",
+                color: "#7f7f7f",
+                value: |s| s.clients as f64,
+            },
+            Series {
+                name: "connected",
+                color: "#2ca02c",
+                value: |s| s.connected as f64,
+            },
+            Series {
+                name: "failed",
+                color: "#d62728",
+                value: |s| s.failed as f64,
+            },
+        ],
+    );
+
+    if !summary.ramp.is_empty() {
+        out.push_str("<h2>Adaptive ramp</h2>\n");
+        out.push_str("<table><tr><th>after s</th><th>action</th><th>reason</th></tr>\n");
+        for decision in &summary.ramp {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                decision.after_ms / 1000,
+                if decision.paused { "paused" } else { "resumed" },
+                escape(&decision.reason)
+            );
+        }
+        out.push_str("</table>\n");
+    }
+
+    if !summary.ice.is_empty() {
+        out.push_str("<h2>ICE</h2>\n");
+        out.push_str("<table><tr><th>aspect</th><th>value</th><th>clients</th></tr>\n");
+        let roles: BTreeMap<String, usize> = summary
+            .ice
+            .roles
+            .iter()
+            .map(|(role, count)| (role.to_string(), *count))
+            .collect();
+        for (aspect, counts) in [
+            ("role", &roles),
+            ("local candidate", &summary.ice.local_types),
+            ("remote candidate", &summary.ice.remote_types),
+            ("transport", &summary.ice.protocols),
+        ] {
+            for (value, count) in counts {
+                let _ = writeln!(
+                    out,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    aspect,
+                    escape(value),
+                    count
+                );
+            }
+        }
+        out.push_str("</table>\n");
+        if summary.ice.relayed > 0 || summary.ice.tcp() > 0 {
+            let _ = writeln!(
+                out,
+                "<p>{} clients relayed, {} over tcp</p>",
+                summary.ice.relayed,
+                summary.ice.tcp()
+            );
+        }
+    }
+
+    out.push_str("<h2>Answer consistency</h2>\n");
+    if summary.answer_divergences.is_empty() {
+        out.push_str("<p>all clients negotiated the same parameters</p>\n");
+    } else {
+        out.push_str(
+            "<table><tr><th>parameter</th><th>value</th><th>clients</th><th>inconsistent clients</th></tr>\n",
+        );
+        for divergence in &summary.answer_divergences {
+            let outliers: Vec<String> = divergence
+                .outliers
+                .iter()
+                .take(20)
+                .map(|id| id.to_string())
+                .collect();
+            for (i, (value, count)) in divergence.variants.iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    if i == 0 {
+                        divergence.parameter.as_str()
+                    } else {
+                        ""
+                    },
+                    escape(value),
+                    count,
+                    if i == 0 {
+                        outliers.join(", ")
+                    } else {
+                        String::new()
+                    }
+                );
+            }
+        }
+        out.push_str("</table>\n");
+    }
+
+    if let Some(conformance) = &summary.conformance {
+        let _ = writeln!(
+            out,
+            "<h2>Conformance</h2>\n<p>{} expected, {:.1}% of samples conformed on average</p>",
+            escape(&conformance.expected),
+            conformance.average()
+        );
+        out.push_str(
+            "<table><tr><th>client</th><th>samples</th><th>video</th><th>audio</th><th>fps</th><th>overall</th></tr>\n",
+        );
+        let percent = |p: Option<f64>| p.map_or("-".to_string(), |p| format!("{:.1}%", p));
+        for (id, client) in conformance.worst(20) {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>",
+                id,
+                client.samples,
+                percent(client.video),
+                percent(client.audio),
+                percent(client.fps),
+                client.overall
+            );
+        }
+        out.push_str("</table>\n");
+    }
+
+    out.push_str("<h2>Failures</h2>\n");
+    if summary.failures.is_empty() {
+        out.push_str("<p>no failed clients</p>\n");
+    } else {
+        out.push_str("<table><tr><th>category</th><th>clients</th><th>share</th></tr>\n");
+        for (category, count) in &summary.failures {
+            let _ = writeln!(
+                out,
+                "<tr><td>{:?}</td><td>{}</td><td>{:.1}%</td></tr>",
+                category,
+                count,
+                *count as f64 * 100.0 / summary.clients.max(1) as f64
+            );
+        }
+        out.push_str("</table>\n");
+    }
+    for (challenge, count) in &summary.auth_challenges {
+        let _ = writeln!(
+            out,
+            "<p>{} clients challenged with WWW-Authenticate <code>{}</code></p>",
+            count,
+            escape(challenge)
+        );
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+fn percentiles_cell(p: &Percentiles) -> String {
+    format!("{:.1} / {:.1} / {:.1}", p.p50, p.p90, p.p99)
+}
+
+/// Cell text of Markdown tables, pipes and line breaks would end the cell
+fn md_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Render the headline metrics, the gate results and the top failure reasons
+/// as Markdown tables
+pub fn render_markdown(summary: &RunSummary, gates: &[GateResult], title: &str) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "### {}\n", md_cell(title));
+    if let Some(abort) = &summary.aborted {
+        let _ = writeln!(out, "**Aborted**: {}\n", md_cell(&abort.to_string()));
+    }
+
+    out.push_str("| metric | value |\n|---|---|\n");
+    let _ = writeln!(out, "| duration | {}s |", summary.duration_ms / 1000);
+    let _ = writeln!(
+        out,
+        "| clients | {} ({} connected, {} failed) |",
+        summary.clients, summary.connected, summary.failed
+    );
+    for (name, p) in [
+        ("connect ms p50 / p90 / p99", &summary.connect_ms),
+        ("recv kbps p50 / p90 / p99", &summary.recv_kbps),
+        ("rtt ms p50 / p90 / p99", &summary.rtt_ms),
+        ("loss p50 / p90 / p99", &summary.loss),
+    ] {
+        let _ = writeln!(out, "| {} | {} |", name, percentiles_cell(p));
+    }
+    if summary.sessions_leaked > 0 {
+        let _ = writeln!(out, "| sessions leaked | {} |", summary.sessions_leaked);
+    }
+    if !summary.answer_divergences.is_empty() {
+        let parameters: Vec<&str> = summary
+            .answer_divergences
+            .iter()
+            .map(|d| d.parameter.as_str())
+            .collect();
+        let _ = writeln!(
+            out,
+            "| answers | {} differ across clients |",
+            parameters.join(", ")
+        );
+    }
+
+    if summary.ice.relayed > 0 || summary.ice.tcp() > 0 {
+        let _ = writeln!(
+            out,
+            "| ice fallbacks | {} relayed, {} over tcp |",
+            summary.ice.relayed,
+            summary.ice.tcp()
+        );
+    }
+    if let Some(conformance) = &summary.conformance {
+        let _ = writeln!(
+            out,
+            "| conformance | {:.1}% to {} |",
+            conformance.average(),
+            md_cell(&conformance.expected)
+        );
+    }
+
+    if !gates.is_empty() {
+        let failed = gates.iter().filter(|result| !result.passed).count();
+        let _ = writeln!(
+            out,
+            "\n**Gates**: {}\n",
+            if failed == 0 {
+                "passed".to_string()
+            } else {
+                format!("{} of {} failed", failed, gates.len())
+            }
+        );
+        out.push_str("| gate | value | result |\n|---|---|---|\n");
+        for result in gates {
+            let value = result
+                .value
+                .map_or("no samples".to_string(), |v| format!("{:.3}", v));
+            let _ = writeln!(
+                out,
+                "| `{}` | {} | {} |",
+                md_cell(&result.gate),
+                value,
+                if result.passed { "pass" } else { "**FAIL**" }
+            );
+        }
+    }
+
+    if !summary.failures.is_empty() {
+        let mut failures: Vec<_> = summary.failures.iter().collect();
+        failures.sort_by(|a, b| b.1.cmp(a.1));
+        out.push_str(
+            "\n**Top failure reasons**\n\n| category | clients | share |\n|---|---|---|\n",
+        );
+        for (category, count) in failures.into_iter().take(TOP_FAILURES) {
+            let _ = writeln!(
+                out,
+                "| {:?} | {} | {:.1}% |",
+                category,
+                count,
+                *count as f64 * 100.0 / summary.clients.max(1) as f64
+            );
+        }
+    }
+    out
+}
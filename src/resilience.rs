@@ -0,0 +1,172 @@
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+/// Missing sequence numbers remembered per stream, older ones can no longer be repaired
+const MAX_MISSING: usize = 512;
+/// Larger jumps are a sender restart rather than loss
+const MAX_GAP: u16 = 1000;
+
+/// Lost media packets and how many of them retransmissions and fec repaired,
+/// cumulative over the session
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct ResilienceStats {
+    /// Sequence gaps of the media streams, before any repair
+    pub lost: u64,
+    /// Lost packets which arrived later as retransmission
+    pub recovered_rtx: u64,
+    /// Lost packets an ULPFEC packet can rebuild, being the only missing one it protects
+    pub recovered_fec: u64,
+    pub rtx_packets: u64,
+    /// ULPFEC and FlexFEC packets
+    pub fec_packets: u64,
+}
+
+impl ResilienceStats {
+    /// Lost packets neither retransmission nor fec repaired
+    pub fn unrecovered(&self) -> u64 {
+        self.lost
+            .saturating_sub(self.recovered_rtx + self.recovered_fec)
+    }
+
+    pub fn add(&mut self, other: &ResilienceStats) {
+        self.lost += other.lost;
+        self.recovered_rtx += other.recovered_rtx;
+        self.recovered_fec += other.recovered_fec;
+        self.rtx_packets += other.rtx_packets;
+        self.fec_packets += other.fec_packets;
+    }
+}
+
+#[derive(Debug, Default)]
+struct SeqState {
+    highest: u16,
+    missing: BTreeSet<u16>,
+}
+
+impl SeqState {
+    /// Sequence number at or below the highest one, with wraparound
+    fn is_past(&self, seq: u16) -> bool {
+        (self.highest.wrapping_sub(seq) as i16) >= 0
+    }
+}
+
+/// Follows the sequence numbers of media streams and matches retransmissions
+/// and fec packets against the gaps
+#[derive(Debug, Default)]
+pub struct ResilienceTracker {
+    stats: ResilienceStats,
+    /// Rtx payload type => payload type it retransmits, from `a=fmtp:<pt> apt=<pt>`
+    rtx_apt: HashMap<u8, u8>,
+    /// Latest media ssrc per payload type, retransmissions only name the payload type
+    ssrc_by_pt: HashMap<u8, u32>,
+    streams: HashMap<u32, SeqState>,
+}
+
+impl ResilienceTracker {
+    pub fn new(rtx_apt: HashMap<u8, u8>) -> Self {
+        Self {
+            rtx_apt,
+            ..Default::default()
+        }
+    }
+
+    pub fn on_media(&mut self, ssrc: u32, pt: u8, seq: u16) {
+        self.ssrc_by_pt.insert(pt, ssrc);
+        let Some(state) = self.streams.get_mut(&ssrc) else {
+            self.streams.insert(
+                ssrc,
+                SeqState {
+                    highest: seq,
+                    missing: BTreeSet::new(),
+                },
+            );
+            return;
+        };
+        let ahead = seq.wrapping_sub(state.highest) as i16;
+        if ahead <= 0 {
+            // reordered, it was counted lost when the gap opened
+            if state.missing.remove(&seq) {
+                self.stats.lost -= 1;
+            }
+            return;
+        }
+        if ahead as u16 <= MAX_GAP {
+            for gap in 1..ahead as u16 {
+                state.missing.insert(state.highest.wrapping_add(gap));
+                self.stats.lost += 1;
+            }
+            while state.missing.len() > MAX_MISSING {
+                state.missing.pop_first();
+            }
+        } else {
+            state.missing.clear();
+        }
+        state.highest = seq;
+    }
+
+    /// Rtx payload starts with the original sequence number, RFC 4588
+    pub fn on_rtx(&mut self, pt: u8, payload: &[u8]) {
+        self.stats.rtx_packets += 1;
+        let Some(osn) = payload.get(..2).map(|b| u16::from_be_bytes([b[0], b[1]])) else {
+            return;
+        };
+        let state = self
+            .rtx_apt
+            .get(&pt)
+            .and_then(|apt| self.ssrc_by_pt.get(apt))
+            .and_then(|ssrc| self.streams.get_mut(ssrc));
+        if let Some(state) = state {
+            if state.missing.remove(&osn) {
+                self.stats.recovered_rtx += 1;
+            }
+        }
+    }
+
+    /// A fec packet on `ssrc`, ULPFEC packets are checked for a repairable gap,
+    /// FlexFEC is only counted
+    pub fn on_fec(&mut self, ssrc: u32, payload: &[u8], ulpfec: bool) {
+        self.stats.fec_packets += 1;
+        if !ulpfec {
+            return;
+        }
+        let Some(state) = self.streams.get_mut(&ssrc) else {
+            return;
+        };
+        let Some(protected) = ulpfec_protected(payload) else {
+            return;
+        };
+        // xor of the others rebuilds exactly one missing packet, once all others arrived
+        if protected.iter().any(|seq| !state.is_past(*seq)) {
+            return;
+        }
+        let mut missing = protected.iter().filter(|seq| state.missing.contains(seq));
+        if let (Some(seq), None) = (missing.next(), missing.next()) {
+            state.missing.remove(seq);
+            self.stats.recovered_fec += 1;
+        }
+    }
+
+    pub fn stats(&self) -> ResilienceStats {
+        self.stats
+    }
+}
+
+/// Sequence numbers protected by the first level of an ULPFEC packet, RFC 5109
+fn ulpfec_protected(payload: &[u8]) -> Option<Vec<u16>> {
+    // E L P X CC, M PT recovery, SN base, TS recovery, length recovery
+    let header = payload.get(..10)?;
+    let long_mask = header[0] & 0x40 != 0;
+    let base = u16::from_be_bytes([header[2], header[3]]);
+    // level 0 header: protection length, then a 16 or 48 bit mask
+    let mask = payload.get(12..if long_mask { 18 } else { 14 })?;
+    let mut protected = vec![];
+    for (byte_index, byte) in mask.iter().enumerate() {
+        for bit in 0..8 {
+            if byte & (0x80 >> bit) != 0 {
+                protected.push(base.wrapping_add((byte_index * 8 + bit) as u16));
+            }
+        }
+    }
+    Some(protected)
+}
@@ -0,0 +1,366 @@
+//! Resolution of the received video, read from the keyframe headers of VP8 and
+//! VP9 and from H264 sequence parameter sets without decoding. A server which
+//! downscales under load shows up as a resolution change of its clients
+
+use std::{fmt, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::media::{self, Codec};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Resolution {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Resolution {
+    pub fn pixels(&self) -> u32 {
+        self.width as u32 * self.height as u32
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// The received resolution changed, `from` is None for the first keyframe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolutionChange {
+    /// Since the client started
+    pub at_ms: u64,
+    pub from: Option<Resolution>,
+    pub to: Resolution,
+}
+
+impl ResolutionChange {
+    pub fn is_downscale(&self) -> bool {
+        self.from
+            .map_or(false, |from| self.to.pixels() < from.pixels())
+    }
+}
+
+#[derive(Debug)]
+pub struct ResolutionTracker {
+    started_at: Instant,
+    current: Option<Resolution>,
+    /// Changes since the previous stats
+    changes: Vec<ResolutionChange>,
+}
+
+impl Default for ResolutionTracker {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            current: None,
+            changes: vec![],
+        }
+    }
+}
+
+impl ResolutionTracker {
+    /// Call with the packets starting a keyframe
+    pub fn on_keyframe(&mut self, now: Instant, codec: Codec, payload: &[u8]) {
+        let Some(resolution) = parse(codec, payload) else {
+            return;
+        };
+        if self.current == Some(resolution) {
+            return;
+        }
+        log::debug!(
+            "[Resolution] {} => {}",
+            self.current.map_or("-".to_string(), |r| r.to_string()),
+            resolution
+        );
+        self.changes.push(ResolutionChange {
+            at_ms: (now - self.started_at).as_millis() as u64,
+            from: self.current,
+            to: resolution,
+        });
+        self.current = Some(resolution);
+    }
+
+    pub fn current(&self) -> Option<Resolution> {
+        self.current
+    }
+
+    pub fn take_changes(&mut self) -> Vec<ResolutionChange> {
+        std::mem::take(&mut self.changes)
+    }
+}
+
+/// Resolution carried by a keyframe packet, None when its headers have none
+pub fn parse(codec: Codec, payload: &[u8]) -> Option<Resolution> {
+    match codec {
+        Codec::Vp8 => parse_vp8(payload),
+        Codec::Vp9 => parse_vp9(payload),
+        Codec::H264 => parse_h264(payload),
+        _ => None,
+    }
+}
+
+fn resolution(width: u32, height: u32) -> Option<Resolution> {
+    let width = u16::try_from(width).ok().filter(|w| *w > 0)?;
+    let height = u16::try_from(height).ok().filter(|h| *h > 0)?;
+    Some(Resolution { width, height })
+}
+
+/// Keyframe header: 3 bytes frame tag, start code 9d 01 2a, 14 bit width and height
+fn parse_vp8(payload: &[u8]) -> Option<Resolution> {
+    let header = payload.get(media::vp8_header_offset(payload)?..)?;
+    if header.len() < 10 || header[0] & 0x01 != 0 || header[3..6] != [0x9d, 0x01, 0x2a] {
+        return None;
+    }
+    let width = u16::from_le_bytes([header[6], header[7]]) & 0x3fff;
+    let height = u16::from_le_bytes([header[8], header[9]]) & 0x3fff;
+    resolution(width as u32, height as u32)
+}
+
+/// Sizes of the scalability structure in the payload descriptor, or the frame size
+/// of the uncompressed header behind it
+fn parse_vp9(payload: &[u8]) -> Option<Resolution> {
+    let first = *payload.first()?;
+    let (picture_id, predicted, layers, flexible, start, structure) = (
+        first & 0x80 != 0,
+        first & 0x40 != 0,
+        first & 0x20 != 0,
+        first & 0x10 != 0,
+        first & 0x08 != 0,
+        first & 0x02 != 0,
+    );
+    if !start {
+        return None;
+    }
+    let mut offset = 1;
+    if picture_id {
+        offset += if *payload.get(offset)? & 0x80 != 0 {
+            2
+        } else {
+            1
+        };
+    }
+    if layers {
+        // layer indices, and TL0PICIDX in non-flexible mode
+        offset += if flexible { 1 } else { 2 };
+    }
+    if flexible && predicted {
+        // reference indices, the N bit tells another one follows
+        while *payload.get(offset)? & 0x01 != 0 {
+            offset += 1;
+        }
+        offset += 1;
+    }
+    if structure {
+        let ss = *payload.get(offset)?;
+        offset += 1;
+        let spatial_layers = (ss >> 5) as usize + 1;
+        if ss & 0x10 != 0 {
+            // sizes of every spatial layer, the last one is the highest
+            let sizes = payload.get(offset..offset + spatial_layers * 4)?;
+            let last = &sizes[sizes.len() - 4..];
+            let width = u16::from_be_bytes([last[0], last[1]]);
+            let height = u16::from_be_bytes([last[2], last[3]]);
+            return resolution(width as u32, height as u32);
+        }
+        // the frame header follows the picture groups of the structure, not worth skipping
+        return None;
+    }
+    parse_vp9_frame(payload.get(offset..)?)
+}
+
+/// Uncompressed header of a VP9 keyframe
+fn parse_vp9_frame(frame: &[u8]) -> Option<Resolution> {
+    let mut bits = BitReader::new(frame);
+    if bits.read(2)? != 2 {
+        return None;
+    }
+    let profile = bits.read(1)? | bits.read(1)? << 1;
+    if profile == 3 {
+        bits.skip(1)?;
+    }
+    // show_existing_frame, then frame_type 0 is a keyframe
+    if bits.read(1)? != 0 || bits.read(1)? != 0 {
+        return None;
+    }
+    // show_frame and error_resilient_mode
+    bits.skip(2)?;
+    if bits.read(24)? != 0x49_83_42 {
+        return None;
+    }
+    if profile >= 2 {
+        bits.skip(1)?;
+    }
+    let color_space = bits.read(3)?;
+    if color_space != 7 {
+        // color range, and subsampling with a reserved bit
+        bits.skip(if profile == 1 || profile == 3 { 4 } else { 1 })?;
+    } else if profile == 1 || profile == 3 {
+        bits.skip(1)?;
+    }
+    let width = bits.read(16)? + 1;
+    let height = bits.read(16)? + 1;
+    resolution(width, height)
+}
+
+/// Sequence parameter set of a single NAL unit or inside a STAP-A
+fn parse_h264(payload: &[u8]) -> Option<Resolution> {
+    let first = *payload.first()?;
+    match first & 0x1f {
+        7 => parse_sps(payload),
+        24 => {
+            let mut offset = 1;
+            while offset + 2 < payload.len() {
+                let size = u16::from_be_bytes([payload[offset], payload[offset + 1]]) as usize;
+                let nal = payload.get(offset + 2..offset + 2 + size)?;
+                if nal.first().map(|b| b & 0x1f) == Some(7) {
+                    return parse_sps(nal);
+                }
+                offset += 2 + size;
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Width and height of a SPS NAL unit after cropping, H.264 section 7.3.2.1
+fn parse_sps(nal: &[u8]) -> Option<Resolution> {
+    // drop emulation prevention bytes, 00 00 03 carries 00 00
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &b in nal.get(1..)? {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        rbsp.push(b);
+    }
+    let mut bits = BitReader::new(&rbsp);
+    let profile_idc = bits.read(8)?;
+    // constraint flags and level
+    bits.skip(16)?;
+    bits.ue()?;
+    let mut chroma_format_idc = 1;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = bits.ue()?;
+        if chroma_format_idc == 3 {
+            bits.skip(1)?;
+        }
+        // bit depths and qpprime_y_zero_transform_bypass_flag
+        bits.ue()?;
+        bits.ue()?;
+        bits.skip(1)?;
+        if bits.read(1)? == 1 {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if bits.read(1)? == 1 {
+                    skip_scaling_list(&mut bits, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    bits.ue()?;
+    match bits.ue()? {
+        0 => {
+            bits.ue()?;
+        }
+        1 => {
+            bits.skip(1)?;
+            bits.se()?;
+            bits.se()?;
+            for _ in 0..bits.ue()? {
+                bits.se()?;
+            }
+        }
+        _ => {}
+    }
+    // max_num_ref_frames and gaps_in_frame_num_value_allowed_flag
+    bits.ue()?;
+    bits.skip(1)?;
+    let width_mbs = bits.ue()? + 1;
+    let height_map_units = bits.ue()? + 1;
+    let frame_mbs_only = bits.read(1)?;
+    if frame_mbs_only == 0 {
+        bits.skip(1)?;
+    }
+    bits.skip(1)?;
+    let (mut crop_x, mut crop_y) = (0, 0);
+    if bits.read(1)? == 1 {
+        let (left, right, top, bottom) = (bits.ue()?, bits.ue()?, bits.ue()?, bits.ue()?);
+        // crop units depend on the chroma subsampling, 4:2:0 crops in pairs
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        crop_x = sub_width * (left + right);
+        crop_y = sub_height * (2 - frame_mbs_only) * (top + bottom);
+    }
+    let width = (width_mbs * 16).checked_sub(crop_x)?;
+    let height = ((2 - frame_mbs_only) * height_map_units * 16).checked_sub(crop_y)?;
+    resolution(width, height)
+}
+
+fn skip_scaling_list(bits: &mut BitReader, size: usize) -> Option<()> {
+    let (mut last, mut next) = (8i32, 8i32);
+    for _ in 0..size {
+        if next != 0 {
+            next = (last + bits.se()? + 256) % 256;
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Some(())
+}
+
+/// Most significant bit first, None past the end
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read(&mut self, n: u32) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            let byte = *self.data.get(self.pos / 8)?;
+            value = value << 1 | (byte >> (7 - self.pos % 8) & 1) as u32;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn skip(&mut self, n: u32) -> Option<()> {
+        self.read(n).map(|_| ())
+    }
+
+    /// Unsigned exp-Golomb
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.read(1)? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << zeros) - 1 + self.read(zeros)?)
+    }
+
+    /// Signed exp-Golomb
+    fn se(&mut self) -> Option<i32> {
+        let k = self.ue()? as i64;
+        let value = if k % 2 == 1 { (k + 1) / 2 } else { -(k / 2) };
+        Some(value as i32)
+    }
+}
@@ -0,0 +1,85 @@
+//! Per-client parameters from a CSV file, for simulating viewers of different
+//! rooms, streams or tenants in one run. Row N of the file is client id N,
+//! clients past the last row and empty fields use the url and token of the run
+//!
+//! The first line names the columns, any of `url`, `token` and `label` in any
+//! order. Blank lines and lines starting with `#` are skipped, fields are not
+//! quoted
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientEntry {
+    pub url: Option<String>,
+    pub token: Option<String>,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Column {
+    Url,
+    Token,
+    Label,
+}
+
+/// Entries in client id order, the first one is client 1
+pub fn parse(source: &str) -> Result<Vec<ClientEntry>, String> {
+    let mut lines = source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    let (_, header) = lines.next().ok_or("empty clients file")?;
+    let columns = header
+        .split(',')
+        .map(|name| match name.trim().to_ascii_lowercase().as_str() {
+            "url" => Ok(Column::Url),
+            "token" => Ok(Column::Token),
+            "label" => Ok(Column::Label),
+            name => Err(format!(
+                "unknown column {} in clients file, expected url, token or label",
+                name
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut entries = vec![];
+    for (number, line) in lines {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() != columns.len() {
+            return Err(format!(
+                "line {} of clients file has {} fields, expected {}",
+                number,
+                fields.len(),
+                columns.len()
+            ));
+        }
+        let mut entry = ClientEntry::default();
+        for (column, field) in columns.iter().zip(fields) {
+            let value = (!field.is_empty()).then(|| field.to_string());
+            match column {
+                Column::Url => {
+                    if let Some(url) = &value {
+                        url::Url::parse(url)
+                            .map_err(|e| format!("invalid url on line {}: {}", number, e))?;
+                    }
+                    entry.url = value;
+                }
+                Column::Token => entry.token = value,
+                Column::Label => entry.label = value,
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Entries of worker `index` of `total`, whose local ids interleave into the
+/// global ones as `(id - 1) * total + index + 1`
+pub fn shard(entries: &[ClientEntry], index: usize, total: usize) -> Vec<ClientEntry> {
+    entries
+        .iter()
+        .skip(index)
+        .step_by(total.max(1))
+        .cloned()
+        .collect()
+}
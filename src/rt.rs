@@ -0,0 +1,96 @@
+//! Async runtime used by the benchmark core (clients, sockets, shards).
+//!
+//! async-std by default, tokio with the `tokio` feature. Channels come from
+//! `async_std::channel` which works on either runtime. The tide based servers
+//! (agent, web ui, cluster) keep running on async-std.
+
+use std::{future::Future, io, time::Duration};
+
+#[cfg(not(feature = "tokio"))]
+pub use async_std::net::UdpSocket;
+#[cfg(feature = "tokio")]
+pub use tokio::net::UdpSocket;
+#[cfg(not(feature = "tokio"))]
+pub use udp_sas_async::async_std::UdpSocketSas;
+#[cfg(feature = "tokio")]
+pub use udp_sas_async::tokio::UdpSocketSas;
+
+#[derive(Debug)]
+pub struct TimedOut;
+
+#[cfg(not(feature = "tokio"))]
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    async_std::task::spawn(future);
+}
+
+#[cfg(feature = "tokio")]
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(future);
+}
+
+/// Register a bound std socket with the runtime
+#[cfg(not(feature = "tokio"))]
+pub fn udp_from_std(socket: std::net::UdpSocket) -> io::Result<UdpSocket> {
+    Ok(UdpSocket::from(socket))
+}
+
+#[cfg(feature = "tokio")]
+pub fn udp_from_std(socket: std::net::UdpSocket) -> io::Result<UdpSocket> {
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket)
+}
+
+#[cfg(not(feature = "tokio"))]
+pub async fn sleep(duration: Duration) {
+    async_std::task::sleep(duration).await
+}
+
+#[cfg(feature = "tokio")]
+pub async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+#[cfg(not(feature = "tokio"))]
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, TimedOut> {
+    async_std::future::timeout(duration, future)
+        .await
+        .map_err(|_| TimedOut)
+}
+
+#[cfg(feature = "tokio")]
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output, TimedOut> {
+    tokio::time::timeout(duration, future)
+        .await
+        .map_err(|_| TimedOut)
+}
+
+/// Drive `future` on the current thread, futures given to [`spawn_local`] run on it too
+#[cfg(not(feature = "tokio"))]
+pub fn block_on_local<F: Future<Output = ()>>(future: F) {
+    async_std::task::block_on(future)
+}
+
+#[cfg(feature = "tokio")]
+pub fn block_on_local<F: Future<Output = ()>>(future: F) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("should build tokio runtime");
+    tokio::task::LocalSet::new().block_on(&runtime, future)
+}
+
+#[cfg(not(feature = "tokio"))]
+pub fn spawn_local<F: Future<Output = ()> + 'static>(future: F) {
+    async_std::task::spawn_local(future);
+}
+
+#[cfg(feature = "tokio")]
+pub fn spawn_local<F: Future<Output = ()> + 'static>(future: F) {
+    tokio::task::spawn_local(future);
+}
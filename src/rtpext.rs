@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use str0m::rtp::ExtensionValues;
+
+/// Received rtp packets carrying each header extension, cumulative over the session.
+/// A counter staying at 0 while `packets` grows means the extension was not negotiated
+/// or the server does not write it
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct HeaderExtensionStats {
+    /// Rtp packets checked, retransmissions and fec included
+    pub packets: u64,
+    pub abs_send_time: u64,
+    pub transport_cc: u64,
+    pub mid: u64,
+    pub rid: u64,
+    pub rid_repair: u64,
+    pub audio_level: u64,
+    #[serde(default)]
+    pub playout_delay: u64,
+}
+
+impl HeaderExtensionStats {
+    pub fn on_packet(&mut self, ext: &ExtensionValues) {
+        self.packets += 1;
+        self.abs_send_time += ext.abs_send_time.is_some() as u64;
+        self.transport_cc += ext.transport_cc.is_some() as u64;
+        self.mid += ext.mid.is_some() as u64;
+        self.rid += ext.rid.is_some() as u64;
+        self.rid_repair += ext.rid_repair.is_some() as u64;
+        self.audio_level += ext.audio_level.is_some() as u64;
+        self.playout_delay += ext.play_delay_min.is_some() as u64;
+    }
+
+    /// Counter by extension name
+    pub fn counts(&self) -> [(&'static str, u64); 7] {
+        [
+            ("abs-send-time", self.abs_send_time),
+            ("transport-cc", self.transport_cc),
+            ("mid", self.mid),
+            ("rid", self.rid),
+            ("repaired-rid", self.rid_repair),
+            ("audio-level", self.audio_level),
+            ("playout-delay", self.playout_delay),
+        ]
+    }
+
+    pub fn add(&mut self, other: &HeaderExtensionStats) {
+        self.packets += other.packets;
+        self.abs_send_time += other.abs_send_time;
+        self.transport_cc += other.transport_cc;
+        self.mid += other.mid;
+        self.rid += other.rid;
+        self.rid_repair += other.rid_repair;
+        self.audio_level += other.audio_level;
+        self.playout_delay += other.playout_delay;
+    }
+}
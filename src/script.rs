@@ -0,0 +1,128 @@
+//! User scripts customizing the clients without forking the crate, written in
+//! [rhai](https://rhai.rs). Every hook is optional:
+//!
+//! ```text
+//! // sdp sent as offer, returning nothing keeps it
+//! fn on_offer(client, sdp) { sdp.replace("useinbandfec=1", "useinbandfec=0"); sdp }
+//! // extra headers of the signaling requests
+//! fn headers(client) { #{ "x-tenant": `tenant-${client % 4}` } }
+//! fn on_connected(client) { print(`client ${client} connected`) }
+//! // true disconnects the client, `stats` has the fields of the stats events
+//! fn on_stats(client, stats) { stats.lost > 0.2 && stats.live_ms > 30000 }
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rhai::{CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope, AST};
+
+use crate::whep::Stats;
+
+/// Operations a single hook call may run, stops endless loops stalling a client
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Compiled script shared by all clients of a run
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    /// Failures are logged at warn once, hooks run for every client
+    failed: AtomicBool,
+}
+
+impl Script {
+    pub fn compile(source: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|s| log::info!("[Script] {}", s));
+        engine.on_debug(|s, _, pos| log::debug!("[Script] {} {}", pos, s));
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        Ok(Self {
+            engine,
+            ast,
+            failed: AtomicBool::new(false),
+        })
+    }
+
+    fn has(&self, hook: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == hook)
+    }
+
+    fn warn(&self, message: String) {
+        if self.failed.swap(true, Ordering::Relaxed) {
+            log::debug!("[Script] {}", message);
+        } else {
+            log::warn!("[Script] {}, further failures are logged at debug", message);
+        }
+    }
+
+    /// None when the script does not define the hook or it failed
+    fn call(&self, hook: &str, args: impl FuncArgs) -> Option<Dynamic> {
+        if !self.has(hook) {
+            return None;
+        }
+        // hooks are plain functions, top level statements of the script never run
+        let options = CallFnOptions::new().eval_ast(false);
+        match self
+            .engine
+            .call_fn_with_options(options, &mut Scope::new(), &self.ast, hook, args)
+        {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.warn(format!("{} failed {}", hook, e));
+                None
+            }
+        }
+    }
+
+    /// Replacement of the offer sdp, None keeps it
+    pub fn on_offer(&self, client_id: usize, sdp: &str) -> Option<String> {
+        let value = self.call("on_offer", (client_id as i64, sdp.to_string()))?;
+        if value.is_unit() {
+            return None;
+        }
+        match value.into_string() {
+            Ok(sdp) => Some(sdp),
+            Err(kind) => {
+                self.warn(format!("on_offer returned {} instead of a string", kind));
+                None
+            }
+        }
+    }
+
+    /// Extra headers of the signaling requests
+    pub fn headers(&self, client_id: usize) -> Vec<(String, String)> {
+        let Some(value) = self.call("headers", (client_id as i64,)) else {
+            return vec![];
+        };
+        let kind = value.type_name();
+        match value.try_cast::<Map>() {
+            Some(map) => map
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            None => {
+                self.warn(format!("headers returned {} instead of a map", kind));
+                vec![]
+            }
+        }
+    }
+
+    pub fn on_connected(&self, client_id: usize) {
+        self.call("on_connected", (client_id as i64,));
+    }
+
+    /// Whether the client should disconnect
+    pub fn on_stats(&self, client_id: usize, stats: &Stats) -> bool {
+        if !self.has("on_stats") {
+            return false;
+        }
+        let stats = match rhai::serde::to_dynamic(stats) {
+            Ok(stats) => stats,
+            Err(e) => {
+                self.warn(format!("cannot pass stats {}", e));
+                return false;
+            }
+        };
+        self.call("on_stats", (client_id as i64, stats))
+            .map_or(false, |value| value.as_bool().unwrap_or(false))
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    media::StreamKind,
+    whepext::{self, WhepExtension},
+};
+
+/// Interesting parts of an answer sdp
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnswerSummary {
+    /// `pt name/clock[/channels]` from `a=rtpmap`
+    pub codecs: Vec<String>,
+    /// `id uri` from `a=extmap`
+    pub extensions: Vec<String>,
+    /// `<kind> <direction>` per m-line, in order
+    #[serde(default)]
+    pub directions: Vec<String>,
+    /// Server runs ICE-lite and never sends connectivity checks itself
+    pub ice_lite: bool,
+    /// `host`, `srflx`, `prflx` or `relay`, each once
+    pub candidate_types: Vec<String>,
+    pub candidates: Vec<String>,
+    /// Advertised by the `Link` headers of the answer, see [`crate::whepext`]
+    #[serde(default)]
+    pub whep_extensions: Vec<WhepExtension>,
+}
+
+impl AnswerSummary {
+    pub fn parse(sdp: &str) -> Self {
+        let mut summary = Self::default();
+        for line in sdp.lines().map(|l| l.trim()) {
+            if let Some(media) = line.strip_prefix("m=") {
+                // sendrecv unless the section says otherwise
+                let kind = media.split_whitespace().next().unwrap_or_default();
+                summary.directions.push(format!("{} sendrecv", kind));
+            } else if let Some(direction @ ("sendrecv" | "sendonly" | "recvonly" | "inactive")) =
+                line.strip_prefix("a=")
+            {
+                if let Some(last) = summary.directions.last_mut() {
+                    let kind = last.split_whitespace().next().unwrap_or_default();
+                    *last = format!("{} {}", kind, direction);
+                }
+            } else if let Some(value) = line.strip_prefix("a=rtpmap:") {
+                push_unique(&mut summary.codecs, value);
+            } else if let Some(value) = line.strip_prefix("a=extmap:") {
+                push_unique(&mut summary.extensions, value);
+            } else if let Some(value) = line.strip_prefix("a=candidate:") {
+                push_unique(&mut summary.candidates, value);
+                // foundation component transport priority address port typ <type>
+                let mut fields = value.split_whitespace().skip_while(|f| *f != "typ");
+                if let Some(kind) = fields.nth(1) {
+                    push_unique(&mut summary.candidate_types, kind);
+                }
+            } else if line == "a=ice-lite" {
+                summary.ice_lite = true;
+            }
+        }
+        summary
+    }
+
+    /// What the server negotiated without the per session candidates, clients
+    /// of a consistent server all share one profile
+    pub fn profile(&self) -> String {
+        let mut types = self.candidate_types.clone();
+        types.sort();
+        format!(
+            "{}, codecs [{}], extensions [{}], directions [{}], candidates [{}]",
+            if self.ice_lite {
+                "ice-lite"
+            } else {
+                "full ice"
+            },
+            self.codecs.join(", "),
+            self.extensions.join(", "),
+            self.directions.join(", "),
+            types.join(", ")
+        )
+    }
+}
+
+impl fmt::Display for AnswerSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "ice: {}", if self.ice_lite { "lite" } else { "full" })?;
+        for codec in &self.codecs {
+            writeln!(f, "codec: {}", codec)?;
+        }
+        for ext in &self.extensions {
+            writeln!(f, "extension: {}", ext)?;
+        }
+        for direction in &self.directions {
+            writeln!(f, "direction: {}", direction)?;
+        }
+        for candidate in &self.candidates {
+            writeln!(f, "candidate: {}", candidate)?;
+        }
+        writeln!(
+            f,
+            "whep extensions: {}",
+            whepext::describe(&self.whep_extensions)
+        )?;
+        Ok(())
+    }
+}
+
+/// Media kinds the answer accepted, a kind whose m-lines all have port 0 or
+/// are inactive or recvonly was rejected
+pub fn accepted_media(sdp: &str) -> Vec<StreamKind> {
+    let mut accepted = vec![];
+    let mut section: Option<(StreamKind, bool)> = None;
+    let close = |section: Option<(StreamKind, bool)>, accepted: &mut Vec<StreamKind>| {
+        if let Some((kind, true)) = section {
+            if !accepted.contains(&kind) {
+                accepted.push(kind);
+            }
+        }
+    };
+    for line in sdp.lines().map(|l| l.trim()) {
+        if let Some(media) = line.strip_prefix("m=") {
+            close(section.take(), &mut accepted);
+            let mut fields = media.split_whitespace();
+            let kind = fields.next().and_then(|kind| kind.parse().ok());
+            let open = fields.next().map_or(false, |port| port != "0");
+            section = kind.map(|kind| (kind, open));
+        } else if line == "a=inactive" || line == "a=recvonly" {
+            if let Some((_, open)) = section.as_mut() {
+                *open = false;
+            }
+        }
+    }
+    close(section, &mut accepted);
+    accepted
+}
+
+/// Fix the usual defects of answers strict parsing rejects: line endings other
+/// than CRLF, blank lines and trailing whitespace, and media sections missing
+/// `a=setup` or `a=rtcp-mux`. Returns the repaired sdp and the fixes applied
+pub fn repair_answer(sdp: &str) -> (String, Vec<&'static str>) {
+    let mut fixes = vec![];
+    let crlf = sdp.matches("\r\n").count();
+    if sdp.matches('\r').count() != crlf || sdp.matches('\n').count() != crlf {
+        fixes.push("line endings");
+    }
+    let normalized = sdp.replace("\r\n", "\n");
+    let mut lines = vec![];
+    let mut blank = 0;
+    for line in normalized.trim_end().split(['\n', '\r']) {
+        let trimmed = line.trim_end();
+        if trimmed.len() != line.len() && !fixes.contains(&"trailing whitespace") {
+            fixes.push("trailing whitespace");
+        }
+        match trimmed.is_empty() {
+            true => blank += 1,
+            false => lines.push(trimmed),
+        }
+    }
+    if blank > 0 {
+        fixes.push("blank lines");
+    }
+
+    let session_setup = lines
+        .iter()
+        .take_while(|l| !l.starts_with("m="))
+        .any(|l| l.starts_with("a=setup:"));
+    let mut repaired = String::with_capacity(sdp.len() + 64);
+    let mut section: Option<(bool, bool)> = None;
+    let close = |section: Option<(bool, bool)>, out: &mut String, fixes: &mut Vec<_>| {
+        let Some((setup, rtcp_mux)) = section else {
+            return;
+        };
+        if !setup && !session_setup {
+            // rfc 4145 default of a missing attribute
+            out.push_str("a=setup:active\r\n");
+            if !fixes.contains(&"missing a=setup") {
+                fixes.push("missing a=setup");
+            }
+        }
+        if !rtcp_mux {
+            out.push_str("a=rtcp-mux\r\n");
+            if !fixes.contains(&"missing a=rtcp-mux") {
+                fixes.push("missing a=rtcp-mux");
+            }
+        }
+    };
+    for line in lines {
+        if line.starts_with("m=") {
+            close(section.take(), &mut repaired, &mut fixes);
+            section = Some((false, false));
+        } else if let Some((setup, rtcp_mux)) = section.as_mut() {
+            *setup |= line.starts_with("a=setup:");
+            *rtcp_mux |= line == "a=rtcp-mux";
+        }
+        repaired.push_str(line);
+        repaired.push_str("\r\n");
+    }
+    close(section, &mut repaired, &mut fixes);
+    (repaired, fixes)
+}
+
+fn push_unique(list: &mut Vec<String>, value: &str) {
+    if !list.iter().any(|v| v == value) {
+        list.push(value.to_string());
+    }
+}
@@ -0,0 +1,123 @@
+//! Resource usage of the benchmark process itself, so a bad result caused by a
+//! saturated load generator can be told apart from a saturated server. Read from
+//! /proc, other platforms report nothing
+
+use std::{fs, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{sketch::QuantileSketch, summary::Percentiles};
+
+/// USER_HZ, fixed at 100 on every mainstream Linux architecture
+const CLOCK_TICKS: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Sample {
+    cpu_ticks: u64,
+    rss_kb: u64,
+    fds: u64,
+    udp_in_errors: u64,
+    udp_rcvbuf_errors: u64,
+}
+
+/// utime + stime, fields 14 and 15 of /proc/self/stat. The command name may
+/// contain spaces, so fields are counted from its closing parenthesis
+fn cpu_ticks() -> Option<u64> {
+    let stat = fs::read_to_string("/proc/self/stat").ok()?;
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// A kB field of /proc/self/status, e.g. `VmRSS:`
+pub fn status_kb(field: &str) -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with(field))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn fds() -> Option<u64> {
+    Some(fs::read_dir("/proc/self/fd").ok()?.count() as u64)
+}
+
+/// InErrors and RcvbufErrors of the Udp table in /proc/net/snmp, a header line
+/// followed by a value line
+fn udp_errors() -> Option<(u64, u64)> {
+    let snmp = fs::read_to_string("/proc/net/snmp").ok()?;
+    let mut udp = snmp.lines().filter(|l| l.starts_with("Udp:"));
+    let names: Vec<&str> = udp.next()?.split_whitespace().collect();
+    let values: Vec<&str> = udp.next()?.split_whitespace().collect();
+    let get = |name: &str| -> Option<u64> {
+        let index = names.iter().position(|n| *n == name)?;
+        values.get(index)?.parse().ok()
+    };
+    Some((get("InErrors")?, get("RcvbufErrors")?))
+}
+
+fn sample() -> Option<Sample> {
+    let (udp_in_errors, udp_rcvbuf_errors) = udp_errors().unwrap_or_default();
+    Some(Sample {
+        cpu_ticks: cpu_ticks()?,
+        rss_kb: status_kb("VmRSS:").unwrap_or(0),
+        fds: fds().unwrap_or(0),
+        udp_in_errors,
+        udp_rcvbuf_errors,
+    })
+}
+
+/// Load generator usage over the run
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ResourceSummary {
+    /// Cpu usage per sample interval, 100 is one full core
+    pub cpu_pct: Percentiles,
+    pub rss_mb_max: f64,
+    pub fds_max: u64,
+    /// Host wide udp receive errors during the run, buffer overflows among them
+    pub udp_in_errors: u64,
+    pub udp_rcvbuf_errors: u64,
+}
+
+/// Periodic samples of this process, call `sample` about once per second
+#[derive(Debug, Default)]
+pub struct ResourceMonitor {
+    first: Option<Sample>,
+    last: Option<(Instant, Sample)>,
+    cpu_pct: QuantileSketch,
+    rss_kb_max: u64,
+    fds_max: u64,
+}
+
+impl ResourceMonitor {
+    pub fn sample(&mut self, now: Instant) {
+        let Some(current) = sample() else {
+            return;
+        };
+        if let Some((at, last)) = self.last {
+            let elapsed = (now - at).as_secs_f64();
+            if elapsed > 0.0 {
+                let ticks = current.cpu_ticks.saturating_sub(last.cpu_ticks) as f64;
+                self.cpu_pct.record(ticks / CLOCK_TICKS / elapsed * 100.0);
+            }
+        }
+        self.first.get_or_insert(current);
+        self.last = Some((now, current));
+        self.rss_kb_max = self.rss_kb_max.max(current.rss_kb);
+        self.fds_max = self.fds_max.max(current.fds);
+    }
+
+    /// None when nothing could be sampled
+    pub fn summary(&self) -> Option<ResourceSummary> {
+        let first = self.first?;
+        let (_, last) = self.last?;
+        Some(ResourceSummary {
+            cpu_pct: self.cpu_pct.percentiles(),
+            rss_mb_max: self.rss_kb_max as f64 / 1024.0,
+            fds_max: self.fds_max,
+            udp_in_errors: last.udp_in_errors.saturating_sub(first.udp_in_errors),
+            udp_rcvbuf_errors: last
+                .udp_rcvbuf_errors
+                .saturating_sub(first.udp_rcvbuf_errors),
+        })
+    }
+}
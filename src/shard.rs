@@ -0,0 +1,133 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_std::channel::{Receiver, Sender};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{
+    bench::{emit, BenchEvent, ClientTask},
+    rt,
+};
+
+/// Aggregates of the clients running on one worker thread
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ShardSummary {
+    pub clients: usize,
+    pub connected: usize,
+    pub failed: usize,
+    pub recv_kbps: u64,
+}
+
+#[derive(Default)]
+struct ShardState {
+    clients: usize,
+    failed: usize,
+    /// Latest recv bitrate of connected clients
+    connected: HashMap<usize, u64>,
+}
+
+impl ShardState {
+    fn on_event(&mut self, event: &BenchEvent) {
+        match event {
+            BenchEvent::Connected(id) => {
+                self.connected.insert(*id, 0);
+            }
+            BenchEvent::Stats(id, stats) => {
+                if let Some(recv) = self.connected.get_mut(id) {
+                    *recv = stats.recv_kbps;
+                }
+            }
+            BenchEvent::Failed(..) => self.failed += 1,
+            BenchEvent::Reconnecting(id, _) | BenchEvent::Faulted(id) => {
+                self.connected.remove(id);
+            }
+            BenchEvent::Disconnected(id) => {
+                self.clients = self.clients.saturating_sub(1);
+                self.connected.remove(id);
+            }
+            BenchEvent::Connecting(_)
+            | BenchEvent::Queued(..)
+            | BenchEvent::StreamChanged(..)
+            | BenchEvent::LayerSwitched(..)
+            | BenchEvent::Throttled(_)
+            | BenchEvent::Recovered(..)
+            | BenchEvent::Starved(..)
+            | BenchEvent::Http(..)
+            | BenchEvent::Answer(..)
+            | BenchEvent::Labeled(..)
+            | BenchEvent::Teardown(..)
+            | BenchEvent::KeepaliveFailed(..)
+            | BenchEvent::KeyframeReceived(..) => {}
+        }
+    }
+
+    fn summary(&self) -> ShardSummary {
+        ShardSummary {
+            clients: self.clients,
+            connected: self.connected.len(),
+            failed: self.failed,
+            recv_kbps: self.connected.values().sum(),
+        }
+    }
+}
+
+/// Dedicated thread driving a subset of the clients on its own executor
+pub(crate) struct Shard {
+    task_tx: Sender<ClientTask>,
+    event_tx: Sender<BenchEvent>,
+    state: Arc<Mutex<ShardState>>,
+}
+
+impl Shard {
+    /// Start the worker thread, events of its clients are aggregated then forwarded to `event_tx`
+    pub(crate) fn start(index: usize, event_tx: Sender<BenchEvent>) -> Self {
+        let (task_tx, task_rx) = async_std::channel::unbounded::<ClientTask>();
+        let (shard_event_tx, shard_event_rx) = async_std::channel::unbounded::<BenchEvent>();
+        let state = Arc::new(Mutex::new(ShardState::default()));
+        let thread_state = state.clone();
+        std::thread::Builder::new()
+            .name(format!("bench-shard-{}", index))
+            .spawn(move || {
+                rt::block_on_local(run_shard(task_rx, shard_event_rx, event_tx, thread_state))
+            })
+            .expect("should spawn shard thread");
+        Self {
+            task_tx,
+            event_tx: shard_event_tx,
+            state,
+        }
+    }
+
+    pub(crate) fn spawn(&self, mut task: ClientTask) {
+        task.event_tx = self.event_tx.clone();
+        self.state.lock().clients += 1;
+        if let Err(e) = self.task_tx.try_send(task) {
+            log::error!(
+                "[Shard] worker thread is gone, drop client {}",
+                e.into_inner().client_id
+            );
+        }
+    }
+
+    pub(crate) fn summary(&self) -> ShardSummary {
+        self.state.lock().summary()
+    }
+}
+
+async fn run_shard(
+    task_rx: Receiver<ClientTask>,
+    event_rx: Receiver<BenchEvent>,
+    event_tx: Sender<BenchEvent>,
+    state: Arc<Mutex<ShardState>>,
+) {
+    // tasks spawned locally are polled by this thread only
+    rt::spawn_local(async move {
+        while let Ok(event) = event_rx.recv().await {
+            state.lock().on_event(&event);
+            emit(&event_tx, event).await;
+        }
+    });
+    while let Ok(task) = task_rx.recv().await {
+        rt::spawn_local(task.supervise());
+    }
+}
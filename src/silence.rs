@@ -0,0 +1,75 @@
+//! Audio silence of a client, told apart by cause. A pause in the audio packets
+//! without missing sequence numbers is the sender in DTX, it chose to not send.
+//! A pause with missing sequence numbers is audio the viewer should have heard
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Pauses between audio packets shorter than this are jitter, not silence
+const MIN_PAUSE: Duration = Duration::from_millis(100);
+/// Opus packets up to this size carry no speech, a sender entering DTX sends one
+const DTX_PAYLOAD: usize = 3;
+
+/// Cumulative over the session
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct SilenceStats {
+    /// Time the sender was in DTX
+    pub dtx_ms: u64,
+    /// Time without audio which was sent
+    pub gap_ms: u64,
+    pub gaps: u64,
+}
+
+impl SilenceStats {
+    pub fn silence_secs(&self) -> f64 {
+        (self.dtx_ms + self.gap_ms) as f64 / 1000.0
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SilenceTracker {
+    stats: SilenceStats,
+    /// Arrival, extended sequence number and payload size of the latest packet
+    last: Option<(Instant, u64, usize)>,
+}
+
+impl SilenceTracker {
+    pub fn on_audio(&mut self, arrival: Instant, seq: u64, payload_len: usize) {
+        if let Some((last_at, last_seq, _)) = self.last {
+            if seq <= last_seq {
+                return;
+            }
+            let pause = arrival.saturating_duration_since(last_at);
+            if pause >= MIN_PAUSE {
+                let ms = pause.as_millis() as u64;
+                if seq == last_seq + 1 {
+                    self.stats.dtx_ms += ms;
+                } else {
+                    self.stats.gap_ms += ms;
+                    self.stats.gaps += 1;
+                }
+            }
+        }
+        self.last = Some((arrival, seq, payload_len));
+    }
+
+    /// Counters including a pause still going on at `now`, which counts as DTX
+    /// when the latest packet was a DTX one
+    pub fn stats(&self, now: Instant) -> SilenceStats {
+        let mut stats = self.stats;
+        if let Some((last_at, _, last_len)) = self.last {
+            let pause = now.saturating_duration_since(last_at);
+            if pause >= MIN_PAUSE {
+                let ms = pause.as_millis() as u64;
+                if last_len <= DTX_PAYLOAD {
+                    stats.dtx_ms += ms;
+                } else {
+                    stats.gap_ms += ms;
+                    stats.gaps += 1;
+                }
+            }
+        }
+        stats
+    }
+}
@@ -0,0 +1,93 @@
+//! Simulcast layers told apart by the RID header extension. A server forwards one
+//! layer of a mid at a time, a packet with another RID on the mid is a layer switch
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Received bitrate of one layer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerStats {
+    pub mid: String,
+    pub rid: String,
+    pub packets: u64,
+    /// Payload bitrate since previous stats
+    pub recv_kbps: u64,
+    /// Layer currently forwarded on the mid
+    pub active: bool,
+}
+
+/// The server forwards another layer of a mid
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerSwitch {
+    pub mid: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Default)]
+struct LayerCounter {
+    packets: u64,
+    bytes: u64,
+    pre_bytes: u64,
+}
+
+/// Counters by mid and RID, with the latest RID of each mid
+#[derive(Debug, Default)]
+pub struct LayerTracker {
+    layers: BTreeMap<String, BTreeMap<String, LayerCounter>>,
+    current: BTreeMap<String, String>,
+}
+
+impl LayerTracker {
+    pub fn on_rtp(&mut self, mid: &str, rid: &str, payload_len: usize) -> Option<LayerSwitch> {
+        if !self.layers.contains_key(mid) {
+            self.layers.insert(mid.to_string(), BTreeMap::new());
+        }
+        let layers = self.layers.get_mut(mid).expect("should have mid");
+        if !layers.contains_key(rid) {
+            layers.insert(rid.to_string(), LayerCounter::default());
+        }
+        let counter = layers.get_mut(rid).expect("should have rid");
+        counter.packets += 1;
+        counter.bytes += payload_len as u64;
+
+        match self.current.get_mut(mid) {
+            Some(current) if current == rid => None,
+            Some(current) => {
+                let from = std::mem::replace(current, rid.to_string());
+                Some(LayerSwitch {
+                    mid: mid.to_string(),
+                    from,
+                    to: rid.to_string(),
+                })
+            }
+            None => {
+                self.current.insert(mid.to_string(), rid.to_string());
+                None
+            }
+        }
+    }
+
+    pub fn stats(&mut self, duration_ms: u64) -> Vec<LayerStats> {
+        let mut stats = vec![];
+        for (mid, layers) in self.layers.iter_mut() {
+            for (rid, counter) in layers.iter_mut() {
+                let recv_kbps = if duration_ms > 0 {
+                    ((counter.bytes - counter.pre_bytes) * 8) / duration_ms
+                } else {
+                    0
+                };
+                counter.pre_bytes = counter.bytes;
+                stats.push(LayerStats {
+                    mid: mid.clone(),
+                    rid: rid.clone(),
+                    packets: counter.packets,
+                    recv_kbps,
+                    active: self.current.get(mid) == Some(rid),
+                });
+            }
+        }
+        stats
+    }
+}
@@ -0,0 +1,97 @@
+//! Quantile sketch with bounded memory, values fall into logarithmic buckets so
+//! every estimated percentile is within `RELATIVE_ACCURACY` of the exact one,
+//! no matter how many samples a long soak produces (DDSketch)
+
+use std::collections::BTreeMap;
+
+use crate::summary::Percentiles;
+
+const RELATIVE_ACCURACY: f64 = 0.01;
+
+#[derive(Debug, Clone)]
+pub struct QuantileSketch {
+    /// ln of the bucket growth factor
+    ln_gamma: f64,
+    /// Values at or below zero, which have no logarithm
+    zeros: u64,
+    buckets: BTreeMap<i32, u64>,
+    count: u64,
+    sum: f64,
+    max: f64,
+}
+
+impl Default for QuantileSketch {
+    fn default() -> Self {
+        let gamma = (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY);
+        Self {
+            ln_gamma: gamma.ln(),
+            zeros: 0,
+            buckets: BTreeMap::new(),
+            count: 0,
+            sum: 0.0,
+            max: 0.0,
+        }
+    }
+}
+
+impl QuantileSketch {
+    pub fn record(&mut self, value: f64) {
+        if self.count == 0 || value > self.max {
+            self.max = value;
+        }
+        self.count += 1;
+        self.sum += value;
+        if value <= 0.0 {
+            self.zeros += 1;
+            return;
+        }
+        let index = (value.ln() / self.ln_gamma).ceil() as i32;
+        *self.buckets.entry(index).or_default() += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Exact mean of the recorded values
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum / self.count as f64
+    }
+
+    /// Midpoint of the bucket holding the value at rank `q`, same ranking as
+    /// [`Percentiles::from_values`]
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let rank = ((self.count - 1) as f64 * q).round() as u64;
+        if rank < self.zeros {
+            return 0.0;
+        }
+        let mut seen = self.zeros;
+        for (index, count) in &self.buckets {
+            seen += count;
+            if seen > rank {
+                let gamma = self.ln_gamma.exp();
+                let value = 2.0 * gamma.powi(*index) / (gamma + 1.0);
+                return value.min(self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn percentiles(&self) -> Percentiles {
+        if self.count == 0 {
+            return Percentiles::default();
+        }
+        Percentiles {
+            p50: self.quantile(0.5),
+            p90: self.quantile(0.9),
+            p99: self.quantile(0.99),
+            max: self.max,
+        }
+    }
+}
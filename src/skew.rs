@@ -0,0 +1,138 @@
+//! Clock skew of a sender, from the RTCP sender reports of a stream against their
+//! local arrival. The NTP clock of the reports tells the drift of the server
+//! clock, the RTP clock the rate media is produced at. A media clock running fast
+//! or slow fills or drains the jitter buffer of a real viewer
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Reports have to span this long before an estimate is given
+const MIN_SPAN: Duration = Duration::from_secs(30);
+/// Media clocks off by more than this are flagged
+pub const FLAG_PPM: f64 = 1000.0;
+
+/// Least squares slope of `y` over `x`, kept as running sums
+#[derive(Debug, Default, Clone, Copy)]
+struct Slope {
+    n: f64,
+    sum_x: f64,
+    sum_y: f64,
+    sum_xx: f64,
+    sum_xy: f64,
+}
+
+impl Slope {
+    fn add(&mut self, x: f64, y: f64) {
+        self.n += 1.0;
+        self.sum_x += x;
+        self.sum_y += y;
+        self.sum_xx += x * x;
+        self.sum_xy += x * y;
+    }
+
+    fn slope(&self) -> Option<f64> {
+        let denom = self.n * self.sum_xx - self.sum_x * self.sum_x;
+        if self.n < 3.0 || denom.abs() < f64::EPSILON {
+            return None;
+        }
+        Some((self.n * self.sum_xy - self.sum_x * self.sum_y) / denom)
+    }
+}
+
+/// Rate error of the sender clocks against the local clock, positive when fast
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClockSkew {
+    /// NTP clock of the sender reports
+    pub ntp_ppm: f32,
+    /// RTP clock of the media
+    pub media_ppm: f32,
+}
+
+impl ClockSkew {
+    pub fn flagged(&self) -> bool {
+        (self.media_ppm as f64).abs() > FLAG_PPM
+    }
+}
+
+#[derive(Debug)]
+struct Report {
+    arrival: Instant,
+    ntp_time: Instant,
+    rtp_ts: u32,
+}
+
+/// Offsets of the sender clocks to the local clock over the reports of a stream
+#[derive(Debug)]
+pub struct SkewEstimator {
+    clock_rate: f64,
+    first: Option<Report>,
+    /// RTP timestamp of the latest report and the ticks since the first one
+    last_rtp: Option<(u32, i64)>,
+    last_ntp: Option<Instant>,
+    span: Duration,
+    ntp: Slope,
+    media: Slope,
+}
+
+/// Signed `a - b` in seconds
+fn diff_secs(a: Instant, b: Instant) -> f64 {
+    match a.checked_duration_since(b) {
+        Some(d) => d.as_secs_f64(),
+        None => -(b - a).as_secs_f64(),
+    }
+}
+
+impl SkewEstimator {
+    pub fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate: clock_rate as f64,
+            first: None,
+            last_rtp: None,
+            last_ntp: None,
+            span: Duration::ZERO,
+            ntp: Slope::default(),
+            media: Slope::default(),
+        }
+    }
+
+    /// Packets repeat the latest report, only a new one is a sample. `arrival` is
+    /// the first packet carrying it
+    pub fn on_sender_report(&mut self, arrival: Instant, ntp_time: Instant, rtp_ts: u32) {
+        if self.last_ntp == Some(ntp_time) {
+            return;
+        }
+        self.last_ntp = Some(ntp_time);
+        let Some(first) = &self.first else {
+            self.first = Some(Report {
+                arrival,
+                ntp_time,
+                rtp_ts,
+            });
+            self.last_rtp = Some((rtp_ts, 0));
+            self.ntp.add(0.0, 0.0);
+            self.media.add(0.0, 0.0);
+            return;
+        };
+        let (last, ticks) = self.last_rtp.unwrap_or((first.rtp_ts, 0));
+        let ticks = ticks + rtp_ts.wrapping_sub(last) as i32 as i64;
+        self.last_rtp = Some((rtp_ts, ticks));
+        let local = diff_secs(arrival, first.arrival);
+        self.ntp
+            .add(local, diff_secs(ntp_time, first.ntp_time) - local);
+        self.media
+            .add(local, ticks as f64 / self.clock_rate - local);
+        self.span = arrival.saturating_duration_since(first.arrival);
+    }
+
+    /// None until the reports span long enough
+    pub fn skew(&self) -> Option<ClockSkew> {
+        if self.span < MIN_SPAN {
+            return None;
+        }
+        Some(ClockSkew {
+            ntp_ppm: (self.ntp.slope()? * 1e6) as f32,
+            media_ppm: (self.media.slope()? * 1e6) as f32,
+        })
+    }
+}
@@ -0,0 +1,151 @@
+//! Soak runs over days. Every rollup interval the summary of the window is written
+//! to the soak directory and the summary collector starts over with the clients
+//! still connected, so memory stays bounded and a crash loses at most one window
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::summary::{RunSummary, SummaryCollector};
+
+#[derive(Debug, Clone)]
+pub struct SoakConfig {
+    pub dir: PathBuf,
+    pub rollup: Duration,
+}
+
+/// Headline numbers of one window, the full summary is in its rollup file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rollup {
+    pub index: usize,
+    /// Start of the window since the start of the run
+    pub started_ms: u64,
+    pub duration_ms: u64,
+    pub clients: usize,
+    pub connected: usize,
+    pub failed: usize,
+    pub joins: usize,
+    pub recv_kbps_p50: f64,
+    pub rtt_ms_p90: f64,
+    pub loss_p90: f64,
+}
+
+impl Rollup {
+    fn of(index: usize, started_ms: u64, summary: &RunSummary) -> Self {
+        Self {
+            index,
+            started_ms,
+            duration_ms: summary.duration_ms,
+            clients: summary.clients,
+            connected: summary.connected,
+            failed: summary.failed,
+            joins: summary.joins,
+            recv_kbps_p50: summary.recv_kbps.p50,
+            rtt_ms_p90: summary.rtt_ms.p90,
+            loss_p90: summary.loss.p90,
+        }
+    }
+}
+
+/// Rollups of a soak run, printable with `{}`
+pub struct SoakTable<'a>(pub &'a [Rollup]);
+
+impl fmt::Display for SoakTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>6}{:>9}{:>9}{:>11}{:>8}{:>7}{:>12}{:>9}{:>9}",
+            "window",
+            "start h",
+            "clients",
+            "connected",
+            "failed",
+            "joins",
+            "recv kbps",
+            "rtt p90",
+            "loss p90"
+        )?;
+        for r in self.0 {
+            writeln!(
+                f,
+                "{:>6}{:>9.1}{:>9}{:>11}{:>8}{:>7}{:>12.0}{:>9.0}{:>9.3}",
+                r.index,
+                r.started_ms as f64 / 3_600_000.0,
+                r.clients,
+                r.connected,
+                r.failed,
+                r.joins,
+                r.recv_kbps_p50,
+                r.rtt_ms_p90,
+                r.loss_p90
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Write through a temporary file, a crash mid-write leaves the previous version
+fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Write the summary of a window as `rollup-NNNN.json` and the rollups so far as
+/// `checkpoint.json`
+fn checkpoint(dir: &Path, summary: &RunSummary, rollups: &[Rollup]) -> io::Result<()> {
+    let index = rollups.last().map_or(0, |r| r.index);
+    let json = serde_json::to_string_pretty(summary).expect("should serialize summary");
+    write_atomic(&dir.join(format!("rollup-{:04}.json", index)), &json)?;
+    let json = serde_json::to_string_pretty(rollups).expect("should serialize rollups");
+    write_atomic(&dir.join("checkpoint.json"), &json)
+}
+
+/// Close the current window of `summary`, returns its summary
+pub fn roll(
+    config: &SoakConfig,
+    summary: &Mutex<SummaryCollector>,
+    rollups: &Mutex<Vec<Rollup>>,
+) -> RunSummary {
+    let window = summary.lock().rollup();
+    let mut rollups = rollups.lock();
+    let started_ms = rollups.last().map_or(0, |r| r.started_ms + r.duration_ms);
+    let index = rollups.len();
+    rollups.push(Rollup::of(index, started_ms, &window));
+    if let Err(e) = checkpoint(&config.dir, &window, &rollups) {
+        log::error!(
+            "[Soak] cannot write checkpoint to {} {:?}",
+            config.dir.display(),
+            e
+        );
+    }
+    window
+}
+
+/// Roll the summary every interval until the task is dropped
+pub async fn run_soak(
+    config: SoakConfig,
+    summary: Arc<Mutex<SummaryCollector>>,
+    rollups: Arc<Mutex<Vec<Rollup>>>,
+) {
+    if let Err(e) = std::fs::create_dir_all(&config.dir) {
+        log::error!("[Soak] cannot create {} {:?}", config.dir.display(), e);
+    }
+    loop {
+        async_std::task::sleep(config.rollup).await;
+        let window = roll(&config, &summary, &rollups);
+        log::info!(
+            "[Soak] window {} closed, {} clients, {} connected, {} failed",
+            rollups.lock().len() - 1,
+            window.clients,
+            window.connected,
+            window.failed
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+use async_std::{channel::Receiver, future::timeout, net::UdpSocket};
+
+use crate::{bench::BenchEvent, collector::Collector, media::StreamKind};
+
+/// StatsD exporter settings, tags use the DogStatsD `|#key:value` extension
+#[derive(Debug, Clone)]
+pub struct StatsdConfig {
+    pub addr: String,
+    pub prefix: String,
+    pub interval: Duration,
+    pub tags: Vec<(String, String)>,
+}
+
+struct Exporter {
+    socket: UdpSocket,
+    config: StatsdConfig,
+    tags: String,
+}
+
+impl Exporter {
+    async fn send(&self, name: &str, value: impl std::fmt::Display, kind: &str, extra_tag: &str) {
+        let mut line = format!("{}.{}:{}|{}", self.config.prefix, name, value, kind);
+        if !self.tags.is_empty() || !extra_tag.is_empty() {
+            line.push_str("|#");
+            line.push_str(&self.tags);
+            if !self.tags.is_empty() && !extra_tag.is_empty() {
+                line.push(',');
+            }
+            line.push_str(extra_tag);
+        }
+        if let Err(e) = self.socket.send(line.as_bytes()).await {
+            log::debug!("[Statsd] send error {:?}", e);
+        }
+    }
+
+    async fn send_gauges(&self, collector: &Collector) {
+        self.send("clients", collector.clients_sum(), "g", "").await;
+        self.send("connected", collector.clients_connected(), "g", "")
+            .await;
+        self.send("failed", collector.clients_failed(), "g", "")
+            .await;
+        self.send("send_kbps", collector.sum_send_kbps(), "g", "")
+            .await;
+        self.send("recv_kbps", collector.sum_recv_kbps(), "g", "")
+            .await;
+        self.send("audio_pps", collector.sum_pps(StreamKind::Audio), "g", "")
+            .await;
+        self.send("video_pps", collector.sum_pps(StreamKind::Video), "g", "")
+            .await;
+        self.send("rtt_ms", collector.avg_rtt_ms(), "g", "").await;
+        if let Some(mos) = collector.avg_mos() {
+            self.send("mos", format!("{:.2}", mos), "g", "").await;
+        }
+    }
+
+    async fn on_event(&self, collector: &Collector, event: &BenchEvent) {
+        match event {
+            BenchEvent::Connected(id) => {
+                if let Some(ms) = collector.get_client(*id).and_then(|c| c.connect_ms) {
+                    self.send("connect_time", ms, "ms", "").await;
+                }
+            }
+            BenchEvent::Failed(_, reason) => {
+                let tag = format!("category:{:?}", reason.category);
+                self.send("failures", 1, "c", &tag).await;
+            }
+            BenchEvent::KeepaliveFailed(_, reason) => {
+                let tag = format!("category:{:?}", reason.category);
+                self.send("keepalive_failures", 1, "c", &tag).await;
+            }
+            BenchEvent::Throttled(_) => self.send("throttled", 1, "c", "").await,
+            BenchEvent::Reconnecting(..) => self.send("reconnects", 1, "c", "").await,
+            BenchEvent::Faulted(_) => self.send("faults", 1, "c", "").await,
+            BenchEvent::Recovered(_, ms) => self.send("recover_time", ms, "ms", "").await,
+            BenchEvent::Starved(_, kind) => {
+                let tag = format!("kind:{:?}", kind);
+                self.send("starved", 1, "c", &tag).await;
+            }
+            BenchEvent::Http(_, timing) => {
+                let tag = format!("method:{:?}", timing.method);
+                self.send("signaling_time", timing.total_ms, "ms", &tag)
+                    .await;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Push gauges every interval and timings/counters as events arrive
+pub async fn run_statsd(
+    config: StatsdConfig,
+    event_rx: Receiver<BenchEvent>,
+) -> std::io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(&config.addr).await?;
+    log::info!("[Statsd] exporting to {}", config.addr);
+    let tags = config
+        .tags
+        .iter()
+        .map(|(k, v)| format!("{}:{}", k, v))
+        .collect::<Vec<_>>()
+        .join(",");
+    let exporter = Exporter {
+        socket,
+        config,
+        tags,
+    };
+
+    let mut collector = Collector::with_history(0);
+    let mut next_flush = Instant::now() + exporter.config.interval;
+    loop {
+        let remain = next_flush.saturating_duration_since(Instant::now());
+        match timeout(remain, event_rx.recv()).await {
+            Ok(Ok(event)) => {
+                collector.on_event(event.clone());
+                exporter.on_event(&collector, &event).await;
+            }
+            Ok(Err(_)) => break,
+            Err(_) => {
+                exporter.send_gauges(&collector).await;
+                next_flush = Instant::now() + exporter.config.interval;
+            }
+        }
+    }
+    exporter.send_gauges(&collector).await;
+    Ok(())
+}
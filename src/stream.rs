@@ -0,0 +1,303 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    histogram::{Histogram, InterarrivalSummary},
+    media::{Codec, StreamKind},
+    skew::{ClockSkew, SkewEstimator},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamStats {
+    pub ssrc: u32,
+    pub codec: Codec,
+    /// None for retransmission and fec streams
+    #[serde(default)]
+    pub kind: Option<StreamKind>,
+    pub packets: u64,
+    /// Lost share of the packets expected since previous stats, from sequence gaps
+    #[serde(default)]
+    pub loss: f32,
+    /// Packets missing since the stream started, reordered ones arriving later are not
+    #[serde(default)]
+    pub lost: u64,
+    /// Highest extended sequence number received
+    #[serde(default)]
+    pub last_seq: Option<u64>,
+    /// Rtp packets per second since previous stats
+    #[serde(default)]
+    pub pps: u64,
+    /// Video frames per second since previous stats, from RTP markers and timestamps
+    #[serde(default)]
+    pub fps: f32,
+    /// Payload bitrate since previous stats
+    pub recv_kbps: u64,
+    /// Estimated capture to arrival delay from RTCP sender reports, only
+    /// absolute when sender and receiver clocks are NTP synced
+    pub latency_ms: Option<f32>,
+    /// Change of the latency estimate since the first one, independent of clock offset
+    pub latency_drift_ms: Option<f32>,
+    /// RFC 3550 interarrival jitter
+    pub jitter_ms: f32,
+    /// Rate error of the sender clocks, None until the sender reports span long enough
+    #[serde(default)]
+    pub clock_skew: Option<ClockSkew>,
+    /// Packet interarrival times since the stream started
+    pub interarrival: Histogram,
+}
+
+/// A media section started sending with a new SSRC, e.g. simulcast layer switch
+/// or encoder restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamChange {
+    pub mid: String,
+    pub old_ssrc: u32,
+    pub new_ssrc: u32,
+}
+
+/// Audio minus video latency, positive when audio arrives late relative to video.
+/// Clock offset between sender and receiver cancels out
+pub fn av_offset_ms(streams: &[StreamStats]) -> Option<f32> {
+    let audio = streams
+        .iter()
+        .find(|s| s.codec == Codec::Opus)
+        .and_then(|s| s.latency_ms)?;
+    let video = streams
+        .iter()
+        .find(|s| s.codec.is_video())
+        .and_then(|s| s.latency_ms)?;
+    Some(audio - video)
+}
+
+/// Packets per second of all streams of a kind
+pub fn pps(streams: &[StreamStats], kind: StreamKind) -> u64 {
+    streams
+        .iter()
+        .filter(|s| s.kind == Some(kind))
+        .map(|s| s.pps)
+        .sum()
+}
+
+/// Received frames per second of all video streams, a simulcast receiver has
+/// one stream at a time
+pub fn fps(streams: &[StreamStats]) -> f32 {
+    streams
+        .iter()
+        .filter(|s| s.kind == Some(StreamKind::Video))
+        .map(|s| s.fps)
+        .sum()
+}
+
+/// Merge the interarrival histograms of all streams by media kind
+pub fn interarrival(streams: &[StreamStats]) -> InterarrivalSummary {
+    let mut summary = InterarrivalSummary::default();
+    for stream in streams {
+        match stream.codec {
+            Codec::Opus => summary.audio.add(&stream.interarrival),
+            codec if codec.is_video() => summary.video.add(&stream.interarrival),
+            _ => {}
+        }
+    }
+    summary
+}
+
+/// Receive counters of a single SSRC
+#[derive(Debug)]
+pub struct StreamTracker {
+    ssrc: u32,
+    codec: Codec,
+    packets: u64,
+    pre_packets: u64,
+    /// Extended sequence numbers of the first and highest packet
+    first_seq: Option<u64>,
+    highest_seq: Option<u64>,
+    pre_highest_seq: Option<u64>,
+    bytes: u64,
+    pre_bytes: u64,
+    /// Latest sender report mapping of NTP time to RTP timestamp
+    sender_report: Option<(Instant, u32)>,
+    skew: SkewEstimator,
+    latency_ms: Option<f64>,
+    first_latency_ms: Option<f64>,
+    last_arrival: Option<Instant>,
+    interarrival: Histogram,
+    /// Arrival and rtp timestamp of the previous packet, for jitter
+    last_transit: Option<(Instant, u32)>,
+    jitter_ms: f64,
+    frames: u64,
+    pre_frames: u64,
+    /// Timestamp of the latest counted frame
+    frame_ts: Option<u32>,
+    /// Timestamp of a frame whose marker packet was not received yet
+    open_ts: Option<u32>,
+}
+
+/// `a` after `b` on the wrapping rtp clock
+fn ts_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+/// Signed `a - b` in miliseconds
+fn diff_ms(a: Instant, b: Instant) -> f64 {
+    match a.checked_duration_since(b) {
+        Some(d) => d.as_secs_f64() * 1000.0,
+        None => -(b - a).as_secs_f64() * 1000.0,
+    }
+}
+
+impl StreamTracker {
+    pub fn new(ssrc: u32, codec: Codec) -> Self {
+        Self {
+            ssrc,
+            codec,
+            packets: 0,
+            pre_packets: 0,
+            first_seq: None,
+            highest_seq: None,
+            pre_highest_seq: None,
+            bytes: 0,
+            pre_bytes: 0,
+            sender_report: None,
+            skew: SkewEstimator::new(codec.clock_rate()),
+            latency_ms: None,
+            first_latency_ms: None,
+            last_arrival: None,
+            interarrival: Histogram::default(),
+            last_transit: None,
+            jitter_ms: 0.0,
+            frames: 0,
+            pre_frames: 0,
+            frame_ts: None,
+            open_ts: None,
+        }
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    pub fn on_sender_report(&mut self, ntp_time: Instant, rtp_ts: u32, arrival: Instant) {
+        self.sender_report = Some((ntp_time, rtp_ts));
+        self.skew.on_sender_report(arrival, ntp_time, rtp_ts);
+    }
+
+    pub fn on_rtp(
+        &mut self,
+        payload_len: usize,
+        seq: u64,
+        rtp_ts: u32,
+        marker: bool,
+        arrival: Instant,
+    ) {
+        self.packets += 1;
+        if self.codec.is_video() {
+            self.on_frame_packet(rtp_ts, marker);
+        }
+        self.first_seq = Some(self.first_seq.map_or(seq, |first| first.min(seq)));
+        self.highest_seq = Some(self.highest_seq.map_or(seq, |highest| highest.max(seq)));
+        self.bytes += payload_len as u64;
+        // reordered arrivals are not interarrival samples
+        if let Some(gap) = self
+            .last_arrival
+            .and_then(|last| arrival.checked_duration_since(last))
+        {
+            self.interarrival.record(gap.as_secs_f64() * 1000.0);
+        }
+        self.last_arrival = Some(self.last_arrival.map_or(arrival, |last| last.max(arrival)));
+        if let Some((last_arrival, last_rtp)) = self.last_transit {
+            let ticks = rtp_ts.wrapping_sub(last_rtp) as i32;
+            let sent_ms = ticks as f64 * 1000.0 / self.codec.clock_rate() as f64;
+            let d = diff_ms(arrival, last_arrival) - sent_ms;
+            self.jitter_ms += (d.abs() - self.jitter_ms) / 16.0;
+        }
+        self.last_transit = Some((arrival, rtp_ts));
+
+        let Some((sr_ntp, sr_rtp)) = self.sender_report else {
+            return;
+        };
+        // signed, packets sent shortly before the report have older timestamps
+        let ticks = rtp_ts.wrapping_sub(sr_rtp) as i32;
+        let sent_after_sr_ms = ticks as f64 * 1000.0 / self.codec.clock_rate() as f64;
+        let sample = diff_ms(arrival, sr_ntp) - sent_after_sr_ms;
+        // smoothed like rtp jitter, 1/16 gain
+        let latency = match self.latency_ms {
+            Some(latency) => latency + (sample - latency) / 16.0,
+            None => sample,
+        };
+        self.latency_ms = Some(latency);
+        self.first_latency_ms.get_or_insert(latency);
+    }
+
+    /// A frame is counted at its marker packet, or once a later frame started when
+    /// the marker was lost. Late packets of counted frames are ignored
+    fn on_frame_packet(&mut self, rtp_ts: u32, marker: bool) {
+        if self.frame_ts.map_or(false, |ts| !ts_after(rtp_ts, ts)) {
+            return;
+        }
+        if let Some(open) = self.open_ts.filter(|open| ts_after(rtp_ts, *open)) {
+            self.frames += 1;
+            self.frame_ts = Some(open);
+            self.open_ts = None;
+        }
+        if marker {
+            self.frames += 1;
+            self.frame_ts = Some(rtp_ts);
+            self.open_ts = None;
+        } else {
+            self.open_ts = Some(rtp_ts);
+        }
+    }
+
+    pub fn stats(&mut self, duration_ms: u64) -> StreamStats {
+        let (recv_kbps, pps, fps) = if duration_ms > 0 {
+            (
+                ((self.bytes - self.pre_bytes) * 8) / duration_ms,
+                ((self.packets - self.pre_packets) * 1000) / duration_ms,
+                (self.frames - self.pre_frames) as f32 * 1000.0 / duration_ms as f32,
+            )
+        } else {
+            (0, 0, 0.0)
+        };
+        // expected packets are the sequence numbers passed since the previous stats
+        let expected = match (self.pre_highest_seq, self.highest_seq) {
+            (Some(pre), Some(highest)) => highest - pre,
+            (None, Some(highest)) => highest - self.first_seq.unwrap_or(highest) + 1,
+            _ => 0,
+        };
+        let received = self.packets - self.pre_packets;
+        let loss = if expected > 0 {
+            expected.saturating_sub(received) as f32 / expected as f32
+        } else {
+            0.0
+        };
+        let lost = match (self.first_seq, self.highest_seq) {
+            (Some(first), Some(highest)) => (highest - first + 1).saturating_sub(self.packets),
+            _ => 0,
+        };
+        self.pre_bytes = self.bytes;
+        self.pre_packets = self.packets;
+        self.pre_frames = self.frames;
+        self.pre_highest_seq = self.highest_seq;
+        StreamStats {
+            ssrc: self.ssrc,
+            codec: self.codec,
+            kind: self.codec.kind(),
+            packets: self.packets,
+            loss,
+            lost,
+            last_seq: self.highest_seq,
+            pps,
+            fps,
+            recv_kbps,
+            latency_ms: self.latency_ms.map(|l| l as f32),
+            latency_drift_ms: self
+                .latency_ms
+                .zip(self.first_latency_ms)
+                .map(|(l, first)| (l - first) as f32),
+            jitter_ms: self.jitter_ms as f32,
+            clock_skew: self.skew.skew(),
+            interarrival: self.interarrival,
+        }
+    }
+}
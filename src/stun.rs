@@ -0,0 +1,217 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+const MAGIC_COOKIE: [u8; 4] = [0x21, 0x12, 0xA4, 0x42];
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const BINDING_ERROR: u16 = 0x0111;
+const MAPPED_ADDRESS: u16 = 0x0001;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const ICE_CONTROLLED: u16 = 0x8029;
+const ICE_CONTROLLING: u16 = 0x802A;
+/// Requests without answer after this count as failed
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Ice connectivity and consent checks over the whole session, counters are cumulative
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StunStats {
+    /// Binding requests sent by us, retransmissions not included
+    pub requests: u64,
+    pub responses: u64,
+    /// Error responses and requests which timed out
+    pub failures: u64,
+    /// Binding requests received from the server
+    pub remote_requests: u64,
+    /// Average round trip of responses since previous stats
+    pub rtt_ms: Option<f32>,
+    pub rtt_max_ms: Option<f32>,
+    /// Role claimed by our latest binding request, changes after a role conflict
+    #[serde(default)]
+    pub role: Option<IceRole>,
+}
+
+/// Role of the local ICE agent, the controlling agent nominates the pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IceRole {
+    Controlling,
+    Controlled,
+}
+
+impl fmt::Display for IceRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IceRole::Controlling => write!(f, "controlling"),
+            IceRole::Controlled => write!(f, "controlled"),
+        }
+    }
+}
+
+/// Message type and transaction id of a STUN packet
+fn parse(data: &[u8]) -> Option<(u16, [u8; 12])> {
+    if data.len() < 20 || data[0] & 0xC0 != 0 || data[4..8] != MAGIC_COOKIE {
+        return None;
+    }
+    let kind = u16::from_be_bytes([data[0], data[1]]);
+    let mut transaction = [0u8; 12];
+    transaction.copy_from_slice(&data[8..20]);
+    Some((kind, transaction))
+}
+
+/// Resolve a STUN server given as `host:port`, optionally prefixed with `stun:`
+pub fn parse_server(s: &str) -> Result<SocketAddr, String> {
+    let host = s.strip_prefix("stun:").unwrap_or(s);
+    let host = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:3478", host)
+    };
+    host.to_socket_addrs()
+        .map_err(|e| format!("cannot resolve stun server {}: {}", s, e))?
+        .next()
+        .ok_or_else(|| format!("stun server {} has no address", s))
+}
+
+/// Binding request without attributes, for asking a STUN server for our public address
+pub fn binding_request(transaction: [u8; 12]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(20);
+    request.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+    request.extend_from_slice(&0u16.to_be_bytes());
+    request.extend_from_slice(&MAGIC_COOKIE);
+    request.extend_from_slice(&transaction);
+    request
+}
+
+/// Address of a binding success response to `transaction`, from XOR-MAPPED-ADDRESS
+/// or the MAPPED-ADDRESS of older servers
+pub fn mapped_address(data: &[u8], transaction: &[u8; 12]) -> Option<SocketAddr> {
+    match parse(data)? {
+        (BINDING_SUCCESS, received) if received == *transaction => {}
+        _ => return None,
+    }
+    let mut mapped = None;
+    let mut offset = 20;
+    while offset + 4 <= data.len() {
+        let kind = u16::from_be_bytes([data[offset], data[offset + 1]]);
+        let len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        let value = data.get(offset + 4..offset + 4 + len)?;
+        match kind {
+            // xored with the magic cookie and the transaction id
+            XOR_MAPPED_ADDRESS => return decode_address(value, Some(&data[4..20])),
+            MAPPED_ADDRESS => mapped = decode_address(value, None),
+            _ => {}
+        }
+        // attributes are padded to 4 bytes
+        offset += 4 + (len + 3) / 4 * 4;
+    }
+    mapped
+}
+
+/// Role attribute of a binding request, None for requests to STUN servers
+fn ice_role(data: &[u8]) -> Option<IceRole> {
+    let mut offset = 20;
+    while offset + 4 <= data.len() {
+        let kind = u16::from_be_bytes([data[offset], data[offset + 1]]);
+        let len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        match kind {
+            ICE_CONTROLLING => return Some(IceRole::Controlling),
+            ICE_CONTROLLED => return Some(IceRole::Controlled),
+            _ => {}
+        }
+        offset += 4 + (len + 3) / 4 * 4;
+    }
+    None
+}
+
+/// Reserved byte, family, port and address
+fn decode_address(value: &[u8], xor: Option<&[u8]>) -> Option<SocketAddr> {
+    let family = *value.get(1)?;
+    let mut port = u16::from_be_bytes([*value.get(2)?, *value.get(3)?]);
+    let mut addr = value.get(4..)?.to_vec();
+    if let Some(key) = xor {
+        port ^= u16::from_be_bytes([key[0], key[1]]);
+        for (byte, key) in addr.iter_mut().zip(key) {
+            *byte ^= key;
+        }
+    }
+    let ip = match family {
+        0x01 => IpAddr::from(<[u8; 4]>::try_from(addr.as_slice()).ok()?),
+        0x02 => IpAddr::from(<[u8; 16]>::try_from(addr.as_slice()).ok()?),
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Match STUN binding requests with their responses on the wire, str0m does
+/// not expose ice checks itself
+#[derive(Debug, Default)]
+pub struct StunTracker {
+    pending: HashMap<[u8; 12], Instant>,
+    stats: StunStats,
+    rtt_sum_ms: f32,
+    rtt_count: u32,
+    rtt_max_ms: Option<f32>,
+    first_response_at: Option<Instant>,
+}
+
+impl StunTracker {
+    /// Arrival of the first successful binding response
+    pub fn first_response_at(&self) -> Option<Instant> {
+        self.first_response_at
+    }
+
+    pub fn on_send(&mut self, data: &[u8], now: Instant) {
+        if let Some((BINDING_REQUEST, transaction)) = parse(data) {
+            // a retransmission keeps the first send time
+            if !self.pending.contains_key(&transaction) {
+                self.pending.insert(transaction, now);
+                self.stats.requests += 1;
+            }
+            if let Some(role) = ice_role(data) {
+                self.stats.role = Some(role);
+            }
+        }
+    }
+
+    pub fn on_recv(&mut self, data: &[u8], now: Instant) {
+        match parse(data) {
+            Some((BINDING_REQUEST, _)) => self.stats.remote_requests += 1,
+            Some((BINDING_SUCCESS, transaction)) => {
+                if let Some(sent_at) = self.pending.remove(&transaction) {
+                    let rtt = (now - sent_at).as_secs_f32() * 1000.0;
+                    self.rtt_sum_ms += rtt;
+                    self.rtt_count += 1;
+                    self.rtt_max_ms = Some(self.rtt_max_ms.map_or(rtt, |max| max.max(rtt)));
+                    self.stats.responses += 1;
+                    self.first_response_at.get_or_insert(now);
+                }
+            }
+            Some((BINDING_ERROR, transaction)) => {
+                if self.pending.remove(&transaction).is_some() {
+                    self.stats.failures += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Expire unanswered requests and take the round trip window
+    pub fn stats(&mut self, now: Instant) -> StunStats {
+        let before = self.pending.len();
+        self.pending
+            .retain(|_, sent_at| now.duration_since(*sent_at) < REQUEST_TIMEOUT);
+        self.stats.failures += (before - self.pending.len()) as u64;
+
+        self.stats.rtt_ms = (self.rtt_count > 0).then(|| self.rtt_sum_ms / self.rtt_count as f32);
+        self.stats.rtt_max_ms = self.rtt_max_ms.take();
+        self.rtt_sum_ms = 0.0;
+        self.rtt_count = 0;
+        self.stats.clone()
+    }
+}
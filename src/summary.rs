@@ -0,0 +1,1369 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ab::{self, AbMetric, AbTable},
+    audiolevel::AudioLevelStats,
+    bench::{BenchEvent, FailureCategory},
+    candidate::{CandidatePair, IceBreakdown},
+    collector::{AggregateSample, Collector},
+    conformance::{self, ConformanceSummary, Expectation},
+    consistency::{self, Divergence},
+    gate::{Distribution, GateMetric},
+    growth::{self, GrowthMonitor, GrowthWarning},
+    histogram::InterarrivalSummary,
+    media::StreamKind,
+    playout::PlayoutDelay,
+    ramp::RampDecision,
+    resilience::ResilienceStats,
+    resolution::Resolution,
+    rtpext::HeaderExtensionStats,
+    selfmon::{ResourceMonitor, ResourceSummary},
+    silence::SilenceStats,
+    sketch::QuantileSketch,
+    skew::{self, ClockSkew},
+    soak::{Rollup, SoakTable},
+    stream,
+    stun::IceRole,
+    sweep::{CapacityCurve, Plateau},
+    teardown::{ShutdownReport, Teardown},
+    timeline::{self, TimelineEntry},
+    traffic::TrafficStats,
+    watchdog::{Abort, Stop},
+    whep::{ConnectPhases, HttpMethod, HttpVersion},
+    whepext,
+};
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    pub fn from_values(mut values: Vec<f64>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let at = |p: f64| values[((values.len() - 1) as f64 * p).round() as usize];
+        Self {
+            p50: at(0.5),
+            p90: at(0.9),
+            p99: at(0.99),
+            max: values[values.len() - 1],
+        }
+    }
+}
+
+/// Results of the clients carrying one label
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LabelSummary {
+    pub clients: usize,
+    pub connected: usize,
+    pub failed: usize,
+    /// First join of each client
+    pub connect_ms: Percentiles,
+    pub recv_kbps: Percentiles,
+    pub rtt_ms: Percentiles,
+    pub loss: Percentiles,
+}
+
+/// Result of a whole run, stats percentiles are over per client averages, timing
+/// percentiles are estimated within 1% by a [`QuantileSketch`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub duration_ms: u64,
+    /// Stats samples of this first part of the run are not included
+    #[serde(default)]
+    pub warmup_ms: u64,
+    pub clients: usize,
+    pub connected: usize,
+    pub failed: usize,
+    /// Failed clients per failure category
+    #[serde(default)]
+    pub failures: BTreeMap<FailureCategory, usize>,
+    /// Clients failed with an auth challenge, per WWW-Authenticate value
+    #[serde(default)]
+    pub auth_challenges: BTreeMap<String, usize>,
+    /// Successful joins, more than connected clients in loop mode
+    #[serde(default)]
+    pub joins: usize,
+    /// Signaling attempts rejected with 429 or 503
+    #[serde(default)]
+    pub throttled: u64,
+    /// Simulcast layer switches of all clients
+    #[serde(default)]
+    pub layer_switches: u64,
+    /// Keepalives of the session resources, and the ones which failed
+    #[serde(default)]
+    pub keepalives: u64,
+    #[serde(default)]
+    pub keepalive_failures: u64,
+    /// Sessions deleted in the background after their client ended without DELETE
+    #[serde(default)]
+    pub sessions_cleaned: u64,
+    /// Sessions left on the server, their DELETE failed or never completed
+    #[serde(default)]
+    pub sessions_leaked: u64,
+    /// Time the shutdown took tearing down the sessions, set by the runner
+    #[serde(default)]
+    pub shutdown: Option<ShutdownReport>,
+    /// Injected faults, and the time from fault until the replacement connected
+    #[serde(default)]
+    pub faults: usize,
+    #[serde(default)]
+    pub recover_ms: Percentiles,
+    /// Answered keyframe requests, and the time until their keyframe arrived
+    #[serde(default)]
+    pub keyframe_requests: usize,
+    #[serde(default)]
+    pub keyframe_ms: Percentiles,
+    /// Clients which received less than the expected bitrate, per media kind
+    #[serde(default)]
+    pub starved: BTreeMap<StreamKind, usize>,
+    /// Clients per origin which answered their offer
+    #[serde(default)]
+    pub edges: BTreeMap<String, usize>,
+    /// Clients per selected candidate pair, e.g. `host-relay/udp`
+    #[serde(default)]
+    pub candidate_pairs: BTreeMap<String, usize>,
+    /// Clients per ice role and per candidate type and transport of their pair
+    #[serde(default)]
+    pub ice: IceBreakdown,
+    /// Clients per latest received video resolution, e.g. `1280x720`
+    #[serde(default)]
+    pub resolutions: BTreeMap<String, usize>,
+    /// Resolution changes of all clients after their first keyframe, and the
+    /// clients which received a lower resolution at some point
+    #[serde(default)]
+    pub resolution_changes: u64,
+    #[serde(default)]
+    pub downscaled: usize,
+    /// Clients per latest playout delay the server asked for, and the changes of
+    /// all clients after the first one
+    #[serde(default)]
+    pub playout_delays: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub playout_delay_changes: u64,
+    pub connect_ms: Percentiles,
+    /// Wait for a handshake slot, see [`crate::handshake`]
+    #[serde(default)]
+    pub queue_ms: Percentiles,
+    /// Connection phases, missing in summaries written by older versions
+    #[serde(default)]
+    pub signaling_ms: Percentiles,
+    #[serde(default)]
+    pub ice_ms: Percentiles,
+    #[serde(default)]
+    pub dtls_ms: Percentiles,
+    /// Signaling requests, missing in summaries written by older versions
+    #[serde(default)]
+    pub dns_ms: Percentiles,
+    /// Lookups served by the shared dns cache, kept out of `dns_ms`
+    #[serde(default)]
+    pub dns_cached_ms: Percentiles,
+    #[serde(default)]
+    pub post_ttfb_ms: Percentiles,
+    #[serde(default)]
+    pub post_ms: Percentiles,
+    #[serde(default)]
+    pub delete_ms: Percentiles,
+    /// Signaling requests per http version of their response
+    #[serde(default)]
+    pub http_versions: BTreeMap<HttpVersion, usize>,
+    /// Signaling host lookups served by the shared dns cache
+    #[serde(default)]
+    pub dns_cached: u64,
+    pub recv_kbps: Percentiles,
+    /// Media payload only, without headers, control traffic and retransmissions
+    #[serde(default)]
+    pub payload_kbps: Percentiles,
+    pub rtt_ms: Percentiles,
+    pub loss: Percentiles,
+    /// Rtp packets per second by media kind
+    #[serde(default)]
+    pub audio_pps: Percentiles,
+    #[serde(default)]
+    pub video_pps: Percentiles,
+    /// Received video frames per second
+    #[serde(default)]
+    pub video_fps: Percentiles,
+    /// Estimated audio MOS of clients which received audio
+    #[serde(default)]
+    pub mos: Percentiles,
+    /// Average audio level and silent share of clients whose audio carried a level
+    #[serde(default)]
+    pub audio_dbov: Percentiles,
+    #[serde(default)]
+    pub audio_silence: Percentiles,
+    /// Seconds without audio packets of clients which received audio, the sender
+    /// in DTX and audio missing in between
+    #[serde(default)]
+    pub audio_dtx_secs: Percentiles,
+    #[serde(default)]
+    pub audio_gap_secs: Percentiles,
+    /// Media clock rate error of the most skewed stream per client, in ppm, and the
+    /// clients whose media clock ran too fast or slow
+    #[serde(default)]
+    pub media_clock_ppm: Percentiles,
+    #[serde(default)]
+    pub skewed_clients: usize,
+    /// Received bytes of all clients, `payload_kbps` of it is unused
+    #[serde(default)]
+    pub traffic: TrafficStats,
+    /// Lost and repaired packets of all clients
+    #[serde(default)]
+    pub resilience: ResilienceStats,
+    /// Received packets of all clients by header extension
+    #[serde(default)]
+    pub extensions: HeaderExtensionStats,
+    /// Clients which received each header extension at least once
+    #[serde(default)]
+    pub extension_clients: BTreeMap<String, usize>,
+    /// Packet interarrival times of all clients
+    #[serde(default)]
+    pub interarrival: InterarrivalSummary,
+    /// Set when an abort rule ended the run early
+    #[serde(default)]
+    pub aborted: Option<Abort>,
+    /// Set when a stop condition ended the run
+    #[serde(default)]
+    pub stopped: Option<Stop>,
+    /// Usage of the load generator itself, None where it cannot be sampled
+    #[serde(default)]
+    pub resources: Option<ResourceSummary>,
+    /// Gauges of the load generator which kept growing
+    #[serde(default)]
+    pub growth: Vec<GrowthWarning>,
+    /// Clients by negotiated answer profile, more than one means the server
+    /// answered differently, e.g. under load
+    #[serde(default)]
+    pub answer_profiles: BTreeMap<String, usize>,
+    /// Negotiated parameters which differed between clients, see [`crate::consistency`]
+    #[serde(default)]
+    pub answer_divergences: Vec<Divergence>,
+    /// Received media compared with the expectation of --expect, None without one
+    #[serde(default)]
+    pub conformance: Option<ConformanceSummary>,
+    /// Clients by the WHEP extensions their answer advertised
+    #[serde(default)]
+    pub whep_extensions: BTreeMap<String, usize>,
+    /// Ids of clients advertised other WHEP extensions than most clients
+    #[serde(default)]
+    pub whep_extension_outliers: Vec<usize>,
+    /// Results per client label
+    #[serde(default)]
+    pub labels: BTreeMap<String, LabelSummary>,
+    /// Both sides of an A/B run compared, empty otherwise
+    #[serde(default)]
+    pub ab: Vec<AbMetric>,
+    /// Pauses and resumes of the adaptive ramp
+    #[serde(default)]
+    pub ramp: Vec<RampDecision>,
+    /// Plateaus of a saturation sweep, empty otherwise
+    #[serde(default)]
+    pub sweep: Vec<Plateau>,
+    /// Windows of a soak run, the summary itself covers the last one
+    #[serde(default)]
+    pub soak: Vec<Rollup>,
+    /// Metrics of the plugins, named `<plugin>.<metric>`
+    #[serde(default)]
+    pub plugins: BTreeMap<String, f64>,
+    /// Aggregates sampled each second, charted by the HTML report
+    #[serde(default)]
+    pub timeline: Vec<AggregateSample>,
+    /// Milestones of each client, kept when enabled with
+    /// [`SummaryCollector::set_client_timelines`]
+    #[serde(default)]
+    pub client_timelines: BTreeMap<usize, Vec<TimelineEntry>>,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "duration {}s, clients {}, connected {}, failed {}, throttled {}",
+            self.duration_ms / 1000,
+            self.clients,
+            self.connected,
+            self.failed,
+            self.throttled
+        )?;
+        if !self.failures.is_empty() {
+            let failures: Vec<String> = self
+                .failures
+                .iter()
+                .map(|(category, count)| format!("{:?} {}", category, count))
+                .collect();
+            writeln!(f, "failures: {}", failures.join(", "))?;
+        }
+        for (challenge, count) in &self.auth_challenges {
+            writeln!(
+                f,
+                "auth challenged {} clients: WWW-Authenticate {}",
+                count, challenge
+            )?;
+        }
+        if self.layer_switches > 0 {
+            writeln!(f, "simulcast layer switches {}", self.layer_switches)?;
+        }
+        if self.keepalives > 0 || self.keepalive_failures > 0 {
+            writeln!(
+                f,
+                "keepalives {}, failed {}",
+                self.keepalives, self.keepalive_failures
+            )?;
+        }
+        if self.sessions_cleaned > 0 || self.sessions_leaked > 0 {
+            writeln!(
+                f,
+                "sessions cleaned up {}, leaked {}",
+                self.sessions_cleaned, self.sessions_leaked
+            )?;
+        }
+        if let Some(shutdown) = &self.shutdown {
+            writeln!(f, "{}", shutdown)?;
+        }
+        if self.joins > self.connected {
+            writeln!(f, "joins {} including reconnects", self.joins)?;
+        }
+        if self.faults > 0 {
+            let p = &self.recover_ms;
+            writeln!(
+                f,
+                "faults {}, recover ms p50 {:.0} p90 {:.0} p99 {:.0} max {:.0}",
+                self.faults, p.p50, p.p90, p.p99, p.max
+            )?;
+        }
+        if self.skewed_clients > 0 {
+            writeln!(
+                f,
+                "clients with a media clock off by more than {} ppm: {}",
+                skew::FLAG_PPM,
+                self.skewed_clients
+            )?;
+        }
+        if self.keyframe_requests > 0 {
+            let p = &self.keyframe_ms;
+            writeln!(
+                f,
+                "requested keyframes {}, arrival ms p50 {:.0} p90 {:.0} p99 {:.0} max {:.0}",
+                self.keyframe_requests, p.p50, p.p90, p.p99, p.max
+            )?;
+        }
+        if !self.starved.is_empty() {
+            let starved: Vec<String> = self
+                .starved
+                .iter()
+                .map(|(kind, count)| format!("{:?} {}", kind, count))
+                .collect();
+            writeln!(f, "starved clients: {}", starved.join(", "))?;
+        }
+        if let Some(abort) = &self.aborted {
+            writeln!(f, "{}", abort)?;
+        }
+        if let Some(stop) = &self.stopped {
+            writeln!(f, "{}", stop)?;
+        }
+        for decision in &self.ramp {
+            writeln!(f, "{}", decision)?;
+        }
+        if self.warmup_ms > 0 {
+            writeln!(
+                f,
+                "stats exclude the first {}s warm-up",
+                self.warmup_ms / 1000
+            )?;
+        }
+        if !self.answer_divergences.is_empty() {
+            writeln!(f, "answers differ across clients:")?;
+            for divergence in &self.answer_divergences {
+                writeln!(f, "{}", divergence)?;
+            }
+        }
+        if let Some(conformance) = &self.conformance {
+            writeln!(f, "{}", conformance)?;
+        }
+        if self.whep_extensions.len() > 1 {
+            writeln!(f, "whep extensions differ across clients:")?;
+            for (extensions, count) in &self.whep_extensions {
+                writeln!(f, "  {} clients: {}", count, extensions)?;
+            }
+            let ids: Vec<String> = self
+                .whep_extension_outliers
+                .iter()
+                .take(20)
+                .map(|id| id.to_string())
+                .collect();
+            let more = self.whep_extension_outliers.len().saturating_sub(ids.len());
+            writeln!(
+                f,
+                "  inconsistent clients: {}{}",
+                ids.join(", "),
+                if more > 0 {
+                    format!(" and {} more", more)
+                } else {
+                    String::new()
+                }
+            )?;
+        } else if let Some(extensions) = self.whep_extensions.keys().next() {
+            writeln!(f, "whep extensions: {}", extensions)?;
+        }
+        if self.edges.len() > 1 {
+            let edges: Vec<String> = self
+                .edges
+                .iter()
+                .map(|(edge, count)| format!("{} {}", edge, count))
+                .collect();
+            writeln!(f, "edges: {}", edges.join(", "))?;
+        }
+        if !self.candidate_pairs.is_empty() {
+            let total = self.candidate_pairs.values().sum::<usize>() as f64;
+            let pairs: Vec<String> = self
+                .candidate_pairs
+                .iter()
+                .map(|(kind, count)| format!("{} {:.1}%", kind, *count as f64 * 100.0 / total))
+                .collect();
+            writeln!(f, "candidate pairs: {}", pairs.join(", "))?;
+        }
+        if !self.ice.is_empty() {
+            writeln!(f, "{}", self.ice)?;
+        }
+        if !self.http_versions.is_empty() {
+            let versions: Vec<String> = self
+                .http_versions
+                .iter()
+                .map(|(version, count)| format!("{} {}", version, count))
+                .collect();
+            writeln!(f, "signaling requests: {}", versions.join(", "))?;
+        }
+        if self.dns_cached > 0 {
+            writeln!(
+                f,
+                "dns lookups served by the cache: {}, ms p50 {:.2} p99 {:.2}",
+                self.dns_cached, self.dns_cached_ms.p50, self.dns_cached_ms.p99
+            )?;
+        }
+        if !self.resolutions.is_empty() {
+            let resolutions: Vec<String> = self
+                .resolutions
+                .iter()
+                .map(|(resolution, count)| format!("{} {}", resolution, count))
+                .collect();
+            writeln!(
+                f,
+                "resolutions: {}, {} changes, {} clients downscaled",
+                resolutions.join(", "),
+                self.resolution_changes,
+                self.downscaled
+            )?;
+        }
+        if !self.playout_delays.is_empty() {
+            let delays: Vec<String> = self
+                .playout_delays
+                .iter()
+                .map(|(delay, count)| format!("{} {}", delay, count))
+                .collect();
+            writeln!(
+                f,
+                "playout delays: {}, {} changes",
+                delays.join(", "),
+                self.playout_delay_changes
+            )?;
+        }
+        if let Some(ratio) = self.traffic.goodput_ratio() {
+            let mb = |bytes: u64| bytes as f64 / 1_000_000.0;
+            let t = &self.traffic;
+            writeln!(
+                f,
+                "received {:.1} MB: payload {:.1}, rtp overhead {:.1}, rtx {:.1}, rtcp {:.1}, stun {:.1}, dtls {:.1}, goodput {:.1}%",
+                mb(t.wire_bytes),
+                mb(t.payload_bytes),
+                mb(t.rtp_overhead_bytes),
+                mb(t.rtx_bytes),
+                mb(t.rtcp_bytes),
+                mb(t.stun_bytes),
+                mb(t.dtls_bytes),
+                ratio * 100.0
+            )?;
+        }
+        let ext = &self.extensions;
+        if ext.packets > 0 {
+            let counts: Vec<String> = ext
+                .counts()
+                .iter()
+                .map(|(name, count)| {
+                    format!(
+                        "{} {:.1}% ({} clients)",
+                        name,
+                        *count as f64 * 100.0 / ext.packets as f64,
+                        self.extension_clients.get(*name).copied().unwrap_or(0)
+                    )
+                })
+                .collect();
+            writeln!(
+                f,
+                "header extensions of {} packets: {}",
+                ext.packets,
+                counts.join(", ")
+            )?;
+        }
+        let r = &self.resilience;
+        if r.lost > 0 || r.rtx_packets > 0 || r.fec_packets > 0 {
+            writeln!(
+                f,
+                "lost packets {}: recovered {} by rtx, {} by fec, {} unrecovered ({} rtx, {} fec packets)",
+                r.lost,
+                r.recovered_rtx,
+                r.recovered_fec,
+                r.unrecovered(),
+                r.rtx_packets,
+                r.fec_packets
+            )?;
+        }
+        if let Some(res) = &self.resources {
+            writeln!(
+                f,
+                "load generator cpu p50 {:.0}% max {:.0}%, rss max {:.0} MB, fds max {}, udp errors {} (rcvbuf {})",
+                res.cpu_pct.p50,
+                res.cpu_pct.max,
+                res.rss_mb_max,
+                res.fds_max,
+                res.udp_in_errors,
+                res.udp_rcvbuf_errors
+            )?;
+        }
+        for warning in &self.growth {
+            writeln!(f, "memory growth: {}", warning)?;
+        }
+        writeln!(
+            f,
+            "{:<12}{:>10}{:>10}{:>10}{:>10}",
+            "metric", "p50", "p90", "p99", "max"
+        )?;
+        for (name, p) in [
+            ("connect ms", &self.connect_ms),
+            ("queue ms", &self.queue_ms),
+            ("signal ms", &self.signaling_ms),
+            ("ice ms", &self.ice_ms),
+            ("dtls ms", &self.dtls_ms),
+            ("dns ms", &self.dns_ms),
+            ("ttfb ms", &self.post_ttfb_ms),
+            ("post ms", &self.post_ms),
+            ("delete ms", &self.delete_ms),
+            ("recv kbps", &self.recv_kbps),
+            ("goodput", &self.payload_kbps),
+            ("rtt ms", &self.rtt_ms),
+            ("loss", &self.loss),
+            ("audio pps", &self.audio_pps),
+            ("video pps", &self.video_pps),
+            ("video fps", &self.video_fps),
+            ("mos", &self.mos),
+            ("audio dBov", &self.audio_dbov),
+            ("silence", &self.audio_silence),
+            ("dtx s", &self.audio_dtx_secs),
+            ("audio gap s", &self.audio_gap_secs),
+            ("clock ppm", &self.media_clock_ppm),
+        ] {
+            writeln!(
+                f,
+                "{:<12}{:>10.2}{:>10.2}{:>10.2}{:>10.2}",
+                name, p.p50, p.p90, p.p99, p.max
+            )?;
+        }
+        for (label, s) in &self.labels {
+            writeln!(
+                f,
+                "label {}: clients {}, connected {}, failed {}, connect ms p50 {:.0} p90 {:.0}, recv kbps p50 {:.0}, rtt ms p50 {:.0}, loss p50 {:.3} p90 {:.3}",
+                label,
+                s.clients,
+                s.connected,
+                s.failed,
+                s.connect_ms.p50,
+                s.connect_ms.p90,
+                s.recv_kbps.p50,
+                s.rtt_ms.p50,
+                s.loss.p50,
+                s.loss.p90
+            )?;
+        }
+        if !self.ab.is_empty() {
+            write!(f, "{}", AbTable(&self.ab))?;
+        }
+        if !self.sweep.is_empty() {
+            write!(f, "{}", CapacityCurve(&self.sweep))?;
+        }
+        if !self.soak.is_empty() {
+            write!(f, "{}", SoakTable(&self.soak))?;
+        }
+        for (metric, value) in &self.plugins {
+            writeln!(f, "plugin {}: {:.3}", metric, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClientTotals {
+    /// Iterations which connected
+    joins: usize,
+    connected_at: Option<Instant>,
+    created_at: Option<Instant>,
+    first_connect_ms: Option<u64>,
+    labels: Vec<String>,
+    failed: Option<FailureCategory>,
+    /// WWW-Authenticate value of an AuthChallenge failure
+    auth_challenge: Option<String>,
+    starved: BTreeSet<StreamKind>,
+    phases: ConnectPhases,
+    edge: Option<String>,
+    candidate_pair: Option<CandidatePair>,
+    ice_role: Option<IceRole>,
+    resolution: Option<Resolution>,
+    resolution_changes: u64,
+    downscaled: bool,
+    playout_delay: Option<PlayoutDelay>,
+    playout_delay_changes: u64,
+    answer_profile: Option<String>,
+    answer_parameters: Option<BTreeMap<&'static str, String>>,
+    whep_extensions: Option<String>,
+    /// Latest counters of the current session
+    traffic: TrafficStats,
+    resilience: ResilienceStats,
+    extensions: HeaderExtensionStats,
+    /// Header extensions seen in any session
+    extensions_seen: BTreeSet<&'static str>,
+    interarrival: InterarrivalSummary,
+    samples: u64,
+    recv_kbps: u64,
+    payload_kbps: u64,
+    rtt_ms: u64,
+    loss: f64,
+    audio_pps: u64,
+    video_pps: u64,
+    video_fps: f64,
+    mos_samples: u64,
+    mos: f64,
+    audio_level: Option<AudioLevelStats>,
+    /// Latest counters of the current session, None until audio arrived
+    silence: Option<SilenceStats>,
+    /// Most skewed stream of the latest stats
+    clock_skew: Option<ClockSkew>,
+    conformance: conformance::Tracker,
+    /// The client task ended, dropped at the next soak rollup
+    ended: bool,
+}
+
+/// Keeps per client totals of a whole run, unlike the collector which forgets
+/// clients once they leave
+#[derive(Debug)]
+pub struct SummaryCollector {
+    started_at: Instant,
+    warmup: Duration,
+    clients: HashMap<usize, ClientTotals>,
+    throttled: u64,
+    layer_switches: u64,
+    keepalives: u64,
+    keepalive_failures: u64,
+    sessions_cleaned: u64,
+    sessions_leaked: u64,
+    faults: usize,
+    /// Samples of unbounded count, one per join or request, go into sketches
+    connect_ms: QuantileSketch,
+    recover_ms: QuantileSketch,
+    /// Requested keyframes until they arrived
+    keyframe_ms: QuantileSketch,
+    /// Handshake slot waits, of failed joins too
+    queue_ms: QuantileSketch,
+    dns_ms: QuantileSketch,
+    dns_cached_ms: QuantileSketch,
+    post_ttfb_ms: QuantileSketch,
+    post_ms: QuantileSketch,
+    delete_ms: QuantileSketch,
+    http_versions: BTreeMap<HttpVersion, usize>,
+    /// Counters of sessions which ended in loop or fault mode
+    ended_traffic: TrafficStats,
+    ended_resilience: ResilienceStats,
+    ended_extensions: HeaderExtensionStats,
+    ended_interarrival: InterarrivalSummary,
+    resources: ResourceMonitor,
+    growth: GrowthMonitor,
+    collector: Collector,
+    timeline: Vec<AggregateSample>,
+    client_timelines: Option<BTreeMap<usize, Vec<TimelineEntry>>>,
+    ab: bool,
+    expectation: Option<Expectation>,
+}
+
+impl Default for SummaryCollector {
+    fn default() -> Self {
+        Self::with_warmup(Duration::ZERO)
+    }
+}
+
+impl SummaryCollector {
+    /// Stats samples arriving within `warmup` are ignored, connection times still count
+    pub fn with_warmup(warmup: Duration) -> Self {
+        Self {
+            started_at: Instant::now(),
+            warmup,
+            clients: HashMap::new(),
+            throttled: 0,
+            layer_switches: 0,
+            keepalives: 0,
+            keepalive_failures: 0,
+            sessions_cleaned: 0,
+            sessions_leaked: 0,
+            faults: 0,
+            connect_ms: QuantileSketch::default(),
+            recover_ms: QuantileSketch::default(),
+            keyframe_ms: QuantileSketch::default(),
+            queue_ms: QuantileSketch::default(),
+            dns_ms: QuantileSketch::default(),
+            dns_cached_ms: QuantileSketch::default(),
+            post_ttfb_ms: QuantileSketch::default(),
+            post_ms: QuantileSketch::default(),
+            delete_ms: QuantileSketch::default(),
+            http_versions: BTreeMap::new(),
+            ended_traffic: TrafficStats::default(),
+            ended_resilience: ResilienceStats::default(),
+            ended_extensions: HeaderExtensionStats::default(),
+            ended_interarrival: InterarrivalSummary::default(),
+            resources: ResourceMonitor::default(),
+            growth: GrowthMonitor::default(),
+            collector: Collector::with_history(0),
+            timeline: vec![],
+            client_timelines: None,
+            ab: false,
+            expectation: None,
+        }
+    }
+
+    /// Compare the clients labeled A with those labeled B in the summary
+    pub fn set_ab(&mut self, ab: bool) {
+        self.ab = ab;
+    }
+
+    /// Keep the milestones of every client for the summary
+    pub fn set_client_timelines(&mut self, enabled: bool) {
+        self.client_timelines = enabled.then(BTreeMap::new);
+    }
+
+    /// Validate the received media of every client against this expectation
+    pub fn set_expectation(&mut self, expectation: Option<Expectation>) {
+        self.expectation = expectation;
+    }
+
+    fn in_warmup(&self) -> bool {
+        self.started_at.elapsed() < self.warmup
+    }
+
+    pub fn on_event(&mut self, event: &BenchEvent) {
+        self.collector.on_event(event.clone());
+        if let Some(timelines) = &mut self.client_timelines {
+            let entries = timeline::entries_of(event, timeline::unix_ms());
+            if !entries.is_empty() {
+                timelines
+                    .entry(event.client_id())
+                    .or_default()
+                    .extend(entries);
+            }
+        }
+        match event {
+            BenchEvent::Connecting(id) => {
+                let client = self.clients.entry(*id).or_default();
+                client.created_at = Some(Instant::now());
+            }
+            BenchEvent::Connected(id) => {
+                let client = self.clients.entry(*id).or_default();
+                let now = Instant::now();
+                client.connected_at = Some(now);
+                client.joins += 1;
+                if let Some(created_at) = client.created_at {
+                    let connect_ms = (now - created_at).as_millis() as u64;
+                    client.first_connect_ms.get_or_insert(connect_ms);
+                    self.connect_ms.record(connect_ms as f64);
+                }
+            }
+            BenchEvent::Stats(id, stats) => {
+                let warmup = self.in_warmup();
+                let client = self.clients.entry(*id).or_default();
+                client.phases = stats.phases;
+                client.traffic = stats.traffic;
+                client.resilience = stats.resilience;
+                client.extensions = stats.extensions;
+                for (name, count) in stats.extensions.counts() {
+                    if count > 0 {
+                        client.extensions_seen.insert(name);
+                    }
+                }
+                if stats.audio_level.is_some() {
+                    client.audio_level = stats.audio_level;
+                }
+                if stats
+                    .streams
+                    .iter()
+                    .any(|s| s.kind == Some(StreamKind::Audio))
+                {
+                    client.silence = Some(stats.silence);
+                }
+                if let Some(skew) = stats
+                    .streams
+                    .iter()
+                    .filter_map(|s| s.clock_skew)
+                    .max_by(|a, b| a.media_ppm.abs().total_cmp(&b.media_ppm.abs()))
+                {
+                    client.clock_skew = Some(skew);
+                }
+                client.interarrival = stream::interarrival(&stats.streams);
+                if client.edge.is_none() {
+                    client.edge = stats.edge.clone();
+                }
+                if stats.candidate_pair.is_some() {
+                    client.candidate_pair = stats.candidate_pair.clone();
+                }
+                if stats.stun.role.is_some() {
+                    client.ice_role = stats.stun.role;
+                }
+                if stats.resolution.is_some() {
+                    client.resolution = stats.resolution;
+                }
+                for change in &stats.resolution_changes {
+                    if change.from.is_some() {
+                        client.resolution_changes += 1;
+                    }
+                    client.downscaled |= change.is_downscale();
+                }
+                if stats.playout_delay.is_some() {
+                    client.playout_delay = stats.playout_delay;
+                }
+                client.playout_delay_changes += stats
+                    .playout_delay_changes
+                    .iter()
+                    .filter(|change| change.from.is_some())
+                    .count() as u64;
+                if warmup {
+                    return;
+                }
+                client.samples += 1;
+                client.recv_kbps += stats.recv_kbps;
+                client.payload_kbps += stats.traffic.payload_kbps;
+                client.rtt_ms += stats.rtt_ms as u64;
+                client.loss += stats.lost as f64;
+                client.audio_pps += stats.audio_pps;
+                client.video_pps += stats.video_pps;
+                client.video_fps += stats.video_fps as f64;
+                if let Some(expectation) = &self.expectation {
+                    client.conformance.on_stats(expectation, stats);
+                }
+                if let Some(mos) = stats.mos {
+                    client.mos_samples += 1;
+                    client.mos += mos as f64;
+                }
+            }
+            BenchEvent::Failed(id, reason) => {
+                let client = self.clients.entry(*id).or_default();
+                client.failed = Some(reason.category);
+                if reason.category == FailureCategory::AuthChallenge {
+                    client.auth_challenge = Some(reason.message.clone());
+                }
+            }
+            BenchEvent::Throttled(_) => self.throttled += 1,
+            BenchEvent::LayerSwitched(..) => self.layer_switches += 1,
+            BenchEvent::KeepaliveFailed(..) => self.keepalive_failures += 1,
+            BenchEvent::Teardown(_, Teardown::Cleaned) => self.sessions_cleaned += 1,
+            BenchEvent::Teardown(_, Teardown::Leaked) => self.sessions_leaked += 1,
+            BenchEvent::Reconnecting(id, _) => self.new_session(*id),
+            BenchEvent::Faulted(id) => {
+                self.faults += 1;
+                self.new_session(*id);
+            }
+            BenchEvent::Recovered(_, ms) => self.recover_ms.record(*ms as f64),
+            BenchEvent::KeyframeReceived(_, ms) => self.keyframe_ms.record(*ms as f64),
+            BenchEvent::Queued(_, ms) => self.queue_ms.record(*ms as f64),
+            BenchEvent::Starved(id, kind) => {
+                self.clients.entry(*id).or_default().starved.insert(*kind);
+            }
+            BenchEvent::Answer(id, answer) => {
+                let client = self.clients.entry(*id).or_default();
+                client.answer_profile = Some(answer.profile());
+                client.answer_parameters = Some(consistency::parameters(answer));
+                client.whep_extensions = Some(whepext::describe(&answer.whep_extensions));
+            }
+            BenchEvent::Labeled(id, labels) => {
+                self.clients.entry(*id).or_default().labels = labels.clone();
+            }
+            BenchEvent::Http(_, timing) => {
+                match timing.dns_ms {
+                    Some(dns_ms) if timing.dns_cached => self.dns_cached_ms.record(dns_ms as f64),
+                    Some(dns_ms) => self.dns_ms.record(dns_ms as f64),
+                    None => {}
+                }
+                if let Some(version) = timing.version {
+                    *self.http_versions.entry(version).or_default() += 1;
+                }
+                match timing.method {
+                    HttpMethod::Post => {
+                        self.post_ttfb_ms.record(timing.ttfb_ms as f64);
+                        self.post_ms.record(timing.total_ms as f64);
+                    }
+                    HttpMethod::Delete => self.delete_ms.record(timing.total_ms as f64),
+                    HttpMethod::Patch | HttpMethod::Head => self.keepalives += 1,
+                }
+            }
+            BenchEvent::Disconnected(id) => {
+                self.clients.entry(*id).or_default().ended = true;
+            }
+            BenchEvent::StreamChanged(..) => {}
+        }
+    }
+
+    /// The client rejoins with fresh counters
+    fn new_session(&mut self, id: usize) {
+        let client = self.clients.entry(id).or_default();
+        client.created_at = Some(Instant::now());
+        self.ended_traffic.add(&std::mem::take(&mut client.traffic));
+        self.ended_resilience
+            .add(&std::mem::take(&mut client.resilience));
+        self.ended_extensions
+            .add(&std::mem::take(&mut client.extensions));
+        self.ended_interarrival
+            .add(&std::mem::take(&mut client.interarrival));
+    }
+
+    /// Sample the usage of this process and the timeline, called each second during the run
+    pub fn sample(&mut self, now: Instant) {
+        self.resources.sample(now);
+        growth::gauge(
+            "clients.alive",
+            self.clients.values().filter(|c| !c.ended).count() as u64,
+        );
+        growth::gauge("summary.clients", self.clients.len() as u64);
+        growth::gauge("summary.timeline", self.timeline.len() as u64);
+        self.growth.sample(now);
+        self.collector.sample(now);
+        self.timeline
+            .extend(self.collector.aggregates().back().cloned());
+    }
+
+    /// Summary of the window so far, then start a new window with the clients
+    /// still running. Keeps the state of a soak run bounded
+    pub fn rollup(&mut self) -> RunSummary {
+        let window = self.summary();
+        self.clients.retain(|_, c| !c.ended);
+        for client in self.clients.values_mut() {
+            // the current session carries over, its results start over
+            *client = ClientTotals {
+                connected_at: client.connected_at,
+                created_at: client.created_at,
+                labels: std::mem::take(&mut client.labels),
+                phases: client.phases,
+                edge: client.edge.take(),
+                candidate_pair: client.candidate_pair.take(),
+                ice_role: client.ice_role,
+                answer_profile: client.answer_profile.take(),
+                answer_parameters: client.answer_parameters.take(),
+                whep_extensions: client.whep_extensions.take(),
+                traffic: client.traffic,
+                resilience: client.resilience,
+                extensions: client.extensions,
+                interarrival: std::mem::take(&mut client.interarrival),
+                ..Default::default()
+            };
+        }
+        *self = Self {
+            started_at: Instant::now(),
+            warmup: Duration::ZERO,
+            clients: std::mem::take(&mut self.clients),
+            resources: std::mem::take(&mut self.resources),
+            growth: {
+                let mut growth = std::mem::take(&mut self.growth);
+                growth.take_warnings();
+                growth
+            },
+            collector: std::mem::replace(&mut self.collector, Collector::with_history(0)),
+            client_timelines: self.client_timelines.as_ref().map(|_| BTreeMap::new()),
+            ab: self.ab,
+            expectation: self.expectation,
+            ..Self::with_warmup(Duration::ZERO)
+        };
+        window
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Samples of a gate metric so far, per join timings or per client values
+    pub fn distribution(&self, metric: GateMetric) -> Distribution {
+        let avg = |f: fn(&ClientTotals) -> f64| {
+            let sampled = self.clients.values().filter(|c| c.samples > 0);
+            Distribution::Values(sampled.map(|c| f(c) / c.samples as f64).collect())
+        };
+        let phase = |f: fn(&ConnectPhases) -> Option<u32>| {
+            Distribution::Values(
+                self.clients
+                    .values()
+                    .filter_map(|c| f(&c.phases))
+                    .map(|v| v as f64)
+                    .collect(),
+            )
+        };
+        let flag = |f: fn(&ClientTotals) -> bool| {
+            Distribution::Values(self.clients.values().map(|c| f(c) as u8 as f64).collect())
+        };
+        match metric {
+            GateMetric::ConnectMs => Distribution::Sketch(self.connect_ms.clone()),
+            GateMetric::RecoverMs => Distribution::Sketch(self.recover_ms.clone()),
+            GateMetric::DnsMs => Distribution::Sketch(self.dns_ms.clone()),
+            GateMetric::PostTtfbMs => Distribution::Sketch(self.post_ttfb_ms.clone()),
+            GateMetric::PostMs => Distribution::Sketch(self.post_ms.clone()),
+            GateMetric::DeleteMs => Distribution::Sketch(self.delete_ms.clone()),
+            GateMetric::QueueMs => Distribution::Sketch(self.queue_ms.clone()),
+            GateMetric::SignalingMs => phase(|p| p.signaling_ms),
+            GateMetric::IceMs => phase(|p| p.ice_ms),
+            GateMetric::DtlsMs => phase(|p| p.dtls_ms),
+            GateMetric::RecvKbps => avg(|c| c.recv_kbps as f64),
+            GateMetric::PayloadKbps => avg(|c| c.payload_kbps as f64),
+            GateMetric::RttMs => avg(|c| c.rtt_ms as f64),
+            GateMetric::Loss => avg(|c| c.loss),
+            GateMetric::Mos => Distribution::Values(
+                self.clients
+                    .values()
+                    .filter(|c| c.mos_samples > 0)
+                    .map(|c| c.mos / c.mos_samples as f64)
+                    .collect(),
+            ),
+            GateMetric::Failed => flag(|c| c.failed.is_some()),
+            GateMetric::Connected => flag(|c| c.connected_at.is_some()),
+        }
+    }
+
+    fn whep_extension_counts(&self) -> BTreeMap<String, usize> {
+        self.clients
+            .values()
+            .filter_map(|c| c.whep_extensions.clone())
+            .fold(BTreeMap::new(), |mut counts, extensions| {
+                *counts.entry(extensions).or_default() += 1;
+                counts
+            })
+    }
+
+    /// Clients whose advertisement differs from the most common one
+    fn whep_extension_outliers(&self) -> Vec<usize> {
+        let counts = self.whep_extension_counts();
+        let Some((common, _)) = counts.iter().max_by_key(|(_, count)| **count) else {
+            return vec![];
+        };
+        let mut ids: Vec<usize> = self
+            .clients
+            .iter()
+            .filter(|(_, c)| c.whep_extensions.as_ref().map_or(false, |e| e != common))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    fn label_summaries(&self) -> BTreeMap<String, LabelSummary> {
+        let mut cohorts: BTreeMap<&str, Vec<&ClientTotals>> = BTreeMap::new();
+        for client in self.clients.values() {
+            for label in &client.labels {
+                cohorts.entry(label).or_default().push(client);
+            }
+        }
+        cohorts
+            .into_iter()
+            .map(|(label, clients)| {
+                let sampled: Vec<_> = clients.iter().filter(|c| c.samples > 0).collect();
+                let avg = |f: fn(&ClientTotals) -> f64| {
+                    Percentiles::from_values(
+                        sampled.iter().map(|c| f(c) / c.samples as f64).collect(),
+                    )
+                };
+                let summary = LabelSummary {
+                    clients: clients.len(),
+                    connected: clients.iter().filter(|c| c.connected_at.is_some()).count(),
+                    failed: clients.iter().filter(|c| c.failed.is_some()).count(),
+                    connect_ms: Percentiles::from_values(
+                        clients
+                            .iter()
+                            .filter_map(|c| c.first_connect_ms)
+                            .map(|ms| ms as f64)
+                            .collect(),
+                    ),
+                    recv_kbps: avg(|c| c.recv_kbps as f64),
+                    rtt_ms: avg(|c| c.rtt_ms as f64),
+                    loss: avg(|c| c.loss),
+                };
+                (label.to_string(), summary)
+            })
+            .collect()
+    }
+
+    fn ab_metrics(&self) -> Vec<AbMetric> {
+        if !self.ab {
+            return vec![];
+        }
+        let side = |label: &str, f: fn(&ClientTotals) -> Option<f64>| -> Vec<f64> {
+            self.clients
+                .values()
+                .filter(|c| c.labels.iter().any(|l| l == label))
+                .filter_map(f)
+                .collect()
+        };
+        fn avg(total: f64, c: &ClientTotals) -> Option<f64> {
+            (c.samples > 0).then(|| total / c.samples as f64)
+        }
+        let metrics: [(&str, fn(&ClientTotals) -> Option<f64>); 4] = [
+            ("connect ms", |c| c.first_connect_ms.map(|ms| ms as f64)),
+            ("recv kbps", |c| avg(c.recv_kbps as f64, c)),
+            ("rtt ms", |c| avg(c.rtt_ms as f64, c)),
+            ("loss", |c| avg(c.loss, c)),
+        ];
+        metrics
+            .iter()
+            .map(|(name, f)| AbMetric::new(name, &side(ab::LABEL_A, *f), &side(ab::LABEL_B, *f)))
+            .collect()
+    }
+
+    pub fn summary(&self) -> RunSummary {
+        let sampled: Vec<&ClientTotals> = self.clients.values().filter(|c| c.samples > 0).collect();
+        let avg = |f: fn(&ClientTotals) -> f64| -> Vec<f64> {
+            sampled.iter().map(|c| f(c) / c.samples as f64).collect()
+        };
+        let phase = |f: fn(&ConnectPhases) -> Option<u32>| -> Percentiles {
+            Percentiles::from_values(
+                self.clients
+                    .values()
+                    .filter_map(|c| f(&c.phases))
+                    .map(|v| v as f64)
+                    .collect(),
+            )
+        };
+        RunSummary {
+            duration_ms: self.elapsed().as_millis() as u64,
+            warmup_ms: self.warmup.as_millis() as u64,
+            clients: self.clients.len(),
+            connected: self
+                .clients
+                .values()
+                .filter(|c| c.connected_at.is_some())
+                .count(),
+            failed: self.clients.values().filter(|c| c.failed.is_some()).count(),
+            failures: self.clients.values().filter_map(|c| c.failed).fold(
+                BTreeMap::new(),
+                |mut failures, category| {
+                    *failures.entry(category).or_default() += 1;
+                    failures
+                },
+            ),
+            auth_challenges: self
+                .clients
+                .values()
+                .filter_map(|c| c.auth_challenge.clone())
+                .fold(BTreeMap::new(), |mut challenges, challenge| {
+                    *challenges.entry(challenge).or_default() += 1;
+                    challenges
+                }),
+            joins: self.clients.values().map(|c| c.joins).sum(),
+            starved: self.clients.values().flat_map(|c| c.starved.iter()).fold(
+                BTreeMap::new(),
+                |mut starved, kind| {
+                    *starved.entry(*kind).or_default() += 1;
+                    starved
+                },
+            ),
+            throttled: self.throttled,
+            layer_switches: self.layer_switches,
+            keepalives: self.keepalives,
+            keepalive_failures: self.keepalive_failures,
+            sessions_cleaned: self.sessions_cleaned,
+            sessions_leaked: self.sessions_leaked,
+            shutdown: None,
+            faults: self.faults,
+            recover_ms: self.recover_ms.percentiles(),
+            keyframe_requests: self.keyframe_ms.count() as usize,
+            keyframe_ms: self.keyframe_ms.percentiles(),
+            dns_ms: self.dns_ms.percentiles(),
+            dns_cached_ms: self.dns_cached_ms.percentiles(),
+            post_ttfb_ms: self.post_ttfb_ms.percentiles(),
+            post_ms: self.post_ms.percentiles(),
+            delete_ms: self.delete_ms.percentiles(),
+            http_versions: self.http_versions.clone(),
+            dns_cached: self.dns_cached_ms.count(),
+            edges: self.clients.values().filter_map(|c| c.edge.clone()).fold(
+                BTreeMap::new(),
+                |mut edges, edge| {
+                    *edges.entry(edge).or_default() += 1;
+                    edges
+                },
+            ),
+            candidate_pairs: self
+                .clients
+                .values()
+                .filter_map(|c| c.candidate_pair.as_ref().map(CandidatePair::kind))
+                .fold(BTreeMap::new(), |mut pairs, pair| {
+                    *pairs.entry(pair).or_default() += 1;
+                    pairs
+                }),
+            ice: self
+                .clients
+                .values()
+                .fold(IceBreakdown::default(), |mut ice, c| {
+                    ice.add(c.ice_role, c.candidate_pair.as_ref());
+                    ice
+                }),
+            resolutions: self.clients.values().filter_map(|c| c.resolution).fold(
+                BTreeMap::new(),
+                |mut resolutions, resolution| {
+                    *resolutions.entry(resolution.to_string()).or_default() += 1;
+                    resolutions
+                },
+            ),
+            resolution_changes: self.clients.values().map(|c| c.resolution_changes).sum(),
+            downscaled: self.clients.values().filter(|c| c.downscaled).count(),
+            playout_delays: self.clients.values().filter_map(|c| c.playout_delay).fold(
+                BTreeMap::new(),
+                |mut delays, delay| {
+                    *delays.entry(delay.to_string()).or_default() += 1;
+                    delays
+                },
+            ),
+            playout_delay_changes: self.clients.values().map(|c| c.playout_delay_changes).sum(),
+            connect_ms: self.connect_ms.percentiles(),
+            queue_ms: self.queue_ms.percentiles(),
+            signaling_ms: phase(|p| p.signaling_ms),
+            ice_ms: phase(|p| p.ice_ms),
+            dtls_ms: phase(|p| p.dtls_ms),
+            recv_kbps: Percentiles::from_values(avg(|c| c.recv_kbps as f64)),
+            payload_kbps: Percentiles::from_values(avg(|c| c.payload_kbps as f64)),
+            rtt_ms: Percentiles::from_values(avg(|c| c.rtt_ms as f64)),
+            loss: Percentiles::from_values(avg(|c| c.loss)),
+            audio_pps: Percentiles::from_values(avg(|c| c.audio_pps as f64)),
+            video_pps: Percentiles::from_values(avg(|c| c.video_pps as f64)),
+            video_fps: Percentiles::from_values(avg(|c| c.video_fps)),
+            mos: Percentiles::from_values(
+                self.clients
+                    .values()
+                    .filter(|c| c.mos_samples > 0)
+                    .map(|c| c.mos / c.mos_samples as f64)
+                    .collect(),
+            ),
+            audio_dbov: Percentiles::from_values(
+                self.clients
+                    .values()
+                    .filter_map(|c| Some(c.audio_level?.avg_dbov as f64))
+                    .collect(),
+            ),
+            audio_silence: Percentiles::from_values(
+                self.clients
+                    .values()
+                    .filter_map(|c| Some(c.audio_level?.silence_ratio as f64))
+                    .collect(),
+            ),
+            audio_dtx_secs: Percentiles::from_values(
+                self.clients
+                    .values()
+                    .filter_map(|c| Some(c.silence?.dtx_ms as f64 / 1000.0))
+                    .collect(),
+            ),
+            audio_gap_secs: Percentiles::from_values(
+                self.clients
+                    .values()
+                    .filter_map(|c| Some(c.silence?.gap_ms as f64 / 1000.0))
+                    .collect(),
+            ),
+            media_clock_ppm: Percentiles::from_values(
+                self.clients
+                    .values()
+                    .filter_map(|c| Some(c.clock_skew?.media_ppm as f64))
+                    .collect(),
+            ),
+            skewed_clients: self
+                .clients
+                .values()
+                .filter(|c| c.clock_skew.map_or(false, |skew| skew.flagged()))
+                .count(),
+            traffic: self
+                .clients
+                .values()
+                .fold(self.ended_traffic, |mut sum, c| {
+                    sum.add(&c.traffic);
+                    sum
+                }),
+            resilience: self
+                .clients
+                .values()
+                .fold(self.ended_resilience, |mut sum, c| {
+                    sum.add(&c.resilience);
+                    sum
+                }),
+            extensions: self
+                .clients
+                .values()
+                .fold(self.ended_extensions, |mut sum, c| {
+                    sum.add(&c.extensions);
+                    sum
+                }),
+            extension_clients: self.clients.values().flat_map(|c| &c.extensions_seen).fold(
+                BTreeMap::new(),
+                |mut clients, name| {
+                    *clients.entry(name.to_string()).or_default() += 1;
+                    clients
+                },
+            ),
+            interarrival: self.clients.values().fold(
+                self.ended_interarrival.clone(),
+                |mut sum, c| {
+                    sum.add(&c.interarrival);
+                    sum
+                },
+            ),
+            aborted: None,
+            stopped: None,
+            resources: self.resources.summary(),
+            growth: self.growth.warnings().to_vec(),
+            answer_profiles: self
+                .clients
+                .values()
+                .filter_map(|c| c.answer_profile.clone())
+                .fold(BTreeMap::new(), |mut profiles, profile| {
+                    *profiles.entry(profile).or_default() += 1;
+                    profiles
+                }),
+            answer_divergences: consistency::divergences(
+                self.clients
+                    .iter()
+                    .filter_map(|(id, c)| Some((*id, c.answer_parameters.as_ref()?))),
+            ),
+            conformance: self.expectation.map(|expectation| ConformanceSummary {
+                expected: expectation.to_string(),
+                clients: self
+                    .clients
+                    .iter()
+                    .filter(|(_, c)| c.samples > 0)
+                    .map(|(id, c)| (*id, c.conformance.conformance(&expectation)))
+                    .collect(),
+            }),
+            whep_extensions: self.whep_extension_counts(),
+            whep_extension_outliers: self.whep_extension_outliers(),
+            labels: self.label_summaries(),
+            ab: self.ab_metrics(),
+            ramp: vec![],
+            sweep: vec![],
+            soak: vec![],
+            plugins: BTreeMap::new(),
+            timeline: self.timeline.clone(),
+            client_timelines: self.client_timelines.clone().unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Egress saturation sweep, raises the client count in plateaus and measures the
+//! aggregate bitrate delivered on each until adding clients stops adding throughput.
+//! The plateaus make up the capacity curve of the server
+
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use async_std::channel::{Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bench::{BenchControl, BenchEvent},
+    collector::Collector,
+    watchdog::parse_duration,
+};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `start=50,step=50,hold=120s`, optionally with `max=<clients>` and
+/// `scaling=<0-1>`, the share of the linear throughput gain a step has to reach
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SweepPlan {
+    pub start: usize,
+    pub step: usize,
+    /// Time on each plateau, the first half lets it settle and is not measured
+    pub hold: Duration,
+    pub max: Option<usize>,
+    pub scaling: f64,
+}
+
+impl FromStr for SweepPlan {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut plan = SweepPlan {
+            start: 0,
+            step: 0,
+            hold: Duration::ZERO,
+            max: None,
+            scaling: 0.5,
+        };
+        let invalid = |part: &str| format!("invalid sweep parameter {}", part);
+        for part in s.split(',') {
+            let (key, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+            match key.trim() {
+                "start" => plan.start = value.parse().map_err(|_| invalid(part))?,
+                "step" => plan.step = value.parse().map_err(|_| invalid(part))?,
+                "hold" => plan.hold = parse_duration(value).ok_or_else(|| invalid(part))?,
+                "max" => plan.max = Some(value.parse().map_err(|_| invalid(part))?),
+                "scaling" => plan.scaling = value.parse().map_err(|_| invalid(part))?,
+                _ => return Err(invalid(part)),
+            }
+        }
+        if plan.start == 0 || plan.step == 0 || plan.hold.is_zero() {
+            return Err(format!(
+                "invalid sweep {}, expected e.g. `start=50,step=50,hold=120s`",
+                s
+            ));
+        }
+        Ok(plan)
+    }
+}
+
+/// Measured second half of one plateau
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plateau {
+    pub clients: usize,
+    /// Average connected clients
+    pub connected: f64,
+    /// Average aggregate received bitrate
+    pub recv_kbps: f64,
+    /// Throughput gain over the previous plateau relative to a linear gain, None
+    /// for the first plateau
+    pub scaling: Option<f64>,
+}
+
+/// Capacity curve of the plateaus, printable with `{}`
+pub struct CapacityCurve<'a>(pub &'a [Plateau]);
+
+impl fmt::Display for CapacityCurve<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>8}{:>11}{:>12}{:>14}{:>9}",
+            "clients", "connected", "recv mbps", "kbps/client", "scaling"
+        )?;
+        for p in self.0 {
+            let per_client = if p.connected > 0.0 {
+                p.recv_kbps / p.connected
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "{:>8}{:>11.1}{:>12.1}{:>14.0}{:>9}",
+                p.clients,
+                p.connected,
+                p.recv_kbps / 1000.0,
+                per_client,
+                p.scaling.map_or("-".to_string(), |s| format!("{:.2}", s))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Feed events into the collector until `deadline`, sampling the aggregates each
+/// second into `samples`. False once the event channel closed
+async fn follow(
+    collector: &mut Collector,
+    event_rx: &Receiver<BenchEvent>,
+    deadline: Instant,
+    mut samples: Option<&mut Vec<(usize, u64)>>,
+) -> bool {
+    let mut next_sample = Instant::now() + SAMPLE_INTERVAL;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        if now >= next_sample {
+            next_sample = now + SAMPLE_INTERVAL;
+            if let Some(samples) = samples.as_mut() {
+                samples.push((collector.clients_connected(), collector.sum_recv_kbps()));
+            }
+        }
+        let wait = next_sample.min(deadline).saturating_duration_since(now);
+        match async_std::future::timeout(wait, event_rx.recv()).await {
+            Ok(Ok(event)) => collector.on_event(event),
+            Ok(Err(_)) => return false,
+            Err(_) => {}
+        }
+    }
+}
+
+/// Drive the runner through the plateaus with spawn commands, one client each
+/// `interval`, then stop it. Returns the plateaus measured so far when the run
+/// ended first
+pub async fn run_sweep(
+    plan: SweepPlan,
+    interval: Duration,
+    control_tx: Sender<BenchControl>,
+    event_rx: Receiver<BenchEvent>,
+) -> Vec<Plateau> {
+    let mut collector = Collector::with_history(0);
+    let mut plateaus: Vec<Plateau> = vec![];
+    // the runner starts the first plateau from its plan
+    let mut clients = plan.start;
+    let mut ramp = interval * plan.start as u32;
+    loop {
+        let settled = Instant::now() + std::mem::take(&mut ramp) + plan.hold / 2;
+        if !follow(&mut collector, &event_rx, settled, None).await {
+            return plateaus;
+        }
+        let mut samples = vec![];
+        let end = settled + plan.hold / 2;
+        if !follow(&mut collector, &event_rx, end, Some(&mut samples)).await {
+            return plateaus;
+        }
+        let count = samples.len().max(1) as f64;
+        let connected = samples.iter().map(|(c, _)| *c as f64).sum::<f64>() / count;
+        let recv_kbps = samples.iter().map(|(_, kbps)| *kbps as f64).sum::<f64>() / count;
+        let scaling = plateaus.last().and_then(|prev| {
+            let linear = prev.recv_kbps * clients as f64 / prev.clients as f64 - prev.recv_kbps;
+            (linear > 0.0).then(|| (recv_kbps - prev.recv_kbps) / linear)
+        });
+        log::info!(
+            "[Sweep] {} clients, {:.1} connected, {:.1} mbps, scaling {:?}",
+            clients,
+            connected,
+            recv_kbps / 1000.0,
+            scaling
+        );
+        plateaus.push(Plateau {
+            clients,
+            connected,
+            recv_kbps,
+            scaling,
+        });
+        let saturated = scaling.map_or(false, |s| s < plan.scaling);
+        let next = clients + plan.step;
+        if saturated || plan.max.map_or(false, |max| next > max) {
+            log::info!(
+                "[Sweep] {} at {} clients, stopping",
+                if saturated {
+                    "saturated"
+                } else {
+                    "reached max"
+                },
+                clients
+            );
+            let _ = control_tx.send(BenchControl::Stop).await;
+            return plateaus;
+        }
+        for _ in 0..plan.step {
+            if control_tx.send(BenchControl::Spawn(1)).await.is_err() {
+                return plateaus;
+            }
+            let deadline = Instant::now() + interval;
+            if !follow(&mut collector, &event_rx, deadline, None).await {
+                return plateaus;
+            }
+        }
+        clients = next;
+    }
+}
@@ -0,0 +1,338 @@
+//! Session teardown guarantee. A client holds a [`SessionGuard`] from the moment
+//! the server created its session. Dropping the guard without a DELETE, after a
+//! panic, an error or a stuck client, retries the DELETE in the background. Open
+//! sessions are kept in a process wide registry, the runner settles them at
+//! shutdown and reports the ones cleaned up this way or leaked
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use reqwest::header::{ETAG, IF_MATCH};
+use serde::{Deserialize, Serialize};
+
+use crate::{growth, rt};
+
+/// DELETE attempts before a session counts as leaked
+pub const DELETE_ATTEMPTS: u32 = 3;
+pub const RETRY_DELAY: Duration = Duration::from_millis(500);
+const SETTLE_POLL: Duration = Duration::from_millis(100);
+/// Check interval of a DELETE waiting for a free slot
+const PERMIT_POLL: Duration = Duration::from_millis(10);
+
+/// How a session which did not end with a plain DELETE was torn down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Teardown {
+    /// Deleted by the guard after the client ended without DELETE
+    Cleaned,
+    /// Every DELETE attempt failed, or none completed before shutdown
+    Leaked,
+}
+
+struct Registry {
+    next_id: u64,
+    /// Client id of each open session
+    open: BTreeMap<u64, usize>,
+    outcomes: Vec<(usize, Teardown)>,
+}
+
+static REGISTRY: Mutex<Registry> = parking_lot::const_mutex(Registry {
+    next_id: 0,
+    open: BTreeMap::new(),
+    outcomes: Vec::new(),
+});
+
+fn close(id: u64, outcome: Option<Teardown>) {
+    let mut registry = REGISTRY.lock();
+    if let (Some(client_id), Some(outcome)) = (registry.open.remove(&id), outcome) {
+        registry.outcomes.push((client_id, outcome));
+    }
+    growth::gauge("sessions.open", registry.open.len() as u64);
+}
+
+/// Bounds of the DELETEs sent while the runner shuts down, firing thousands at
+/// once knocks over the signaling plane
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DeleteLimits {
+    /// DELETEs in flight at once, 0 is unbounded
+    pub concurrency: usize,
+    /// DELETEs started per second
+    pub per_sec: Option<f64>,
+}
+
+impl DeleteLimits {
+    /// Time `sessions` DELETEs take at least at the rate
+    pub fn min_duration(&self, sessions: usize) -> Duration {
+        self.per_sec
+            .filter(|rate| *rate > 0.0)
+            .map_or(Duration::ZERO, |rate| {
+                Duration::from_secs_f64(sessions as f64 / rate)
+            })
+    }
+}
+
+impl fmt::Display for DeleteLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.concurrency, self.per_sec) {
+            (0, Some(rate)) => write!(f, "{}/s", rate),
+            (concurrency, None) => write!(f, "{} in flight", concurrency),
+            (concurrency, Some(rate)) => write!(f, "{} in flight, {}/s", concurrency, rate),
+        }
+    }
+}
+
+/// How long the shutdown took until every session was torn down or counted as leaked
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    pub teardown_ms: u64,
+    /// DELETE requests sent during the shutdown, retries included
+    pub deletes: u64,
+    pub limits: Option<DeleteLimits>,
+}
+
+impl fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "teardown: {} deletes in {:.1}s",
+            self.deletes,
+            self.teardown_ms as f64 / 1000.0
+        )?;
+        if let Some(limits) = &self.limits {
+            write!(f, " (limited to {})", limits)?;
+        }
+        Ok(())
+    }
+}
+
+struct Gate {
+    limits: Option<DeleteLimits>,
+    in_flight: usize,
+    next_at: Option<Instant>,
+    /// DELETEs since the limits were set
+    sent: u64,
+}
+
+static GATE: Mutex<Gate> = parking_lot::const_mutex(Gate {
+    limits: None,
+    in_flight: 0,
+    next_at: None,
+    sent: 0,
+});
+
+/// Bound the DELETEs from now on and count them from zero, None lifts the bounds
+pub fn limit_deletes(limits: Option<DeleteLimits>) {
+    let mut gate = GATE.lock();
+    gate.limits = limits;
+    gate.next_at = None;
+    gate.sent = 0;
+}
+
+/// DELETEs since the limits were last set
+pub fn deletes_sent() -> u64 {
+    GATE.lock().sent
+}
+
+/// Held while a DELETE is in flight
+pub struct DeletePermit {
+    counted: bool,
+}
+
+impl Drop for DeletePermit {
+    fn drop(&mut self) {
+        if self.counted {
+            let mut gate = GATE.lock();
+            gate.in_flight = gate.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+/// Wait until the limits allow another DELETE
+pub async fn delete_permit() -> DeletePermit {
+    loop {
+        let wait = {
+            let mut gate = GATE.lock();
+            let Some(limits) = gate.limits else {
+                gate.sent += 1;
+                return DeletePermit { counted: false };
+            };
+            let now = Instant::now();
+            let full = limits.concurrency > 0 && gate.in_flight >= limits.concurrency;
+            match gate.next_at.filter(|at| *at > now) {
+                Some(at) => at - now,
+                None if full => PERMIT_POLL,
+                None => {
+                    gate.in_flight += 1;
+                    gate.sent += 1;
+                    if let Some(rate) = limits.per_sec.filter(|rate| *rate > 0.0) {
+                        gate.next_at = Some(now + Duration::from_secs_f64(1.0 / rate));
+                    }
+                    return DeletePermit { counted: true };
+                }
+            }
+        };
+        rt::sleep(wait).await;
+    }
+}
+
+/// Whether a DELETE answer means the session is gone
+pub fn is_gone(status: u16) -> bool {
+    (200..300).contains(&status) || status == 404
+}
+
+/// Whether a DELETE answer is worth another attempt
+pub fn is_retryable(status: u16) -> bool {
+    status == 429 || status >= 500
+}
+
+async fn delete_with_retry(
+    http: &reqwest::Client,
+    location: &str,
+    mut etag: Option<String>,
+) -> bool {
+    for attempt in 1..=DELETE_ATTEMPTS {
+        let mut req = http.delete(location);
+        if let Some(etag) = &etag {
+            req = req.header(IF_MATCH, etag);
+        }
+        let permit = delete_permit().await;
+        let res = req.send().await;
+        drop(permit);
+        match res {
+            Ok(res) if is_gone(res.status().as_u16()) => return true,
+            // stale etag, retry with the current one or unconditionally
+            Ok(res) if res.status().as_u16() == 412 => {
+                etag = res
+                    .headers()
+                    .get(ETAG)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string);
+                continue;
+            }
+            Ok(res) if !is_retryable(res.status().as_u16()) => {
+                log::warn!("[Teardown] delete {} answered {}", location, res.status());
+                return false;
+            }
+            Ok(res) => log::debug!("[Teardown] delete {} answered {}", location, res.status()),
+            Err(e) => log::debug!("[Teardown] delete {} failed {:?}", location, e),
+        }
+        if attempt < DELETE_ATTEMPTS {
+            rt::sleep(RETRY_DELAY).await;
+        }
+    }
+    false
+}
+
+/// Open session of a client, deleted in the background when dropped while armed
+pub struct SessionGuard {
+    id: u64,
+    http: reqwest::Client,
+    location: Option<String>,
+    /// ETag of the session resource, sent as If-Match on updates and DELETE
+    etag: Option<String>,
+}
+
+impl SessionGuard {
+    pub fn new(
+        client_id: usize,
+        http: reqwest::Client,
+        location: String,
+        etag: Option<String>,
+    ) -> Self {
+        let mut registry = REGISTRY.lock();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.open.insert(id, client_id);
+        growth::gauge("sessions.open", registry.open.len() as u64);
+        Self {
+            id,
+            http,
+            location: Some(location),
+            etag,
+        }
+    }
+
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    pub fn set_etag(&mut self, etag: Option<String>) {
+        self.etag = etag;
+    }
+
+    pub fn location(&self) -> &str {
+        self.location.as_deref().expect("should be armed")
+    }
+
+    /// The session was deleted
+    pub fn release(mut self) {
+        self.location = None;
+        close(self.id, None);
+    }
+
+    /// Drop the session without DELETE on purpose, the server has to reap it
+    pub fn abandon(mut self) {
+        self.location = None;
+        close(self.id, None);
+    }
+
+    /// The DELETE failed every attempt
+    pub fn leak(mut self) {
+        if let Some(location) = self.location.take() {
+            log::warn!("[Teardown] session {} leaked", location);
+        }
+        close(self.id, Some(Teardown::Leaked));
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let Some(location) = self.location.take() else {
+            return;
+        };
+        log::debug!(
+            "[Teardown] client ended without delete, deleting {}",
+            location
+        );
+        let http = self.http.clone();
+        let etag = self.etag.take();
+        let id = self.id;
+        rt::spawn(async move {
+            let outcome = if delete_with_retry(&http, &location, etag).await {
+                Teardown::Cleaned
+            } else {
+                log::warn!("[Teardown] session {} leaked", location);
+                Teardown::Leaked
+            };
+            close(id, Some(outcome));
+        });
+    }
+}
+
+/// Outcomes since the previous call, by client id
+pub fn take_outcomes() -> Vec<(usize, Teardown)> {
+    std::mem::take(&mut REGISTRY.lock().outcomes)
+}
+
+/// Wait up to `timeout` for the open sessions to be torn down, the ones still
+/// open then count as leaked. Returns all outcomes not taken yet
+pub async fn settle(timeout: Duration) -> Vec<(usize, Teardown)> {
+    let deadline = Instant::now() + timeout;
+    while !REGISTRY.lock().open.is_empty() && Instant::now() < deadline {
+        rt::sleep(SETTLE_POLL).await;
+    }
+    let mut registry = REGISTRY.lock();
+    let open = std::mem::take(&mut registry.open);
+    if !open.is_empty() {
+        log::warn!("[Teardown] {} sessions still open at shutdown", open.len());
+    }
+    let mut outcomes = std::mem::take(&mut registry.outcomes);
+    outcomes.extend(
+        open.into_values()
+            .map(|client_id| (client_id, Teardown::Leaked)),
+    );
+    outcomes
+}
@@ -0,0 +1,108 @@
+//! Timeline of what each client went through, from its creation over signaling
+//! and its first media to stalls and the disconnect, for reconstructing a single
+//! client after the run. Media milestones are marked by the client and arrive
+//! with its stats, the others are taken from the events as they pass
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{bench::BenchEvent, whep::HttpMethod};
+
+/// Media pauses at least this long count as a stall
+const STALL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Milestone {
+    Created,
+    OfferSent,
+    AnswerReceived,
+    Connected,
+    FirstRtp,
+    /// Media paused, the entry is at the start of the pause
+    Stall,
+    Reconnecting,
+    Failed,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    /// Unix time in milliseconds
+    pub at_ms: u64,
+    pub milestone: Milestone,
+    /// Length of a stall, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+impl TimelineEntry {
+    fn new(at_ms: u64, milestone: Milestone) -> Self {
+        Self {
+            at_ms,
+            milestone,
+            duration_ms: None,
+        }
+    }
+}
+
+pub fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Media milestones of a session, the first packet and the stalls
+#[derive(Debug, Default)]
+pub struct MediaTimeline {
+    last_media: Option<Instant>,
+    /// Entries since the previous stats
+    entries: Vec<TimelineEntry>,
+}
+
+impl MediaTimeline {
+    pub fn on_media(&mut self, now: Instant) {
+        match self.last_media {
+            None => self
+                .entries
+                .push(TimelineEntry::new(unix_ms(), Milestone::FirstRtp)),
+            Some(last) if now.saturating_duration_since(last) >= STALL => {
+                let pause = now - last;
+                self.entries.push(TimelineEntry {
+                    at_ms: unix_ms().saturating_sub(pause.as_millis() as u64),
+                    milestone: Milestone::Stall,
+                    duration_ms: Some(pause.as_millis() as u64),
+                });
+            }
+            Some(_) => {}
+        }
+        self.last_media = Some(now);
+    }
+
+    pub fn take_entries(&mut self) -> Vec<TimelineEntry> {
+        std::mem::take(&mut self.entries)
+    }
+}
+
+/// Entries an event adds to the timeline of its client, `now_ms` is when it passed
+pub fn entries_of(event: &BenchEvent, now_ms: u64) -> Vec<TimelineEntry> {
+    let entry = |milestone| vec![TimelineEntry::new(now_ms, milestone)];
+    match event {
+        BenchEvent::Connecting(_) => entry(Milestone::Created),
+        BenchEvent::Connected(_) => entry(Milestone::Connected),
+        BenchEvent::Reconnecting(..) => entry(Milestone::Reconnecting),
+        BenchEvent::Failed(..) => entry(Milestone::Failed),
+        BenchEvent::Disconnected(_) => entry(Milestone::Disconnected),
+        // the offer went out when the answer came back less the request time
+        BenchEvent::Http(_, timing) if timing.method == HttpMethod::Post => vec![
+            TimelineEntry::new(
+                now_ms.saturating_sub(timing.total_ms as u64),
+                Milestone::OfferSent,
+            ),
+            TimelineEntry::new(now_ms, Milestone::AnswerReceived),
+        ],
+        BenchEvent::Stats(_, stats) => stats.timeline.clone(),
+        _ => vec![],
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+/// Received bytes split by what they carried, cumulative over the session
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct TrafficStats {
+    /// Everything received on the socket, udp payloads without ip/udp headers
+    pub wire_bytes: u64,
+    /// Media payload of original rtp packets, the goodput
+    pub payload_bytes: u64,
+    /// Rtp headers, padding and SRTP tags of original packets
+    pub rtp_overhead_bytes: u64,
+    /// Whole retransmitted rtp packets
+    pub rtx_bytes: u64,
+    pub rtcp_bytes: u64,
+    pub stun_bytes: u64,
+    pub dtls_bytes: u64,
+    /// Goodput since previous stats
+    pub payload_kbps: u64,
+}
+
+impl TrafficStats {
+    /// Share of the wire bytes which was media payload
+    pub fn goodput_ratio(&self) -> Option<f64> {
+        (self.wire_bytes > 0).then(|| self.payload_bytes as f64 / self.wire_bytes as f64)
+    }
+
+    pub fn add(&mut self, other: &TrafficStats) {
+        self.wire_bytes += other.wire_bytes;
+        self.payload_bytes += other.payload_bytes;
+        self.rtp_overhead_bytes += other.rtp_overhead_bytes;
+        self.rtx_bytes += other.rtx_bytes;
+        self.rtcp_bytes += other.rtcp_bytes;
+        self.stun_bytes += other.stun_bytes;
+        self.dtls_bytes += other.dtls_bytes;
+        self.payload_kbps += other.payload_kbps;
+    }
+}
+
+/// Counts received datagrams by protocol, demultiplexed by their first byte as
+/// in RFC 7983, and decrypted rtp packets by payload and retransmission
+#[derive(Debug, Default)]
+pub struct TrafficCounter {
+    stats: TrafficStats,
+    /// Encrypted size of all rtp datagrams
+    rtp_wire_bytes: u64,
+    pre_payload_bytes: u64,
+}
+
+impl TrafficCounter {
+    pub fn on_datagram(&mut self, data: &[u8]) {
+        let len = data.len() as u64;
+        self.stats.wire_bytes += len;
+        match data.first() {
+            Some(0..=3) => self.stats.stun_bytes += len,
+            Some(20..=63) => self.stats.dtls_bytes += len,
+            // rtcp packet types 192-223 sit where rtp has marker bit and payload type
+            Some(128..=191) if matches!(data.get(1), Some(192..=223)) => {
+                self.stats.rtcp_bytes += len
+            }
+            Some(128..=191) => self.rtp_wire_bytes += len,
+            _ => {}
+        }
+    }
+
+    /// A decrypted rtp packet, `rtx` for retransmissions
+    pub fn on_rtp(&mut self, header_len: usize, payload_len: usize, rtx: bool) {
+        if rtx {
+            self.stats.rtx_bytes += (header_len + payload_len) as u64;
+        } else {
+            self.stats.payload_bytes += payload_len as u64;
+        }
+    }
+
+    pub fn stats(&mut self, duration_ms: u64) -> TrafficStats {
+        self.stats.rtp_overhead_bytes = self
+            .rtp_wire_bytes
+            .saturating_sub(self.stats.payload_bytes + self.stats.rtx_bytes);
+        if duration_ms > 0 {
+            self.stats.payload_kbps =
+                (self.stats.payload_bytes - self.pre_payload_bytes) * 8 / duration_ms;
+        }
+        self.pre_payload_bytes = self.stats.payload_bytes;
+        self.stats
+    }
+}
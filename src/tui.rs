@@ -1,202 +1,973 @@
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write as _,
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use async_std::{channel::Receiver, stream::StreamExt};
-use dioxus::prelude::*;
-use futures_util::{select, FutureExt};
+use async_std::channel::{Receiver, Sender, TryRecvError};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
 use parking_lot::RwLock;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Paragraph, Row, Sparkline, Table},
+};
 
-use crate::{bench::BenchEvent, whep::Stats};
+use crate::{
+    bench::{BenchControl, BenchEvent},
+    collector::{Client, ClientStatus, Collector, Health, HealthThresholds},
+    logbuf::LogBuffer,
+    whep::Stats,
+    whepext,
+};
 
-#[derive(Default)]
-pub struct Client {
-    id: usize,
-    connected: bool,
-    stats: Option<Stats>,
+const TICK: Duration = Duration::from_millis(300);
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Log lines shown in the bottom pane
+const LOG_LINES: usize = 5;
+/// Clients per group when grouping by id
+const GROUP_SIZE: usize = 100;
+/// How long the path of an exported snapshot stays in the footer
+const NOTICE_TIME: Duration = Duration::from_secs(5);
+const COLUMNS: [&str; 9] = [
+    "Client",
+    "State",
+    "Send kbps",
+    "Recv kbps",
+    "RTT ms",
+    "Lost",
+    "Up s",
+    "GOP ms",
+    "FPS",
+];
+const WIDTHS: [u16; 9] = [12, 11, 10, 10, 8, 8, 8, 8, 6];
+
+/// How rows collapse into groups once there are more clients than the group threshold
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Grouping {
+    #[default]
+    Id,
+    Health,
+    Off,
+}
+
+impl Grouping {
+    fn label(&self) -> &'static str {
+        match self {
+            Grouping::Id => "id",
+            Grouping::Health => "health",
+            Grouping::Off => "off",
+        }
+    }
+}
+
+/// A line of the client table, groups expand into their clients with Enter
+enum TableRow<'a> {
+    Group {
+        key: usize,
+        label: String,
+        health: Option<Health>,
+        clients: Vec<&'a Client>,
+    },
+    Client(&'a Client),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum SortColumn {
+    #[default]
+    Id,
+    RecvKbps,
+    Rtt,
+    Lost,
+    Uptime,
+}
+
+impl SortColumn {
+    fn label(&self) -> &'static str {
+        match self {
+            SortColumn::Id => "id",
+            SortColumn::RecvKbps => "recv kbps",
+            SortColumn::Rtt => "rtt",
+            SortColumn::Lost => "loss",
+            SortColumn::Uptime => "uptime",
+        }
+    }
+
+    fn compare(&self, a: &Client, b: &Client) -> std::cmp::Ordering {
+        let (sa, sb) = (a.stats.as_ref(), b.stats.as_ref());
+        match self {
+            SortColumn::Id => a.id.cmp(&b.id),
+            SortColumn::RecvKbps => sa.map(|s| s.recv_kbps).cmp(&sb.map(|s| s.recv_kbps)),
+            SortColumn::Rtt => sa.map(|s| s.rtt_ms).cmp(&sb.map(|s| s.rtt_ms)),
+            SortColumn::Lost => sa
+                .map(|s| s.lost)
+                .partial_cmp(&sb.map(|s| s.lost))
+                .unwrap_or(std::cmp::Ordering::Equal),
+            SortColumn::Uptime => sa.map(|s| s.live_ms).cmp(&sb.map(|s| s.live_ms)),
+        }
+        .then(a.id.cmp(&b.id))
+    }
 }
 
+/// Scroll position, selection, ordering and filtering of the client table
 #[derive(Default)]
-struct AppState {
-    clients: BTreeMap<usize, Client>,
+struct TableView {
+    offset: usize,
+    selected: usize,
+    sort: SortColumn,
+    descending: bool,
+    filter: Option<ClientStatus>,
+    /// Client shown in detail pane
+    detail: Option<usize>,
+    grouping: Grouping,
+    /// Keys of the groups showing their clients
+    expanded: HashSet<usize>,
 }
 
-impl AppState {
-    pub fn add_client(&mut self, id: usize) {
-        self.clients.insert(
-            id,
-            Client {
-                id,
-                ..Default::default()
-            },
-        );
+impl TableView {
+    /// Cycle status filter: all => connecting => connected => stalled => errored => all
+    fn next_filter(&mut self) {
+        self.filter = match self.filter {
+            None => Some(ClientStatus::Connecting),
+            Some(ClientStatus::Connecting) => Some(ClientStatus::Connected),
+            Some(ClientStatus::Connected) => Some(ClientStatus::Stalled),
+            Some(ClientStatus::Stalled) => Some(ClientStatus::Errored),
+            Some(ClientStatus::Errored) => None,
+        };
+        self.offset = 0;
+        self.selected = 0;
+    }
+
+    /// Cycle grouping: id => health => off => id
+    fn next_grouping(&mut self) {
+        self.grouping = match self.grouping {
+            Grouping::Id => Grouping::Health,
+            Grouping::Health => Grouping::Off,
+            Grouping::Off => Grouping::Id,
+        };
+        self.expanded.clear();
+        self.offset = 0;
+        self.selected = 0;
+    }
+
+    fn toggle_group(&mut self, key: usize) {
+        if !self.expanded.remove(&key) {
+            self.expanded.insert(key);
+        }
+    }
+
+    fn filter_label(&self) -> &'static str {
+        self.filter.map(|f| f.label()).unwrap_or("All")
     }
 
-    pub fn set_client_connected(&mut self, id: usize) {
-        if let Some(client) = self.clients.get_mut(&id) {
-            client.connected = true;
+    /// Select sort column, selecting the current column again flips the direction
+    fn sort_by(&mut self, column: SortColumn) {
+        if self.sort == column {
+            self.descending = !self.descending;
+        } else {
+            self.sort = column;
+            self.descending = false;
+        }
+    }
+
+    fn rows<'a>(&self, clients: impl Iterator<Item = &'a Client>) -> Vec<&'a Client> {
+        let mut rows: Vec<_> = clients
+            .filter(|c| self.filter.map(|f| c.status() == f).unwrap_or(true))
+            .collect();
+        rows.sort_by(|a, b| {
+            let ord = self.sort.compare(a, b);
+            if self.descending {
+                ord.reverse()
+            } else {
+                ord
+            }
+        });
+        rows
+    }
+
+    /// Sorted clients, collapsed into groups when there are more than `threshold`.
+    /// Clients keep their sort order within a group
+    fn table_rows<'a>(
+        &self,
+        clients: impl Iterator<Item = &'a Client>,
+        thresholds: &HealthThresholds,
+        threshold: usize,
+    ) -> Vec<TableRow<'a>> {
+        let clients = self.rows(clients);
+        if self.grouping == Grouping::Off || clients.len() <= threshold {
+            return clients.into_iter().map(TableRow::Client).collect();
+        }
+        let mut groups: BTreeMap<usize, Vec<&Client>> = BTreeMap::new();
+        for client in clients {
+            let key = match self.grouping {
+                Grouping::Health => match thresholds.classify(client) {
+                    Some(Health::Bad) => 0,
+                    Some(Health::Warn) => 1,
+                    Some(Health::Good) => 2,
+                    None => 3,
+                },
+                _ => client.id / GROUP_SIZE,
+            };
+            groups.entry(key).or_default().push(client);
+        }
+        let mut rows = vec![];
+        for (key, clients) in groups {
+            let (label, health) = match self.grouping {
+                Grouping::Health => match key {
+                    0 => ("Bad".to_string(), Some(Health::Bad)),
+                    1 => ("Warn".to_string(), Some(Health::Warn)),
+                    2 => ("Good".to_string(), Some(Health::Good)),
+                    _ => ("No stats".to_string(), None),
+                },
+                _ => (
+                    format!("{}-{}", key * GROUP_SIZE, (key + 1) * GROUP_SIZE - 1),
+                    clients.iter().filter_map(|c| thresholds.classify(c)).max(),
+                ),
+            };
+            let expanded = self.expanded.contains(&key);
+            let members = expanded.then(|| clients.clone());
+            rows.push(TableRow::Group {
+                key,
+                label,
+                health,
+                clients,
+            });
+            rows.extend(members.into_iter().flatten().map(TableRow::Client));
         }
+        rows
     }
 
-    pub fn set_client_stats(&mut self, id: usize, stats: Stats) {
-        if let Some(client) = self.clients.get_mut(&id) {
-            client.stats = Some(stats);
+    fn sort_label(&self) -> String {
+        let dir = if self.descending { "desc" } else { "asc" };
+        format!("{} {}", self.sort.label(), dir)
+    }
+
+    fn max_offset(total: usize, page: usize) -> usize {
+        total.saturating_sub(page)
+    }
+
+    /// Move selection and scroll so the selected row stays visible
+    fn move_selection(&mut self, delta: isize, total: usize, page: usize) {
+        let selected = (self.selected as isize + delta).max(0) as usize;
+        self.selected = selected.min(total.saturating_sub(1));
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + page {
+            self.offset = self.selected + 1 - page;
         }
     }
 
-    pub fn remove_client(&mut self, id: usize) {
-        self.clients.remove(&id);
+    fn home(&mut self) {
+        self.offset = 0;
+        self.selected = 0;
     }
 
-    pub fn get_clients(&self) -> &BTreeMap<usize, Client> {
-        &self.clients
+    fn end(&mut self, total: usize, page: usize) {
+        self.offset = Self::max_offset(total, page);
+        self.selected = total.saturating_sub(1);
     }
 
-    pub fn clients_sum(&self) -> usize {
-        self.clients.len()
+    fn visible(&self, total: usize, page: usize) -> Range<usize> {
+        let start = self.offset.min(Self::max_offset(total, page));
+        start..(start + page).min(total)
     }
+}
 
-    pub fn clients_connected(&self) -> usize {
-        self.clients.values().filter(|v| v.connected).count()
+fn detail_lines(client: &Client) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Sender {} - {}",
+        client.id,
+        client.status().label()
+    )];
+    match (client.connect_ms, client.created_at) {
+        (Some(connect_ms), _) => lines.push(format!("Connected after {} ms", connect_ms)),
+        (None, Some(created_at)) => lines.push(format!(
+            "Connecting for {} ms",
+            created_at.elapsed().as_millis()
+        )),
+        _ => {}
+    }
+    if let Some(stats) = &client.stats {
+        let ms = |v: Option<u32>| v.map(|v| format!("{} ms", v)).unwrap_or_else(|| "-".into());
+        if let Some(edge) = &stats.edge {
+            lines.push(format!("Edge: {}", edge));
+        }
+        if let Some(pair) = &stats.candidate_pair {
+            lines.push(format!(
+                "Candidate pair: {}, nominated after {}",
+                pair,
+                ms(pair.nominated_ms)
+            ));
+        }
+        if let Some(drops) = stats.socket_drops {
+            lines.push(format!("Socket drops: {}", drops));
+        }
+        if stats.oversized > 0 {
+            lines.push(format!("Oversized datagrams: {}", stats.oversized));
+        }
+        if let Some(resolution) = stats.resolution {
+            lines.push(format!(
+                "Resolution: {}, {} changes",
+                resolution, client.resolution_changes
+            ));
+        }
+        if let Some(delay) = stats.playout_delay {
+            lines.push(format!("Playout delay: {}", delay));
+        }
+        let traffic = &stats.traffic;
+        lines.push(format!(
+            "Bytes: {} wire, {} payload, {} rtp overhead, {} rtx, {} rtcp, {} stun, {} dtls",
+            traffic.wire_bytes,
+            traffic.payload_bytes,
+            traffic.rtp_overhead_bytes,
+            traffic.rtx_bytes,
+            traffic.rtcp_bytes,
+            traffic.stun_bytes,
+            traffic.dtls_bytes
+        ));
+        if let Some(level) = &stats.audio_level {
+            lines.push(format!(
+                "Audio level: avg {:.0} dBov, peak {:.0} dBov, silence {:.0}%",
+                level.avg_dbov,
+                level.peak_dbov,
+                level.silence_ratio * 100.0
+            ));
+        }
+        let extensions: Vec<String> = stats
+            .extensions
+            .counts()
+            .iter()
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect();
+        lines.push(format!(
+            "Header extensions of {} packets: {}",
+            stats.extensions.packets,
+            extensions.join(", ")
+        ));
+        let silence = &stats.silence;
+        lines.push(format!(
+            "Audio silence: {:.1} s, dtx {:.1} s, {} gaps {:.1} s",
+            silence.silence_secs(),
+            silence.dtx_ms as f64 / 1000.0,
+            silence.gaps,
+            silence.gap_ms as f64 / 1000.0
+        ));
+        let resilience = &stats.resilience;
+        lines.push(format!(
+            "Repair: {} lost, {} recovered by rtx, {} by fec, {} unrecovered ({} rtx, {} fec packets)",
+            resilience.lost,
+            resilience.recovered_rtx,
+            resilience.recovered_fec,
+            resilience.unrecovered(),
+            resilience.rtx_packets,
+            resilience.fec_packets
+        ));
+        let phases = &stats.phases;
+        lines.push(format!(
+            "Phases: queue {}, signaling {}, ice {}, dtls {}, total {}",
+            ms(client.queue_ms),
+            ms(phases.signaling_ms),
+            ms(phases.ice_ms),
+            ms(phases.dtls_ms),
+            ms(phases.total_ms)
+        ));
     }
 
-    pub fn sum_send_kbps(&self) -> u64 {
-        self.clients
-            .values()
-            .filter_map(|v| v.stats.as_ref())
-            .map(|v| v.send_kbps)
-            .sum::<u64>()
+    if let Some(answer) = &client.answer {
+        lines.push(format!("Answer: {}", answer.profile()));
+        lines.push(format!(
+            "WHEP extensions: {}",
+            whepext::describe(&answer.whep_extensions)
+        ));
+    }
+    lines.push("Recent stats:".to_string());
+    for stats in client.history.iter().rev() {
+        lines.push(format!(
+            "  up {} s: {} kbps/ {} kbps, rtt {} ms, lost {:.2}, gop {} ms, {:.1} fps, mos {}",
+            stats.live_ms / 1000,
+            stats.send_kbps,
+            stats.recv_kbps,
+            stats.rtt_ms,
+            stats.lost,
+            stats.keyframe_interval_ms,
+            stats.video_fps,
+            stats
+                .mos
+                .map(|mos| format!("{:.2}", mos))
+                .unwrap_or_else(|| "-".to_string())
+        ));
+    }
+
+    lines.push("Streams:".to_string());
+    if let Some(stats) = &client.stats {
+        if let (Some(offset), Some(drift)) = (stats.av_offset_ms, stats.av_drift_ms) {
+            lines.push(format!(
+                "  a/v offset {:.1} ms, drift {:.1} ms",
+                offset, drift
+            ));
+        }
+        for stream in &stats.streams {
+            lines.push(format!(
+                "  ssrc {} {:?}: {} packets, {} pps, {:.1} fps, {} kbps, loss {:.3} ({} lost), jitter {:.1} ms, seq {}",
+                stream.ssrc,
+                stream.codec,
+                stream.packets,
+                stream.pps,
+                stream.fps,
+                stream.recv_kbps,
+                stream.loss,
+                stream.lost,
+                stream.jitter_ms,
+                stream.last_seq.map_or("-".to_string(), |seq| seq.to_string())
+            ));
+            if let Some(skew) = stream.clock_skew {
+                lines.push(format!(
+                    "    clock skew: ntp {:.0} ppm, media {:.0} ppm{}",
+                    skew.ntp_ppm,
+                    skew.media_ppm,
+                    if skew.flagged() {
+                        ", too fast or slow"
+                    } else {
+                        ""
+                    }
+                ));
+            }
+        }
+        let stun = &stats.stun;
+        lines.push(format!(
+            "Stun: {} requests, {} responses, {} failed, {} from server, rtt {}",
+            stun.requests,
+            stun.responses,
+            stun.failures,
+            stun.remote_requests,
+            stun.rtt_ms
+                .map(|rtt| format!("{:.1} ms", rtt))
+                .unwrap_or_else(|| "-".to_string())
+        ));
+        if let Some(role) = stun.role {
+            lines.push(format!("Ice role: {}", role));
+        }
     }
 
-    pub fn sum_recv_kbps(&self) -> u64 {
-        self.clients
-            .values()
-            .filter_map(|v| v.stats.as_ref())
-            .map(|v| v.recv_kbps)
-            .sum::<u64>()
+    lines.push("Errors:".to_string());
+    for err in client.errors.iter().rev() {
+        lines.push(format!("  {}", err));
     }
+    lines.push("(Esc to close)".to_string());
+    lines
 }
 
 pub struct AppProps {
-    pub rx: Arc<Receiver<BenchEvent>>,
+    pub rx: Receiver<BenchEvent>,
+    /// Control channel of the local runner, `+`/`-` keys are disabled without it
+    pub control: Option<Sender<BenchControl>>,
+    pub scale_step: usize,
+    /// Clients asked for a keyframe at once by `k`
+    pub keyframe_burst: usize,
+    /// Stats samples kept per client for the detail view
+    pub history_depth: usize,
+    /// More clients than this collapse into expandable groups
+    pub group_threshold: usize,
+    /// Replaced when the config file is reloaded
+    pub thresholds: Arc<RwLock<HealthThresholds>>,
+    /// Recent warnings and errors, rendered instead of writing logs to the terminal
+    pub logs: LogBuffer,
+    /// Set from outside to close the UI and restore the terminal
+    pub shutdown: Arc<AtomicBool>,
+    /// Stops the run gracefully, the UI stays open until `shutdown` is set
+    pub quit: Sender<()>,
+    /// Directory of the snapshots exported with `s`
+    pub snapshot_dir: PathBuf,
 }
 
-pub fn dioxus_app(cx: Scope<AppProps>) -> Element {
-    let ver = use_state(cx, || 0);
-    let state = use_state(cx, || RwLock::new(AppState::default()));
+struct App {
+    props: AppProps,
+    collector: Collector,
+    view: TableView,
+    /// Number of client rows fitting the table area in the last draw
+    page: usize,
+    last_sample: Instant,
+    /// Quit was requested, clients are disconnecting
+    quitting: bool,
+    /// Pause sent to the runner, with hold
+    paused: Option<bool>,
+    /// Result of the latest snapshot export
+    notice: Option<(String, Instant)>,
+}
+
+/// Run the terminal UI until the run ended, blocking the current thread. `q`, Esc
+/// and Ctrl+C stop the run gracefully
+pub fn run_tui(props: AppProps) -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let mut app = App {
+        collector: Collector::with_history(props.history_depth),
+        props,
+        view: TableView::default(),
+        page: 1,
+        last_sample: Instant::now(),
+        quitting: false,
+        paused: None,
+        notice: None,
+    };
+    let res = app.run(&mut terminal);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    res
+}
 
-    let _ = use_coroutine(cx, |_: UnboundedReceiver<()>| {
-        let rx = cx.props.rx.clone();
-        let state = state.to_owned();
-        let ver = ver.to_owned();
-        let mut tick = async_std::stream::interval(Duration::from_millis(300));
-        async move {
-            let mut has_update = false;
+impl App {
+    fn thresholds(&self) -> HealthThresholds {
+        *self.props.thresholds.read()
+    }
+
+    fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        loop {
+            if self.props.shutdown.load(Ordering::Relaxed) {
+                return Ok(());
+            }
             loop {
-                select! {
-                    _ = tick.next().fuse() => {
-                        if has_update {
-                            ver.set(*ver + 1);
-                        }
-                    }
-                    event = rx.recv().fuse() => {
-                        let mut state = state.write();
-                        match event {
-                            Ok(BenchEvent::Connecting(id)) => {
-                                state.add_client(id);
-                            }
-                            Ok(BenchEvent::Connected(id)) => {
-                                state.set_client_connected(id);
-                            }
-                            Ok(BenchEvent::Stats(id, stats)) => {
-                                state.set_client_stats(id, stats);
-                            }
-                            Ok(BenchEvent::Disconnected(id)) => {
-                                state.remove_client(id);
-                            }
-                            Err(_) => {
-                                break;
-                            }
-                        };
-
-                        has_update = true;
-                    }
-                };
+                match self.props.rx.try_recv() {
+                    Ok(event) => self.collector.on_event(event),
+                    Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => break,
+                }
             }
-        }
-    });
-
-    let state = state.get().read();
-    let clients_sum = state.clients_sum();
-    let clients_connected = state.clients_connected();
-    let sum_send_kbps = state.sum_send_kbps();
-    let sum_recv_kbps = state.sum_recv_kbps();
-    let clients = state.get_clients();
-
-    cx.render(rsx! {
-        div{
-            width: "100%",
-            flex_direction: "column",
-            header {
-                width: "100%",
-
-                ul {
-                    overflow: "hidden",
-                    background_color: "#ffffff",
-                    color: "#000000",
-                    width: "100%",
-
-                    div {
-                        flex_direction: "row",
-                        width: "100%",
-
-                        li {
-                            width: "40%",
-
-                            "Clients {clients_sum}"
-                        }
-                        li {
-                            width: "30%",
-
-                            "Connected {clients_connected}"
-                        }
-                        li {
-                            width: "30%",
-
-                            "Send: {sum_send_kbps} kbps, Recv: {sum_recv_kbps} kbps"
-                        }
+            if self.last_sample.elapsed() >= SAMPLE_INTERVAL {
+                self.last_sample = Instant::now();
+                self.collector.sample(self.last_sample);
+            }
+
+            terminal.draw(|f| self.draw(f))?;
+
+            if event::poll(TICK)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !self.on_key(key) {
+                        return Ok(());
                     }
                 }
             }
-            div {
-                width: "100%",
-
-                ul {
-                    width: "100%",
-                    flex_direction: "column",
-
-                    clients.values().into_iter().map(|v| {
-                        rsx!(div {
-                            flex_direction: "row",
-                            width: "100%",
-
-                            li {
-                                width: "40%",
-
-                                format!("Sender {}", v.id)
-                            }
-                            li {
-                                width: "30%",
-
-                                if v.connected { "Running" } else { "Connecting" }
-                            }
-                            li {
-                                width: "30%",
-
-                                if let Some(stats) = &v.stats { format!("{} kbps/ {} kbps", stats.send_kbps, stats.recv_kbps) } else { format!("...") }
-                            }
-                        })
-                    })
-                }
+        }
+    }
+
+    /// Handle a key press, returns false when the UI should exit right away
+    fn on_key(&mut self, key: KeyEvent) -> bool {
+        let rows = self.view.table_rows(
+            self.collector.get_clients().values(),
+            &self.thresholds(),
+            self.props.group_threshold,
+        );
+        let total = rows.len();
+        let selected = rows.get(self.view.selected).map(|row| match row {
+            TableRow::Group { key, .. } => Err(*key),
+            TableRow::Client(c) => Ok(c.id),
+        });
+        let page = self.page;
+        match key.code {
+            // a second quit leaves the UI, teardown continues without it
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return self.quit()
+            }
+            KeyCode::Char('q') => return self.quit(),
+            KeyCode::Esc if self.view.detail.is_none() => return self.quit(),
+            KeyCode::Down => self.view.move_selection(1, total, page),
+            KeyCode::Up => self.view.move_selection(-1, total, page),
+            KeyCode::PageDown => self.view.move_selection(page as isize, total, page),
+            KeyCode::PageUp => self.view.move_selection(-(page as isize), total, page),
+            KeyCode::Home => self.view.home(),
+            KeyCode::End => self.view.end(total, page),
+            KeyCode::Enter => match selected {
+                Some(Err(key)) => self.view.toggle_group(key),
+                selected => self.view.detail = selected.and_then(Result::ok),
+            },
+            KeyCode::Esc => self.view.detail = None,
+            KeyCode::Char('i') => self.view.sort_by(SortColumn::Id),
+            KeyCode::Char('b') => self.view.sort_by(SortColumn::RecvKbps),
+            KeyCode::Char('r') => self.view.sort_by(SortColumn::Rtt),
+            KeyCode::Char('l') => self.view.sort_by(SortColumn::Lost),
+            KeyCode::Char('u') => self.view.sort_by(SortColumn::Uptime),
+            KeyCode::Char('f') => self.view.next_filter(),
+            KeyCode::Char('g') => self.view.next_grouping(),
+            KeyCode::Char('+') => self.send_control(BenchControl::Spawn(self.props.scale_step)),
+            KeyCode::Char('-') => self.send_control(BenchControl::Kill(self.props.scale_step)),
+            KeyCode::Char('p') => self.toggle_pause(false),
+            KeyCode::Char('P') => self.toggle_pause(true),
+            KeyCode::Char('s') => self.export_snapshot(),
+            KeyCode::Char('k') => {
+                self.send_control(BenchControl::RequestKeyframe(self.props.keyframe_burst))
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Write the header and the client table as text, and the aggregates and all
+    /// clients as JSON, to `snapshot-<unix ms>.txt` and `.json`
+    fn export_snapshot(&mut self) {
+        let taken_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let base = self
+            .props
+            .snapshot_dir
+            .join(format!("snapshot-{}", taken_at_ms));
+        let notice = match self.write_snapshot(&base, taken_at_ms) {
+            Ok(()) => format!("Snapshot written to {}.txt/.json", base.display()),
+            Err(e) => {
+                log::error!("[Tui] cannot write snapshot {} {:?}", base.display(), e);
+                format!("Snapshot failed: {}", e)
+            }
+        };
+        self.notice = Some((notice, Instant::now()));
+    }
+
+    fn write_snapshot(&self, base: &Path, taken_at_ms: u64) -> io::Result<()> {
+        let header: String = self
+            .header_line()
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        let mut text = format!(
+            "{}\nsort {}, filter {}\n\n",
+            header,
+            self.view.sort_label(),
+            self.view.filter_label()
+        );
+        let line = |cells: Vec<String>| -> String {
+            let mut line = String::new();
+            for (cell, width) in cells.iter().zip(WIDTHS) {
+                let _ = write!(line, "{:<width$}", cell, width = width as usize);
+            }
+            line.trim_end().to_string()
+        };
+        text.push_str(&line(COLUMNS.iter().map(|c| c.to_string()).collect()));
+        text.push('\n');
+        for client in self.view.rows(self.collector.get_clients().values()) {
+            text.push_str(&line(client_cells(client)));
+            text.push('\n');
+        }
+        std::fs::write(base.with_extension("txt"), text)?;
+
+        let (good, warn, bad) = self.collector.health_counts(&self.thresholds());
+        let json = serde_json::json!({
+            "taken_at_ms": taken_at_ms,
+            "header": {
+                "clients": self.collector.clients_sum(),
+                "connected": self.collector.clients_connected(),
+                "failed": self.collector.clients_failed(),
+                "send_kbps": self.collector.sum_send_kbps(),
+                "recv_kbps": self.collector.sum_recv_kbps(),
+                "avg_rtt_ms": self.collector.avg_rtt_ms(),
+                "max_rtt_ms": self.collector.max_rtt_ms(),
+                "avg_loss": self.collector.avg_loss(),
+                "stalled": self.collector.clients_stalled(),
+                "good": good,
+                "warn": warn,
+                "bad": bad,
+            },
+            "aggregates": self.collector.aggregates(),
+            "snapshot": self.collector.snapshot(),
+        });
+        let json = serde_json::to_string_pretty(&json).expect("should serialize snapshot");
+        std::fs::write(base.with_extension("json"), json)
+    }
+
+    /// Request a graceful stop, false when it was already requested
+    fn quit(&mut self) -> bool {
+        if self.quitting {
+            return false;
+        }
+        self.quitting = true;
+        let _ = self.props.quit.try_send(());
+        true
+    }
+
+    /// Pause spawning, or resume when already paused
+    fn toggle_pause(&mut self, hold: bool) {
+        if self.props.control.is_none() {
+            return;
+        }
+        if self.paused.take().is_some() {
+            self.send_control(BenchControl::Resume);
+        } else {
+            self.paused = Some(hold);
+            self.send_control(BenchControl::Pause { hold });
+        }
+    }
+
+    fn send_control(&self, cmd: BenchControl) {
+        if let Some(control) = &self.props.control {
+            if let Err(e) = control.try_send(cmd) {
+                log::warn!("[Tui] control channel error {:?}", e);
+            }
+        }
+    }
+
+    /// Client counts, bitrates and health of all clients
+    fn header_line(&self) -> Line<'static> {
+        let (good, warn, bad) = self.collector.health_counts(&self.thresholds());
+        let mut summary = Line::from(vec![
+            Span::raw(format!(
+                "Clients {}  Connected {}  Send: {} kbps, Recv: {} kbps  ",
+                self.collector.clients_sum(),
+                self.collector.clients_connected(),
+                self.collector.sum_send_kbps(),
+                self.collector.sum_recv_kbps()
+            )),
+            Span::styled(format!("Good {} ", good), Style::default().fg(Color::Green)),
+            Span::styled(
+                format!("Warn {} ", warn),
+                Style::default().fg(Color::Yellow),
+            ),
+            Span::styled(format!("Bad {}", bad), Style::default().fg(Color::Red)),
+            Span::raw(format!(
+                "  RTT avg {} max {} ms  Lost {:.2}  ",
+                self.collector.avg_rtt_ms(),
+                self.collector.max_rtt_ms(),
+                self.collector.avg_loss()
+            )),
+        ]);
+        let stalled = self.collector.clients_stalled();
+        summary.spans.push(match stalled {
+            0 => Span::raw("Stalled 0"),
+            _ => Span::styled(
+                format!("Stalled {}", stalled),
+                Style::default().fg(Color::Yellow),
+            ),
+        });
+        let failures = self.collector.failure_summary();
+        if !failures.is_empty() {
+            summary.spans.push(Span::styled(
+                format!("  Failed: {}", failures),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        summary
+    }
+
+    fn draw(&mut self, f: &mut Frame) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Length(3),
+                Constraint::Min(2),
+                Constraint::Length(1),
+                Constraint::Length(LOG_LINES as u16 + 1),
+            ])
+            .split(f.size());
+
+        let summary = self.header_line();
+        f.render_widget(
+            Paragraph::new(summary).style(Style::default().bg(Color::Black)),
+            layout[0],
+        );
+        self.draw_sparklines(f, layout[1]);
+
+        // header row of the table takes one line
+        self.page = (layout[2].height as usize).saturating_sub(1).max(1);
+        let thresholds = self.thresholds();
+        let rows = self.view.table_rows(
+            self.collector.get_clients().values(),
+            &thresholds,
+            self.props.group_threshold,
+        );
+        let total = rows.len();
+        let visible = self.view.visible(total, self.page);
+
+        match self
+            .view
+            .detail
+            .and_then(|id| self.collector.get_client(id))
+        {
+            Some(client) => {
+                let lines: Vec<Line> = detail_lines(client).into_iter().map(Line::from).collect();
+                f.render_widget(Paragraph::new(lines), layout[2]);
             }
+            None => {
+                // only visible rows are built, the rest of the table is never rendered
+                let table_rows = rows[visible.clone()].iter().enumerate().map(|(i, r)| {
+                    let (row, health) = match r {
+                        TableRow::Group {
+                            key,
+                            label,
+                            health,
+                            clients,
+                        } => (
+                            group_row(label, clients, self.view.expanded.contains(key)),
+                            *health,
+                        ),
+                        TableRow::Client(c) => (client_row(c), thresholds.classify(c)),
+                    };
+                    let row = match health {
+                        Some(health) => row.style(Style::default().fg(health_color(health))),
+                        None => row,
+                    };
+                    if visible.start + i == self.view.selected {
+                        row.style(Style::default().add_modifier(Modifier::REVERSED))
+                    } else {
+                        row
+                    }
+                });
+                let widths = WIDTHS.map(Constraint::Length);
+                let header =
+                    Row::new(COLUMNS.to_vec()).style(Style::default().add_modifier(Modifier::BOLD));
+                f.render_widget(Table::new(table_rows, widths).header(header), layout[2]);
+            }
+        }
+
+        let first_row = if visible.is_empty() {
+            0
+        } else {
+            visible.start + 1
+        };
+        let footer = format!(
+            "Rows {}-{} of {}, sort {}, filter {}, group {} (Up/Down select, PgUp/PgDn page, Home/End, Enter detail/expand, sort i/b/r/l/u, filter f, group g, scale +/-, pause p/P, keyframes k, snapshot s, quit q)",
+            first_row,
+            visible.end,
+            total,
+            self.view.sort_label(),
+            self.view.filter_label(),
+            self.view.grouping.label(),
+        );
+        let footer = match &self.notice {
+            Some((notice, at)) if at.elapsed() < NOTICE_TIME => format!("{}. {}", notice, footer),
+            _ => footer,
+        };
+        let footer = match (self.quitting, self.paused) {
+            (true, _) => format!("Stopping, deleting sessions... {}", footer),
+            (false, Some(true)) => format!("Paused, holding clients. {}", footer),
+            (false, Some(false)) => format!("Paused. {}", footer),
+            (false, None) => footer,
+        };
+        f.render_widget(Paragraph::new(footer), layout[3]);
+        self.draw_logs(f, layout[4]);
+    }
+
+    fn draw_logs(&self, f: &mut Frame, area: Rect) {
+        let records = self.props.logs.records();
+        let skip = records.len().saturating_sub(LOG_LINES);
+        let lines: Vec<Line> = records
+            .iter()
+            .skip(skip)
+            .map(|r| {
+                let color = if r.level == "ERROR" {
+                    Color::Red
+                } else {
+                    Color::Yellow
+                };
+                Line::styled(
+                    format!("{} {}: {}", r.level, r.target, r.message),
+                    Style::default().fg(color),
+                )
+            })
+            .collect();
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().title("Logs")),
+            area,
+        );
+    }
+
+    fn draw_sparklines(&self, f: &mut Frame, area: Rect) {
+        let halves = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(area);
+        let width = halves[0].width as usize;
+        let aggregates = self.collector.aggregates();
+        let skip = aggregates.len().saturating_sub(width);
+        let recv: Vec<u64> = aggregates.iter().skip(skip).map(|a| a.recv_kbps).collect();
+        let rtt: Vec<u64> = aggregates
+            .iter()
+            .skip(skip)
+            .map(|a| a.avg_rtt_ms as u64)
+            .collect();
+
+        f.render_widget(
+            Sparkline::default()
+                .block(Block::default().title("Recv kbps"))
+                .data(&recv)
+                .style(Style::default().fg(Color::Cyan)),
+            halves[0],
+        );
+        f.render_widget(
+            Sparkline::default()
+                .block(
+                    Block::default().title(format!("RTT avg {} ms", self.collector.avg_rtt_ms())),
+                )
+                .data(&rtt)
+                .style(Style::default().fg(Color::Yellow)),
+            halves[1],
+        );
+    }
+}
+
+fn health_color(health: Health) -> Color {
+    match health {
+        Health::Good => Color::Green,
+        Health::Warn => Color::Yellow,
+        Health::Bad => Color::Red,
+    }
+}
+
+/// Averages over the clients of a group which have stats
+fn group_row(label: &str, clients: &[&Client], expanded: bool) -> Row<'static> {
+    let stats: Vec<_> = clients.iter().filter_map(|c| c.stats.as_ref()).collect();
+    let avg = |value: fn(&Stats) -> f64| {
+        if stats.is_empty() {
+            return 0.0;
         }
-    })
+        stats.iter().map(|s| value(s)).sum::<f64>() / stats.len() as f64
+    };
+    let marker = if expanded { "-" } else { "+" };
+    Row::new(vec![
+        format!("{} {}", marker, label),
+        format!("{} clients", clients.len()),
+        format!("{:.0}", avg(|s| s.send_kbps as f64)),
+        format!("{:.0}", avg(|s| s.recv_kbps as f64)),
+        format!("{:.0}", avg(|s| s.rtt_ms as f64)),
+        format!("{:.2}", avg(|s| s.lost as f64)),
+        format!("{:.0}", avg(|s| s.live_ms as f64) / 1000.0),
+        format!("{:.0}", avg(|s| s.keyframe_interval_ms as f64)),
+        format!("{:.1}", avg(|s| s.video_fps as f64)),
+    ])
+}
+
+fn client_row(client: &Client) -> Row<'static> {
+    Row::new(client_cells(client))
+}
+
+fn client_cells(client: &Client) -> Vec<String> {
+    let mut cells = vec![
+        format!("Sender {}", client.id),
+        client.status().label().to_string(),
+    ];
+    match &client.stats {
+        Some(stats) => cells.extend([
+            stats.send_kbps.to_string(),
+            stats.recv_kbps.to_string(),
+            stats.rtt_ms.to_string(),
+            format!("{:.2}", stats.lost),
+            (stats.live_ms / 1000).to_string(),
+            stats.keyframe_interval_ms.to_string(),
+            format!("{:.1}", stats.video_fps),
+        ]),
+        None => cells.extend(std::iter::repeat("...".to_string()).take(7)),
+    }
+    cells
 }
@@ -0,0 +1,81 @@
+//! Placeholders in the whep url, for spreading clients across streams or rooms,
+//! e.g. `https://edge/whep/room-{id % 10}`. A placeholder is `{id}` or `{id <op> n}`
+//! with op one of `+ - * / %`, ids start at 1. `{{` and `}}` are literal braces
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+fn eval(expr: &str, client_id: usize) -> Result<usize, String> {
+    let invalid = || {
+        format!(
+            "invalid url placeholder {{{}}}, expected e.g. {{id % 10}}",
+            expr
+        )
+    };
+    let rest = expr.trim().strip_prefix("id").ok_or_else(invalid)?.trim();
+    if rest.is_empty() {
+        return Ok(client_id);
+    }
+    let op = match rest.chars().next() {
+        Some('+') => Op::Add,
+        Some('-') => Op::Sub,
+        Some('*') => Op::Mul,
+        Some('/') => Op::Div,
+        Some('%') => Op::Rem,
+        _ => return Err(invalid()),
+    };
+    let n: usize = rest[1..].trim().parse().map_err(|_| invalid())?;
+    match op {
+        Op::Add => Ok(client_id + n),
+        Op::Sub => Ok(client_id.saturating_sub(n)),
+        Op::Mul => Ok(client_id * n),
+        Op::Div | Op::Rem if n == 0 => Err(invalid()),
+        Op::Div => Ok(client_id / n),
+        Op::Rem => Ok(client_id % n),
+    }
+}
+
+pub fn is_template(url: &str) -> bool {
+    url.contains('{')
+}
+
+/// The url of client `client_id`
+pub fn render(template: &str, client_id: usize) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        out.push_str(&rest[..at]);
+        let tail = &rest[at..];
+        if let Some(tail) = tail.strip_prefix("{{") {
+            out.push('{');
+            rest = tail;
+        } else if let Some(tail) = tail.strip_prefix("}}") {
+            out.push('}');
+            rest = tail;
+        } else if let Some(tail) = tail.strip_prefix('{') {
+            let end = tail
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in url {}", template))?;
+            out.push_str(&eval(&tail[..end], client_id)?.to_string());
+            rest = &tail[end + 1..];
+        } else {
+            return Err(format!("unmatched }} in url {}", template));
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Check the placeholders and that the url of the first client parses
+pub fn validate(template: &str) -> Result<(), String> {
+    let url = render(template, 1)?;
+    url::Url::parse(&url)
+        .map(|_| ())
+        .map_err(|e| format!("invalid url {}: {}", url, e))
+}
@@ -0,0 +1,386 @@
+//! Abort guard, ends a run early once it degraded beyond repair instead of
+//! hammering a dead server until the plan is done. Stop conditions share its
+//! metrics and end a run normally on a domain condition
+
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use async_std::channel::Receiver;
+use serde::{Deserialize, Serialize};
+
+use crate::{bench::BenchEvent, collector::Collector, gate::Op};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Metric {
+    /// Connected share of all started clients, failed ones included
+    ConnectedRatio,
+    FailedRatio,
+    /// Average received bitrate of connected clients
+    RecvKbps,
+    RttMs,
+    Loss,
+    Mos,
+    /// Number of connected clients
+    Connected,
+    /// Number of failed clients
+    Failed,
+    /// Received by all clients since the start
+    TotalRecvGbytes,
+}
+
+impl Metric {
+    const NAMES: [(&'static str, Metric); 9] = [
+        ("connected_ratio", Metric::ConnectedRatio),
+        ("failed_ratio", Metric::FailedRatio),
+        ("recv_kbps", Metric::RecvKbps),
+        ("rtt_ms", Metric::RttMs),
+        ("loss", Metric::Loss),
+        ("mos", Metric::Mos),
+        ("connected", Metric::Connected),
+        ("failed", Metric::Failed),
+        ("total_recv_gbytes", Metric::TotalRecvGbytes),
+    ];
+
+    fn name(&self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, m)| m == self)
+            .map(|(name, _)| *name)
+            .expect("should have a name")
+    }
+
+    /// None while there is nothing to measure, e.g. before the first client
+    fn value(&self, collector: &Collector) -> Option<f64> {
+        let clients = collector.get_clients();
+        let total = clients.len();
+        let connected = collector.clients_connected();
+        let stats: Vec<_> = clients.values().filter_map(|c| c.stats.as_ref()).collect();
+        match self {
+            Metric::ConnectedRatio => (total > 0).then(|| connected as f64 / total as f64),
+            Metric::FailedRatio => {
+                (total > 0).then(|| collector.clients_failed() as f64 / total as f64)
+            }
+            Metric::RecvKbps => {
+                (connected > 0).then(|| collector.sum_recv_kbps() as f64 / connected as f64)
+            }
+            Metric::RttMs => (!stats.is_empty()).then(|| collector.avg_rtt_ms() as f64),
+            Metric::Loss => (!stats.is_empty())
+                .then(|| stats.iter().map(|s| s.lost as f64).sum::<f64>() / stats.len() as f64),
+            Metric::Mos => collector.avg_mos().map(|mos| mos as f64),
+            Metric::Connected => Some(connected as f64),
+            Metric::Failed => Some(collector.clients_failed() as f64),
+            Metric::TotalRecvGbytes => Some(collector.total_recv_bytes() as f64 / 1e9),
+        }
+    }
+
+    fn parse(name: &str) -> Result<Self, String> {
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, m)| *m)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::NAMES.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "unknown metric {}, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// `<metric> <|> <threshold> for <duration>`, e.g. `connected_ratio < 0.5 for 30s`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AbortRule {
+    pub metric: Metric,
+    pub below: bool,
+    pub threshold: f64,
+    pub hold: Duration,
+    /// Not checked before this many clients started, so the first few failures
+    /// of a run do not decide a ratio
+    #[serde(default)]
+    pub min_clients: usize,
+}
+
+impl AbortRule {
+    /// Abort once more than `ratio` of the clients failed, checked from `min_clients` on
+    pub fn max_failure_ratio(ratio: f64, min_clients: usize) -> Self {
+        Self {
+            metric: Metric::FailedRatio,
+            below: false,
+            threshold: ratio,
+            hold: Duration::ZERO,
+            min_clients,
+        }
+    }
+}
+
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => s.split_at(at),
+        None => (s, "s"),
+    };
+    let value: u64 = value.parse().ok()?;
+    match unit {
+        "s" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_secs(value * 60)),
+        "h" => Some(Duration::from_secs(value * 3600)),
+        "ms" => Some(Duration::from_millis(value)),
+        _ => None,
+    }
+}
+
+/// Value parser of duration flags, e.g. `30s`, `5m` or `500ms`. Bare numbers are seconds
+pub fn parse_duration_arg(s: &str) -> Result<Duration, String> {
+    parse_duration(s.trim())
+        .ok_or_else(|| format!("invalid duration {}, expected e.g. 30s or 500ms", s))
+}
+
+impl FromStr for AbortRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || {
+            format!(
+                "invalid rule {}, expected e.g. `connected_ratio < 0.5 for 30s`",
+                s
+            )
+        };
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let [metric, op, threshold, "for", hold] = parts[..] else {
+            return Err(usage());
+        };
+        let metric = Metric::parse(metric)?;
+        let below = match op {
+            "<" => true,
+            ">" => false,
+            _ => return Err(usage()),
+        };
+        Ok(AbortRule {
+            metric,
+            below,
+            threshold: threshold.parse().map_err(|_| usage())?,
+            hold: parse_duration(hold).ok_or_else(usage)?,
+            min_clients: 0,
+        })
+    }
+}
+
+impl fmt::Display for AbortRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} for {}s",
+            self.metric.name(),
+            if self.below { "<" } else { ">" },
+            self.threshold,
+            self.hold.as_secs()
+        )?;
+        if self.min_clients > 0 {
+            write!(f, " after {} clients", self.min_clients)?;
+        }
+        Ok(())
+    }
+}
+
+/// `<metric> <op> <threshold> [for <duration>]` ending the run like a finished
+/// one, e.g. `total_recv_gbytes > 100` or `connected == 0 for 60s`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StopCondition {
+    pub metric: Metric,
+    pub op: Op,
+    pub threshold: f64,
+    pub hold: Duration,
+}
+
+impl FromStr for StopCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || {
+            format!(
+                "invalid stop condition {}, expected e.g. `connected == 0 for 60s`",
+                s
+            )
+        };
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let (metric, op, threshold, hold) = match parts[..] {
+            [metric, op, threshold] => (metric, op, threshold, Duration::ZERO),
+            [metric, op, threshold, "for", hold] => (
+                metric,
+                op,
+                threshold,
+                parse_duration(hold).ok_or_else(usage)?,
+            ),
+            _ => return Err(usage()),
+        };
+        Ok(StopCondition {
+            metric: Metric::parse(metric)?,
+            op: Op::SYMBOLS
+                .iter()
+                .find(|(symbol, _)| *symbol == op)
+                .map(|(_, op)| *op)
+                .ok_or_else(usage)?,
+            threshold: threshold.parse().map_err(|_| usage())?,
+            hold,
+        })
+    }
+}
+
+impl fmt::Display for StopCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.metric.name(),
+            self.op.symbol(),
+            self.threshold
+        )?;
+        if !self.hold.is_zero() {
+            write!(f, " for {}s", self.hold.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+/// Why the run was aborted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Abort {
+    pub rule: String,
+    /// Metric value when the rule fired
+    pub value: f64,
+    /// Run time when the rule fired
+    pub after_ms: u64,
+}
+
+impl fmt::Display for Abort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "aborted after {}s, `{}` held, value {:.3}",
+            self.after_ms / 1000,
+            self.rule,
+            self.value
+        )
+    }
+}
+
+/// Condition which ended a run normally
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stop {
+    pub condition: String,
+    pub value: f64,
+    pub after_ms: u64,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stopped after {}s, `{}` held, value {:.3}",
+            self.after_ms / 1000,
+            self.condition,
+            self.value
+        )
+    }
+}
+
+/// Watch the events until a rule held for its whole duration, returns None when
+/// the event channel closed first
+pub async fn run_watchdog(rules: Vec<AbortRule>, event_rx: Receiver<BenchEvent>) -> Option<Abort> {
+    let checks = rules
+        .iter()
+        .map(|rule| Check {
+            metric: rule.metric,
+            op: if rule.below { Op::Lt } else { Op::Gt },
+            threshold: rule.threshold,
+            hold: rule.hold,
+            min_clients: rule.min_clients,
+        })
+        .collect();
+    let (index, value, after_ms) = watch(checks, event_rx).await?;
+    Some(Abort {
+        rule: rules[index].to_string(),
+        value,
+        after_ms,
+    })
+}
+
+/// Like [`run_watchdog`] for stop conditions
+pub async fn run_stop_conditions(
+    conditions: Vec<StopCondition>,
+    event_rx: Receiver<BenchEvent>,
+) -> Option<Stop> {
+    let checks = conditions
+        .iter()
+        .map(|c| Check {
+            metric: c.metric,
+            op: c.op,
+            threshold: c.threshold,
+            hold: c.hold,
+            min_clients: 0,
+        })
+        .collect();
+    let (index, value, after_ms) = watch(checks, event_rx).await?;
+    Some(Stop {
+        condition: conditions[index].to_string(),
+        value,
+        after_ms,
+    })
+}
+
+struct Check {
+    metric: Metric,
+    op: Op,
+    threshold: f64,
+    hold: Duration,
+    min_clients: usize,
+}
+
+/// Index of the first check which held for its duration, with the value and run time.
+/// Checks start with the first event, counts are 0 before any client started
+async fn watch(checks: Vec<Check>, event_rx: Receiver<BenchEvent>) -> Option<(usize, f64, u64)> {
+    let started = Instant::now();
+    let mut seen_event = false;
+    let mut collector = Collector::with_history(0);
+    let mut since: Vec<Option<Instant>> = vec![None; checks.len()];
+    let mut next_check = Instant::now() + CHECK_INTERVAL;
+    loop {
+        // checked first, a busy event stream must not delay the rules
+        let wait = next_check.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            match async_std::future::timeout(wait, event_rx.recv()).await {
+                Ok(Ok(event)) => {
+                    seen_event = true;
+                    collector.on_event(event);
+                    continue;
+                }
+                Ok(Err(_)) => return None,
+                Err(_) => {}
+            }
+        }
+        let now = Instant::now();
+        next_check = now + CHECK_INTERVAL;
+        if !seen_event {
+            continue;
+        }
+        for (index, (check, since)) in checks.iter().zip(since.iter_mut()).enumerate() {
+            let value = (collector.get_clients().len() >= check.min_clients)
+                .then(|| check.metric.value(&collector))
+                .flatten();
+            if !value.map_or(false, |v| check.op.holds(v, check.threshold)) {
+                *since = None;
+                continue;
+            }
+            if now - *since.get_or_insert(now) >= check.hold {
+                let after_ms = started.elapsed().as_millis() as u64;
+                return Some((index, value.unwrap_or_default(), after_ms));
+            }
+        }
+    }
+}
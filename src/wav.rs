@@ -0,0 +1,129 @@
+//! Received audio of sampled clients decoded to WAV files, for listening to what
+//! a viewer got during a load test. Lost packets are concealed by the decoder so
+//! the file keeps the timing of the session
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use opus::{Channels, Decoder};
+
+const SAMPLE_RATE: u32 = 48_000;
+const CHANNELS: u16 = 2;
+/// Longest Opus packet, 120 ms per channel
+const MAX_FRAME: usize = 5_760;
+/// Gaps up to this many packets are concealed, larger jumps start over silently
+const MAX_CONCEALED: u16 = 50;
+const HEADER_LEN: u32 = 44;
+
+/// WAV file of one client, the header sizes are patched when it is dropped
+pub struct AudioDump {
+    decoder: Decoder,
+    writer: BufWriter<File>,
+    /// Bytes of samples written so far
+    data_len: u32,
+    ssrc: Option<u32>,
+    last_seq: Option<u16>,
+    /// Samples per channel of the last decoded frame, the length of concealed frames
+    frame: usize,
+    pcm: Vec<i16>,
+}
+
+impl AudioDump {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let decoder = Decoder::new(SAMPLE_RATE, Channels::Stereo)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_header(&mut writer, 0)?;
+        Ok(Self {
+            decoder,
+            writer,
+            data_len: 0,
+            ssrc: None,
+            last_seq: None,
+            frame: 960,
+            pcm: vec![0; MAX_FRAME * CHANNELS as usize],
+        })
+    }
+
+    /// Decode the payload of an Opus RTP packet, late and duplicate packets are skipped
+    pub fn on_packet(&mut self, ssrc: u32, seq: u16, payload: &[u8]) -> io::Result<()> {
+        if self.ssrc != Some(ssrc) {
+            // a new session or stream, its sequence numbers start anywhere
+            self.ssrc = Some(ssrc);
+            self.last_seq = None;
+        }
+        if let Some(last) = self.last_seq {
+            let gap = seq.wrapping_sub(last);
+            if gap == 0 || gap > u16::MAX / 2 {
+                return Ok(());
+            }
+            if gap - 1 <= MAX_CONCEALED {
+                for _ in 1..gap {
+                    self.decode(&[])?;
+                }
+            }
+        }
+        self.last_seq = Some(seq);
+        self.decode(payload)
+    }
+
+    /// Decode one frame, an empty payload conceals a lost one
+    fn decode(&mut self, payload: &[u8]) -> io::Result<()> {
+        let output = if payload.is_empty() {
+            &mut self.pcm[..self.frame * CHANNELS as usize]
+        } else {
+            &mut self.pcm[..]
+        };
+        let samples = match self.decoder.decode(payload, output, false) {
+            Ok(samples) => samples,
+            Err(e) => {
+                log::debug!("[AudioDump] cannot decode packet {:?}", e);
+                return Ok(());
+            }
+        };
+        if !payload.is_empty() {
+            self.frame = samples;
+        }
+        for sample in &self.pcm[..samples * CHANNELS as usize] {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len = self
+            .data_len
+            .saturating_add((samples * CHANNELS as usize * 2) as u32);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, self.data_len)?;
+        self.writer.flush()
+    }
+}
+
+impl Drop for AudioDump {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("[AudioDump] cannot finish wav file {:?}", e);
+        }
+    }
+}
+
+/// RIFF header of 16 bit PCM with `data_len` bytes of samples
+fn write_header(writer: &mut impl Write, data_len: u32) -> io::Result<()> {
+    let block_align = CHANNELS * 2;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&data_len.saturating_add(HEADER_LEN - 8).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&CHANNELS.to_le_bytes())?;
+    writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    writer.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())
+}
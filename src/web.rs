@@ -0,0 +1,101 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_std::channel::{Receiver, Sender};
+use parking_lot::Mutex;
+use tide::{http::mime, Body, Request, Response, StatusCode};
+use tide_websockets::{WebSocket, WebSocketConnection};
+
+use crate::{bench::BenchEvent, collector::Collector, logbuf::LogBuffer};
+
+const DASHBOARD_HTML: &str = include_str!("../assets/dashboard.html");
+
+#[derive(Default)]
+struct WebState {
+    collector: Collector,
+    subscribers: Vec<Sender<Arc<str>>>,
+}
+
+#[derive(Clone)]
+struct Dashboard {
+    state: Arc<Mutex<WebState>>,
+    logs: LogBuffer,
+}
+
+impl Dashboard {
+    fn subscribe(&self) -> Receiver<Arc<str>> {
+        let (tx, rx) = async_std::channel::unbounded();
+        self.state.lock().subscribers.push(tx);
+        rx
+    }
+}
+
+async fn index(_req: Request<Dashboard>) -> tide::Result {
+    Ok(Response::builder(StatusCode::Ok)
+        .content_type(mime::HTML)
+        .body(DASHBOARD_HTML)
+        .build())
+}
+
+async fn get_state(req: Request<Dashboard>) -> tide::Result {
+    let dashboard = req.state();
+    let state = dashboard.state.lock();
+    let body = Body::from_json(&serde_json::json!({
+        "snapshot": state.collector.snapshot(),
+        "history": state.collector.aggregates(),
+        "logs": dashboard.logs.records(),
+    }))?;
+    Ok(Response::builder(StatusCode::Ok).body(body).build())
+}
+
+async fn stream_events(req: Request<Dashboard>, stream: WebSocketConnection) -> tide::Result<()> {
+    let rx = req.state().subscribe();
+    while let Ok(json) = rx.recv().await {
+        stream.send_string(json.to_string()).await?;
+    }
+    Ok(())
+}
+
+/// Serve the web dashboard, fed by events from the given channel
+pub async fn run_web_ui(
+    listen: &str,
+    event_rx: Receiver<BenchEvent>,
+    logs: LogBuffer,
+) -> std::io::Result<()> {
+    let dashboard = Dashboard {
+        state: Default::default(),
+        logs,
+    };
+
+    let state = dashboard.state.clone();
+    async_std::task::spawn(async move {
+        while let Ok(event) = event_rx.recv().await {
+            // serialized once, shared by all subscribers
+            let json: Arc<str> = serde_json::to_string(&event)
+                .expect("should serialize event")
+                .into();
+            let mut state = state.lock();
+            state
+                .subscribers
+                .retain(|tx| tx.try_send(json.clone()).is_ok());
+            state.collector.on_event(event);
+        }
+    });
+
+    let state = dashboard.state.clone();
+    async_std::task::spawn(async move {
+        loop {
+            async_std::task::sleep(Duration::from_secs(1)).await;
+            state.lock().collector.sample(Instant::now());
+        }
+    });
+
+    let mut app = tide::with_state(dashboard);
+    app.at("/").get(index);
+    app.at("/api/state").get(get_state);
+    app.at("/ws/events").get(WebSocket::new(stream_events));
+    log::info!("[WebUi] listening on {}", listen);
+    app.listen(listen.to_string()).await
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+
+use crate::{summary::RunSummary, watchdog::Abort};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run lifecycle notification, `text` makes it render as is in Slack compatible hooks
+#[derive(Debug, Serialize)]
+pub struct Notification<'a> {
+    pub event: &'static str,
+    pub run_id: &'a str,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<&'a RunSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abort: Option<&'a Abort>,
+}
+
+impl<'a> Notification<'a> {
+    pub fn started(run_id: &'a str, url: &str, count: usize) -> Self {
+        Self {
+            event: "started",
+            run_id,
+            text: format!("run {} started, {} clients against {}", run_id, count, url),
+            summary: None,
+            abort: None,
+        }
+    }
+
+    pub fn completed(run_id: &'a str, summary: &'a RunSummary) -> Self {
+        Self {
+            event: "completed",
+            run_id,
+            text: format!("run {} completed\n{}", run_id, summary),
+            summary: Some(summary),
+            abort: None,
+        }
+    }
+
+    pub fn violation(run_id: &'a str, reason: &str, summary: &'a RunSummary) -> Self {
+        Self {
+            event: "violation",
+            run_id,
+            text: format!("run {} violated: {}", run_id, reason),
+            summary: Some(summary),
+            abort: None,
+        }
+    }
+
+    pub fn aborted(run_id: &'a str, abort: &'a Abort, summary: &'a RunSummary) -> Self {
+        Self {
+            event: "aborted",
+            run_id,
+            text: format!("run {} {}", run_id, abort),
+            summary: Some(summary),
+            abort: Some(abort),
+        }
+    }
+}
+
+/// Posts notifications as JSON, failures are logged and never fail the run
+pub struct Webhook {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            http: reqwest::Client::builder()
+                .timeout(TIMEOUT)
+                .build()
+                .expect("should build http client"),
+        }
+    }
+
+    pub async fn notify(&self, notification: &Notification<'_>) {
+        let body = serde_json::to_string(notification).expect("should serialize notification");
+        let res = self
+            .http
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await;
+        match res {
+            Ok(res) if res.status().is_success() => {
+                log::debug!("[Webhook] sent {}", notification.event);
+            }
+            Ok(res) => log::warn!(
+                "[Webhook] {} rejected with {}",
+                notification.event,
+                res.status()
+            ),
+            Err(e) => log::warn!("[Webhook] {} failed {:?}", notification.event, e),
+        }
+    }
+}
@@ -1,28 +1,166 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     error::Error,
-    net::SocketAddr,
-    time::{Duration, Instant},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use async_std::prelude::FutureExt;
 use local_ip_address::list_afinet_netifas;
-use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use parking_lot::Mutex;
+use reqwest::{
+    dns,
+    header::{
+        HeaderMap, ACCEPT, AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, LOCATION, RETRY_AFTER,
+        USER_AGENT, WWW_AUTHENTICATE,
+    },
+    redirect, StatusCode,
+};
+use serde::{Deserialize, Serialize};
 use str0m::{
     bwe::Bitrate,
     change::SdpAnswer,
-    media::{Direction, MediaKind},
+    media::{Direction, KeyframeRequestKind, MediaKind},
     net::{Protocol, Receive},
+    rtp::{Extension, Ssrc},
     Candidate, Event, IceConnectionState, Input, Output, Rtc,
 };
-use udp_sas_async::async_std::UdpSocketSas;
 
-#[derive(Debug)]
+use crate::{
+    audiolevel::{AudioLevelStats, AudioLevelTracker},
+    candidate::{self, CandidatePair, CandidatePolicy, InterfaceFilter},
+    delay::DelayRange,
+    dnscache,
+    downlink::DownlinkLimiter,
+    mdns,
+    media::{self, Codec, KeyframeTracker, StreamKind},
+    mos, munge,
+    mux::{self, ClientSocket, IpFamily, SocketOptions},
+    playout::{PlayoutDelay, PlayoutDelayChange, PlayoutDelayTracker},
+    resilience::{ResilienceStats, ResilienceTracker},
+    resolution::{Resolution, ResolutionChange, ResolutionTracker},
+    rt,
+    rtpext::HeaderExtensionStats,
+    script::Script,
+    sdp::{self, AnswerSummary},
+    silence::{SilenceStats, SilenceTracker},
+    simulcast::{LayerStats, LayerSwitch, LayerTracker},
+    stream::{self, StreamChange, StreamStats, StreamTracker},
+    stun::{self, StunStats, StunTracker},
+    teardown::{self, SessionGuard},
+    timeline::{MediaTimeline, TimelineEntry},
+    traffic::{TrafficCounter, TrafficStats},
+    watchdog::parse_duration,
+    whepext,
+};
+
+const AUDIO_MID: &str = "audio_0";
+const VIDEO_MID: &str = "video_0";
+/// Time to wait for mDNS answers to each `.local` candidate
+const MDNS_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_REDIRECTS: usize = 5;
+/// Time each STUN server gets to tell the server reflexive address
+const SRFLX_TIMEOUT: Duration = Duration::from_secs(2);
+const SRFLX_RETRANSMIT: Duration = Duration::from_millis(500);
+/// Offered id of the playout-delay extension, free in the default extension map
+const PLAYOUT_DELAY_ID: u8 = 6;
+/// Repairs of every answer would flood the log, only the first is a warning
+static REPAIR_LOGGED: AtomicBool = AtomicBool::new(false);
+/// Server reflexive address by local port and STUN server, clients of a shared
+/// socket ask once
+static SRFLX: Mutex<BTreeMap<(u16, SocketAddr), SocketAddr>> =
+    parking_lot::const_mutex(BTreeMap::new());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Stats {
     pub send_kbps: u64,
     pub recv_kbps: u64,
     pub live_ms: u32,
     pub rtt_ms: u32,
     pub lost: f32,
+    pub keyframes: u64,
+    pub keyframe_interval_ms: u32,
+    /// Rtp packets per second by media kind, only collected in rtp mode
+    #[serde(default)]
+    pub audio_pps: u64,
+    #[serde(default)]
+    pub video_pps: u64,
+    /// Received video frames per second, only collected in rtp mode
+    #[serde(default)]
+    pub video_fps: f32,
+    pub streams: Vec<StreamStats>,
+    /// Audio to video offset, see [`crate::stream::av_offset_ms`]
+    pub av_offset_ms: Option<f32>,
+    /// Change of the audio to video offset since it was first measured
+    pub av_drift_ms: Option<f32>,
+    /// Ice connectivity and consent checks
+    pub stun: StunStats,
+    pub phases: ConnectPhases,
+    /// Origin which answered the offer, differs from the url after redirects
+    pub edge: Option<String>,
+    /// Kernel receive drops of the local socket, counted for all clients of a
+    /// shared socket. Linux only
+    pub socket_drops: Option<u64>,
+    /// Datagrams dropped because they were larger than the datagram size
+    pub oversized: u64,
+    /// E-model estimate of the audio quality, None without audio
+    pub mos: Option<f32>,
+    /// Received bytes by payload, overhead and retransmissions
+    pub traffic: TrafficStats,
+    /// Lost packets and their repair by retransmission and fec
+    pub resilience: ResilienceStats,
+    /// Levels of received audio, None when the server sends no audio level extension
+    pub audio_level: Option<AudioLevelStats>,
+    /// Audio pauses by DTX and by missing packets, only collected in rtp mode
+    #[serde(default)]
+    pub silence: SilenceStats,
+    /// Received packets by header extension
+    pub extensions: HeaderExtensionStats,
+    /// Simulcast layers by RID, empty when the server sends no RID extension
+    #[serde(default)]
+    pub layers: Vec<LayerStats>,
+    /// Candidate pair media flows on, None until ICE connected
+    #[serde(default)]
+    pub candidate_pair: Option<CandidatePair>,
+    /// Datagrams dropped by the downlink cap of a throttled client
+    #[serde(default)]
+    pub downlink_drops: u64,
+    /// Video resolution of the latest keyframe, only collected in rtp mode
+    #[serde(default)]
+    pub resolution: Option<Resolution>,
+    /// Resolution changes since previous stats
+    #[serde(default)]
+    pub resolution_changes: Vec<ResolutionChange>,
+    /// Playout delay the server asks for, None when it sends no playout-delay extension
+    #[serde(default)]
+    pub playout_delay: Option<PlayoutDelay>,
+    /// Playout delay changes since previous stats
+    #[serde(default)]
+    pub playout_delay_changes: Vec<PlayoutDelayChange>,
+    /// First packet and stalls since previous stats
+    #[serde(default)]
+    pub timeline: Vec<TimelineEntry>,
+}
+
+/// Durations of the connection setup phases, set once connected
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct ConnectPhases {
+    /// Offer sent until the answer was received
+    pub signaling_ms: Option<u32>,
+    /// Answer received until the first successful STUN binding
+    pub ice_ms: Option<u32>,
+    /// Ice connected until the DTLS handshake finished and SRTP is ready
+    pub dtls_ms: Option<u32>,
+    /// Offer sent until connected
+    pub total_ms: Option<u32>,
+}
+
+fn span_ms(from: Option<Instant>, to: Option<Instant>) -> Option<u32> {
+    let (from, to) = (from?, to?);
+    Some(to.saturating_duration_since(from).as_millis() as u32)
 }
 
 #[derive(Debug)]
@@ -30,6 +168,9 @@ pub enum WhepEvent {
     Continue,
     Connected,
     Stats(Stats),
+    StreamChanged(StreamChange),
+    /// Another simulcast layer is forwarded
+    LayerSwitched(LayerSwitch),
     Disconnected,
 }
 
@@ -38,105 +179,812 @@ pub enum WhepError {
     UrlError,
     ServerError(Box<dyn Error + Send + Sync>),
     SdpError,
-    WebrtcError,
+    WebrtcError(String),
     NetworkError(Box<dyn Error + Send + Sync>),
+    /// Signaling request did not complete in time
+    Timeout,
+    /// Offer answered with 401 or 403
+    AuthRejected(u16),
+    /// Offer answered with 401 and this WWW-Authenticate challenge, the endpoint
+    /// wants other credentials than those sent, if any
+    AuthChallenge(String),
+    /// Offer answered with another unexpected status
+    HttpStatus(u16),
+    /// Ice did not connect in time
+    IceTimeout,
+    /// Ice connected but the DTLS handshake did not finish in time
+    DtlsTimeout,
+    /// Connected but no media arrived within the stall timeout
+    MediaStall,
+    /// Answer rejected a required media kind with port 0 or an inactive m-line
+    MediaRejected(StreamKind),
+    /// Server answered 429 or 503, with the Retry-After delay if given in seconds
+    Throttled(Option<Duration>),
+}
+
+/// Pins a signaling host to an address like curl's `--resolve`, parsed from
+/// `host:port:ip`. Applies to urls with that host and port
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolveOverride {
+    pub host: String,
+    pub port: u16,
+    pub ip: IpAddr,
+}
+
+impl std::str::FromStr for ResolveOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("invalid resolve {}, expected host:port:ip", s);
+        let mut parts = s.splitn(3, ':');
+        let (Some(host), Some(port), Some(ip)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(usage());
+        };
+        if host.is_empty() {
+            return Err(usage());
+        }
+        Ok(Self {
+            host: host.to_ascii_lowercase(),
+            port: port.parse().map_err(|_| usage())?,
+            ip: ip
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse()
+                .map_err(|_| usage())?,
+        })
+    }
+}
+
+/// Periodic request refreshing the session resource, for gateways expiring idle
+/// sessions. Parsed from `patch:30s` or `head:30s`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keepalive {
+    pub method: HttpMethod,
+    pub interval: Duration,
+}
+
+impl std::str::FromStr for Keepalive {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || {
+            format!(
+                "invalid keepalive {}, expected e.g. patch:30s or head:30s",
+                s
+            )
+        };
+        let (method, interval) = s.split_once(':').ok_or_else(usage)?;
+        let method = match method.to_ascii_lowercase().as_str() {
+            "patch" => HttpMethod::Patch,
+            "head" => HttpMethod::Head,
+            _ => return Err(usage()),
+        };
+        let interval = parse_duration(interval)
+            .filter(|d| !d.is_zero())
+            .ok_or_else(usage)?;
+        Ok(Self { method, interval })
+    }
+}
+
+/// Per client settings, mostly passed through to the `Rtc` builder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientConfig {
+    /// Timeout of each signaling http request
+    pub signal_timeout: Duration,
+    /// Time allowed from sending the offer until ice is connected
+    pub ice_timeout: Duration,
+    /// Verify the remote DTLS fingerprint against the answer
+    pub dtls_fingerprint_verification: bool,
+    /// Fail a connected client after this long without media, None never does
+    pub stall_timeout: Option<Duration>,
+    /// Reordering buffer size in packets, None keeps the str0m default
+    pub reorder_audio: Option<usize>,
+    pub reorder_video: Option<usize>,
+    /// Receive raw rtp packets instead of depacketized frames, keyframe and
+    /// per stream stats are only collected in rtp mode
+    pub rtp_mode: bool,
+    /// Resolve `.local` candidates of the answer with mDNS, they are dropped otherwise
+    pub resolve_mdns: bool,
+    /// Signaling attempts repeated after the server answered 429 or 503
+    pub throttle_retries: u32,
+    /// Keep the Authorization header when the offer is redirected to another origin
+    pub redirect_auth: bool,
+    pub user_agent: String,
+    /// Header carrying `<run id>/<client id>` on the offer, for correlating server logs
+    pub client_id_header: Option<String>,
+    pub run_id: Option<String>,
+    /// Media kinds the answer has to accept
+    pub require: Vec<StreamKind>,
+    /// Addresses used for signaling hosts instead of DNS
+    pub resolve: Vec<ResolveOverride>,
+    pub socket: SocketOptions,
+    /// Repair malformed answers instead of failing with SdpError
+    pub lenient_sdp: bool,
+    pub keepalive: Option<Keepalive>,
+    /// STUN servers asked for a server reflexive candidate before offering
+    pub stun_servers: Vec<SocketAddr>,
+    pub candidate_policy: CandidatePolicy,
+    /// Interfaces offered as host candidates
+    pub interfaces: InterfaceFilter,
+    /// Media kinds offered, both when empty
+    pub media: Vec<StreamKind>,
+    /// Drop received datagrams above this bitrate, see [`crate::downlink`]
+    pub downlink_kbps: Option<u64>,
+    /// Signaling without upgrade or fallback to another version, None negotiates
+    pub http_version: Option<HttpVersion>,
+    /// Random delay before each offer, keepalive and DELETE, not part of their timings
+    #[serde(default)]
+    pub signal_delay: Option<DelayRange>,
+    /// Rewrites of the offer before it is posted, see [`crate::munge`]
+    #[serde(default)]
+    pub offer_rewrites: Vec<munge::OfferRewrite>,
+    /// Share lookups of signaling hosts between clients for this long, see
+    /// [`crate::dnscache`]. None resolves per request
+    #[serde(default)]
+    pub dns_cache_ttl: Option<Duration>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            signal_timeout: Duration::from_secs(10),
+            ice_timeout: Duration::from_secs(30),
+            dtls_fingerprint_verification: true,
+            stall_timeout: None,
+            reorder_audio: None,
+            reorder_video: None,
+            rtp_mode: true,
+            resolve_mdns: true,
+            throttle_retries: 3,
+            redirect_auth: true,
+            user_agent: "Whep Benchmark in Rust".to_string(),
+            client_id_header: None,
+            run_id: None,
+            require: vec![],
+            resolve: vec![],
+            socket: SocketOptions::default(),
+            lenient_sdp: false,
+            keepalive: None,
+            stun_servers: vec![],
+            candidate_policy: CandidatePolicy::All,
+            interfaces: InterfaceFilter::default(),
+            media: vec![],
+            downlink_kbps: None,
+            http_version: None,
+            signal_delay: None,
+            offer_rewrites: vec![],
+            dns_cache_ttl: None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for WhepError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            WhepError::Timeout
+        } else {
+            WhepError::ServerError(e.into())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HttpMethod {
+    Post,
+    Delete,
+    /// Keepalive of the session resource
+    Patch,
+    Head,
+}
+
+/// Http version of the signaling requests, parsed from `1.1`, `2` or `3`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum HttpVersion {
+    Http1,
+    Http2,
+    Http3,
+}
+
+impl HttpVersion {
+    fn of(version: reqwest::Version) -> Option<Self> {
+        match version {
+            reqwest::Version::HTTP_10 | reqwest::Version::HTTP_11 => Some(HttpVersion::Http1),
+            reqwest::Version::HTTP_2 => Some(HttpVersion::Http2),
+            reqwest::Version::HTTP_3 => Some(HttpVersion::Http3),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for HttpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1.1" | "1" => Ok(HttpVersion::Http1),
+            "2" => Ok(HttpVersion::Http2),
+            "3" if cfg!(feature = "http3") => Ok(HttpVersion::Http3),
+            "3" => Err("http version 3 needs the http3 feature".to_string()),
+            _ => Err(format!("invalid http version {}, expected 1.1, 2 or 3", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpVersion::Http1 => write!(f, "HTTP/1.1"),
+            HttpVersion::Http2 => write!(f, "HTTP/2"),
+            HttpVersion::Http3 => write!(f, "HTTP/3"),
+        }
+    }
+}
+
+/// Timing of one signaling request. Tcp connect and tls handshake of a new
+/// connection are not exposed by reqwest, they are part of `ttfb_ms`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpTiming {
+    pub method: HttpMethod,
+    pub status: u16,
+    /// Lookup of the host the request connected with, None when it reused a
+    /// pooled connection and for ip urls and pinned hosts
+    pub dns_ms: Option<u32>,
+    /// The lookup was served by the shared dns cache
+    #[serde(default)]
+    pub dns_cached: bool,
+    /// Until the response headers arrived
+    pub ttfb_ms: u32,
+    /// Until the whole body was read
+    pub total_ms: u32,
+    /// Version of the response
+    #[serde(default)]
+    pub version: Option<HttpVersion>,
+    /// Headers and bodies, only for clients recording their signaling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exchange: Option<Box<HttpExchange>>,
+}
+
+/// Request and response of a signaling request. Redirects are not included,
+/// only the request which got the final response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpExchange {
+    /// Unix time in ms when the request was sent
+    pub started_ms: u64,
+    pub url: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: Option<String>,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: Option<String>,
+}
+
+fn header_etag(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Header names and values, the bearer token is redacted
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if name == AUTHORIZATION {
+                "Bearer <redacted>".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).to_string()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Lookup reqwest did for a new connection, in ms and whether the shared cache
+/// served it
+#[derive(Debug, Clone, Copy)]
+struct DnsLookup {
+    ms: u32,
+    cached: bool,
+}
+
+/// Looks up signaling hosts for reqwest, through the shared cache when it is
+/// enabled, and keeps the timing for the request which needed the lookup. Keeps
+/// only the addresses of the preferred family when there are any, so the
+/// connection never races over the other one
+struct SignalingResolver {
+    family: Option<IpFamily>,
+    cache_ttl: Option<Duration>,
+    lookup: Arc<Mutex<Option<DnsLookup>>>,
+}
+
+impl dns::Resolve for SignalingResolver {
+    fn resolve(&self, name: dns::Name) -> dns::Resolving {
+        let family = self.family;
+        let cache_ttl = self.cache_ttl;
+        let lookup = self.lookup.clone();
+        Box::pin(async move {
+            let started = Instant::now();
+            let (addrs, cached): (Vec<SocketAddr>, bool) = match cache_ttl {
+                Some(ttl) => {
+                    let found = dnscache::lookup(name.as_str(), ttl).await?;
+                    let addrs = found
+                        .addrs
+                        .into_iter()
+                        .map(|ip| SocketAddr::new(ip, 0))
+                        .collect();
+                    (addrs, found.cached)
+                }
+                None => {
+                    let addrs = async_std::net::ToSocketAddrs::to_socket_addrs(&(name.as_str(), 0))
+                        .await?
+                        .collect();
+                    (addrs, false)
+                }
+            };
+            *lookup.lock() = Some(DnsLookup {
+                ms: started.elapsed().as_millis() as u32,
+                cached,
+            });
+            let preferred: Vec<SocketAddr> = addrs
+                .iter()
+                .copied()
+                .filter(|addr| family.map_or(true, |family| family.matches(&addr.ip())))
+                .collect();
+            let addrs = if preferred.is_empty() {
+                addrs
+            } else {
+                preferred
+            };
+            Ok(Box::new(addrs.into_iter()) as dns::Addrs)
+        })
+    }
+}
+
+/// Local addresses of the interfaces `filter` allows advertised as host candidates,
+/// those of `family` or ipv4 when there are none. Link-local ipv6 is skipped as it
+/// needs a scope id
+fn candidate_ips(family: IpFamily, filter: &InterfaceFilter) -> Vec<IpAddr> {
+    let interfaces = list_afinet_netifas()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, ip)| !matches!(ip, IpAddr::V6(v6) if (v6.segments()[0] & 0xffc0) == 0xfe80))
+        .collect();
+    let ips = filter.apply(interfaces);
+    let preferred: Vec<IpAddr> = ips
+        .iter()
+        .copied()
+        .filter(|ip| family.matches(ip))
+        .collect();
+    if !preferred.is_empty() {
+        return preferred;
+    }
+    ips.into_iter().filter(|ip| ip.is_ipv4()).collect()
+}
+
+/// Outcome of the signaling request
+#[derive(Debug, Clone)]
+pub struct SignalingInfo {
+    pub status: u16,
+    pub latency: Duration,
+    /// Values of `Link` headers, e.g. advertised ice servers
+    pub links: Vec<String>,
+    pub answer: String,
+    /// Url which answered the offer
+    pub url: String,
 }
 
 pub struct WhepClient {
     rtc: Rtc,
-    socket: UdpSocketSas,
-    location: Option<String>,
+    socket: ClientSocket,
+    http: reqwest::Client,
+    /// Session created by the answer, deleted on disconnect
+    session: Option<SessionGuard>,
+    keepalive: Option<Keepalive>,
+    next_keepalive: Option<Instant>,
+    signal_delay: Option<DelayRange>,
+    offer_rewrites: Vec<munge::OfferRewrite>,
     parse_url: url::Url,
+    /// Set by the resolver when a request opened a connection
+    dns_lookup: Arc<Mutex<Option<DnsLookup>>>,
     url: String,
     token: String,
     live_at: Option<Instant>,
+    offer_at: Option<Instant>,
+    ice_timeout: Duration,
+    stall_timeout: Option<Duration>,
+    last_media_at: Option<Instant>,
+    resolve_mdns: bool,
+    redirect_auth: bool,
+    user_agent: String,
+    client_id_header: Option<String>,
+    run_id: Option<String>,
+    require: Vec<StreamKind>,
+    lenient_sdp: bool,
+    script: Option<Arc<Script>>,
+    client_id: usize,
+    /// Value of the client id header
+    identity: Option<String>,
+    http_timings: Vec<HttpTiming>,
+    /// Keep headers and bodies of the signaling requests in their timings
+    record_http: bool,
+    #[cfg(feature = "wav")]
+    audio_dump: Option<Arc<Mutex<crate::wav::AudioDump>>>,
+    /// Headers of the latest offer request while recording
+    offer_headers: Vec<(String, String)>,
+    answer_summary: Option<AnswerSummary>,
+    edge: Option<String>,
     rtt: u32,
-    buf: [u8; 1500],
     pre_ts: Instant,
     pre_send_bytes: u64,
     pre_recv_bytes: u64,
+    codecs: HashMap<u8, Codec>,
+    signaling: Option<SignalingInfo>,
+    keyframes: KeyframeTracker,
+    resolution: ResolutionTracker,
+    /// Latest keyframe request not answered yet
+    keyframe_requested_at: Option<Instant>,
+    /// Time from the request until its keyframe arrived, taken by the runner
+    keyframe_latency_ms: Option<u64>,
+    playout_delay: PlayoutDelayTracker,
+    timeline: MediaTimeline,
+    streams: HashMap<u32, StreamTracker>,
+    /// Current SSRC of each mid
+    mids: HashMap<String, u32>,
+    stun: StunTracker,
+    traffic: TrafficCounter,
+    resilience: ResilienceTracker,
+    audio_level: AudioLevelTracker,
+    silence: SilenceTracker,
+    extensions: HeaderExtensionStats,
+    layers: LayerTracker,
+    /// Switch seen on a packet which also changed the stream, emitted next
+    pending_switch: Option<LayerSwitch>,
+    answer_at: Option<Instant>,
+    ice_connected_at: Option<Instant>,
+    phases: ConnectPhases,
+    first_av_offset_ms: Option<f32>,
+    /// Type of each local candidate address
+    local_candidates: HashMap<SocketAddr, String>,
+    /// Type and transport of each candidate address of the answer
+    remote_candidates: HashMap<SocketAddr, (String, String)>,
+    candidate_pair: Option<CandidatePair>,
+    stun_servers: Vec<SocketAddr>,
+    candidate_policy: CandidatePolicy,
+    media: Vec<StreamKind>,
+    downlink: Option<DownlinkLimiter>,
 }
 
 impl WhepClient {
-    pub fn new(url: &str, token: &str) -> Result<Self, WhepError> {
+    pub fn new(url: &str, token: &str, config: &ClientConfig) -> Result<Self, WhepError> {
         let socket =
-            UdpSocketSas::bind("0.0.0.0:0".parse().unwrap()).expect("Should bind udp socket");
-        let mut rtc = Rtc::builder()
-            .set_rtp_mode(true)
+            ClientSocket::bind(&config.socket).map_err(|e| WhepError::NetworkError(e.into()))?;
+        Self::with_socket(url, token, config, socket)
+    }
+
+    /// Create a client on a given socket, e.g. a slot of a shared [`crate::mux::SocketPool`]
+    pub fn with_socket(
+        url: &str,
+        token: &str,
+        config: &ClientConfig,
+        socket: ClientSocket,
+    ) -> Result<Self, WhepError> {
+        let parse_url = url::Url::parse(url).map_err(|_| WhepError::UrlError)?;
+        let port = parse_url.port_or_known_default();
+        // redirects are followed by hand, reqwest would drop the auth header across origins
+        let mut http = reqwest::Client::builder()
+            .timeout(config.signal_timeout)
+            .redirect(redirect::Policy::none());
+        match config.http_version {
+            Some(HttpVersion::Http1) => http = http.http1_only(),
+            Some(HttpVersion::Http2) => http = http.http2_prior_knowledge(),
+            #[cfg(feature = "http3")]
+            Some(HttpVersion::Http3) => http = http.http3_prior_knowledge(),
+            #[cfg(not(feature = "http3"))]
+            Some(HttpVersion::Http3) => {
+                return Err(WhepError::NetworkError(
+                    "http version 3 needs the http3 feature".into(),
+                ))
+            }
+            None => {}
+        }
+        // pinned hosts below bypass the resolver
+        let dns_lookup = Arc::new(Mutex::new(None));
+        http = http.dns_resolver(Arc::new(SignalingResolver {
+            family: config.socket.prefer,
+            cache_ttl: config.dns_cache_ttl,
+            lookup: dns_lookup.clone(),
+        }));
+        for pin in config.resolve.iter().filter(|pin| Some(pin.port) == port) {
+            // reqwest connects to the port of the url, which is the pinned one here
+            http = http.resolve(&pin.host, SocketAddr::new(pin.ip, pin.port));
+        }
+        let http = http
+            .build()
+            .map_err(|e| WhepError::NetworkError(e.into()))?;
+        let mut builder = Rtc::builder()
+            .set_rtp_mode(config.rtp_mode)
+            .set_fingerprint_verification(config.dtls_fingerprint_verification)
             .set_stats_interval(Some(Duration::from_secs(2)))
             .enable_bwe(Some(Bitrate::kbps(1000)))
-            .build();
-
-        if let Ok(network_interfaces) = list_afinet_netifas() {
-            for (_name, ip) in network_interfaces {
-                if ip.is_ipv4() {
-                    rtc.add_local_candidate(
-                        Candidate::host(
-                            SocketAddr::new(ip, socket.local_addr().port()),
-                            str0m::net::Protocol::Udp,
-                        )
-                        .expect(""),
-                    );
+            .set_extension(PLAYOUT_DELAY_ID, Extension::PlayoutDelay);
+        if let Some(size) = config.reorder_audio {
+            builder = builder.set_reordering_size_audio(size);
+        }
+        if let Some(size) = config.reorder_video {
+            builder = builder.set_reordering_size_video(size);
+        }
+        let mut rtc = builder.build();
+
+        let mut local_candidates = HashMap::new();
+        let ips = match socket.bound_ip() {
+            Some(ip) => vec![ip],
+            None => candidate_ips(
+                config.socket.prefer.unwrap_or(IpFamily::V4),
+                &config.interfaces,
+            ),
+        };
+        for ip in ips {
+            let addr = SocketAddr::new(ip, socket.local_port());
+            match Candidate::host(addr, str0m::net::Protocol::Udp) {
+                Ok(candidate) => {
+                    rtc.add_local_candidate(candidate);
+                    local_candidates.insert(addr, "host".to_string());
                 }
+                Err(e) => log::warn!("[WhepClient] skip invalid candidate {} {:?}", addr, e),
             }
         }
 
         Ok(Self {
             socket,
+            http,
             rtc,
-            location: None,
+            session: None,
+            keepalive: config.keepalive,
+            next_keepalive: None,
+            signal_delay: config.signal_delay,
+            offer_rewrites: config.offer_rewrites.clone(),
             live_at: None,
-            parse_url: url::Url::parse(url).map_err(|_| WhepError::UrlError)?,
+            offer_at: None,
+            ice_timeout: config.ice_timeout,
+            stall_timeout: config.stall_timeout,
+            last_media_at: None,
+            resolve_mdns: config.resolve_mdns,
+            redirect_auth: config.redirect_auth,
+            user_agent: config.user_agent.clone(),
+            client_id_header: config.client_id_header.clone(),
+            run_id: config.run_id.clone(),
+            require: config.require.clone(),
+            lenient_sdp: config.lenient_sdp,
+            script: None,
+            client_id: 0,
+            identity: None,
+            http_timings: vec![],
+            record_http: false,
+            #[cfg(feature = "wav")]
+            audio_dump: None,
+            offer_headers: vec![],
+            answer_summary: None,
+            edge: None,
+            parse_url,
+            dns_lookup,
             url: url.to_string(),
             token: token.to_string(),
             rtt: 0,
-            buf: [0; 1500],
             pre_ts: Instant::now(),
             pre_send_bytes: 0,
             pre_recv_bytes: 0,
+            codecs: HashMap::new(),
+            signaling: None,
+            keyframes: KeyframeTracker::default(),
+            resolution: ResolutionTracker::default(),
+            keyframe_requested_at: None,
+            keyframe_latency_ms: None,
+            playout_delay: PlayoutDelayTracker::default(),
+            timeline: MediaTimeline::default(),
+            streams: HashMap::new(),
+            mids: HashMap::new(),
+            stun: StunTracker::default(),
+            traffic: TrafficCounter::default(),
+            resilience: ResilienceTracker::default(),
+            audio_level: AudioLevelTracker::default(),
+            silence: SilenceTracker::default(),
+            extensions: HeaderExtensionStats::default(),
+            layers: LayerTracker::default(),
+            pending_switch: None,
+            answer_at: None,
+            ice_connected_at: None,
+            phases: ConnectPhases::default(),
+            first_av_offset_ms: None,
+            local_candidates,
+            remote_candidates: HashMap::new(),
+            candidate_pair: None,
+            stun_servers: config.stun_servers.clone(),
+            candidate_policy: config.candidate_policy,
+            media: config.media.clone(),
+            downlink: config
+                .downlink_kbps
+                .map(|kbps| DownlinkLimiter::new(kbps, Instant::now())),
         })
     }
 
     pub async fn prepare(&mut self) -> Result<(), WhepError> {
+        self.gather_srflx().await;
         let mut change = self.rtc.sdp_api();
-        change.add_media(
-            MediaKind::Audio,
-            Direction::RecvOnly,
-            Some("audio_0".to_string()),
-            Some("audio_0".to_string()),
-        );
-        change.add_media(
-            MediaKind::Video,
-            Direction::RecvOnly,
-            Some("video_0".to_string()),
-            Some("video_0".to_string()),
-        );
+        let offers = |kind| self.media.is_empty() || self.media.contains(&kind);
+        if offers(StreamKind::Audio) {
+            change.add_media(
+                MediaKind::Audio,
+                Direction::RecvOnly,
+                Some(AUDIO_MID.to_string()),
+                Some(AUDIO_MID.to_string()),
+            );
+        }
+        if offers(StreamKind::Video) {
+            change.add_media(
+                MediaKind::Video,
+                Direction::RecvOnly,
+                Some(VIDEO_MID.to_string()),
+                Some(VIDEO_MID.to_string()),
+            );
+        }
 
-        let (offer, pending) = change.apply().expect("");
+        let (offer, pending) = change.apply().ok_or(WhepError::SdpError)?;
 
-        let offer_str = offer.to_sdp_string();
-        log::info!("offer: {}", offer_str);
+        let mut offer_str = munge::rewrite(&offer.to_sdp_string(), &self.offer_rewrites);
+        if let Some(sdp) = self
+            .script
+            .as_ref()
+            .and_then(|script| script.on_offer(self.client_id, &offer_str))
+        {
+            offer_str = sdp;
+        }
+        if let Some(ufrag) = mux::sdp_ice_ufrag(&offer_str) {
+            self.socket.set_ufrag(ufrag);
+        }
+        log::trace!("offer: {}", offer_str);
 
-        let res = reqwest::Client::new()
-            .post(&self.url)
-            .header(CONTENT_TYPE, "application/sdp")
-            .header(USER_AGENT, "Whep Benchmark in Rust")
-            .header(ACCEPT, "application/sdp")
-            //set token with Bear header
-            .header(AUTHORIZATION, format!("Bearer {}", self.token))
-            .body(offer_str)
-            .send()
-            .await
-            .map_err(|e| WhepError::ServerError(e.into()))?;
+        self.delay_signaling().await;
+        let sent_at = Instant::now();
+        self.offer_at = Some(sent_at);
+        let started_ms = unix_ms();
+        self.dns_lookup.lock().take();
+        let (res, answered_by) = self.post_offer(&offer_str).await?;
+        let ttfb_ms = sent_at.elapsed().as_millis() as u32;
+        let (dns_ms, dns_cached) = self.take_dns_lookup();
+        self.edge = Some(answered_by.origin().ascii_serialization());
+        let mut exchange = self.record_http.then(|| {
+            Box::new(HttpExchange {
+                started_ms,
+                url: answered_by.to_string(),
+                request_headers: std::mem::take(&mut self.offer_headers),
+                request_body: Some(offer_str.clone()),
+                response_headers: header_pairs(res.headers()),
+                response_body: None,
+            })
+        });
 
         // get answer sdp from body
         let location = res.headers().get("location").cloned();
+        let etag = header_etag(res.headers());
+        let challenge = res
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let links = res
+            .headers()
+            .get_all("link")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+            .collect();
         let http_code = res.status();
-        let answer = res
-            .text()
-            .await
-            .map_err(|e| WhepError::ServerError(e.into()))?;
-        log::info!("answer: {} {}", http_code, answer);
+        let version = HttpVersion::of(res.version());
+        if http_code == StatusCode::TOO_MANY_REQUESTS
+            || http_code == StatusCode::SERVICE_UNAVAILABLE
+        {
+            let retry_after = res
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            log::debug!(
+                "[WhepClient] throttled {} retry after {:?}",
+                http_code,
+                retry_after
+            );
+            self.http_timings.push(HttpTiming {
+                method: HttpMethod::Post,
+                status: http_code.as_u16(),
+                dns_ms,
+                dns_cached,
+                ttfb_ms,
+                total_ms: ttfb_ms,
+                version,
+                exchange,
+            });
+            return Err(WhepError::Throttled(retry_after));
+        }
+        let answer = res.text().await?;
+        self.answer_at = Some(Instant::now());
+        if let Some(exchange) = exchange.as_mut() {
+            exchange.response_body = Some(answer.clone());
+        }
+        self.http_timings.push(HttpTiming {
+            method: HttpMethod::Post,
+            status: http_code.as_u16(),
+            dns_ms,
+            dns_cached,
+            ttfb_ms,
+            total_ms: sent_at.elapsed().as_millis() as u32,
+            version,
+            exchange,
+        });
+        log::trace!("answer: {} {}", http_code, answer);
+        let whep_extensions = whepext::advertised(&links);
+        self.signaling = Some(SignalingInfo {
+            status: http_code.as_u16(),
+            latency: sent_at.elapsed(),
+            links,
+            answer: answer.clone(),
+            url: answered_by.to_string(),
+        });
+        match (http_code, challenge) {
+            (StatusCode::UNAUTHORIZED, Some(challenge)) => {
+                return Err(WhepError::AuthChallenge(challenge))
+            }
+            (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN, _) => {
+                return Err(WhepError::AuthRejected(http_code.as_u16()))
+            }
+            (code, _) if !code.is_success() => return Err(WhepError::HttpStatus(code.as_u16())),
+            _ => {}
+        }
+        self.codecs = media::parse_rtpmap(&answer);
+        self.resilience = ResilienceTracker::new(media::parse_rtx_apt(&answer));
+        self.answer_summary = Some(AnswerSummary {
+            whep_extensions,
+            ..AnswerSummary::parse(&answer)
+        });
+        let answer_sdp = answer.clone();
+        let answer = if answer.contains(".local ") {
+            let wait = self.resolve_mdns.then_some(MDNS_TIMEOUT);
+            mdns::resolve_candidates(&answer, wait).await
+        } else {
+            answer
+        };
+        let answer = self.candidate_policy.filter(&answer).ok_or_else(|| {
+            WhepError::ServerError(
+                format!(
+                    "answer has no candidates allowed by policy {:?}",
+                    self.candidate_policy
+                )
+                .into(),
+            )
+        })?;
+        self.remote_candidates = candidate::parse_candidates(&answer);
+        let answer = if self.lenient_sdp {
+            let (repaired, fixes) = sdp::repair_answer(&answer);
+            if !fixes.is_empty() {
+                log::debug!("[WhepClient] repaired answer: {}", fixes.join(", "));
+                if !REPAIR_LOGGED.swap(true, Ordering::Relaxed) {
+                    log::warn!(
+                        "[WhepClient] answer needed repairs: {}, further ones are logged at debug",
+                        fixes.join(", ")
+                    );
+                }
+            }
+            repaired
+        } else {
+            answer
+        };
         let answer = SdpAnswer::from_sdp_string(&answer).map_err(|_| WhepError::SdpError)?;
 
         // get location form header location
@@ -148,15 +996,22 @@ impl WhepClient {
 
         // if location started with / then concat with based url, else use location as url
         let url = if location.starts_with("/") {
-            format!(
-                "{}{}",
-                self.parse_url.origin().ascii_serialization(),
-                location
-            )
+            format!("{}{}", answered_by.origin().ascii_serialization(), location)
         } else {
             location.to_string()
         };
-        self.location = Some(url);
+        self.session = Some(SessionGuard::new(
+            self.client_id,
+            self.http.clone(),
+            url,
+            etag,
+        ));
+        self.next_keepalive = self.keepalive.map(|k| Instant::now() + k.interval);
+
+        let accepted = sdp::accepted_media(&answer_sdp);
+        if let Some(kind) = self.require.iter().find(|kind| !accepted.contains(kind)) {
+            return Err(WhepError::MediaRejected(*kind));
+        }
 
         // apply answer sdp
         self.rtc
@@ -167,27 +1022,451 @@ impl WhepClient {
         Ok(())
     }
 
+    /// What the server negotiated, set once an answer was accepted
+    pub fn answer_summary(&self) -> Option<&AnswerSummary> {
+        self.answer_summary.as_ref()
+    }
+
+    /// Timings of the signaling requests since the previous call
+    pub fn take_http_timings(&mut self) -> Vec<HttpTiming> {
+        std::mem::take(&mut self.http_timings)
+    }
+
+    /// Record headers and bodies of the signaling requests into their timings
+    pub fn set_record_http(&mut self, record: bool) {
+        self.record_http = record;
+    }
+
+    /// Decode the received audio into `dump`
+    #[cfg(feature = "wav")]
+    pub fn set_audio_dump(&mut self, dump: Option<Arc<Mutex<crate::wav::AudioDump>>>) {
+        self.audio_dump = dump;
+    }
+
+    /// Run the offer and header hooks of `script` for this client
+    pub fn set_script(&mut self, script: Option<Arc<Script>>) {
+        self.script = script;
+    }
+
+    /// Identify this client to the server with the configured header
+    pub fn set_client_id(&mut self, client_id: usize) {
+        self.client_id = client_id;
+        self.identity = Some(match &self.run_id {
+            Some(run_id) => format!("{}/{}", run_id, client_id),
+            None => client_id.to_string(),
+        });
+    }
+
+    /// Post the offer, re-posting it to the target of each redirect.
+    /// Returns the response and the url which gave it
+    async fn post_offer(
+        &mut self,
+        offer: &str,
+    ) -> Result<(reqwest::Response, url::Url), WhepError> {
+        let mut url = self.parse_url.clone();
+        let headers = self
+            .script
+            .as_ref()
+            .map(|script| script.headers(self.client_id))
+            .unwrap_or_default();
+        for _ in 0..=MAX_REDIRECTS {
+            let mut req = self
+                .http
+                .post(url.as_str())
+                .header(CONTENT_TYPE, "application/sdp")
+                .header(USER_AGENT, &self.user_agent)
+                .header(ACCEPT, "application/sdp")
+                .body(offer.to_string());
+            if let (Some(name), Some(identity)) = (&self.client_id_header, &self.identity) {
+                req = req.header(name.as_str(), identity);
+            }
+            for (name, value) in &headers {
+                req = req.header(name.as_str(), value.as_str());
+            }
+            // anonymous without a token
+            if !self.token.is_empty()
+                && (self.redirect_auth || url.origin() == self.parse_url.origin())
+            {
+                //set token with Bear header
+                req = req.header(AUTHORIZATION, format!("Bearer {}", self.token));
+            }
+            let req = req.build()?;
+            if self.record_http {
+                self.offer_headers = header_pairs(req.headers());
+            }
+            let res = self.http.execute(req).await?;
+            if !res.status().is_redirection() {
+                return Ok((res, url));
+            }
+            let target = res
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or(WhepError::ServerError("Redirect without Location".into()))?;
+            let next = url.join(target).map_err(|_| WhepError::UrlError)?;
+            log::debug!("[WhepClient] {} redirected to {}", res.status(), next);
+            url = next;
+        }
+        Err(WhepError::ServerError("Too many redirects".into()))
+    }
+
+    /// Result of the last signaling request, also set when `prepare` failed after it
+    pub fn signaling(&self) -> Option<&SignalingInfo> {
+        self.signaling.as_ref()
+    }
+
+    /// Delete the session, retrying failed attempts. A session which could not be
+    /// deleted counts as leaked
     pub async fn disconnect(&mut self) -> Result<(), WhepError> {
-        if let Some(location) = self.location.take() {
-            reqwest::Client::new()
-                .delete(location)
-                .send()
-                .await
-                .map_err(|e| WhepError::ServerError(e.into()))?;
+        let Some(mut session) = self.session.take() else {
+            return Ok(());
+        };
+        let mut attempt = 1;
+        loop {
+            let location = session.location().to_string();
+            let res = self.delete(&location, session.etag()).await;
+            match res {
+                Ok((status, _)) if teardown::is_gone(status) => {
+                    session.release();
+                    return Ok(());
+                }
+                // the session changed since its etag, retry with the current one or
+                // unconditionally when the server did not tell
+                Ok((412, etag)) if attempt < teardown::DELETE_ATTEMPTS => {
+                    log::debug!("[WhepClient] delete precondition failed, etag {:?}", etag);
+                    session.set_etag(etag);
+                    attempt += 1;
+                }
+                Ok((status, _)) if !teardown::is_retryable(status) => {
+                    log::warn!("[WhepClient] delete answered {}", status);
+                    session.leak();
+                    return Ok(());
+                }
+                _ if attempt < teardown::DELETE_ATTEMPTS => {
+                    log::debug!("[WhepClient] delete attempt {} failed {:?}", attempt, res);
+                    attempt += 1;
+                    rt::sleep(teardown::RETRY_DELAY).await;
+                }
+                Ok(_) => {
+                    session.leak();
+                    return Ok(());
+                }
+                Err(e) => {
+                    session.leak();
+                    return Err(e);
+                }
+            }
         }
-        Ok(())
+    }
+
+    /// Send a PLI on the received video streams and time the keyframe answering it,
+    /// false without video. Only rtp mode tracks the streams
+    pub fn request_keyframe(&mut self) -> bool {
+        let ssrcs: Vec<u32> = self
+            .streams
+            .iter()
+            .filter(|(_, stream)| stream.codec().is_video())
+            .map(|(ssrc, _)| *ssrc)
+            .collect();
+        let mut requested = false;
+        for ssrc in ssrcs {
+            if let Some(stream) = self.rtc.direct_api().stream_rx(&Ssrc::from(ssrc)) {
+                stream.request_keyframe(KeyframeRequestKind::Pli);
+                requested = true;
+            }
+        }
+        if requested {
+            self.keyframe_requested_at.get_or_insert_with(Instant::now);
+        }
+        requested
+    }
+
+    /// Latency of the keyframe answering the latest request, once it arrived
+    pub fn take_keyframe_latency(&mut self) -> Option<u64> {
+        self.keyframe_latency_ms.take()
+    }
+
+    /// Drop the session without DELETE, left for the server to reap
+    pub fn abandon(&mut self) {
+        if let Some(session) = self.session.take() {
+            session.abandon();
+        }
+    }
+
+    pub fn keepalive_due(&self) -> bool {
+        self.next_keepalive.map_or(false, |at| Instant::now() >= at)
+    }
+
+    /// Refresh the session resource, fails on any answer but 2xx
+    pub async fn keepalive(&mut self) -> Result<(), WhepError> {
+        let (Some(keepalive), Some(session)) = (self.keepalive, &self.session) else {
+            return Ok(());
+        };
+        self.next_keepalive = Some(Instant::now() + keepalive.interval);
+        let location = session.location().to_string();
+        let etag = session.etag().map(str::to_string);
+        let req = match keepalive.method {
+            HttpMethod::Head => self.http.head(&location),
+            // an empty fragment, the session is refreshed without trickling candidates
+            _ => self
+                .http
+                .patch(&location)
+                .header(CONTENT_TYPE, "application/trickle-ice-sdpfrag"),
+        };
+        let mut req = req.header(USER_AGENT, &self.user_agent);
+        if !self.token.is_empty() {
+            req = req.header(AUTHORIZATION, format!("Bearer {}", self.token));
+        }
+        if let Some(etag) = &etag {
+            req = req.header(IF_MATCH, etag);
+        }
+        self.delay_signaling().await;
+        let (status, new_etag) = self
+            .session_request(keepalive.method, req, &location)
+            .await?;
+        // a 412 with the current etag lets the next keepalive match again
+        if new_etag.is_some() || status == 412 {
+            if let Some(session) = self.session.as_mut() {
+                session.set_etag(new_etag);
+            }
+        }
+        if (200..300).contains(&status) {
+            Ok(())
+        } else {
+            Err(WhepError::HttpStatus(status))
+        }
+    }
+
+    /// Answer status with the etag the answer carried
+    async fn delete(
+        &mut self,
+        location: &str,
+        etag: Option<&str>,
+    ) -> Result<(u16, Option<String>), WhepError> {
+        let mut req = self.http.delete(location);
+        if let Some(etag) = etag {
+            req = req.header(IF_MATCH, etag);
+        }
+        self.delay_signaling().await;
+        let _permit = teardown::delete_permit().await;
+        self.session_request(HttpMethod::Delete, req, location)
+            .await
+    }
+
+    /// Lookup of the request which just completed, when it opened a connection
+    fn take_dns_lookup(&self) -> (Option<u32>, bool) {
+        match self.dns_lookup.lock().take() {
+            Some(lookup) => (Some(lookup.ms), lookup.cached),
+            None => (None, false),
+        }
+    }
+
+    /// Sleep for the configured signal delay, emulating a distant viewer
+    async fn delay_signaling(&self) {
+        if let Some(range) = self.signal_delay {
+            let delay = range.sample(&mut rand::thread_rng());
+            rt::sleep(delay).await;
+        }
+    }
+
+    /// Send a request to the session resource, its timing is kept
+    async fn session_request(
+        &mut self,
+        method: HttpMethod,
+        req: reqwest::RequestBuilder,
+        location: &str,
+    ) -> Result<(u16, Option<String>), WhepError> {
+        let sent_at = Instant::now();
+        let started_ms = unix_ms();
+        let req = req.build()?;
+        let request_headers = header_pairs(req.headers());
+        self.dns_lookup.lock().take();
+        let res = self.http.execute(req).await?;
+        let ttfb_ms = sent_at.elapsed().as_millis() as u32;
+        let (dns_ms, dns_cached) = self.take_dns_lookup();
+        let status = res.status().as_u16();
+        let version = HttpVersion::of(res.version());
+        let etag = header_etag(res.headers());
+        let response_headers = header_pairs(res.headers());
+        let body = res.bytes().await?;
+        let exchange = self.record_http.then(|| {
+            Box::new(HttpExchange {
+                started_ms,
+                url: location.to_string(),
+                request_headers,
+                request_body: None,
+                response_headers,
+                response_body: Some(String::from_utf8_lossy(&body).to_string()),
+            })
+        });
+        self.http_timings.push(HttpTiming {
+            method,
+            status,
+            dns_ms,
+            dns_cached,
+            ttfb_ms,
+            total_ms: sent_at.elapsed().as_millis() as u32,
+            version,
+            exchange,
+        });
+        Ok((status, etag))
+    }
+
+    /// Ask each STUN server for the public address of the socket and offer it as a
+    /// server reflexive candidate, servers which do not answer are skipped
+    async fn gather_srflx(&mut self) {
+        if self.candidate_policy == CandidatePolicy::Host {
+            return;
+        }
+        let port = self.socket.local_port();
+        for server in self.stun_servers.clone() {
+            let Some(base) = self
+                .local_candidates
+                .keys()
+                .find(|addr| addr.is_ipv4() == server.is_ipv4())
+                .copied()
+            else {
+                continue;
+            };
+            let cached = SRFLX.lock().get(&(port, server)).copied();
+            let mapped = match cached {
+                Some(mapped) => mapped,
+                None => match self.stun_binding(base, server).await {
+                    Some(mapped) => {
+                        SRFLX.lock().insert((port, server), mapped);
+                        mapped
+                    }
+                    None => {
+                        log::debug!("[WhepClient] stun server {} did not answer", server);
+                        continue;
+                    }
+                },
+            };
+            // the same address as the host candidate, there is no NAT in between
+            if mapped == base {
+                continue;
+            }
+            match Candidate::server_reflexive(mapped, base, Protocol::Udp) {
+                Ok(candidate) => {
+                    log::debug!("[WhepClient] srflx candidate {} from {}", mapped, server);
+                    self.rtc.add_local_candidate(candidate);
+                    self.local_candidates.insert(mapped, "srflx".to_string());
+                }
+                Err(e) => log::warn!("[WhepClient] skip invalid srflx {} {:?}", mapped, e),
+            }
+        }
+    }
+
+    async fn stun_binding(&mut self, base: SocketAddr, server: SocketAddr) -> Option<SocketAddr> {
+        let transaction: [u8; 12] = rand::random();
+        let request = stun::binding_request(transaction);
+        let deadline = Instant::now() + SRFLX_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Err(e) = self.socket.send(&request, base.ip(), server).await {
+                log::debug!("[WhepClient] stun request to {} failed {:?}", server, e);
+                return None;
+            }
+            let retransmit = Instant::now() + SRFLX_RETRANSMIT;
+            loop {
+                let wait = retransmit.saturating_duration_since(Instant::now());
+                let Ok(Ok((data, source, _))) = rt::timeout(wait, self.socket.recv()).await else {
+                    break;
+                };
+                if source == server {
+                    if let Some(mapped) = stun::mapped_address(data, &transaction) {
+                        return Some(mapped);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn on_selected_pair(&mut self, local: SocketAddr, remote: SocketAddr) {
+        let local_type = self
+            .local_candidates
+            .get(&local)
+            .cloned()
+            .unwrap_or_else(|| "host".to_string());
+        // a remote address missing from the answer was learned from its checks
+        let (remote_type, protocol) = self
+            .remote_candidates
+            .get(&remote)
+            .cloned()
+            .unwrap_or_else(|| ("prflx".to_string(), "udp".to_string()));
+        let pair = CandidatePair {
+            local_type,
+            local,
+            remote_type,
+            remote,
+            protocol,
+            nominated_ms: span_ms(self.answer_at, self.ice_connected_at),
+        };
+        log::debug!("[WhepClient] selected candidate pair {}", pair);
+        self.candidate_pair = Some(pair);
+    }
+
+    /// Remember the SSRC of a mid, a different one replaces the stream of the old SSRC
+    fn on_mid_ssrc(&mut self, mid: String, ssrc: u32) -> Option<StreamChange> {
+        let old_ssrc = self.mids.insert(mid.clone(), ssrc)?;
+        if old_ssrc == ssrc {
+            return None;
+        }
+        log::debug!(
+            "[WhepClient] mid {} changed ssrc {} => {}",
+            mid,
+            old_ssrc,
+            ssrc
+        );
+        self.streams.remove(&old_ssrc);
+        Some(StreamChange {
+            mid,
+            old_ssrc,
+            new_ssrc: ssrc,
+        })
     }
 
     pub async fn recv<'a>(&mut self) -> Result<WhepEvent, WhepError> {
-        let timeout = match self.rtc.poll_output().map_err(|_| WhepError::WebrtcError)? {
+        if let Some(switch) = self.pending_switch.take() {
+            return Ok(WhepEvent::LayerSwitched(switch));
+        }
+        if self.live_at.is_none() && self.offer_at.map(|t| t.elapsed()) > Some(self.ice_timeout) {
+            return Err(match self.ice_connected_at {
+                Some(_) => WhepError::DtlsTimeout,
+                None => WhepError::IceTimeout,
+            });
+        }
+        if let (Some(live_at), Some(stall_timeout)) = (self.live_at, self.stall_timeout) {
+            if self.last_media_at.unwrap_or(live_at).elapsed() > stall_timeout {
+                return Err(WhepError::MediaStall);
+            }
+        }
+        let timeout = match self
+            .rtc
+            .poll_output()
+            .map_err(|e| WhepError::WebrtcError(e.to_string()))?
+        {
             Output::Event(event) => match event {
                 Event::Connected => {
-                    self.live_at = Some(Instant::now());
+                    let now = Some(Instant::now());
+                    self.live_at = now;
+                    let stun_at = self.stun.first_response_at();
+                    self.phases = ConnectPhases {
+                        signaling_ms: span_ms(self.offer_at, self.answer_at),
+                        ice_ms: span_ms(self.answer_at, stun_at),
+                        dtls_ms: span_ms(self.ice_connected_at, now),
+                        total_ms: span_ms(self.offer_at, now),
+                    };
+                    log::debug!("[WhepClient] connected, phases {:?}", self.phases);
                     return Ok(WhepEvent::Connected);
                 }
                 Event::IceConnectionStateChange(state) => {
-                    log::info!("[WhepClient] ice connection state change: {:?}", state);
+                    log::debug!("[WhepClient] ice connection state change: {:?}", state);
                     match state {
+                        IceConnectionState::Connected | IceConnectionState::Completed => {
+                            self.ice_connected_at.get_or_insert_with(Instant::now);
+                            return Ok(WhepEvent::Continue);
+                        }
                         IceConnectionState::Disconnected => return Ok(WhepEvent::Disconnected),
                         _ => return Ok(WhepEvent::Continue),
                     }
@@ -205,20 +1484,178 @@ impl WhepClient {
                     self.pre_send_bytes = stats.peer_bytes_tx;
                     self.pre_recv_bytes = stats.peer_bytes_rx;
 
+                    let streams: Vec<StreamStats> = self
+                        .streams
+                        .values_mut()
+                        .map(|s| s.stats(duration))
+                        .collect();
+                    let lost = stats.ingress_loss_fraction.unwrap_or(0.0);
+                    let mos = streams
+                        .iter()
+                        .find(|s| s.codec == Codec::Opus)
+                        .map(|s| mos::estimate(self.rtt as f32, s.jitter_ms, lost));
+                    let av_offset_ms = stream::av_offset_ms(&streams);
+                    if let Some(offset) = av_offset_ms {
+                        self.first_av_offset_ms.get_or_insert(offset);
+                    }
+
                     return Ok(WhepEvent::Stats(Stats {
                         send_kbps,
                         recv_kbps,
-                        lost: stats.ingress_loss_fraction.unwrap_or(0.0),
+                        lost,
                         live_ms: self
                             .live_at
                             .map(|t| t.elapsed().as_millis() as u32)
                             .unwrap_or(0),
                         rtt_ms: self.rtt,
+                        keyframes: self.keyframes.count(),
+                        keyframe_interval_ms: self.keyframes.interval_ms(),
+                        audio_pps: stream::pps(&streams, StreamKind::Audio),
+                        video_pps: stream::pps(&streams, StreamKind::Video),
+                        video_fps: stream::fps(&streams),
+                        streams,
+                        av_offset_ms,
+                        av_drift_ms: av_offset_ms
+                            .zip(self.first_av_offset_ms)
+                            .map(|(offset, first)| offset - first),
+                        stun: self.stun.stats(Instant::now()),
+                        phases: self.phases,
+                        edge: self.edge.clone(),
+                        socket_drops: mux::udp_drops(self.socket.local_port()),
+                        oversized: self.socket.oversized(),
+                        mos,
+                        traffic: self.traffic.stats(duration),
+                        resilience: self.resilience.stats(),
+                        audio_level: self.audio_level.stats(),
+                        silence: self.silence.stats(Instant::now()),
+                        extensions: self.extensions,
+                        layers: self.layers.stats(duration),
+                        candidate_pair: self.candidate_pair.clone(),
+                        downlink_drops: self.downlink.as_ref().map_or(0, |d| d.dropped()),
+                        resolution: self.resolution.current(),
+                        resolution_changes: self.resolution.take_changes(),
+                        playout_delay: self.playout_delay.current(),
+                        playout_delay_changes: self.playout_delay.take_changes(),
+                        timeline: self.timeline.take_entries(),
                     }));
                 }
+                Event::MediaData(_) => {
+                    self.last_media_at = Some(Instant::now());
+                    self.timeline.on_media(Instant::now());
+                    return Ok(WhepEvent::Continue);
+                }
                 Event::RtpPacket(pkt) => {
+                    self.last_media_at = Some(Instant::now());
+                    self.timeline.on_media(Instant::now());
                     log::trace!("rtp packet: {:?}", pkt);
-                    return Ok(WhepEvent::Continue);
+                    let codec = self
+                        .codecs
+                        .get(&*pkt.header.payload_type)
+                        .copied()
+                        .unwrap_or(Codec::Other);
+                    if codec.is_video() && media::is_keyframe(codec, &pkt.payload) {
+                        let now = Instant::now();
+                        self.keyframes.on_keyframe(now, pkt.header.timestamp);
+                        if let Some(at) = self.keyframe_requested_at.take() {
+                            self.keyframe_latency_ms = Some((now - at).as_millis() as u64);
+                        }
+                        self.resolution.on_keyframe(now, codec, &pkt.payload);
+                    }
+                    self.extensions.on_packet(&pkt.header.ext_vals);
+                    self.playout_delay
+                        .on_packet(Instant::now(), &pkt.header.ext_vals);
+                    if codec == Codec::Opus {
+                        if let Some(level) = pkt.header.ext_vals.audio_level {
+                            self.audio_level.on_level(level);
+                        }
+                        self.silence
+                            .on_audio(pkt.timestamp, *pkt.seq_no, pkt.payload.len());
+                        #[cfg(feature = "wav")]
+                        if let Some(dump) = &self.audio_dump {
+                            let seq = pkt.header.sequence_number;
+                            let res = dump.lock().on_packet(*pkt.header.ssrc, seq, &pkt.payload);
+                            if let Err(e) = res {
+                                log::error!("[WhepClient] cannot write audio dump {:?}", e);
+                                self.audio_dump = None;
+                            }
+                        }
+                    }
+                    let ssrc = *pkt.header.ssrc;
+                    let pt = *pkt.header.payload_type;
+                    match codec {
+                        Codec::Rtx => self.resilience.on_rtx(pt, &pkt.payload),
+                        Codec::Ulpfec | Codec::Flexfec => {
+                            self.resilience
+                                .on_fec(ssrc, &pkt.payload, codec == Codec::Ulpfec)
+                        }
+                        _ if codec.kind().is_some() => {
+                            self.resilience
+                                .on_media(ssrc, pt, pkt.header.sequence_number)
+                        }
+                        _ => {}
+                    }
+                    // retransmissions and fec have no kind and must not count as a switch
+                    let change = if codec.kind().is_none() {
+                        None
+                    } else {
+                        let mid = match pkt.header.ext_vals.mid {
+                            Some(mid) => mid.to_string(),
+                            None if codec.is_video() => VIDEO_MID.to_string(),
+                            None => AUDIO_MID.to_string(),
+                        };
+                        self.on_mid_ssrc(mid, ssrc)
+                    };
+                    let switch = match (&pkt.header.ext_vals.rid, codec.kind()) {
+                        (Some(rid), Some(_)) => {
+                            let mid = pkt
+                                .header
+                                .ext_vals
+                                .mid
+                                .map_or(VIDEO_MID.to_string(), |mid| mid.to_string());
+                            self.layers
+                                .on_rtp(&mid, &rid.to_string(), pkt.payload.len())
+                        }
+                        _ => None,
+                    };
+                    self.traffic.on_rtp(
+                        pkt.header.header_len,
+                        pkt.payload.len(),
+                        codec.kind().is_none(),
+                    );
+                    let stream = self
+                        .streams
+                        .entry(ssrc)
+                        .or_insert_with(|| StreamTracker::new(ssrc, codec));
+                    if let Some(info) = &pkt.last_sender_info {
+                        stream.on_sender_report(
+                            info.ntp_time,
+                            info.rtp_time.numer() as u32,
+                            pkt.timestamp,
+                        );
+                    }
+                    stream.on_rtp(
+                        pkt.payload.len(),
+                        *pkt.seq_no,
+                        pkt.header.timestamp,
+                        pkt.header.marker,
+                        pkt.timestamp,
+                    );
+                    if let Some(switch) = &switch {
+                        log::debug!(
+                            "[WhepClient] mid {} switched layer {} => {}",
+                            switch.mid,
+                            switch.from,
+                            switch.to
+                        );
+                    }
+                    return Ok(match (change, switch) {
+                        (Some(change), switch) => {
+                            self.pending_switch = switch;
+                            WhepEvent::StreamChanged(change)
+                        }
+                        (None, Some(switch)) => WhepEvent::LayerSwitched(switch),
+                        (None, None) => WhepEvent::Continue,
+                    });
                 }
                 _ => {
                     return Ok(WhepEvent::Continue);
@@ -226,11 +1663,22 @@ impl WhepClient {
             },
             Output::Timeout(timeout) => timeout,
             Output::Transmit(send) => {
+                self.stun.on_send(&send.contents, Instant::now());
+                if self.candidate_pair.is_none()
+                    && self.ice_connected_at.is_some()
+                    && !candidate::is_stun(&send.contents)
+                {
+                    self.on_selected_pair(send.source, send.destination);
+                }
                 if let Err(e) = self
                     .socket
-                    .send_sas(&send.contents, send.source.ip(), send.destination)
+                    .send(&send.contents, send.source.ip(), send.destination)
                     .await
                 {
+                    // another client of the shared socket owns the remote
+                    if e.kind() == std::io::ErrorKind::AddrInUse {
+                        return Err(WhepError::NetworkError(e.into()));
+                    }
                     log::debug!(
                         "sending to {} => {}, len {} error {:?}",
                         send.source,
@@ -255,17 +1703,32 @@ impl WhepClient {
             };
         }
 
-        let input = match self.socket.recv_sas(&mut self.buf).timeout(duration).await {
-            Ok(Ok((n, source, destination))) => {
+        let port = self.socket.local_port();
+        let input = match rt::timeout(duration, self.socket.recv()).await {
+            Ok(Ok((data, source, destination))) => {
                 // UDP data received.
-                log::trace!("received from {} => {}, len {}", source, destination, n);
+                log::trace!(
+                    "received from {} => {}, len {}",
+                    source,
+                    destination,
+                    data.len()
+                );
+                if let Some(downlink) = &mut self.downlink {
+                    if !candidate::is_stun(data) && !downlink.admit(data.len(), Instant::now()) {
+                        return Ok(WhepEvent::Continue);
+                    }
+                }
+                self.stun.on_recv(data, Instant::now());
+                self.traffic.on_datagram(data);
                 Input::Receive(
                     Instant::now(),
                     Receive {
                         proto: Protocol::Udp,
                         source,
-                        destination: SocketAddr::new(destination, self.socket.local_addr().port()),
-                        contents: (&self.buf[..n]).try_into().expect("should webrtc"),
+                        destination: SocketAddr::new(destination, port),
+                        contents: data
+                            .try_into()
+                            .map_err(|_| WhepError::WebrtcError("invalid datagram".into()))?,
                     },
                 )
             }
@@ -283,7 +1746,7 @@ impl WhepClient {
         // Input is either a Timeout or Receive of data. Both drive the state forward.
         self.rtc
             .handle_input(input)
-            .map_err(|_| WhepError::WebrtcError)?;
+            .map_err(|e| WhepError::WebrtcError(e.to_string()))?;
         return Ok(WhepEvent::Continue);
     }
 }
@@ -0,0 +1,149 @@
+//! WHEP protocol extensions a server advertises with `Link` headers on the
+//! answer, like server sent events, layer selection and ICE servers. Clients of
+//! one server should all be offered the same ones
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+const EXT_PREFIX: &str = "urn:ietf:params:whep:ext:";
+const REL_EVENTS: &str = "urn:ietf:params:whep:ext:core:server-sent-events";
+const REL_LAYER: &str = "urn:ietf:params:whep:ext:core:layer";
+const REL_ICE_SERVER: &str = "ice-server";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum WhepExtension {
+    /// Server sent events, with the event types the server offers
+    Events(Vec<String>),
+    Layer,
+    IceServer,
+    /// Another `urn:ietf:params:whep:ext:` relation
+    Other(String),
+}
+
+impl fmt::Display for WhepExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WhepExtension::Events(events) if events.is_empty() => write!(f, "events"),
+            WhepExtension::Events(events) => write!(f, "events [{}]", events.join(", ")),
+            WhepExtension::Layer => write!(f, "layer"),
+            WhepExtension::IceServer => write!(f, "ice-server"),
+            WhepExtension::Other(rel) => write!(f, "{}", rel.trim_start_matches(EXT_PREFIX)),
+        }
+    }
+}
+
+/// One link of a `Link` header, `<target>; rel="..."; key="value"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub target: String,
+    pub rel: Option<String>,
+    pub params: Vec<(String, String)>,
+}
+
+impl Link {
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Links of `Link` header values, a value may hold several separated by `,`
+pub fn parse_links(values: &[String]) -> Vec<Link> {
+    values
+        .iter()
+        .flat_map(|value| split_outside_quotes(value, ','))
+        .filter_map(|link| parse_link(&link))
+        .collect()
+}
+
+fn parse_link(s: &str) -> Option<Link> {
+    let mut parts = split_outside_quotes(s, ';').into_iter();
+    let target = parts.next()?;
+    let target = target.trim().strip_prefix('<')?.strip_suffix('>')?;
+    let mut link = Link {
+        target: target.to_string(),
+        rel: None,
+        params: vec![],
+    };
+    for part in parts {
+        let (key, value) = part.split_once('=').unwrap_or((&part, ""));
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim().trim_matches('"').to_string();
+        if key == "rel" && link.rel.is_none() {
+            link.rel = Some(value);
+        } else {
+            link.params.push((key, value));
+        }
+    }
+    Some(link)
+}
+
+/// Split on `sep` except inside quotes and `<>`
+fn split_outside_quotes(s: &str, sep: char) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let (mut quoted, mut bracketed) = (false, false);
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '<' if !quoted => bracketed = true,
+            '>' if !quoted => bracketed = false,
+            c if c == sep && !quoted && !bracketed => {
+                parts.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(current);
+    parts.retain(|p| !p.trim().is_empty());
+    parts
+}
+
+/// Extensions advertised by the links, sorted and each once. Links with other
+/// relations are ignored
+pub fn advertised(links: &[String]) -> Vec<WhepExtension> {
+    let mut extensions: Vec<WhepExtension> = parse_links(links)
+        .into_iter()
+        .filter_map(|link| {
+            let rel = link.rel.as_deref()?;
+            // a link may carry several relations
+            rel.split_whitespace().find_map(|rel| match rel {
+                REL_EVENTS => {
+                    let mut events: Vec<String> = link
+                        .param("events")
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(|e| e.trim().to_string())
+                        .filter(|e| !e.is_empty())
+                        .collect();
+                    events.sort();
+                    Some(WhepExtension::Events(events))
+                }
+                REL_LAYER => Some(WhepExtension::Layer),
+                REL_ICE_SERVER => Some(WhepExtension::IceServer),
+                rel if rel.starts_with(EXT_PREFIX) => Some(WhepExtension::Other(rel.to_string())),
+                _ => None,
+            })
+        })
+        .collect();
+    extensions.sort();
+    extensions.dedup();
+    extensions
+}
+
+/// One line of extensions, `none` without any
+pub fn describe(extensions: &[WhepExtension]) -> String {
+    if extensions.is_empty() {
+        return "none".to_string();
+    }
+    extensions
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}